@@ -0,0 +1,131 @@
+//! Benchmarks of whole programs run by the VM, and of the globals Dict
+//!
+//! Run with `cargo bench`. Each program runs in a new `Memory`, so that every run starts from the
+//! same heap and none of them pays to collect what earlier runs allocated.
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use evalrus::{Dict, HashIndexedAnyContainer, Memory, Mutator, MutatorView, RuntimeError, Session};
+
+/// There are no number literals, so constants are made from text
+const CONSTANTS: &[&str] = &[
+    "(set 'one (string->number \"1\"))",
+    "(set 'two (string->number \"2\"))",
+    "(set 'twenty (string->number \"20\"))",
+    "(set 'thousand (string->number \"1000\"))",
+];
+
+/// Allocates three Pairs on each of a thousand iterations
+const ALLOCATION: (&[&str], &str) = (&[], "(dotimes (i thousand) (cons (list i i) (list i)))");
+
+/// Tree-recursive Fibonacci, which makes about twenty thousand non-tail calls
+const RECURSION: (&[&str], &str) = (
+    &["(def fib (n) \
+         (cond (zero? n) n \
+               (zero? (+ n (neg one))) n \
+               true (+ (fib (+ n (neg one))) (fib (+ n (neg two))))))"],
+    "(fib twenty)",
+);
+
+/// Integer arithmetic on each of a thousand iterations, with no allocation
+const ARITHMETIC: (&[&str], &str) = (
+    &[],
+    "(dotimes (i thousand) (+ (* i i) (quotient i two) (modulo i two)))",
+);
+
+/// Repeatedly assigns and reads globals, which live in a Dict
+const GLOBALS: (&[&str], &str) = (
+    &["(set 'a nil)", "(set 'b nil)", "(set 'c nil)"],
+    "(dotimes (i thousand) (set 'a i) (set 'b a) (set 'c (cons b c)))",
+);
+
+/// Start a session with the constants and the given definitions
+fn session(definitions: &[&str]) -> (Memory, Session) {
+    let mem = Memory::new();
+    let session = Session::new(&mem).unwrap();
+
+    for source in CONSTANTS.iter().chain(definitions) {
+        session.eval(&mem, source).unwrap();
+    }
+
+    (mem, session)
+}
+
+fn programs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("program");
+
+    for (name, (definitions, program)) in &[
+        ("allocation", ALLOCATION),
+        ("recursion", RECURSION),
+        ("arithmetic", ARITHMETIC),
+        ("globals", GLOBALS),
+    ] {
+        group.bench_function(*name, |b| {
+            b.iter_batched(
+                || session(definitions),
+                // the memory is returned so that freeing it is not timed
+                |(mem, session)| {
+                    let result = session.eval(&mem, program).unwrap();
+                    (mem, session, result)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+/// Associates `count` Symbol keys with values in a new Dict, giving the time taken by the
+/// `assoc()` calls alone
+struct DictAssoc {
+    count: usize,
+}
+
+impl Mutator for DictAssoc {
+    type Input = ();
+    type Output = Duration;
+
+    fn run(&self, mem: &MutatorView, _input: ()) -> Result<Duration, RuntimeError> {
+        let keys: Vec<_> = (0..self.count)
+            .map(|n| mem.lookup_sym(&format!("key{}", n)))
+            .collect();
+        let dict = Dict::alloc(mem)?;
+
+        let start = Instant::now();
+        for key in keys {
+            dict.assoc(mem, key, key)?;
+        }
+        Ok(start.elapsed())
+    }
+}
+
+fn dict_assoc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Dict::assoc");
+
+    // large objects aren't supported yet, so the Dict's entries must fit in a block
+    for count in &[10, 500] {
+        group.bench_function(format!("{} keys", count), |b| {
+            b.iter_custom(|iters| {
+                let mut mem = Memory::new();
+                let mut elapsed = Duration::default();
+
+                for _ in 0..iters {
+                    elapsed += mem.mutate(&DictAssoc { count: *count }, ()).unwrap();
+                    // forget the Dict, keeping the interned keys
+                    // SAFETY: the Dict and keys are only reachable from within the mutation
+                    // that has just returned, and no Session or other heap handle is held here
+                    unsafe { mem.reset() };
+                }
+
+                elapsed
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, programs, dict_assoc);
+criterion_main!(benches);
@@ -13,12 +13,12 @@ pub use stickyimmix::{AllocObject, ArraySize};
 
 use crate::containers::{
     AnyContainerFromPairList, AnyContainerFromSlice, Container, ContainerFromSlice,
-    FillAnyContainer, FillContainer, IndexedAnyContainer, IndexedContainer, SliceableContainer,
-    StackAnyContainer, StackContainer,
+    FillAnyContainer, FillContainer, IndexedAnyContainer, IndexedContainer, InsertableAnyContainer,
+    InsertableContainer, SliceableContainer, StackAnyContainer, StackContainer,
 };
 use crate::error::{ErrorKind, RuntimeError};
 use crate::headers::TypeList;
-use crate::memory::MutatorView;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::rawarray::{default_array_growth, RawArray, DEFAULT_ARRAY_SIZE};
 use crate::safeptr::{MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
@@ -83,6 +83,10 @@ impl<T: Sized + Clone> Array<T> {
     /// Return a bounds-checked pointer to the object at the given index
     // ANCHOR: DefArrayGetOffset
     fn get_offset(&self, index: ArraySize) -> Result<*mut T, RuntimeError> {
+        // the length must never exceed the allocated capacity, or the bounds check against the
+        // length is meaningless
+        debug_assert!(self.length.get() <= self.data.get().capacity());
+
         if index >= self.length.get() {
             Err(RuntimeError::new(ErrorKind::BoundsError))
         } else {
@@ -210,6 +214,11 @@ impl<T: Sized + Clone> FillContainer<T> for Array<T> {
         size: ArraySize,
         item: T,
     ) -> Result<(), RuntimeError> {
+        // filling may reallocate the array, which must not happen while a slice is held
+        if self.borrow.get() != INTERIOR_ONLY {
+            return Err(RuntimeError::new(ErrorKind::MutableBorrowError));
+        }
+
         let length = self.length();
 
         if length > size {
@@ -220,11 +229,13 @@ impl<T: Sized + Clone> FillContainer<T> for Array<T> {
             let capacity = array.capacity();
 
             if size > capacity {
-                if capacity == 0 {
-                    array.resize(mem, DEFAULT_ARRAY_SIZE)?;
+                // grow by at least the default rate, but enough to hold `size` items
+                let new_capacity = if capacity == 0 {
+                    DEFAULT_ARRAY_SIZE
                 } else {
-                    array.resize(mem, default_array_growth(capacity)?)?;
-                }
+                    default_array_growth(capacity)?
+                };
+                array.resize(mem, new_capacity.max(size))?;
                 // Replace the struct's copy with the resized RawArray object
                 self.data.set(array);
             }
@@ -324,6 +335,49 @@ impl<T: Sized + Clone> IndexedContainer<T> for Array<T> {
     }
 }
 
+impl<T: Sized + Clone> InsertableContainer<T> for Array<T> {
+    /// Insert an item before the given index, shifting the following items up by one.
+    fn insert<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        index: ArraySize,
+        item: T,
+    ) -> Result<(), RuntimeError> {
+        if index > self.length.get() {
+            return Err(RuntimeError::new(ErrorKind::BoundsError));
+        }
+
+        // push takes care of the borrow check and any resize, leaving the new item at the end
+        StackContainer::push(self, mem, item)?;
+
+        let slice = unsafe { self.as_slice(mem) };
+        slice[index as usize..].rotate_right(1);
+
+        Ok(())
+    }
+
+    /// Remove the item at the given index, shifting the following items down by one.
+    fn remove<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<T, RuntimeError> {
+        if self.borrow.get() != INTERIOR_ONLY {
+            return Err(RuntimeError::new(ErrorKind::MutableBorrowError));
+        }
+
+        if index >= self.length.get() {
+            return Err(RuntimeError::new(ErrorKind::BoundsError));
+        }
+
+        // move the item to the end so that it can be popped
+        let slice = unsafe { self.as_slice(guard) };
+        slice[index as usize..].rotate_left(1);
+
+        StackContainer::pop(self, guard)
+    }
+}
+
 impl<T: Sized + Clone> SliceableContainer<T> for Array<T> {
     fn access_slice<'guard, F, R>(&self, guard: &'guard dyn MutatorScope, f: F) -> R
     where
@@ -350,6 +404,12 @@ impl Print for ArrayU8 {
     }
 }
 
+impl Trace for ArrayU8 {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.array(guard, self);
+    }
+}
+
 /// Array of u16
 pub type ArrayU16 = Array<u16>;
 
@@ -363,6 +423,12 @@ impl Print for ArrayU16 {
     }
 }
 
+impl Trace for ArrayU16 {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.array(guard, self);
+    }
+}
+
 /// Array of u32
 pub type ArrayU32 = Array<u32>;
 
@@ -376,6 +442,12 @@ impl Print for ArrayU32 {
     }
 }
 
+impl Trace for ArrayU32 {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.array(guard, self);
+    }
+}
+
 impl FillAnyContainer for Array<TaggedCellPtr> {
     fn fill<'guard>(
         &self,
@@ -383,6 +455,11 @@ impl FillAnyContainer for Array<TaggedCellPtr> {
         size: ArraySize,
         item: TaggedScopedPtr<'guard>,
     ) -> Result<(), RuntimeError> {
+        // filling may reallocate the array, which must not happen while a slice is held
+        if self.borrow.get() != INTERIOR_ONLY {
+            return Err(RuntimeError::new(ErrorKind::MutableBorrowError));
+        }
+
         let length = self.length();
 
         if length > size {
@@ -393,11 +470,13 @@ impl FillAnyContainer for Array<TaggedCellPtr> {
             let capacity = array.capacity();
 
             if size > capacity {
-                if capacity == 0 {
-                    array.resize(mem, DEFAULT_ARRAY_SIZE)?;
+                // grow by at least the default rate, but enough to hold `size` items
+                let new_capacity = if capacity == 0 {
+                    DEFAULT_ARRAY_SIZE
                 } else {
-                    array.resize(mem, default_array_growth(capacity)?)?;
-                }
+                    default_array_growth(capacity)?
+                };
+                array.resize(mem, new_capacity.max(size))?;
                 // Replace the struct's copy with the resized RawArray object
                 self.data.set(array);
             }
@@ -465,6 +544,32 @@ impl IndexedAnyContainer for Array<TaggedCellPtr> {
     }
 }
 
+impl InsertableAnyContainer for Array<TaggedCellPtr> {
+    /// Insert an object pointer before the given index. Bounds-checked.
+    fn insert<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        index: ArraySize,
+        item: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        InsertableContainer::<TaggedCellPtr>::insert(
+            self,
+            mem,
+            index,
+            TaggedCellPtr::new_with(item),
+        )
+    }
+
+    /// Return the object pointer at the given index, removing it. Bounds-checked.
+    fn remove<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        Ok(InsertableContainer::<TaggedCellPtr>::remove(self, guard, index)?.get(guard))
+    }
+}
+
 impl AnyContainerFromPairList for Array<TaggedCellPtr> {
     fn from_pair_list<'guard>(
         &self,
@@ -540,10 +645,23 @@ impl Print for Array<TaggedCellPtr> {
     }
 }
 
+impl Trace for Array<TaggedCellPtr> {
+    /// Every item is live, so every item is traced along with the array
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.array(guard, self);
+        self.access_slice(guard, |items| {
+            for item in items.iter() {
+                roots.value(item.get(guard));
+            }
+        });
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::{
-        AnyContainerFromPairList, Array, Container, IndexedAnyContainer, IndexedContainer,
+        AnyContainerFromPairList, Array, Container, FillContainer, IndexedAnyContainer,
+        IndexedContainer, InsertableAnyContainer, InsertableContainer, SliceableContainer,
         StackAnyContainer, StackContainer,
     };
     use crate::error::{ErrorKind, RuntimeError};
@@ -702,6 +820,168 @@ mod test {
         mem.mutate(&test, ()).unwrap();
     }
 
+    #[test]
+    fn array_fill_beyond_growth_rate() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<u32> = Array::new();
+                array.push(view, 1)?;
+
+                // fill well beyond what a single growth step would allocate
+                array.fill(view, 100, 7)?;
+
+                assert!(array.length() == 100);
+                assert!(array.data.get().capacity() >= 100);
+                assert!(IndexedContainer::get(&array, view, 0)? == 1);
+                for index in 1..100 {
+                    assert!(IndexedContainer::get(&array, view, index)? == 7);
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn array_generic_insert_and_remove() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<i64> = Array::new();
+
+                // middle, start and end, enough to trigger reallocation
+                for i in 0..8 {
+                    array.push(view, i)?;
+                }
+                array.insert(view, 4, 100)?;
+                array.insert(view, 0, 101)?;
+                array.insert(view, 10, 102)?;
+
+                let expected = [101, 0, 1, 2, 3, 100, 4, 5, 6, 7, 102];
+                assert!(array.length() == expected.len() as u32);
+                assert!(array.access_slice(view, |items| items == expected));
+
+                // and back out again
+                assert!(array.remove(view, 5) == Ok(100));
+                assert!(array.remove(view, 0) == Ok(101));
+                assert!(array.remove(view, 8) == Ok(102));
+
+                let expected = [0, 1, 2, 3, 4, 5, 6, 7];
+                assert!(array.length() == expected.len() as u32);
+                assert!(array.access_slice(view, |items| items == expected));
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn array_insert_and_remove_bounds() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<i64> = Array::new();
+
+                match array.remove(view, 0) {
+                    Ok(_) => panic!("Removing from an empty array should be out of bounds!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+
+                array.insert(view, 0, 1)?;
+
+                match array.insert(view, 2, 2) {
+                    Ok(_) => panic!("Insert index should have been out of bounds!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+
+                match array.remove(view, 1) {
+                    Ok(_) => panic!("Remove index should have been out of bounds!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+
+                assert!(array.length() == 1);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn arrayany_insert_and_remove() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<TaggedCellPtr> = Array::new();
+                let array = view.alloc(array)?;
+
+                StackAnyContainer::push(&*array, view, view.lookup_sym("a"))?;
+                StackAnyContainer::push(&*array, view, view.lookup_sym("c"))?;
+                InsertableAnyContainer::insert(&*array, view, 1, view.lookup_sym("b"))?;
+
+                for (index, name) in ["a", "b", "c"].iter().enumerate() {
+                    let item = IndexedAnyContainer::get(&*array, view, index as u32)?;
+                    assert!(item == view.lookup_sym(name));
+                }
+
+                let removed = InsertableAnyContainer::remove(&*array, view, 0)?;
+                assert!(removed == view.lookup_sym("a"));
+                assert!(array.length() == 2);
+                assert!(IndexedAnyContainer::get(&*array, view, 0)? == view.lookup_sym("b"));
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
     #[test]
     fn arrayany_from_pair_list() {
         let mem = Memory::new();
@@ -751,4 +1031,177 @@ mod test {
         let test = Test {};
         mem.mutate(&test, ()).unwrap();
     }
+
+    // The following tests exercise the raw pointer arithmetic in get_offset(), read() and write()
+    // at the edges of the allocation. They are kept small and fast so that they can be run under
+    // Miri to check for undefined behavior:
+    //
+    //     cargo +nightly miri test array::
+
+    #[test]
+    fn array_offset_unallocated() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<u64> = Array::new();
+
+                // no backing storage at all
+                match array.get_offset(0) {
+                    Ok(_) => panic!("Unallocated array should have no valid offsets!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+                assert!(unsafe { array.as_slice(view) }.is_empty());
+                assert!(unsafe { array.as_capacity_slice(view) }.is_empty());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn array_offset_read_write_at_capacity() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<u64> = Array::with_capacity(view, 4)?;
+
+                // allocated but empty: capacity does not make an index valid
+                assert!(array.read(view, 0).is_err());
+                assert!(array.write(view, 0, 1).is_err());
+
+                // fill exactly to capacity, without reallocating
+                array.fill(view, 4, 0)?;
+                assert!(array.data.get().capacity() == 4);
+
+                // first and last slots
+                array.write(view, 0, u64::MAX)?;
+                array.write(view, 3, u64::MAX - 3)?;
+                assert!(array.read(view, 0)? == u64::MAX);
+                assert!(*array.read_ref(view, 3)? == u64::MAX - 3);
+
+                // one past the end
+                match array.write(view, 4, 4) {
+                    Ok(_) => panic!("Write should have been out of bounds!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+                match array.read(view, 4) {
+                    Ok(_) => panic!("Read should have been out of bounds!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+
+                let slice = unsafe { array.as_capacity_slice(view) };
+                assert!(slice == [u64::MAX, 0, 0, u64::MAX - 3]);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn array_fill_while_sliced() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<u64> = Array::with_capacity(view, 2)?;
+                array.fill(view, 2, 0)?;
+
+                // reallocating would invalidate the slice
+                let result = array.access_slice(view, |_| array.fill(view, 64, 0));
+                match result {
+                    Ok(_) => panic!("Array should not be resizable while sliced!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::MutableBorrowError),
+                }
+                assert!(array.length() == 2);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn array_push_out_of_memory() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let array: Array<i64> = Array::new();
+
+                // the first push allocates the backing array
+                view.fail_next_allocations(1);
+                match array.push(view, 0) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::OutOfMemory),
+                    Ok(_) => panic!("push should have failed to allocate"),
+                }
+                assert!(array.length() == 0);
+
+                for i in 0..8 {
+                    array.push(view, i)?;
+                }
+
+                // a full array has to grow, and when it can't, it is left as it was
+                view.fail_next_allocations(1);
+                match array.push(view, 8) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::OutOfMemory),
+                    Ok(_) => panic!("push should have failed to allocate"),
+                }
+                assert!(array.length() == 8);
+                for i in 0..8 {
+                    assert!(IndexedContainer::get(&array, view, i)? == i as i64);
+                }
+
+                array.push(view, 8)?;
+                assert!(array.length() == 9);
+                assert!(array.pop(view)? == 8);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
 }
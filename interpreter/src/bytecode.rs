@@ -1,6 +1,7 @@
 use itertools::join;
 use std::cell::Cell;
 use std::fmt;
+use std::mem::size_of;
 
 use crate::array::{Array, ArraySize};
 use crate::containers::{
@@ -8,16 +9,20 @@ use crate::containers::{
 };
 use crate::error::{err_eval, RuntimeError};
 use crate::list::List;
-use crate::memory::MutatorView;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedScopedPtr};
 use crate::taggedptr::TaggedPtr;
+use crate::vm::FIRST_ARG_REG;
 
 /// A register can be in the range 0..255
 // ANCHOR: DefRegister
 pub type Register = u8;
 // ANCHOR_END: DefRegister
 
+/// The number of registers in a function's register window, one for each `Register` value
+pub const REGISTER_COUNT: usize = Register::MAX as usize + 1;
+
 /// A literal integer that can be baked into an opcode can be in the range -32768..32767
 pub type LiteralInteger = i16;
 
@@ -42,6 +47,12 @@ pub type NumArgs = u8;
 /// Direct u32 is more ergonomic for the compiler but enum struct variants is
 /// more ergonomic for the vm and probably more performant. Lots of match repetition
 /// though :(
+///
+/// That 24 bit budget for the fields of each variant allows for three 8 bit
+/// values (`Register`, `UpvalueId`, `NumArgs`), or one 8 bit value and one 16 bit
+/// value (`LiteralId`, `LiteralInteger`, `JumpOffset`). The discriminant takes
+/// the remaining 8 bits, so there can be no more than 256 variants. A variant
+/// that goes over budget makes every opcode bigger, which fails the build.
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Opcode {
     NoOp,
@@ -60,6 +71,10 @@ pub enum Opcode {
         dest: Register,
         test: Register,
     },
+    IsEmpty {
+        dest: Register,
+        test: Register,
+    },
     FirstOfPair {
         dest: Register,
         reg: Register,
@@ -68,11 +83,24 @@ pub enum Opcode {
         dest: Register,
         reg: Register,
     },
+    StrictFirstOfPair {
+        dest: Register,
+        reg: Register,
+    },
+    StrictSecondOfPair {
+        dest: Register,
+        reg: Register,
+    },
     MakePair {
         dest: Register,
         reg1: Register,
         reg2: Register,
     },
+    Equal {
+        dest: Register,
+        test1: Register,
+        test2: Register,
+    },
     IsIdentical {
         dest: Register,
         test1: Register,
@@ -137,6 +165,39 @@ pub enum Opcode {
         num: Register,
         denom: Register,
     },
+    FloorDivideInteger {
+        dest: Register,
+        num: Register,
+        denom: Register,
+    },
+    Remainder {
+        dest: Register,
+        num: Register,
+        denom: Register,
+    },
+    Modulo {
+        dest: Register,
+        num: Register,
+        denom: Register,
+    },
+    Negate {
+        dest: Register,
+        src: Register,
+    },
+    Abs {
+        dest: Register,
+        src: Register,
+    },
+    Min {
+        dest: Register,
+        reg1: Register,
+        reg2: Register,
+    },
+    Max {
+        dest: Register,
+        reg1: Register,
+        reg2: Register,
+    },
     GetUpvalue {
         dest: Register,
         src: UpvalueId,
@@ -150,8 +211,217 @@ pub enum Opcode {
         reg2: Register,
         reg3: Register,
     },
+    Concat {
+        dest: Register,
+        first: Register,
+        count: NumArgs,
+    },
+    Print {
+        dest: Register,
+        src: Register,
+    },
+    PrintLine {
+        dest: Register,
+        src: Register,
+    },
+    IsLessThan {
+        dest: Register,
+        test1: Register,
+        test2: Register,
+    },
+    IsZero {
+        dest: Register,
+        test: Register,
+    },
+    IsPositive {
+        dest: Register,
+        test: Register,
+    },
+    IsNegative {
+        dest: Register,
+        test: Register,
+    },
+    IsEven {
+        dest: Register,
+        test: Register,
+    },
+    IsOdd {
+        dest: Register,
+        test: Register,
+    },
+    TailCall {
+        function: Register,
+        dest: Register,
+        arg_count: NumArgs,
+    },
+    ListInsert {
+        list: Register,
+        index: Register,
+        item: Register,
+    },
+    ListRemove {
+        dest: Register,
+        list: Register,
+        index: Register,
+    },
+    NumberToText {
+        dest: Register,
+        number: Register,
+        radix: Register,
+    },
+    TextToNumber {
+        dest: Register,
+        text: Register,
+        radix: Register,
+    },
+    LoadGlobalNames {
+        dest: Register,
+    },
+    IsBound {
+        dest: Register,
+        name: Register,
+    },
+    FunctionParams {
+        dest: Register,
+        function: Register,
+    },
+    FunctionName {
+        dest: Register,
+        function: Register,
+    },
+    FunctionArity {
+        dest: Register,
+        function: Register,
+    },
+    FunctionSize {
+        dest: Register,
+        function: Register,
+    },
+    MakeTextBuilder {
+        dest: Register,
+    },
+    AppendText {
+        dest: Register,
+        builder: Register,
+        value: Register,
+    },
+    BuildText {
+        dest: Register,
+        builder: Register,
+    },
+    MakeListBuilder {
+        dest: Register,
+    },
+    ListAppend {
+        dest: Register,
+        builder: Register,
+        value: Register,
+    },
+    BuildList {
+        dest: Register,
+        builder: Register,
+    },
+    MakeValues {
+        dest: Register,
+        first: Register,
+        count: u8,
+    },
+    UnpackValues {
+        dest: Register,
+        count: u8,
+        src: Register,
+    },
+    MakeRecord {
+        dest: Register,
+        first: Register,
+        count: u8,
+    },
+    IsRecordOf {
+        dest: Register,
+        test: Register,
+        type_name: Register,
+    },
+    CheckRecord {
+        record: Register,
+        type_name: Register,
+    },
+    RecordGet {
+        dest: Register,
+        record: Register,
+        index: u8,
+    },
+    RecordSet {
+        record: Register,
+        index: u8,
+        value: Register,
+    },
+    TypeOf {
+        dest: Register,
+        src: Register,
+    },
+    CharToNumber {
+        dest: Register,
+        src: Register,
+    },
+    NumberToChar {
+        dest: Register,
+        src: Register,
+    },
+    TextToChars {
+        dest: Register,
+        text: Register,
+    },
+    CharsToText {
+        dest: Register,
+        list: Register,
+    },
+    LoadFile {
+        dest: Register,
+        path: Register,
+    },
+    RequireFile {
+        dest: Register,
+        path: Register,
+    },
+    Assert {
+        form: Register,
+        test: Register,
+    },
+    AssertEqual {
+        form: Register,
+        first: Register,
+        second: Register,
+    },
+    SortList {
+        dest: Register,
+        list: Register,
+    },
+    SortListWith {
+        dest: Register,
+        list: Register,
+        less: Register,
+    },
+    DeepCopy {
+        dest: Register,
+        src: Register,
+    },
+    LoadGcStats {
+        dest: Register,
+    },
+    Random {
+        dest: Register,
+        low: Register,
+        high: Register,
+    },
+    SeedRandom {
+        dest: Register,
+        seed: Register,
+    },
 }
 
+// Opcodes must stay 32 bits, see above
+const _: () = assert!(size_of::<Opcode>() == 4);
+
 /// Bytecode is stored as fixed-width 32-bit values.
 /// This is not the most efficient format but it is easy to work with.
 // ANCHOR: DefArrayOpcode
@@ -211,6 +481,144 @@ impl ByteCode {
         Ok(())
     }
 
+    /// Turn every tail call from the given instruction onwards into an ordinary call, for when
+    /// the code that follows those calls turns out to have more to do after they return
+    pub fn demote_tail_calls<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        from: ArraySize,
+    ) -> Result<(), RuntimeError> {
+        self.code.access_slice(mem, |code| {
+            for opcode in code.iter_mut().skip(from as usize) {
+                if let Opcode::TailCall {
+                    function,
+                    dest,
+                    arg_count,
+                } = *opcode
+                {
+                    *opcode = Opcode::Call {
+                        function,
+                        dest,
+                        arg_count,
+                    };
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Check that every jump instruction has had its offset set. A jump offset left as
+    /// `JUMP_UNKNOWN` is a compiler bug that would send the instruction pointer into the weeds.
+    pub fn check_jumps<'guard>(&self, guard: &'guard dyn MutatorScope) -> Result<(), RuntimeError> {
+        self.code.access_slice(guard, |code| {
+            for (index, opcode) in code.iter().enumerate() {
+                match opcode {
+                    Opcode::Jump { offset }
+                    | Opcode::JumpIfTrue { offset, .. }
+                    | Opcode::JumpIfNotTrue { offset, .. }
+                        if *offset == JUMP_UNKNOWN =>
+                    {
+                        return Err(err_eval(&format!(
+                            "Compiler bug: unpatched jump at instruction {}",
+                            index
+                        )));
+                    }
+                    _ => (),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Check that every operand of every instruction refers to something that exists, so that
+    /// the VM can't index beyond the register window, the literals list or the closure's
+    /// upvalues, and that every jump lands on an instruction. `upvalue_count` is the number of
+    /// nonlocal variables of the Function that the bytecode belongs to.
+    ///
+    /// The compiler should never produce bytecode that fails this check; it is for catching
+    /// compiler bugs and hand-assembled bytecode before it is run.
+    pub fn verify<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        upvalue_count: usize,
+    ) -> Result<(), RuntimeError> {
+        self.check_jumps(guard)?;
+
+        let literal_count = self.literals.length() as usize;
+
+        self.code.access_slice(guard, |code| {
+            let instruction_count = code.len() as i64;
+
+            for (index, opcode) in code.iter().enumerate() {
+                let invalid = |reason: String| {
+                    Err(err_eval(&format!(
+                        "Invalid bytecode at instruction {} ({:?}): {}",
+                        index, opcode, reason
+                    )))
+                };
+
+                match *opcode {
+                    Opcode::LoadLiteral { literal_id, .. } => {
+                        if literal_id as usize >= literal_count {
+                            return invalid(format!(
+                                "literal {} is out of bounds, there are {} literals",
+                                literal_id, literal_count
+                            ));
+                        }
+                    }
+
+                    Opcode::GetUpvalue { src: upvalue, .. }
+                    | Opcode::SetUpvalue { dest: upvalue, .. } => {
+                        if upvalue as usize >= upvalue_count {
+                            return invalid(format!(
+                                "upvalue {} is out of bounds, there are {} upvalues",
+                                upvalue, upvalue_count
+                            ));
+                        }
+                    }
+
+                    Opcode::Call {
+                        dest, arg_count, ..
+                    }
+                    | Opcode::TailCall {
+                        dest, arg_count, ..
+                    } => {
+                        let args_end = dest as usize + FIRST_ARG_REG + arg_count as usize;
+                        if args_end > REGISTER_COUNT {
+                            return invalid(format!(
+                                "{} arguments from register {} overflow the register window",
+                                arg_count, dest
+                            ));
+                        }
+                    }
+
+                    Opcode::Concat { first, count, .. } => {
+                        if first as usize + count as usize > REGISTER_COUNT {
+                            return invalid(format!(
+                                "{} values from register {} overflow the register window",
+                                count, first
+                            ));
+                        }
+                    }
+
+                    Opcode::Jump { offset }
+                    | Opcode::JumpIfTrue { offset, .. }
+                    | Opcode::JumpIfNotTrue { offset, .. } => {
+                        // offsets are relative to the instruction after the jump
+                        let target = index as i64 + 1 + offset as i64;
+                        if target < 0 || target >= instruction_count {
+                            return invalid(format!("jump target {} is out of bounds", target));
+                        }
+                    }
+
+                    _ => (),
+                }
+            }
+
+            Ok(())
+        })
+    }
+
     /// Append a literal-load operation to the back of the sequence
     pub fn push_loadlit<'guard>(
         &self,
@@ -234,6 +642,25 @@ impl ByteCode {
         Ok(lit_id)
     }
 
+    /// Return the number of instructions in the bytecode
+    pub fn instruction_count(&self) -> ArraySize {
+        self.code.length()
+    }
+
+    /// Return the number of literals used by the bytecode
+    pub fn literal_count(&self) -> ArraySize {
+        self.literals.length()
+    }
+
+    /// Return the literal at the given index into the literals list
+    pub fn literal<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        Ok(IndexedContainer::get(&self.literals, guard, index)?.get(guard))
+    }
+
     /// Get the index into the bytecode array of the last instruction
     pub fn last_instruction(&self) -> ArraySize {
         self.code.length() - 1
@@ -261,6 +688,14 @@ impl Print for ByteCode {
     }
 }
 
+impl Trace for ByteCode {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.array(guard, &self.code);
+        self.literals.trace(guard, roots);
+    }
+}
+
 /// An InstructionStream is a pointer to a ByteCode instance and an instruction pointer giving the
 /// current index into the ByteCode
 // ANCHOR: DefInstructionStream
@@ -326,17 +761,48 @@ impl InstructionStream {
         self.ip.get()
     }
 
-    /// Adjust the instruction pointer by the given signed offset from the current ip
-    pub fn jump(&self, offset: JumpOffset) {
-        let mut ip = self.ip.get() as i32;
-        ip += offset as i32;
+    /// Adjust the instruction pointer by the given signed offset from the current ip. The new ip
+    /// must point at an instruction within the current ByteCode.
+    pub fn jump<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        offset: JumpOffset,
+    ) -> Result<(), RuntimeError> {
+        let ip = self.ip.get() as i64 + offset as i64;
+        let length = self.instructions.get(guard).code.length() as i64;
+
+        if ip < 0 || ip >= length {
+            return Err(err_eval(&format!(
+                "Jump offset {} from instruction {} is out of bounds",
+                offset,
+                self.ip.get()
+            )));
+        }
+
         self.ip.set(ip as ArraySize);
+        Ok(())
+    }
+}
+
+impl Trace for InstructionStream {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        self.instructions.get(guard).trace(guard, roots);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Opcode;
+    use super::{
+        ByteCode, InstructionStream, JumpOffset, LiteralId, LiteralInteger, NumArgs, Opcode,
+        Register, UpvalueId, JUMP_UNKNOWN,
+    };
+    use crate::array::ArrayU16;
+    use crate::containers::StackContainer;
+    use crate::error::{ErrorKind, RuntimeError};
+    use crate::function::Function;
+    use crate::list::List;
+    use crate::memory::{Memory, Mutator, MutatorView};
     use std::mem::size_of;
 
     // ANCHOR: DefTestOpcodeIs32Bits
@@ -345,6 +811,248 @@ mod test {
         // An Opcode should be 32 bits; anything bigger and we've mis-defined some
         // variant
         assert!(size_of::<Opcode>() == 4);
+
+        // The discriminant plus the fields of the largest variants must fit into those 32
+        // bits. These catch an operand type growing even before a variant uses it.
+        let discriminant = size_of::<u8>();
+        assert!(discriminant + 3 * size_of::<Register>() <= 4);
+        assert!(discriminant + 3 * size_of::<UpvalueId>() <= 4);
+        assert!(discriminant + 3 * size_of::<NumArgs>() <= 4);
+        assert!(discriminant + size_of::<Register>() + size_of::<LiteralId>() <= 4);
+        assert!(discriminant + size_of::<Register>() + size_of::<LiteralInteger>() <= 4);
+        assert!(discriminant + size_of::<Register>() + size_of::<JumpOffset>() <= 4);
     }
     // ANCHOR_END: DefTestOpcodeIs32Bits
+
+    #[test]
+    fn test_unpatched_jump_is_detected() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let code = ByteCode::alloc(view)?;
+                code.push(view, Opcode::LoadNil { dest: 0 })?;
+                code.push(
+                    view,
+                    Opcode::JumpIfNotTrue {
+                        test: 0,
+                        offset: JUMP_UNKNOWN,
+                    },
+                )?;
+                code.push(view, Opcode::Return { reg: 0 })?;
+
+                // the jump was left unpatched
+                assert!(code.check_jumps(view).is_err());
+
+                // patching the jump satisfies the check
+                code.update_jump_offset(view, 1, 0)?;
+                assert!(code.check_jumps(view).is_ok());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn test_jump_offsets() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let code = ByteCode::alloc(view)?;
+                for dest in 0..4 {
+                    code.push(view, Opcode::LoadNil { dest })?;
+                }
+
+                let instr = InstructionStream::alloc(view, code)?;
+
+                // step past the first three instructions
+                for _ in 0..3 {
+                    instr.get_next_opcode(view)?;
+                }
+                assert!(instr.get_next_ip() == 3);
+
+                // backward jump to the first instruction
+                instr.jump(view, -3)?;
+                assert!(instr.get_next_ip() == 0);
+                assert!(instr.get_next_opcode(view)? == Opcode::LoadNil { dest: 0 });
+
+                // forward jump to exactly the last instruction
+                instr.jump(view, 2)?;
+                assert!(instr.get_next_ip() == 3);
+                assert!(instr.get_next_opcode(view)? == Opcode::LoadNil { dest: 3 });
+
+                // past the end, or before the beginning, are rejected and the ip is unchanged
+                assert!(instr.jump(view, 0).is_err());
+                assert!(instr.jump(view, -5).is_err());
+                assert!(instr.jump(view, JUMP_UNKNOWN).is_err());
+                assert!(instr.get_next_ip() == 4);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                // bytecode consisting of the given instruction followed by a return
+                let bytecode = |opcode| -> Result<_, RuntimeError> {
+                    let code = ByteCode::alloc(view)?;
+                    code.push_lit(view, view.nil())?;
+                    code.push(view, opcode)?;
+                    code.push(view, Opcode::Return { reg: 0 })?;
+                    Ok(code)
+                };
+
+                let verify = |opcode, upvalue_count| -> Result<(), RuntimeError> {
+                    bytecode(opcode)?.verify(view, upvalue_count)
+                };
+
+                let rejected =
+                    |opcode, upvalue_count, reason: &str| match verify(opcode, upvalue_count) {
+                        Err(e) => match e.error_kind() {
+                            ErrorKind::EvalError(message) => {
+                                assert!(message.starts_with("Invalid bytecode at instruction 0"));
+                                assert!(message.ends_with(reason), "{}", message);
+                            }
+                            _ => panic!("unexpected error {}", e),
+                        },
+                        Ok(_) => panic!("{:?} should not verify", opcode),
+                    };
+
+                // literal ids
+                verify(
+                    Opcode::LoadLiteral {
+                        dest: 0,
+                        literal_id: 0,
+                    },
+                    0,
+                )?;
+                rejected(
+                    Opcode::LoadLiteral {
+                        dest: 0,
+                        literal_id: 1,
+                    },
+                    0,
+                    "literal 1 is out of bounds, there are 1 literals",
+                );
+
+                // upvalue ids
+                verify(Opcode::GetUpvalue { dest: 0, src: 1 }, 2)?;
+                rejected(
+                    Opcode::GetUpvalue { dest: 0, src: 2 },
+                    2,
+                    "upvalue 2 is out of bounds, there are 2 upvalues",
+                );
+                rejected(
+                    Opcode::SetUpvalue { dest: 0, src: 0 },
+                    0,
+                    "upvalue 0 is out of bounds, there are 0 upvalues",
+                );
+
+                // register ranges that must fit in the window
+                verify(
+                    Opcode::Call {
+                        function: 0,
+                        dest: 250,
+                        arg_count: 4,
+                    },
+                    0,
+                )?;
+                rejected(
+                    Opcode::TailCall {
+                        function: 0,
+                        dest: 250,
+                        arg_count: 5,
+                    },
+                    0,
+                    "5 arguments from register 250 overflow the register window",
+                );
+                rejected(
+                    Opcode::Concat {
+                        dest: 0,
+                        first: 200,
+                        count: 100,
+                    },
+                    0,
+                    "100 values from register 200 overflow the register window",
+                );
+
+                // jumps must land on an instruction
+                verify(Opcode::Jump { offset: 0 }, 0)?;
+                verify(Opcode::Jump { offset: -1 }, 0)?;
+                rejected(
+                    Opcode::Jump { offset: 1 },
+                    0,
+                    "jump target 2 is out of bounds",
+                );
+                rejected(
+                    Opcode::JumpIfTrue {
+                        test: 0,
+                        offset: -2,
+                    },
+                    0,
+                    "jump target -1 is out of bounds",
+                );
+                assert!(verify(
+                    Opcode::Jump {
+                        offset: JUMP_UNKNOWN
+                    },
+                    0
+                )
+                .is_err());
+
+                // a Function's upvalue count comes from its nonlocal references
+                let params = List::alloc(view)?;
+                let code = bytecode(Opcode::GetUpvalue { dest: 0, src: 0 })?;
+                let function = Function::alloc(view, view.nil(), params, view.nil(), code, None)?;
+                assert!(function.verify(view).is_err());
+
+                let nonlocals = ArrayU16::alloc(view)?;
+                nonlocals.push(view, 0)?;
+                let function =
+                    Function::alloc(view, view.nil(), params, view.nil(), code, Some(nonlocals))?;
+                function.verify(view)?;
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
 }
@@ -0,0 +1,104 @@
+/// A heap-allocated character type
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::error::{err_eval, RuntimeError};
+use crate::memory::{Roots, Trace};
+use crate::printer::Print;
+use crate::safeptr::MutatorScope;
+
+/// Characters that are written by name in a `#\name` literal
+const NAMED_CHARS: [(&str, char); 3] = [("space", ' '), ("newline", '\n'), ("tab", '\t')];
+
+/// Return the character for the name following `#\` in a character literal: either a single
+/// character or one of the named characters. Returns None if the name is not recognized.
+pub fn char_from_name(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => NAMED_CHARS
+            .iter()
+            .find(|(char_name, _)| *char_name == name)
+            .map(|(_, c)| *c),
+    }
+}
+
+/// Convert an integer to a character, which must be a valid Unicode scalar value
+pub fn char_from_codepoint(value: isize) -> Result<char, RuntimeError> {
+    u32::try_from(value)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| err_eval(&format!("Invalid character code point {}", value)))
+}
+
+/// A single Unicode character
+pub struct Char {
+    value: char,
+}
+
+impl Char {
+    pub fn new(value: char) -> Char {
+        Char { value }
+    }
+
+    /// Return the native character
+    pub fn value(&self) -> char {
+        self.value
+    }
+}
+
+impl Print for Char {
+    fn print<'guard>(
+        &self,
+        _guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match NAMED_CHARS.iter().find(|(_, c)| *c == self.value) {
+            Some((name, _)) => write!(f, "#\\{}", name),
+            None => write!(f, "#\\{}", self.value),
+        }
+    }
+}
+
+impl Trace for Char {
+    fn trace(&self, _guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{char_from_codepoint, char_from_name};
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn char_names() {
+        assert!(char_from_name("a") == Some('a'));
+        assert!(char_from_name("(") == Some('('));
+        assert!(char_from_name("λ") == Some('λ'));
+        assert!(char_from_name("space") == Some(' '));
+        assert!(char_from_name("newline") == Some('\n'));
+        assert!(char_from_name("tab") == Some('\t'));
+        assert!(char_from_name("").is_none());
+        assert!(char_from_name("ab").is_none());
+    }
+
+    #[test]
+    fn char_codepoints() {
+        assert!(char_from_codepoint(97).unwrap() == 'a');
+        assert!(char_from_codepoint(0x3bb).unwrap() == 'λ');
+
+        for invalid in [-1, 0xd800, 0x110000] {
+            match char_from_codepoint(invalid) {
+                Err(e) => assert!(
+                    *e.error_kind()
+                        == ErrorKind::EvalError(format!(
+                            "Invalid character code point {}",
+                            invalid
+                        ))
+                ),
+                Ok(_) => panic!("{} is not a valid code point", invalid),
+            }
+        }
+    }
+}
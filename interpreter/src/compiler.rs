@@ -1,16 +1,21 @@
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::array::{ArraySize, ArrayU16};
-use crate::bytecode::{ByteCode, JumpOffset, Opcode, Register, UpvalueId, JUMP_UNKNOWN};
+use crate::bytecode::{
+    ByteCode, JumpOffset, LiteralInteger, Opcode, Register, UpvalueId, JUMP_UNKNOWN,
+};
 use crate::containers::{AnyContainerFromSlice, StackContainer};
-use crate::error::{err_eval, RuntimeError};
+use crate::dict::Dict;
+use crate::error::{err_eval, ErrorKind, RuntimeError, SourcePos};
 use crate::function::Function;
 use crate::list::List;
 use crate::memory::MutatorView;
-use crate::pair::{value_from_1_pair, values_from_2_pairs, vec_from_pairs};
-use crate::safeptr::{CellPtr, ScopedPtr, TaggedScopedPtr};
-use crate::taggedptr::Value;
+use crate::pair::{
+    pairs_from_slice, value_from_1_pair, values_from_2_pairs, values_from_3_pairs, vec_from_pairs,
+};
+use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedScopedPtr};
+use crate::taggedptr::{TaggedPtr, Value};
 use crate::vm::FIRST_ARG_REG;
 
 // ANCHOR: DefBinding
@@ -140,6 +145,10 @@ struct Variables<'parent> {
     nonlocals: RefCell<HashMap<String, Nonlocal>>,
     /// The next upvalue index to assign when a new nonlocal is encountered.
     next_upvalue: Cell<u8>,
+    /// In strict mode, the names of all globals that may be referenced: those bound when
+    /// compilation started and those defined by the code compiled so far. Only the outermost
+    /// function's Variables has this.
+    globals: Option<RefCell<HashSet<String>>>,
 }
 // ANCHOR_END: DefVariables
 
@@ -150,6 +159,42 @@ impl<'parent> Variables<'parent> {
             scopes: Vec::new(),
             nonlocals: RefCell::new(HashMap::new()),
             next_upvalue: Cell::new(0),
+            globals: None,
+        }
+    }
+
+    /// Instantiate outermost Variables that only allow references to the given global names
+    fn new_strict(globals: HashSet<String>) -> Variables<'parent> {
+        Variables {
+            globals: Some(RefCell::new(globals)),
+            ..Variables::new(None)
+        }
+    }
+
+    /// Return the outermost function's Variables
+    fn outermost(&self) -> &Variables<'parent> {
+        let mut vars = self;
+        while let Some(parent) = vars.parent {
+            vars = parent;
+        }
+        vars
+    }
+
+    /// In strict mode, return an error if the name is not a known global
+    fn check_global(&self, name: &str) -> Result<(), RuntimeError> {
+        match self.outermost().globals {
+            Some(ref globals) if !globals.borrow().contains(name) => Err(err_eval(&format!(
+                "Symbol {} is not bound to a value",
+                name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// In strict mode, record that the code being compiled defines the named global
+    fn define_global(&self, name: &str) {
+        if let Some(ref globals) = self.outermost().globals {
+            globals.borrow_mut().insert(String::from(name));
         }
     }
 
@@ -159,14 +204,7 @@ impl<'parent> Variables<'parent> {
         name: TaggedScopedPtr<'guard>,
     ) -> Result<Option<Binding>, RuntimeError> {
         //  return value should be (count-of-parent-functions-followed, Variable)
-        let name_string = match *name {
-            Value::Symbol(s) => String::from(s.as_str(&name)),
-            _ => {
-                return Err(err_eval(
-                    "Cannot lookup a variable bound to a non-symbol type",
-                ))
-            }
-        };
+        let name_string = String::from(name.as_symbol_str()?);
 
         // The frame_offset is the number of parent nesting functions searched for a variable
         let mut frame_offset: u8 = 0;
@@ -338,63 +376,86 @@ impl<'parent> Compiler<'parent> {
             return Err(err_eval("A function must have at least one expression"));
         }
 
-        // compile expressions
+        // compile expressions, the last of which is in tail position
         let mut result_reg = 0;
-        for expr in exprs.iter() {
-            result_reg = self.compile_eval(mem, *expr)?;
+        let mut tail_start = 0;
+        for (index, expr) in exprs.iter().enumerate() {
+            let tail = index == exprs.len() - 1;
+            if tail {
+                tail_start = self.bytecode.get(mem).next_instruction();
+            }
+            result_reg = self.compile_eval_tail(mem, *expr, tail, None)?;
         }
 
         // pop parameter scope
-        let closing_instructions = self.vars.pop_scope();
-        for opcode in &closing_instructions {
-            self.push(mem, *opcode)?;
-        }
+        self.pop_scope_after(mem, tail_start)?;
 
         // finish with a return
         let fn_bytecode = self.bytecode.get(mem);
         fn_bytecode.push(mem, Opcode::Return { reg: result_reg })?;
 
+        // all jump instructions should have been given a real offset by now
+        fn_bytecode.check_jumps(mem)?;
+
         let fn_nonlocals = self.vars.get_nonlocals(mem)?;
 
-        Ok(Function::alloc(
+        let function = Function::alloc(
             mem,
             fn_name,
             fn_params,
+            mem.nil(),
             fn_bytecode,
             fn_nonlocals,
-        )?)
+        )?;
+
+        // catch compiler bugs here rather than as a panic or misbehavior when the code is run
+        if cfg!(debug_assertions) {
+            function.verify(mem)?;
+        }
+
+        Ok(function)
     }
     // ANCHOR_END: DefCompilerCompileFunction
 
-    /// Compile an expression - this can be an 'atomic' value or a nested function application
+    /// Compile an expression - this can be an 'atomic' value or a nested function application.
+    /// If `dest` is given, the result is written to that register, otherwise a register is
+    /// acquired for it, or, for a local variable, the variable's own register is returned.
     // ANCHOR: DefCompileEval
     fn compile_eval<'guard>(
         &mut self,
         mem: &'guard MutatorView,
         ast_node: TaggedScopedPtr<'guard>,
+        dest: Option<Register>,
     ) -> Result<Register, RuntimeError> {
         match *ast_node {
             // ANCHOR: DefCompileEvalPair
-            Value::Pair(p) => self.compile_apply(mem, p.first.get(mem), p.second.get(mem)),
+            Value::Pair(p) => self.compile_apply(
+                mem,
+                p.first.get(mem),
+                p.second.get(mem),
+                p.first_pos.get(),
+                false,
+                dest,
+            ),
             // ANCHOR_END: DefCompileEvalPair
             Value::Symbol(s) => {
                 match s.as_str(mem) {
                     "nil" => {
-                        let dest = self.acquire_reg();
+                        let dest = self.acquire_dest_reg(dest)?;
                         self.push(mem, Opcode::LoadNil { dest })?;
                         Ok(dest)
                     }
 
-                    "true" => self.push_load_literal(mem, mem.lookup_sym("true")),
+                    "true" => self.push_load_literal(mem, mem.true_sym(), dest),
 
                     // Search scopes for a binding; if none do a global lookup
                     _ => {
                         match self.vars.lookup_binding(ast_node)? {
-                            Some(Binding::Local(register)) => Ok(register),
+                            Some(Binding::Local(register)) => self.copy_result(mem, register, dest),
 
                             Some(Binding::Upvalue(upvalue_id)) => {
                                 // Retrieve the value via Upvalue indirection
-                                let dest = self.acquire_reg();
+                                let dest = self.acquire_dest_reg(dest)?;
                                 self.push(
                                     mem,
                                     Opcode::GetUpvalue {
@@ -407,7 +468,8 @@ impl<'parent> Compiler<'parent> {
 
                             None => {
                                 // Otherwise do a late-binding global lookup
-                                let name = self.push_load_literal(mem, ast_node)?;
+                                self.vars.check_global(s.as_str(mem))?;
+                                let name = self.push_load_literal(mem, ast_node, dest)?;
                                 let dest = name; // reuse the register
                                 self.push(mem, Opcode::LoadGlobal { dest, name })?;
                                 Ok(dest)
@@ -417,52 +479,276 @@ impl<'parent> Compiler<'parent> {
                 }
             }
 
-            _ => self.push_load_literal(mem, ast_node),
+            // small integers can be baked into the instruction rather than the literals list
+            Value::Number(n)
+                if n >= LiteralInteger::MIN as isize && n <= LiteralInteger::MAX as isize =>
+            {
+                let dest = self.acquire_dest_reg(dest)?;
+                let integer = n as LiteralInteger;
+                self.push(mem, Opcode::LoadInteger { dest, integer })?;
+                Ok(dest)
+            }
+
+            _ => self.push_load_literal(mem, ast_node, dest),
         }
     }
     // ANCHOR_END: DefCompileEval
 
-    /// Compile a function or special-form application
+    /// Compile an expression, given whether it is in tail position, that is, whether its result
+    /// is the return value of the function being compiled
+    fn compile_eval_tail<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        ast_node: TaggedScopedPtr<'guard>,
+        tail: bool,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        match *ast_node {
+            Value::Pair(p) if tail => self.compile_apply(
+                mem,
+                p.first.get(mem),
+                p.second.get(mem),
+                p.first_pos.get(),
+                true,
+                dest,
+            ),
+            _ => self.compile_eval(mem, ast_node, dest),
+        }
+    }
+
+    /// Compile a function or special-form application. `pos` is the position of the form in the
+    /// source code, if known, for reporting a special form given the wrong number of arguments.
     // ANCHOR: DefCompileApply
     fn compile_apply<'guard>(
         &mut self,
         mem: &'guard MutatorView,
         function: TaggedScopedPtr<'guard>,
         args: TaggedScopedPtr<'guard>,
+        pos: Option<SourcePos>,
+        tail: bool,
+        dest: Option<Register>,
     ) -> Result<Register, RuntimeError> {
-        match *function {
-            Value::Symbol(s) => match s.as_str(mem) {
-                "quote" => self.push_load_literal(mem, value_from_1_pair(mem, args)?),
-                "atom?" => self.push_op2(mem, args, |dest, test| Opcode::IsAtom { dest, test }),
-                // ANCHOR: DefCompileApplyIsNil
-                "nil?" => self.push_op2(mem, args, |dest, test| Opcode::IsNil { dest, test }),
-                // ANCHOR_END: DefCompileApplyIsNil
-                "car" => self.push_op2(mem, args, |dest, reg| Opcode::FirstOfPair { dest, reg }),
-                "cdr" => self.push_op2(mem, args, |dest, reg| Opcode::SecondOfPair { dest, reg }),
-                "cons" => self.push_op3(mem, args, |dest, reg1, reg2| Opcode::MakePair {
-                    dest,
-                    reg1,
-                    reg2,
-                }),
-                "cond" => self.compile_apply_cond(mem, args),
-                "is?" => self.push_op3(mem, args, |dest, test1, test2| Opcode::IsIdentical {
-                    dest,
-                    test1,
-                    test2,
-                }),
-                "set" => self.compile_apply_assign(mem, args),
-                "def" => self.compile_named_function(mem, args),
-                // ANCHOR: DefCompileApplyLambda
-                "lambda" => self.compile_anonymous_function(mem, args),
-                // ANCHOR_END: DefCompileApplyLambda
-                "\\" => self.compile_anonymous_function(mem, args),
-                "let" => self.compile_apply_let(mem, args),
-                _ => self.compile_apply_call(mem, function, args),
-            },
+        let result =
+            match *function {
+                Value::Symbol(s) => {
+                    check_special_form_arity(mem, s.as_str(mem), args, pos)?;
+
+                    match s.as_str(mem) {
+                        "quote" => self.push_load_literal(mem, value_from_1_pair(mem, args)?, dest),
+                        "atom?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsAtom { dest, test }),
+                        // ANCHOR: DefCompileApplyIsNil
+                        "nil?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsNil { dest, test }),
+                        // ANCHOR_END: DefCompileApplyIsNil
+                        "empty?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsEmpty { dest, test }),
+                        "car" => self.push_op2(mem, args, dest, |dest, reg| Opcode::FirstOfPair {
+                            dest,
+                            reg,
+                        }),
+                        "cdr" => self.push_op2(mem, args, dest, |dest, reg| Opcode::SecondOfPair {
+                            dest,
+                            reg,
+                        }),
+                        // unlike car and cdr, these raise an error when given nil
+                        "strict-car" => self.push_op2(mem, args, dest, |dest, reg| {
+                            Opcode::StrictFirstOfPair { dest, reg }
+                        }),
+                        "strict-cdr" => self.push_op2(mem, args, dest, |dest, reg| {
+                            Opcode::StrictSecondOfPair { dest, reg }
+                        }),
+                        "cons" => self.push_op3(mem, args, dest, |dest, reg1, reg2| {
+                            Opcode::MakePair { dest, reg1, reg2 }
+                        }),
+                        "cond" => self.compile_apply_cond(mem, args, tail, dest),
+                        "case" => self.compile_apply_case(mem, args, tail, dest),
+                        "when" => self.compile_apply_when(mem, args, tail, true, dest),
+                        "unless" => self.compile_apply_when(mem, args, tail, false, dest),
+                        "equal?" => self.push_op3(mem, args, dest, |dest, test1, test2| {
+                            Opcode::Equal { dest, test1, test2 }
+                        }),
+                        "is?" => self.push_op3(mem, args, dest, |dest, test1, test2| {
+                            Opcode::IsIdentical { dest, test1, test2 }
+                        }),
+                        "set" => self.compile_apply_assign(mem, args),
+                        "def" => self.compile_named_function(mem, args),
+                        "defstruct" => self.compile_apply_defstruct(mem, args),
+                        "record-new" => self.compile_apply_record_new(mem, args),
+                        "record?" => self.push_op3(mem, args, dest, |dest, test, type_name| {
+                            Opcode::IsRecordOf {
+                                dest,
+                                test,
+                                type_name,
+                            }
+                        }),
+                        "record-get" => self.compile_apply_record_get(mem, args),
+                        "type-of" => {
+                            self.push_op2(mem, args, dest, |dest, src| Opcode::TypeOf { dest, src })
+                        }
+                        "record-set!" => self.compile_apply_record_set(mem, args),
+                        // ANCHOR: DefCompileApplyLambda
+                        "lambda" => self.compile_anonymous_function(mem, args),
+                        // ANCHOR_END: DefCompileApplyLambda
+                        "\\" => self.compile_anonymous_function(mem, args),
+                        "let" => self.compile_apply_let(mem, args, tail),
+                        "let*" => self.compile_apply_let_star(mem, args, tail),
+                        "let-values" => self.compile_apply_let_values(mem, args, tail),
+                        "values" => self.compile_apply_values(mem, args),
+                        "dotimes" => self.compile_apply_dotimes(mem, args),
+                        "concat" => self.compile_apply_concat(mem, args),
+                        "cons*" => self.compile_apply_cons_star(mem, args),
+                        "list" => self.compile_apply_list(mem, args),
+                        "print" => {
+                            self.push_op2(mem, args, dest, |dest, src| Opcode::Print { dest, src })
+                        }
+                        "println" => self
+                            .push_op2(mem, args, dest, |dest, src| Opcode::PrintLine { dest, src }),
+                        "insert" => self.compile_apply_insert(mem, args),
+                        "env-keys" => self.compile_apply_env_keys(mem, args),
+                        "gc-stats" => self.compile_apply_gc_stats(mem, args),
+                        "random" => self.compile_apply_random(mem, args),
+                        "seed!" => self.push_op2(mem, args, dest, |dest, seed| {
+                            Opcode::SeedRandom { dest, seed }
+                        }),
+                        "bound?" => self
+                            .push_op2(mem, args, dest, |dest, name| Opcode::IsBound { dest, name }),
+                        "fn-params" => self.push_op2(mem, args, dest, |dest, function| {
+                            Opcode::FunctionParams { dest, function }
+                        }),
+                        "fn-name" => self.push_op2(mem, args, dest, |dest, function| {
+                            Opcode::FunctionName { dest, function }
+                        }),
+                        "number->string" => {
+                            self.compile_apply_radix_conversion(mem, args, |dest, number, radix| {
+                                Opcode::NumberToText {
+                                    dest,
+                                    number,
+                                    radix,
+                                }
+                            })
+                        }
+                        "string->number" => {
+                            self.compile_apply_radix_conversion(mem, args, |dest, text, radix| {
+                                Opcode::TextToNumber { dest, text, radix }
+                            })
+                        }
+                        "arity" => self.push_op2(mem, args, dest, |dest, function| {
+                            Opcode::FunctionArity { dest, function }
+                        }),
+                        "fn-size" => self.push_op2(mem, args, dest, |dest, function| {
+                            Opcode::FunctionSize { dest, function }
+                        }),
+                        "remove" => self.push_op3(mem, args, dest, |dest, list, index| {
+                            Opcode::ListRemove { dest, list, index }
+                        }),
+                        "zero?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsZero { dest, test }),
+                        "positive?" => self.push_op2(mem, args, dest, |dest, test| {
+                            Opcode::IsPositive { dest, test }
+                        }),
+                        "negative?" => self.push_op2(mem, args, dest, |dest, test| {
+                            Opcode::IsNegative { dest, test }
+                        }),
+                        "even?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsEven { dest, test }),
+                        "odd?" => self
+                            .push_op2(mem, args, dest, |dest, test| Opcode::IsOdd { dest, test }),
+                        "neg" => {
+                            self.push_op2(mem, args, dest, |dest, src| Opcode::Negate { dest, src })
+                        }
+                        "abs" => {
+                            self.push_op2(mem, args, dest, |dest, src| Opcode::Abs { dest, src })
+                        }
+                        "+" => {
+                            self.compile_apply_fold(mem, args, "+", Some(0), |dest, reg1, reg2| {
+                                Opcode::Add { dest, reg1, reg2 }
+                            })
+                        }
+                        "*" => {
+                            self.compile_apply_fold(mem, args, "*", Some(1), |dest, reg1, reg2| {
+                                Opcode::Multiply { dest, reg1, reg2 }
+                            })
+                        }
+                        // `quotient` rounds toward zero and `floor-div` toward negative infinity. The
+                        // matching `remainder` and `modulo` satisfy
+                        // `(= n (+ (* (quotient n d) d) (remainder n d)))` and likewise for
+                        // `floor-div` and `modulo`, so a remainder has the sign of `n` and a modulo
+                        // the sign of `d`.
+                        "quotient" => self.push_op3(mem, args, dest, |dest, num, denom| {
+                            Opcode::DivideInteger { dest, num, denom }
+                        }),
+                        "floor-div" => self.push_op3(mem, args, dest, |dest, num, denom| {
+                            Opcode::FloorDivideInteger { dest, num, denom }
+                        }),
+                        "remainder" => self.push_op3(mem, args, dest, |dest, num, denom| {
+                            Opcode::Remainder { dest, num, denom }
+                        }),
+                        "modulo" => self.push_op3(mem, args, dest, |dest, num, denom| {
+                            Opcode::Modulo { dest, num, denom }
+                        }),
+                        "min" => {
+                            self.compile_apply_fold(mem, args, "min", None, |dest, reg1, reg2| {
+                                Opcode::Min { dest, reg1, reg2 }
+                            })
+                        }
+                        "max" => {
+                            self.compile_apply_fold(mem, args, "max", None, |dest, reg1, reg2| {
+                                Opcode::Max { dest, reg1, reg2 }
+                            })
+                        }
+                        "char->integer" => self.push_op2(mem, args, dest, |dest, src| {
+                            Opcode::CharToNumber { dest, src }
+                        }),
+                        "integer->char" => self.push_op2(mem, args, dest, |dest, src| {
+                            Opcode::NumberToChar { dest, src }
+                        }),
+                        "string->list" => self.push_op2(mem, args, dest, |dest, text| {
+                            Opcode::TextToChars { dest, text }
+                        }),
+                        "list->string" => self.push_op2(mem, args, dest, |dest, list| {
+                            Opcode::CharsToText { dest, list }
+                        }),
+                        "assert" => self.compile_apply_assert(mem, args, false, dest),
+                        "assert-eq" => self.compile_apply_assert(mem, args, true, dest),
+                        "sort" => self.compile_apply_sort(mem, args, dest),
+                        "deep-copy" => self
+                            .push_op2(mem, args, dest, |dest, src| Opcode::DeepCopy { dest, src }),
+                        "load" => self.compile_apply_load(mem, args, false),
+                        "require" => self.compile_apply_load(mem, args, true),
+                        "sb-new" => self.compile_apply_sb_new(mem, args),
+                        "sb-append" => self.push_op3(mem, args, dest, |dest, builder, value| {
+                            Opcode::AppendText {
+                                dest,
+                                builder,
+                                value,
+                            }
+                        }),
+                        "sb-finish" => self.push_op2(mem, args, dest, |dest, builder| {
+                            Opcode::BuildText { dest, builder }
+                        }),
+                        "lb-new" => self.compile_apply_lb_new(mem, args),
+                        "lb-append" => self.push_op3(mem, args, dest, |dest, builder, value| {
+                            Opcode::ListAppend {
+                                dest,
+                                builder,
+                                value,
+                            }
+                        }),
+                        "lb-finish" => self.push_op2(mem, args, dest, |dest, builder| {
+                            Opcode::BuildList { dest, builder }
+                        }),
+                        _ => self.compile_apply_call(mem, function, args, tail),
+                    }
+                }
 
-            // Here we allow the value in the function position to be evaluated dynamically
-            _ => self.compile_apply_call(mem, function, args),
-        }
+                // Here we allow the value in the function position to be evaluated dynamically
+                _ => self.compile_apply_call(mem, function, args, tail),
+            }?;
+
+        // forms that cannot write their result to a given register leave it where it is, so
+        // copy it over
+        self.copy_result(mem, result, dest)
     }
     // ANCHOR_END: DefCompileApply
 
@@ -470,12 +756,17 @@ impl<'parent> Compiler<'parent> {
     /// (cond
     ///   (<if-expr-is-true?>) (<then-expr>)
     ///   (<or-expr-is-true?) (<then-expr>)
+    ///   (<default-expr>)
     /// )
-    /// result is nil if no expression evaluates to true
+    /// Conditions and expressions are paired; an odd, trailing, lone expression is the default.
+    /// result is the default expression if no condition evaluates to true, or nil if there is no
+    /// default expression
     fn compile_apply_cond<'guard>(
         &mut self,
         mem: &'guard MutatorView,
         args: TaggedScopedPtr<'guard>,
+        tail: bool,
+        dest: Option<Register>,
     ) -> Result<Register, RuntimeError> {
         //
         //   for each arg:
@@ -488,8 +779,12 @@ impl<'parent> Compiler<'parent> {
 
         let mut end_jumps: Vec<ArraySize> = Vec::new();
         let mut last_cond_jump: Option<ArraySize> = None;
+        let mut default_expr: Option<TaggedScopedPtr<'guard>> = None;
 
-        let dest = self.next_reg;
+        // conditions are evaluated from the first free register, which is also the result
+        // register if none was given
+        let start = self.next_reg;
+        let dest = dest.unwrap_or(start);
 
         let mut head = args;
         while let Value::Pair(p) = *head {
@@ -509,41 +804,364 @@ impl<'parent> Compiler<'parent> {
 
                     // We have a condition to evaluate. If the resut is Not True, jump to the
                     // next condition.
-                    self.reset_reg(dest); // reuse this register for condition and dest
-                    let test = self.compile_eval(mem, cond)?;
+                    self.reset_reg(start);
+                    let test = self.compile_eval(mem, cond, None)?;
                     let offset = JUMP_UNKNOWN;
                     self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
                     last_cond_jump = Some(bytecode.last_instruction());
 
                     // Compile the expression and jump to the end of the entire cond
-                    self.reset_reg(dest); // reuse this register for condition and dest
-                    let _expr_result = self.compile_eval(mem, expr)?;
+                    self.reset_reg(start);
+                    self.compile_eval_tail(mem, expr, tail, Some(dest))?;
                     let offset = JUMP_UNKNOWN;
                     bytecode.push(mem, Opcode::Jump { offset })?;
                     end_jumps.push(bytecode.last_instruction());
                 }
 
+                // A lone trailing expression is the default
+                Value::Nil => default_expr = Some(cond),
+
                 _ => return Err(err_eval("Unexpected end of cond list")),
             }
         }
 
-        // Close out with a default nil result if none of the conditions passed
+        // Close out with the default result if none of the conditions passed
         if let Some(address) = last_cond_jump {
-            self.reset_reg(dest);
-            self.push(mem, Opcode::LoadNil { dest })?;
             let offset = bytecode.next_instruction() - address - 1;
             bytecode.update_jump_offset(mem, address, offset as JumpOffset)?;
         }
 
+        if let Some(expr) = default_expr {
+            self.reset_reg(start);
+            self.compile_eval_tail(mem, expr, tail, Some(dest))?;
+        } else if last_cond_jump.is_some() {
+            self.reset_reg(start);
+            self.push(mem, Opcode::LoadNil { dest })?;
+        }
+
         // Update all the post-expr jumps to point at the next instruction after the entire cond
         for address in end_jumps.iter() {
             let offset = bytecode.next_instruction() - address - 1;
             bytecode.update_jump_offset(mem, *address, offset as JumpOffset)?;
         }
 
+        self.reset_reg(start.max(dest + 1));
+        Ok(dest)
+    }
+
+    /// Evaluate the body expressions in order if the test is true, for `when`, or not true, for
+    /// `unless`. The result is the value of the last body expression, or nil if the body is not
+    /// evaluated or is empty.
+    /// (when <test-expr>
+    ///   <expr>
+    ///   <expr>)
+    /// (unless <test-expr>
+    ///   <expr>
+    ///   <expr>)
+    fn compile_apply_when<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+        when: bool,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        //
+        //   eval test
+        //   if the body should not be evaluated then jmp -> skip
+        //   eval each expr
+        //   jmp -> end
+        // skip:
+        //   nil
+        // end:
+        //
+        let form = if when { "when" } else { "unless" };
+
+        let mut exprs = mem.scratch_from_pairs(args)?;
+        if exprs.is_empty() {
+            return Err(err_eval(&format!("{} requires a test expression", form)));
+        }
+        let test_expr = exprs.remove(0);
+        if exprs.is_empty() {
+            exprs.push(mem.nil());
+        }
+
+        let bytecode = self.bytecode.get(mem);
+        let start = self.next_reg;
+        let dest = dest.unwrap_or(start);
+
+        let test = self.compile_eval(mem, test_expr, None)?;
+        let offset = JUMP_UNKNOWN;
+        if when {
+            self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+        } else {
+            self.push(mem, Opcode::JumpIfTrue { test, offset })?;
+        }
+        let skip_jump = bytecode.last_instruction();
+
+        // the last body expression may be in tail position
+        for (index, expr) in exprs.iter().enumerate() {
+            self.reset_reg(start);
+            let last = index == exprs.len() - 1;
+            if last {
+                self.compile_eval_tail(mem, *expr, tail, Some(dest))?;
+            } else {
+                self.compile_eval(mem, *expr, None)?;
+            }
+        }
+        let offset = JUMP_UNKNOWN;
+        bytecode.push(mem, Opcode::Jump { offset })?;
+        let end_jump = bytecode.last_instruction();
+
+        let offset = bytecode.next_instruction() - skip_jump - 1;
+        bytecode.update_jump_offset(mem, skip_jump, offset as JumpOffset)?;
+
+        self.reset_reg(start);
+        self.push(mem, Opcode::LoadNil { dest })?;
+
+        let offset = bytecode.next_instruction() - end_jump - 1;
+        bytecode.update_jump_offset(mem, end_jump, offset as JumpOffset)?;
+
+        self.reset_reg(start.max(dest + 1));
+        Ok(dest)
+    }
+
+    /// Structural pattern matching. The value of the first expression is matched against each
+    /// pattern in turn and the expressions following the first matching pattern are evaluated.
+    /// (case <expr>
+    ///   (<pattern> <expr> <expr>)
+    ///   (<pattern> <expr> <expr>)
+    /// )
+    /// A pattern may be:
+    ///  * `_` - matches anything
+    ///  * `nil` - matches nil
+    ///  * a symbol - matches anything, binding the value to the symbol in the clause expressions
+    ///  * a quoted value - matches an equal value, as with `equal?`
+    ///  * a pair of patterns `(<pattern> . <pattern>)`, or a list of patterns - matches a pair or
+    ///    list whose parts match the sub-patterns
+    ///
+    /// result is nil if no pattern matches
+    fn compile_apply_case<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        //
+        //   for each clause:
+        //     match pattern
+        //     if any part does not match then jmp -> next
+        //     else eval exprs
+        //     jmp -> end
+        //
+        let case_expr = mem.scratch_from_pairs(args)?;
+        if case_expr.is_empty() {
+            return Err(err_eval("A case expression must have at least 1 argument"));
+        }
+
+        let bytecode = self.bytecode.get(mem);
+
+        let start = self.next_reg;
+        let dest = self.acquire_dest_reg(dest)?;
+
+        // evaluate the value to match into a register of its own
+        let value = self.acquire_reg();
+        self.compile_eval(mem, case_expr[0], Some(value))?;
+        let clause_reg = value + 1;
+
+        let mut end_jumps: Vec<ArraySize> = Vec::new();
+
+        for clause in &case_expr[1..] {
+            let clause = mem.scratch_from_pairs(*clause)?;
+            if clause.len() < 2 {
+                return Err(err_eval(
+                    "A case clause must have a pattern and at least 1 expression",
+                ));
+            }
+
+            // compile the pattern, collecting the jumps to take if it doesn't match
+            self.reset_reg(clause_reg);
+            let mut fail_jumps: Vec<ArraySize> = Vec::new();
+            let mut clause_scope = Scope::new();
+            self.compile_pattern(mem, clause[0], value, &mut clause_scope, &mut fail_jumps)?;
+
+            // compile the expressions with the pattern bindings in scope
+            self.vars.scopes.push(clause_scope);
+
+            let exprs = &clause[1..];
+            let mut tail_start = 0;
+            for (index, expr) in exprs.iter().enumerate() {
+                if index == exprs.len() - 1 {
+                    tail_start = bytecode.next_instruction();
+                    self.compile_eval_tail(mem, *expr, tail, Some(dest))?;
+                } else {
+                    self.compile_eval(mem, *expr, None)?;
+                }
+            }
+
+            self.pop_scope_after(mem, tail_start)?;
+
+            let offset = JUMP_UNKNOWN;
+            self.push(mem, Opcode::Jump { offset })?;
+            end_jumps.push(bytecode.last_instruction());
+
+            // a failed match continues with the next clause
+            for address in fail_jumps.iter() {
+                let offset = bytecode.next_instruction() - address - 1;
+                bytecode.update_jump_offset(mem, *address, offset as JumpOffset)?;
+            }
+        }
+
+        // default nil result if no pattern matched
+        self.push(mem, Opcode::LoadNil { dest })?;
+
+        for address in end_jumps.iter() {
+            let offset = bytecode.next_instruction() - address - 1;
+            bytecode.update_jump_offset(mem, *address, offset as JumpOffset)?;
+        }
+
+        self.reset_reg(start.max(dest + 1));
         Ok(dest)
     }
 
+    /// Compile a `case` pattern to be matched against the `value` register. Any variable bindings
+    /// are added to `scope` and any jumps to be taken on failure to match are added to
+    /// `fail_jumps`.
+    fn compile_pattern<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        pattern: TaggedScopedPtr<'guard>,
+        value: Register,
+        scope: &mut Scope,
+        fail_jumps: &mut Vec<ArraySize>,
+    ) -> Result<(), RuntimeError> {
+        let bytecode = self.bytecode.get(mem);
+        let offset = JUMP_UNKNOWN;
+
+        match *pattern {
+            Value::Symbol(s) => match s.as_str(mem) {
+                "_" => (),
+
+                "nil" => {
+                    let test = self.acquire_reg();
+                    self.push(
+                        mem,
+                        Opcode::IsNil {
+                            dest: test,
+                            test: value,
+                        },
+                    )?;
+                    self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+                    fail_jumps.push(bytecode.last_instruction());
+                }
+
+                _ => {
+                    let reg = self.acquire_reg();
+                    self.push(
+                        mem,
+                        Opcode::CopyRegister {
+                            dest: reg,
+                            src: value,
+                        },
+                    )?;
+                    scope.push_binding(pattern, reg)?;
+                }
+            },
+
+            Value::Nil => {
+                let test = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::IsNil {
+                        dest: test,
+                        test: value,
+                    },
+                )?;
+                self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+                fail_jumps.push(bytecode.last_instruction());
+            }
+
+            Value::Pair(p) if p.first.get(mem) == mem.lookup_sym("quote") => {
+                let literal =
+                    self.push_load_literal(mem, value_from_1_pair(mem, p.second.get(mem))?, None)?;
+                let test = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::Equal {
+                        dest: test,
+                        test1: value,
+                        test2: literal,
+                    },
+                )?;
+                self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+                fail_jumps.push(bytecode.last_instruction());
+            }
+
+            Value::Pair(p) => {
+                // the value must be a pair: neither nil nor an atom
+                let test = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::IsNil {
+                        dest: test,
+                        test: value,
+                    },
+                )?;
+                self.push(mem, Opcode::JumpIfTrue { test, offset })?;
+                fail_jumps.push(bytecode.last_instruction());
+
+                self.push(
+                    mem,
+                    Opcode::IsAtom {
+                        dest: test,
+                        test: value,
+                    },
+                )?;
+                self.push(mem, Opcode::JumpIfTrue { test, offset })?;
+                fail_jumps.push(bytecode.last_instruction());
+
+                let first = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::FirstOfPair {
+                        dest: first,
+                        reg: value,
+                    },
+                )?;
+                self.compile_pattern(mem, p.first.get(mem), first, scope, fail_jumps)?;
+
+                let second = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::SecondOfPair {
+                        dest: second,
+                        reg: value,
+                    },
+                )?;
+                self.compile_pattern(mem, p.second.get(mem), second, scope, fail_jumps)?;
+            }
+
+            // any other value is matched as a literal
+            _ => {
+                let literal = self.push_load_literal(mem, pattern, None)?;
+                let test = self.acquire_reg();
+                self.push(
+                    mem,
+                    Opcode::Equal {
+                        dest: test,
+                        test1: value,
+                        test2: literal,
+                    },
+                )?;
+                self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+                fail_jumps.push(bytecode.last_instruction());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Assignment expression - evaluate the two expressions, binding the result of the first
     /// to the (hopefully) symbol provided by the second
     /// (set <identifier-expr> <expr>)
@@ -553,8 +1171,17 @@ impl<'parent> Compiler<'parent> {
         params: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
         let (first, second) = values_from_2_pairs(mem, params)?;
-        let src = self.compile_eval(mem, second)?;
-        let name = self.compile_eval(mem, first)?;
+        let src = self.compile_eval(mem, second, None)?;
+        let name = self.compile_eval(mem, first, None)?;
+
+        // a quoted name, as in (set 'name value), is a global that later code may refer to
+        if let Value::Pair(p) = *first {
+            if matches!(*p.first.get(mem), Value::Symbol(s) if s.as_str(mem) == "quote") {
+                if let Value::Symbol(s) = *value_from_1_pair(mem, p.second.get(mem))? {
+                    self.vars.define_global(s.as_str(mem));
+                }
+            }
+        }
         self.push(mem, Opcode::StoreGlobal { src, name })?;
         Ok(src)
     }
@@ -570,7 +1197,7 @@ impl<'parent> Compiler<'parent> {
         params: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
         // ANCHOR_END: DefCompilerCompileAnonymousFunctionSig
-        let items = vec_from_pairs(mem, params)?;
+        let items = mem.scratch_from_pairs(params)?;
 
         if items.len() < 2 {
             return Err(err_eval(
@@ -579,14 +1206,14 @@ impl<'parent> Compiler<'parent> {
         }
 
         // a function consists of (name (params) expr1 .. exprn)
-        let fn_params = vec_from_pairs(mem, items[0])?;
+        let fn_params = mem.scratch_from_pairs(items[0])?;
         let fn_exprs = &items[1..];
 
         // compile the function to a Function object
         let fn_object = compile_function(mem, Some(&self.vars), mem.nil(), &fn_params, fn_exprs)?;
 
         // load the function object as a literal
-        let dest = self.push_load_literal(mem, fn_object)?;
+        let dest = self.push_load_literal(mem, fn_object, None)?;
 
         // if fn_object has nonlocal refs, compile a MakeClosure instruction in addition, replacing
         // the Function register with a Partial with a closure environment
@@ -616,7 +1243,7 @@ impl<'parent> Compiler<'parent> {
         mem: &'guard MutatorView,
         params: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
-        let items = vec_from_pairs(mem, params)?;
+        let items = mem.scratch_from_pairs(params)?;
 
         if items.len() < 3 {
             return Err(err_eval(
@@ -626,16 +1253,19 @@ impl<'parent> Compiler<'parent> {
 
         // a function consists of (name (params) expr1 .. exprn)
         let fn_name = items[0];
-        let fn_params = vec_from_pairs(mem, items[1])?;
+        let fn_params = mem.scratch_from_pairs(items[1])?;
         let fn_exprs = &items[2..];
 
+        // the function may refer to itself
+        self.vars.define_global(fn_name.as_symbol_str()?);
+
         // compile the function to a Function object
         let fn_object = compile_function(mem, Some(&self.vars), fn_name, &fn_params, fn_exprs)?;
 
         // load the function object as a literal and associate it with a global name
         // TODO store in local scope if we're nested in an expression
-        let name = self.push_load_literal(mem, fn_name)?;
-        let src = self.push_load_literal(mem, fn_object)?;
+        let name = self.push_load_literal(mem, fn_name, None)?;
+        let src = self.push_load_literal(mem, fn_object, None)?;
         self.push(mem, Opcode::StoreGlobal { src, name })?;
 
         Ok(src)
@@ -643,247 +1273,3420 @@ impl<'parent> Compiler<'parent> {
         // TODO if fn_object has nonlocal refs, compile a MakeClosure instruction in addition
     }
 
-    /// (name <arg-expr-1> <arg-expr-n>)
-    fn compile_apply_call<'guard>(
+    /// Declare a record type, defining global functions to construct a record, test whether a
+    /// value is one, and get and set each field. The result is the type name.
+    /// (defstruct <name> <field> ...)
+    /// For example `(defstruct point x y)` defines `(make-point x y)`, `(point? value)`,
+    /// `(point-x record)` and `(set-point-x! record value)`, and likewise for `y`.
+    fn compile_apply_defstruct<'guard>(
         &mut self,
         mem: &'guard MutatorView,
-        function_expr: TaggedScopedPtr<'guard>,
         args: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
-        // allocate a register for the return value
-        let dest = self.acquire_reg();
-        // allocate a register for a closure environment pointer
-        let _closure_env = self.acquire_reg();
+        let items = mem.scratch_from_pairs(args)?;
+        if items.is_empty() {
+            return Err(err_eval("A defstruct expression must name the type"));
+        }
 
-        // evaluate arguments first
-        let arg_list = vec_from_pairs(mem, args)?;
-        let arg_count = arg_list.len() as u8;
+        let type_name = items[0];
+        let name = String::from(type_name.as_symbol_str()?);
+        let fields = &items[1..];
+        if fields.len() > 255 {
+            return Err(err_eval("A record type may have at most 255 fields"));
+        }
 
-        for arg in arg_list {
-            let src = self.compile_eval(mem, arg)?;
-            // if a local variable register was returned, we need to copy the register to the arg
-            // list. Bound registers are necessarily lower indexes than where the function call is
-            // situated because expression scope and register acquisition progresses the register
-            // index in use.
-            if src <= dest {
-                let dest = self.acquire_reg();
-                self.push(mem, Opcode::CopyRegister { dest, src })?;
-            }
+        let sym = |name: &str| mem.lookup_sym(name);
+        let quoted_type = pairs_from_slice(mem, &[sym("quote"), type_name])?;
+        let record = sym("record");
+        let value = sym("value");
+
+        // each definition leaves its registers free for the next
+        let start = self.next_reg;
+
+        let mut constructor = vec![sym("record-new"), quoted_type];
+        constructor.extend_from_slice(fields);
+        self.compile_record_function(mem, &format!("make-{}", name), fields, &constructor)?;
+        self.reset_reg(start);
+
+        let predicate = [sym("record?"), value, quoted_type];
+        self.compile_record_function(mem, &format!("{}?", name), &[value], &predicate)?;
+        self.reset_reg(start);
+
+        for (index, field) in fields.iter().enumerate() {
+            let field_name = field.as_symbol_str()?;
+            let index = TaggedScopedPtr::new(mem, TaggedPtr::number(index as isize));
+
+            let getter = [sym("record-get"), record, quoted_type, index];
+            let getter_name = format!("{}-{}", name, field_name);
+            self.compile_record_function(mem, &getter_name, &[record], &getter)?;
+            self.reset_reg(start);
+
+            let setter = [sym("record-set!"), record, quoted_type, index, value];
+            let setter_name = format!("set-{}-{}!", name, field_name);
+            self.compile_record_function(mem, &setter_name, &[record, value], &setter)?;
+            self.reset_reg(start);
         }
 
-        // put the function pointer in the last register of the call so it'll be discarded
-        let function = self.compile_eval(mem, function_expr)?;
-        self.push(
-            mem,
-            Opcode::Call {
-                function,
-                dest,
-                arg_count,
-            },
-        )?;
+        self.push_load_literal(mem, type_name, None)
+    }
 
-        // ignore use of any registers beyond the result once the call is complete
-        self.reset_reg(dest + 1);
-        Ok(dest)
+    /// Define a global function for a record type, with the given parameters and a body that is
+    /// a single application, by compiling `(def <name> (<params>) (<body>))`
+    fn compile_record_function<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        name: &str,
+        params: &[TaggedScopedPtr<'guard>],
+        body: &[TaggedScopedPtr<'guard>],
+    ) -> Result<(), RuntimeError> {
+        let definition = [
+            mem.lookup_sym(name),
+            pairs_from_slice(mem, params)?,
+            pairs_from_slice(mem, body)?,
+        ];
+        self.compile_named_function(mem, pairs_from_slice(mem, &definition)?)?;
+        Ok(())
     }
 
-    /// Basic non-recursive let expressions
-    /// (let
-    ///   ((<name> <expr>)
-    ///    (<name> <expr>))
-    ///   (<expr>)
-    /// )
-    fn compile_apply_let<'guard>(
+    /// Make a record of the type named by the first argument, a Symbol, with the remaining
+    /// arguments as its fields
+    /// (record-new <type-expr> <field-expr> ...)
+    fn compile_apply_record_new<'guard>(
         &mut self,
         mem: &'guard MutatorView,
         args: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
-        let let_expr = vec_from_pairs(mem, args)?;
-        if let_expr.len() < 2 {
-            return Err(err_eval("A let expression must have at least 2 arguments"));
+        let items = mem.scratch_from_pairs(args)?;
+        if items.is_empty() {
+            return Err(err_eval("A record-new expression must give the type name"));
         }
 
-        // the binding expressions should be a pair-list itself, and each expression another
-        // pair list of length 2.  Convert it to a Vec<(name, expr)> structure for convenience.
-        let let_exprs: Vec<(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>)> = {
-            let vec_of_pairs = vec_from_pairs(mem, let_expr[0])?;
-            let mut vec_of_tuples = Vec::new();
-            for pairs in &vec_of_pairs {
-                vec_of_tuples.push(values_from_2_pairs(mem, *pairs)?);
-            }
-            vec_of_tuples
-        };
-
-        // acquire a let expression dest reg
         let dest = self.acquire_reg();
-
-        // get the names of each binding to push a scope, assigning registers post-result for
-        // each binding
-        let names: Vec<TaggedScopedPtr<'guard>> = let_exprs.iter().map(|tup| tup.0).collect();
-
-        let mut let_scope = Scope::new();
-        self.next_reg = let_scope.push_bindings(&names, self.next_reg)?;
-        self.vars.scopes.push(let_scope);
-
-        // compile each binding expression
-        for (name, expr) in let_exprs {
-            let src = self.compile_eval(mem, expr)?;
-            let dest = self.compile_eval(mem, name)?;
-            // TODO - more efficient to be able to write the result directly to the let binding reg
-            self.push(mem, Opcode::CopyRegister { dest, src })?;
+        let first = self.next_reg;
+        if first as usize + items.len() > 255 {
+            return Err(err_eval("Too many fields for the registers available"));
         }
 
-        // compile the expressions after the bindings
-        let result_exprs = &let_expr[1..];
-
-        for expr in result_exprs {
-            let src = self.compile_eval(mem, *expr)?;
-            // TODO - more efficient to be able to write the result directly to the let binding reg
-            self.push(mem, Opcode::CopyRegister { dest, src })?;
+        // evaluate the type name and each field into consecutive registers following `dest`
+        for (index, item) in items.iter().enumerate() {
+            self.compile_eval_into(mem, *item, first + index as Register)?;
         }
 
-        // finish up - pop the scope, de-scope all registers except the result, return the result
-        let closing_instructions = self.vars.pop_scope();
-        for opcode in &closing_instructions {
-            self.push(mem, *opcode)?;
-        }
+        self.push(
+            mem,
+            Opcode::MakeRecord {
+                dest,
+                first,
+                count: (items.len() - 1) as u8,
+            },
+        )?;
 
         self.reset_reg(dest + 1);
         Ok(dest)
     }
 
-    /// Push an instruction to the function bytecode list
-    fn push<'guard>(&mut self, mem: &'guard MutatorView, op: Opcode) -> Result<(), RuntimeError> {
-        self.bytecode.get(mem).push(mem, op)
-    }
-
-    /// Push an instruction with a result and a single argument to the function bytecode list
-    // ANCHOR: DefCompilerPushOp2
-    fn push_op2<'guard, F>(
+    /// Get a field of a record, checking that the record is of the named type. The field index
+    /// must be a Number in the expression itself, as generated by `defstruct`.
+    /// (record-get <record-expr> <type-expr> <index>)
+    fn compile_apply_record_get<'guard>(
         &mut self,
         mem: &'guard MutatorView,
-        params: TaggedScopedPtr<'guard>,
-        f: F,
-    ) -> Result<Register, RuntimeError>
-    where
-        F: Fn(Register, Register) -> Opcode,
-    {
-        let result = self.acquire_reg();
-        let reg1 = self.compile_eval(mem, value_from_1_pair(mem, params)?)?;
-        self.bytecode.get(mem).push(mem, f(result, reg1))?;
-        Ok(result)
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let (record, type_name, index) = values_from_3_pairs(mem, args)?;
+        let index = record_field_index(index)?;
+
+        let dest = self.acquire_reg();
+        let record = self.compile_eval(mem, record, None)?;
+        let type_name = self.compile_eval(mem, type_name, None)?;
+        self.push(mem, Opcode::CheckRecord { record, type_name })?;
+        self.push(
+            mem,
+            Opcode::RecordGet {
+                dest,
+                record,
+                index,
+            },
+        )?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
     }
-    // ANCHOR_END: DefCompilerPushOp2
 
-    /// Push an instruction with a result and two arguments to the function bytecode list
-    fn push_op3<'guard, F>(
+    /// Set a field of a record, checking that the record is of the named type, evaluating to
+    /// the new value. As with record-get, the field index must be a Number.
+    /// (record-set! <record-expr> <type-expr> <index> <value-expr>)
+    fn compile_apply_record_set<'guard>(
         &mut self,
         mem: &'guard MutatorView,
-        params: TaggedScopedPtr<'guard>,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let items = mem.scratch_from_pairs(args)?;
+        if items.len() != 4 {
+            return Err(err_eval(
+                "A record-set! expression must have (record-set! record type index value)",
+            ));
+        }
+        let index = record_field_index(items[2])?;
+
+        let dest = self.acquire_reg();
+        let record = self.compile_eval(mem, items[0], None)?;
+        let type_name = self.compile_eval(mem, items[1], None)?;
+        self.push(mem, Opcode::CheckRecord { record, type_name })?;
+
+        self.compile_eval(mem, items[3], Some(dest))?;
+        self.push(
+            mem,
+            Opcode::RecordSet {
+                record,
+                index,
+                value: dest,
+            },
+        )?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// (name <arg-expr-1> <arg-expr-n>)
+    fn compile_apply_call<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        function_expr: TaggedScopedPtr<'guard>,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+    ) -> Result<Register, RuntimeError> {
+        // allocate a register for the return value
+        let dest = self.acquire_reg();
+        // allocate a register for a closure environment pointer
+        let _closure_env = self.acquire_reg();
+
+        // evaluate arguments first
+        let arg_list = mem.scratch_from_pairs(args)?;
+        let arg_count = arg_list.len() as u8;
+
+        // each argument is evaluated directly into the next register in the sequence
+        for arg in arg_list.iter() {
+            let arg_reg = self.next_reg;
+            self.compile_eval(mem, *arg, Some(arg_reg))?;
+            self.reset_reg(arg_reg + 1);
+        }
+
+        // put the function pointer in the last register of the call so it'll be discarded
+        let function = self.compile_eval(mem, function_expr, None)?;
+        if tail {
+            self.push(
+                mem,
+                Opcode::TailCall {
+                    function,
+                    dest,
+                    arg_count,
+                },
+            )?;
+        } else {
+            self.push(
+                mem,
+                Opcode::Call {
+                    function,
+                    dest,
+                    arg_count,
+                },
+            )?;
+        }
+
+        // ignore use of any registers beyond the result once the call is complete
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Compile a 'sort' application, which returns a new list of the elements of a list in
+    /// ascending order. Without a comparator, numbers are ordered by value and Symbols and Text
+    /// by name, and a list of values of different types is an error. A comparator is a function of
+    /// two elements that returns true if the first belongs before the second. The VM calls the
+    /// comparator as a two argument call whose result register is the sort's, so that register
+    /// is allocated above any register still in use. Both sorts are stable.
+    /// (sort <list-expr>)
+    /// (sort <list-expr> <comparator-expr>)
+    fn compile_apply_sort<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        let arg_count = mem.scratch_from_pairs(args)?.len();
+
+        match arg_count {
+            1 => self.push_op2(mem, args, dest, |dest, list| Opcode::SortList {
+                dest,
+                list,
+            }),
+            2 => {
+                let dest = self.acquire_reg();
+                if dest as usize + FIRST_ARG_REG + 2 > 255 {
+                    return Err(err_eval(
+                        "Too many registers in use to call a sort comparator",
+                    ));
+                }
+
+                let (list, less) = values_from_2_pairs(mem, args)?;
+                let list = self.compile_eval(mem, list, None)?;
+                let less = self.compile_eval(mem, less, None)?;
+                self.push(mem, Opcode::SortListWith { dest, list, less })?;
+
+                // as for a call, the registers beyond the result are overwritten
+                self.reset_reg(dest + 1);
+                Ok(dest)
+            }
+            _ => Err(err_eval(
+                "sort takes a list and, optionally, a comparator function",
+            )),
+        }
+    }
+
+    /// Compile an assertion, which raises an error if it does not hold and otherwise evaluates to
+    /// nil. `assert` holds if its argument is not nil, `assert-eq` if its arguments are
+    /// structurally equal. The argument list is kept as a literal so that the error can show the
+    /// expressions that were asserted and where they are in the source.
+    /// (assert <expr>)
+    /// (assert-eq <expr> <expected-expr>)
+    fn compile_apply_assert<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        equal: bool,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        let dest = self.acquire_dest_reg(dest)?;
+        let form = self.push_load_literal(mem, args, None)?;
+
+        if equal {
+            let (first, second) = values_from_2_pairs(mem, args)?;
+            let first = self.compile_eval(mem, first, None)?;
+            let second = self.compile_eval(mem, second, None)?;
+            self.push(
+                mem,
+                Opcode::AssertEqual {
+                    form,
+                    first,
+                    second,
+                },
+            )?;
+        } else {
+            let test = self.compile_eval(mem, value_from_1_pair(mem, args)?, None)?;
+            self.push(mem, Opcode::Assert { form, test })?;
+        }
+
+        self.push(mem, Opcode::LoadNil { dest })?;
+        Ok(dest)
+    }
+
+    /// Read, compile and evaluate the source file at the given path, or, for `require`, only if
+    /// it has not been loaded before. The file's code is entered as a call would be, so its
+    /// register window begins at the result register.
+    /// (load <path-expr>)
+    /// (require <path-expr>)
+    fn compile_apply_load<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        once: bool,
+    ) -> Result<Register, RuntimeError> {
+        // allocate a register for the result above any register still in use
+        let dest = self.acquire_reg();
+
+        let path = self.compile_eval(mem, value_from_1_pair(mem, args)?, None)?;
+        if once {
+            self.push(mem, Opcode::RequireFile { dest, path })?;
+        } else {
+            self.push(mem, Opcode::LoadFile { dest, path })?;
+        }
+
+        // as for a call, the registers beyond the result are overwritten
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Render each argument as a string and join them into a single Text value
+    /// (concat <expr-1> <expr-n>)
+    fn compile_apply_concat<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        // allocate a register for the result
+        let dest = self.acquire_reg();
+
+        let arg_list = mem.scratch_from_pairs(args)?;
+        if dest as usize + 1 + arg_list.len() > 255 {
+            return Err(err_eval("Too many arguments for the registers available"));
+        }
+        let count = arg_list.len() as u8;
+
+        // evaluate the arguments into a contiguous sequence of registers following `dest`
+        let first = dest + 1;
+        for (index, arg) in arg_list.iter().enumerate() {
+            let arg_reg = first + index as u8;
+            self.compile_eval(mem, *arg, Some(arg_reg))?;
+            self.reset_reg(arg_reg + 1);
+        }
+
+        self.push(mem, Opcode::Concat { dest, first, count })?;
+
+        // discard the argument registers
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Combine arguments from left to right with a binary operation, accumulating the result in a
+    /// single register. The chain only ever needs that register plus one more for the argument
+    /// being combined, however many arguments there are:
+    ///
+    /// ```text
+    /// (+ a b c)
+    ///
+    ///     acc <- a
+    ///     tmp <- b
+    ///     acc <- acc + tmp
+    ///     tmp <- c
+    ///     acc <- acc + tmp
+    /// ```
+    ///
+    /// An argument that is a local variable is combined from its own register rather than being
+    /// copied to `tmp`. With no arguments the result is the `identity` of the operation, or an
+    /// error if it has none. A single argument is combined with the identity, or with itself if
+    /// there is none, so that it is still type checked.
+    /// (+ <expr>...)
+    fn compile_apply_fold<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        name: &str,
+        identity: Option<LiteralInteger>,
+        f: fn(Register, Register, Register) -> Opcode,
+    ) -> Result<Register, RuntimeError> {
+        // allocate the accumulator register for the result
+        let dest = self.acquire_reg();
+
+        let arg_list = mem.scratch_from_pairs(args)?;
+        let (first, rest) = match (arg_list.split_first(), identity) {
+            (Some(split), _) => split,
+            (None, Some(integer)) => {
+                self.push(mem, Opcode::LoadInteger { dest, integer })?;
+                return Ok(dest);
+            }
+            (None, None) => {
+                return Err(err_eval(&format!(
+                    "{} requires at least one argument",
+                    name
+                )))
+            }
+        };
+
+        // evaluate the first argument straight into the accumulator
+        self.compile_eval(mem, *first, Some(dest))?;
+        self.reset_reg(dest + 1);
+
+        if rest.is_empty() {
+            match identity {
+                Some(integer) => {
+                    let tmp = self.acquire_reg();
+                    self.push(mem, Opcode::LoadInteger { dest: tmp, integer })?;
+                    self.push(mem, f(dest, dest, tmp))?;
+                }
+                None => self.push(mem, f(dest, dest, dest))?,
+            }
+        }
+
+        for arg in rest {
+            let arg_reg = self.compile_eval(mem, *arg, None)?;
+            self.push(mem, f(dest, dest, arg_reg))?;
+            self.reset_reg(dest + 1);
+        }
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Build a list of the leading arguments onto the last argument, which becomes the tail
+    /// (cons* <expr>... <tail-expr>)
+    fn compile_apply_cons_star<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let arg_list = mem.scratch_from_pairs(args)?;
+        let (tail, items) = match arg_list.split_last() {
+            Some(split) => split,
+            None => return Err(err_eval("cons* requires at least one argument")),
+        };
+
+        // allocate a register for the result
+        let dest = self.acquire_reg();
+        if dest as usize + 1 + items.len() > 255 {
+            return Err(err_eval("Too many arguments for the registers available"));
+        }
+
+        // evaluate the leading arguments into a contiguous sequence of registers following
+        // `dest` and then the tail into `dest` itself
+        let first = dest + 1;
+        for (index, item) in items.iter().enumerate() {
+            let item_reg = first + index as u8;
+            self.compile_eval(mem, *item, Some(item_reg))?;
+            self.reset_reg(item_reg + 1);
+        }
+        self.compile_eval(mem, *tail, Some(dest))?;
+
+        // cons each leading argument onto the tail, last first
+        for index in (0..items.len()).rev() {
+            let reg1 = first + index as u8;
+            self.push(
+                mem,
+                Opcode::MakePair {
+                    dest,
+                    reg1,
+                    reg2: dest,
+                },
+            )?;
+        }
+
+        // discard the argument registers
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Build a list of the values of the arguments, in order. The list is appended to a
+    /// ListBuilder one argument at a time, so any number of arguments can be given.
+    /// (list <expr>...)
+    fn compile_apply_list<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let dest = self.acquire_reg();
+
+        if args == mem.nil() {
+            self.push(mem, Opcode::LoadNil { dest })?;
+            return Ok(dest);
+        }
+
+        // the builder lives in `dest` until the finished list replaces it
+        self.push(mem, Opcode::MakeListBuilder { dest })?;
+
+        for item in mem.scratch_from_pairs(args)?.iter() {
+            let value = self.compile_eval(mem, *item, None)?;
+            self.push(
+                mem,
+                Opcode::ListAppend {
+                    dest,
+                    builder: dest,
+                    value,
+                },
+            )?;
+            self.reset_reg(dest + 1);
+        }
+
+        self.push(
+            mem,
+            Opcode::BuildList {
+                dest,
+                builder: dest,
+            },
+        )?;
+        Ok(dest)
+    }
+
+    /// Insert a value into a List before the given index, evaluating to the List
+    /// (insert <list-expr> <index-expr> <item-expr>)
+    fn compile_apply_insert<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let dest = self.acquire_reg();
+
+        let (list_expr, index_expr, item_expr) = values_from_3_pairs(mem, args)?;
+        // the list is evaluated into the result register, as the insert returns it
+        let list = self.compile_eval(mem, list_expr, Some(dest))?;
+        let index = self.compile_eval(mem, index_expr, None)?;
+        let item = self.compile_eval(mem, item_expr, None)?;
+
+        self.push(mem, Opcode::ListInsert { list, index, item })?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Compile a pseudo-random integer from a range that starts at 0 if only its end is given
+    /// (random <high>)
+    /// (random <low> <high>)
+    fn compile_apply_random<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let dest = self.acquire_reg();
+
+        let arg_list = mem.scratch_from_pairs(args)?;
+        let (low_expr, high_expr) = match arg_list.as_slice() {
+            [high] => (TaggedScopedPtr::new(mem, TaggedPtr::number(0)), *high),
+            [low, high] => (*low, *high),
+            _ => {
+                return Err(err_eval(&format!(
+                    "random takes 1 or 2 arguments, got {}",
+                    arg_list.len()
+                )))
+            }
+        };
+
+        let low = self.compile_eval(mem, low_expr, None)?;
+        let high = self.compile_eval(mem, high_expr, None)?;
+        self.push(mem, Opcode::Random { dest, low, high })?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Compile a conversion between numbers and text with an optional radix, which defaults to 10
+    /// (number->string <number> [<radix>])
+    /// (string->number <text> [<radix>])
+    fn compile_apply_radix_conversion<'guard, F>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
         f: F,
     ) -> Result<Register, RuntimeError>
     where
         F: Fn(Register, Register, Register) -> Opcode,
     {
-        let result = self.acquire_reg();
-        let (first, second) = values_from_2_pairs(mem, params)?;
-        let reg1 = self.compile_eval(mem, first)?;
-        let reg2 = self.compile_eval(mem, second)?;
-        self.bytecode.get(mem).push(mem, f(result, reg1, reg2))?;
-        Ok(result)
+        let dest = self.acquire_reg();
+
+        let arg_list = mem.scratch_from_pairs(args)?;
+        let (value_expr, radix_expr) = match arg_list.as_slice() {
+            [value] => (*value, TaggedScopedPtr::new(mem, TaggedPtr::number(10))),
+            [value, radix] => (*value, *radix),
+            _ => {
+                return Err(err_eval(&format!(
+                    "Conversion takes 1 or 2 arguments, got {}",
+                    arg_list.len()
+                )))
+            }
+        };
+
+        let value = self.compile_eval(mem, value_expr, None)?;
+        let radix = self.compile_eval(mem, radix_expr, None)?;
+        self.push(mem, f(dest, value, radix))?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
     }
 
-    // Push a literal onto the literals list and a load instruction onto the bytecode list
-    fn push_load_literal<'guard>(
+    /// Evaluate to a list of the names of all global bindings
+    /// (env-keys)
+    fn compile_apply_env_keys<'guard>(
         &mut self,
         mem: &'guard MutatorView,
-        literal: TaggedScopedPtr<'guard>,
+        args: TaggedScopedPtr<'guard>,
     ) -> Result<Register, RuntimeError> {
-        let result = self.acquire_reg();
-        let lit_id = self.bytecode.get(mem).push_lit(mem, literal)?;
-        self.bytecode.get(mem).push_loadlit(mem, result, lit_id)?;
-        Ok(result)
-    }
+        if args != mem.nil() {
+            return Err(err_eval("env-keys takes no arguments"));
+        }
 
-    // this is a naive way of allocating registers - every result gets it's own register
-    fn acquire_reg(&mut self) -> Register {
-        // TODO check overflow
-        let reg = self.next_reg;
-        self.next_reg += 1;
-        reg
+        let dest = self.acquire_reg();
+        self.push(mem, Opcode::LoadGlobalNames { dest })?;
+        Ok(dest)
     }
 
-    // TODO use this function instead of acquire_reg
-    // this is a naive way of allocating registers - every result gets it's own register
-    fn acquire_dest_reg(&mut self, push_dest: Option<Register>) -> Result<Register, RuntimeError> {
-        if let Some(dest) = push_dest {
-            Ok(dest)
-        } else {
-            let dest = self.next_reg;
-            // check for 8 bit overflow. A function cannot allocate more than 255 registers for
-            // itself.
-            if dest == 255 {
-                return Err(err_eval(
-                    "Compiler ran out of registers for this function, consider reducing complexity",
-                ));
-            }
-            self.next_reg += 1;
-            Ok(dest)
+    /// Evaluate to an association list of garbage collection activity counters: the number of
+    /// collections run, the total bytes reclaimed and the duration of the last collection in
+    /// microseconds
+    /// (gc-stats)
+    fn compile_apply_gc_stats<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        if args != mem.nil() {
+            return Err(err_eval("gc-stats takes no arguments"));
         }
-    }
+
+        let dest = self.acquire_reg();
+        self.push(mem, Opcode::LoadGcStats { dest })?;
+        Ok(dest)
+    }
+
+    /// Create a new, empty TextBuilder. Strings are appended to it with `sb-append` and it is
+    /// made into a Text with `sb-finish`.
+    /// (sb-new)
+    fn compile_apply_sb_new<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        if args != mem.nil() {
+            return Err(err_eval("sb-new takes no arguments"));
+        }
+
+        let dest = self.acquire_reg();
+        self.push(mem, Opcode::MakeTextBuilder { dest })?;
+        Ok(dest)
+    }
+
+    /// Create a new, empty ListBuilder. Values are appended to the end of its list with
+    /// `lb-append` and the list is taken from it with `lb-finish`.
+    /// (lb-new)
+    fn compile_apply_lb_new<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        if args != mem.nil() {
+            return Err(err_eval("lb-new takes no arguments"));
+        }
+
+        let dest = self.acquire_reg();
+        self.push(mem, Opcode::MakeListBuilder { dest })?;
+        Ok(dest)
+    }
+
+    /// Basic non-recursive let expressions
+    /// (let
+    ///   ((<name> <expr>)
+    ///    (<name> <expr>))
+    ///   (<expr>)
+    /// )
+    fn compile_apply_let<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+    ) -> Result<Register, RuntimeError> {
+        let let_expr = mem.scratch_from_pairs(args)?;
+        if let_expr.len() < 2 {
+            return Err(err_eval("A let expression must have at least 2 arguments"));
+        }
+
+        // the binding expressions should be a pair-list itself, and each expression another
+        // pair list of length 2.  Convert it to a Vec<(name, expr)> structure for convenience.
+        let let_exprs: Vec<(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>)> = {
+            let vec_of_pairs = mem.scratch_from_pairs(let_expr[0])?;
+            let mut vec_of_tuples = Vec::new();
+            for pairs in vec_of_pairs.iter() {
+                vec_of_tuples.push(values_from_2_pairs(mem, *pairs)?);
+            }
+            vec_of_tuples
+        };
+
+        // acquire a let expression dest reg
+        let dest = self.acquire_reg();
+
+        // get the names of each binding to push a scope, assigning registers post-result for
+        // each binding
+        let names: Vec<TaggedScopedPtr<'guard>> = let_exprs.iter().map(|tup| tup.0).collect();
+
+        let mut let_scope = Scope::new();
+        let after_bindings = let_scope.push_bindings(&names, self.next_reg)?;
+        self.vars.scopes.push(let_scope);
+
+        // compile each binding expression directly into its binding register. The registers of
+        // the bindings that follow have not been assigned yet, so they are free to be used while
+        // evaluating the expression.
+        for (name, expr) in let_exprs {
+            let reg = self.compile_eval(mem, name, None)?;
+            self.compile_eval_into(mem, expr, reg)?;
+        }
+        self.reset_reg(after_bindings);
+
+        // compile the expressions after the bindings, the last of which may be in tail position
+        // and is the result of the let expression
+        let result_exprs = &let_expr[1..];
+
+        let mut tail_start = 0;
+        for (index, expr) in result_exprs.iter().enumerate() {
+            let last = index == result_exprs.len() - 1;
+            if last {
+                tail_start = self.bytecode.get(mem).next_instruction();
+                self.compile_eval_tail(mem, *expr, tail, Some(dest))?;
+            } else {
+                self.compile_eval(mem, *expr, None)?;
+            }
+        }
+
+        // finish up - pop the scope, de-scope all registers except the result, return the result
+        self.pop_scope_after(mem, tail_start)?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Sequential let expressions, where each binding expression can refer to the bindings
+    /// preceding it
+    /// (let*
+    ///   ((<name> <expr>)
+    ///    (<name> <expr>))
+    ///   (<expr>)
+    /// )
+    fn compile_apply_let_star<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+    ) -> Result<Register, RuntimeError> {
+        let let_expr = mem.scratch_from_pairs(args)?;
+        if let_expr.len() < 2 {
+            return Err(err_eval("A let* expression must have at least 2 arguments"));
+        }
+
+        let let_exprs: Vec<(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>)> = {
+            let vec_of_pairs = mem.scratch_from_pairs(let_expr[0])?;
+            let mut vec_of_tuples = Vec::new();
+            for pairs in vec_of_pairs.iter() {
+                vec_of_tuples.push(values_from_2_pairs(mem, *pairs)?);
+            }
+            vec_of_tuples
+        };
+
+        // acquire a let expression dest reg
+        let dest = self.acquire_reg();
+
+        // reserve a register for each binding up front. Each binding expression is evaluated
+        // directly into its register, using the registers after it, which includes those of
+        // the bindings that have not been assigned yet
+        let first_binding = self.next_reg;
+        let binding_count = let_exprs.len() as Register;
+        let after_bindings = first_binding + binding_count;
+
+        // compile each binding expression and only then bind its name, each in a new nested
+        // scope. Later expressions see earlier bindings, and a repeated name shadows the
+        // previous binding rather than replacing it.
+        for (index, (name, expr)) in let_exprs.into_iter().enumerate() {
+            let reg = first_binding + index as Register;
+            self.compile_eval_into(mem, expr, reg)?;
+
+            let mut let_scope = Scope::new();
+            let_scope.push_binding(name, reg)?;
+            self.vars.scopes.push(let_scope);
+        }
+        self.reset_reg(after_bindings);
+
+        // compile the expressions after the bindings, the last of which may be in tail position
+        // and is the result of the let* expression
+        let result_exprs = &let_expr[1..];
+
+        let mut tail_start = 0;
+        for (index, expr) in result_exprs.iter().enumerate() {
+            let last = index == result_exprs.len() - 1;
+            if last {
+                tail_start = self.bytecode.get(mem).next_instruction();
+                self.compile_eval_tail(mem, *expr, tail, Some(dest))?;
+            } else {
+                self.compile_eval(mem, *expr, None)?;
+            }
+        }
+
+        // finish up - pop the scopes, de-scope all registers except the result, return the result
+        for _ in 0..binding_count {
+            self.pop_scope_after(mem, tail_start)?;
+        }
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Bundle the values of the arguments together, to be returned as multiple values and
+    /// taken apart by let-values. A single value is not bundled.
+    /// (values <expr> ...)
+    fn compile_apply_values<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        let items = mem.scratch_from_pairs(args)?;
+        if items.len() == 1 {
+            return self.compile_eval(mem, items[0], None);
+        }
+
+        let dest = self.acquire_reg();
+        let first = self.next_reg;
+        if first as usize + items.len() > 255 {
+            return Err(err_eval("Too many values for the registers available"));
+        }
+
+        // evaluate each value into consecutive registers following `dest`
+        for (index, item) in items.iter().enumerate() {
+            self.compile_eval_into(mem, *item, first + index as Register)?;
+        }
+
+        self.push(
+            mem,
+            Opcode::MakeValues {
+                dest,
+                first,
+                count: items.len() as u8,
+            },
+        )?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Let expressions where each binding expression gives multiple values, bound to a list of
+    /// names. An expression that does not give a bundle of values is a single value.
+    /// (let-values
+    ///   (((<name> ...) <expr>)
+    ///    ((<name> ...) <expr>))
+    ///   (<expr>)
+    /// )
+    fn compile_apply_let_values<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+        tail: bool,
+    ) -> Result<Register, RuntimeError> {
+        let let_expr = mem.scratch_from_pairs(args)?;
+        if let_expr.len() < 2 {
+            return Err(err_eval(
+                "A let-values expression must have at least 2 arguments",
+            ));
+        }
+
+        // convert the bindings to a Vec<(names, expr)> structure
+        let let_exprs: Vec<(Vec<TaggedScopedPtr<'guard>>, TaggedScopedPtr<'guard>)> = {
+            let vec_of_pairs = mem.scratch_from_pairs(let_expr[0])?;
+            let mut vec_of_tuples = Vec::new();
+            for pairs in vec_of_pairs.iter() {
+                let (names, expr) = values_from_2_pairs(mem, *pairs)?;
+                vec_of_tuples.push((vec_from_pairs(mem, names)?, expr));
+            }
+            vec_of_tuples
+        };
+
+        let dest = self.acquire_reg();
+
+        // the names of all the bindings are given consecutive registers, in order
+        let names: Vec<TaggedScopedPtr<'guard>> = let_exprs
+            .iter()
+            .flat_map(|(names, _)| names.iter().copied())
+            .collect();
+
+        let first = self.next_reg;
+        let mut let_scope = Scope::new();
+        let after_bindings = let_scope.push_bindings(&names, first)?;
+        self.vars.scopes.push(let_scope);
+
+        // compile each binding expression into the registers of its names. As with let, the
+        // registers of the bindings that follow are free to be used while evaluating it.
+        let mut reg = first;
+        for (names, expr) in let_exprs {
+            self.compile_values_into(mem, expr, reg, names.len())?;
+            reg += names.len() as Register;
+        }
+        self.reset_reg(after_bindings);
+
+        // compile the expressions after the bindings, the last of which may be in tail position
+        // and is the result of the let-values expression
+        let result_exprs = &let_expr[1..];
+
+        let mut tail_start = 0;
+        for (index, expr) in result_exprs.iter().enumerate() {
+            let last = index == result_exprs.len() - 1;
+            if last {
+                tail_start = self.bytecode.get(mem).next_instruction();
+                self.compile_eval_tail(mem, *expr, tail, Some(dest))?;
+            } else {
+                self.compile_eval(mem, *expr, None)?;
+            }
+        }
+
+        // finish up - pop the scope, de-scope all registers except the result, return the result
+        self.pop_scope_after(mem, tail_start)?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Compile an expression giving `count` values into the registers starting at `first`
+    fn compile_values_into<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        expr: TaggedScopedPtr<'guard>,
+        first: Register,
+        count: usize,
+    ) -> Result<(), RuntimeError> {
+        // a values form is compiled straight into the registers, without allocating a bundle
+        if let Value::Pair(p) = *expr {
+            if let Value::Symbol(s) = *p.first.get(mem) {
+                if s.as_str(mem) == "values" {
+                    let items = mem.scratch_from_pairs(p.second.get(mem))?;
+                    if items.len() != count {
+                        return Err(err_eval(&format!(
+                            "Expected {} values but {} were given",
+                            count,
+                            items.len()
+                        )));
+                    }
+
+                    for (index, item) in items.iter().enumerate() {
+                        self.compile_eval_into(mem, *item, first + index as Register)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        self.compile_eval_into(mem, expr, first)?;
+        self.push(
+            mem,
+            Opcode::UnpackValues {
+                dest: first,
+                count: count as u8,
+                src: first,
+            },
+        )
+    }
+
+    /// Evaluate the body expressions `count` times, binding `name` to 0 through count - 1. The
+    /// result is always nil.
+    /// (dotimes (<name> <count-expr>)
+    ///   <expr>
+    ///   <expr>)
+    fn compile_apply_dotimes<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        args: TaggedScopedPtr<'guard>,
+    ) -> Result<Register, RuntimeError> {
+        //
+        //   counter = 0
+        //   start:
+        //     if not counter < limit then jmp -> end
+        //     eval body
+        //     counter = counter + 1
+        //     jmp -> start
+        //   end:
+        //
+        let dotimes_expr = mem.scratch_from_pairs(args)?;
+        if dotimes_expr.is_empty() {
+            return Err(err_eval(
+                "A dotimes expression must have at least 1 argument",
+            ));
+        }
+
+        let (name, count_expr) = values_from_2_pairs(mem, dotimes_expr[0])?;
+
+        let bytecode = self.bytecode.get(mem);
+
+        // acquire a dotimes expression dest reg
+        let dest = self.acquire_reg();
+
+        // evaluate the iteration count before the counter is bound so that it cannot refer to it
+        let limit = self.acquire_reg();
+        self.compile_eval(mem, count_expr, Some(limit))?;
+        self.reset_reg(limit + 1);
+
+        let step = self.acquire_reg();
+        self.push(
+            mem,
+            Opcode::LoadInteger {
+                dest: step,
+                integer: 1,
+            },
+        )?;
+
+        // bind the counter in a new scope
+        let counter = self.next_reg;
+        let mut dotimes_scope = Scope::new();
+        self.next_reg = dotimes_scope.push_bindings(&[name], counter)?;
+        self.vars.scopes.push(dotimes_scope);
+
+        self.push(
+            mem,
+            Opcode::LoadInteger {
+                dest: counter,
+                integer: 0,
+            },
+        )?;
+
+        // loop condition
+        let loop_start = bytecode.next_instruction();
+        let body_reg = self.next_reg;
+
+        let test = self.acquire_reg();
+        self.push(
+            mem,
+            Opcode::IsLessThan {
+                dest: test,
+                test1: counter,
+                test2: limit,
+            },
+        )?;
+        let offset = JUMP_UNKNOWN;
+        self.push(mem, Opcode::JumpIfNotTrue { test, offset })?;
+        let end_jump = bytecode.last_instruction();
+
+        // loop body, discarding each result
+        for expr in &dotimes_expr[1..] {
+            self.reset_reg(body_reg);
+            self.compile_eval(mem, *expr, None)?;
+        }
+        self.reset_reg(body_reg);
+
+        // increment the counter and jump back to the condition
+        self.push(
+            mem,
+            Opcode::Add {
+                dest: counter,
+                reg1: counter,
+                reg2: step,
+            },
+        )?;
+
+        let back_offset = loop_start as i64 - bytecode.next_instruction() as i64 - 1;
+        if back_offset < JumpOffset::MIN as i64 {
+            return Err(err_eval("dotimes body is too large to jump over"));
+        }
+        self.push(
+            mem,
+            Opcode::Jump {
+                offset: back_offset as JumpOffset,
+            },
+        )?;
+
+        let offset = bytecode.next_instruction() - end_jump - 1;
+        bytecode.update_jump_offset(mem, end_jump, offset as JumpOffset)?;
+
+        // finish up - pop the scope, de-scope all registers except the result, return nil
+        let closing_instructions = self.vars.pop_scope();
+        for opcode in &closing_instructions {
+            self.push(mem, *opcode)?;
+        }
+
+        self.push(mem, Opcode::LoadNil { dest })?;
+
+        self.reset_reg(dest + 1);
+        Ok(dest)
+    }
+
+    /// Push an instruction to the function bytecode list
+    fn push<'guard>(&mut self, mem: &'guard MutatorView, op: Opcode) -> Result<(), RuntimeError> {
+        self.bytecode.get(mem).push(mem, op)
+    }
+
+    /// Pop the innermost scope, closing any of its variables that were captured by a closure.
+    /// The closing instructions follow the code compiled from instruction `tail_start` onwards,
+    /// so if there are any, a tail call in that code is not the last thing done and must be an
+    /// ordinary call.
+    fn pop_scope_after<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        tail_start: ArraySize,
+    ) -> Result<(), RuntimeError> {
+        let closing_instructions = self.vars.pop_scope();
+        if !closing_instructions.is_empty() {
+            self.bytecode.get(mem).demote_tail_calls(mem, tail_start)?;
+        }
+        for opcode in &closing_instructions {
+            self.push(mem, *opcode)?;
+        }
+        Ok(())
+    }
+
+    /// Push an instruction with a result and a single argument to the function bytecode list
+    // ANCHOR: DefCompilerPushOp2
+    fn push_op2<'guard, F>(
+        &mut self,
+        mem: &'guard MutatorView,
+        params: TaggedScopedPtr<'guard>,
+        dest: Option<Register>,
+        f: F,
+    ) -> Result<Register, RuntimeError>
+    where
+        F: Fn(Register, Register) -> Opcode,
+    {
+        let result = self.acquire_dest_reg(dest)?;
+        let reg1 = self.compile_eval(mem, value_from_1_pair(mem, params)?, None)?;
+        self.bytecode.get(mem).push(mem, f(result, reg1))?;
+        Ok(result)
+    }
+    // ANCHOR_END: DefCompilerPushOp2
+
+    /// Push an instruction with a result and two arguments to the function bytecode list
+    fn push_op3<'guard, F>(
+        &mut self,
+        mem: &'guard MutatorView,
+        params: TaggedScopedPtr<'guard>,
+        dest: Option<Register>,
+        f: F,
+    ) -> Result<Register, RuntimeError>
+    where
+        F: Fn(Register, Register, Register) -> Opcode,
+    {
+        let result = self.acquire_dest_reg(dest)?;
+        let (first, second) = values_from_2_pairs(mem, params)?;
+        let reg1 = self.compile_eval(mem, first, None)?;
+        let reg2 = self.compile_eval(mem, second, None)?;
+        self.bytecode.get(mem).push(mem, f(result, reg1, reg2))?;
+        Ok(result)
+    }
+
+    // Push a literal onto the literals list and a load instruction onto the bytecode list
+    fn push_load_literal<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        literal: TaggedScopedPtr<'guard>,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        let result = self.acquire_dest_reg(dest)?;
+        let lit_id = self.bytecode.get(mem).push_lit(mem, literal)?;
+        self.bytecode.get(mem).push_loadlit(mem, result, lit_id)?;
+        Ok(result)
+    }
+
+    // this is a naive way of allocating registers - every result gets it's own register
+    fn acquire_reg(&mut self) -> Register {
+        // TODO check overflow
+        let reg = self.next_reg;
+        self.next_reg += 1;
+        reg
+    }
+
+    // use the given result register if there is one, otherwise acquire the next register
+    fn acquire_dest_reg(&mut self, push_dest: Option<Register>) -> Result<Register, RuntimeError> {
+        if let Some(dest) = push_dest {
+            Ok(dest)
+        } else {
+            let dest = self.next_reg;
+            // check for 8 bit overflow. A function cannot allocate more than 255 registers for
+            // itself.
+            if dest == 255 {
+                return Err(err_eval(
+                    "Compiler ran out of registers for this function, consider reducing complexity",
+                ));
+            }
+            self.next_reg += 1;
+            Ok(dest)
+        }
+    }
+
+    /// Compile an expression so that its result is left in the `dest` register. Registers are
+    /// allocated from `dest` upward while compiling, so any expression that computes a new value
+    /// writes it straight into `dest` without a copy. Only an expression whose value already
+    /// lives in another register, such as a variable reference, needs a `CopyRegister`.
+    ///
+    /// The `dest` register and every register above it must be free to be overwritten.
+    fn compile_eval_into<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        ast_node: TaggedScopedPtr<'guard>,
+        dest: Register,
+    ) -> Result<(), RuntimeError> {
+        self.reset_reg(dest);
+        self.compile_eval(mem, ast_node, Some(dest))?;
+        Ok(())
+    }
+
+    /// Copy a result from the `src` register into the `dest` register, if one is given and it
+    /// is not already the same register, returning the register the result is in
+    fn copy_result<'guard>(
+        &mut self,
+        mem: &'guard MutatorView,
+        src: Register,
+        dest: Option<Register>,
+    ) -> Result<Register, RuntimeError> {
+        match dest {
+            Some(dest) if dest != src => {
+                self.push(mem, Opcode::CopyRegister { dest, src })?;
+                Ok(dest)
+            }
+            _ => Ok(src),
+        }
+    }
 
     // reset the next register back to the given one so that it is reused
     fn reset_reg(&mut self, reg: Register) {
         self.next_reg = reg
     }
-}
+}
+
+/// Return the field index given as a Number in a record-get or record-set! expression
+fn record_field_index(index: TaggedScopedPtr) -> Result<u8, RuntimeError> {
+    match *index {
+        Value::Number(n) if (0..=255).contains(&n) => Ok(n as u8),
+        _ => Err(err_eval(
+            "A record field index must be a Number from 0 to 255",
+        )),
+    }
+}
+
+/// Return the number of arguments a special form takes, for those forms that always take the
+/// same number. Forms that take a variable number check their own arguments as they compile.
+fn special_form_arity(name: &str) -> Option<usize> {
+    match name {
+        "quote" | "atom?" | "nil?" | "empty?" | "car" | "cdr" | "strict-car" | "strict-cdr"
+        | "type-of" | "print" | "println" | "seed!" | "bound?" | "fn-params" | "fn-name"
+        | "arity" | "fn-size" | "zero?" | "positive?" | "negative?" | "even?" | "odd?" | "neg"
+        | "abs" | "char->integer" | "integer->char" | "string->list" | "list->string"
+        | "deep-copy" | "sb-finish" | "lb-finish" => Some(1),
+
+        "cons" | "equal?" | "is?" | "record?" | "remove" | "quotient" | "floor-div"
+        | "remainder" | "modulo" | "sb-append" | "lb-append" => Some(2),
+
+        _ => None,
+    }
+}
+
+/// Check that a special form that takes a fixed number of arguments was given that many. The
+/// error is at the position of the form, if the parser recorded one.
+fn check_special_form_arity<'guard>(
+    guard: &'guard dyn MutatorScope,
+    name: &str,
+    args: TaggedScopedPtr<'guard>,
+    pos: Option<SourcePos>,
+) -> Result<(), RuntimeError> {
+    let expected = match special_form_arity(name) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    let mut given = 0;
+    let mut next = args;
+    while let Value::Pair(pair) = *next {
+        given += 1;
+        next = pair.second.get(guard);
+    }
+
+    // an incorrectly terminated argument list is reported when the arguments are unpacked
+    if given == expected {
+        return Ok(());
+    }
+
+    let kind = ErrorKind::EvalError(format!(
+        "{} expects {} argument{}, got {}",
+        name,
+        expected,
+        if expected == 1 { "" } else { "s" },
+        given
+    ));
+    Err(match pos {
+        Some(pos) => RuntimeError::with_pos(kind, pos),
+        None => RuntimeError::new(kind),
+    })
+}
+
+/// Compile a function - parameters and expression, returning a tagged Function object
+fn compile_function<'guard, 'scope>(
+    mem: &'guard MutatorView,
+    parent: Option<&'scope Variables<'scope>>,
+    name: TaggedScopedPtr<'guard>,
+    params: &[TaggedScopedPtr<'guard>],
+    exprs: &[TaggedScopedPtr<'guard>],
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+    let compiler = Compiler::new(mem, parent)?;
+    Ok(compiler
+        .compile_function(mem, name, params, exprs)?
+        .as_tagged(mem))
+}
+
+/// Compile the given AST and return an anonymous Function object
+pub fn compile<'guard>(
+    mem: &'guard MutatorView,
+    ast: TaggedScopedPtr<'guard>,
+) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+    let compiler = Compiler::new(mem, None)?;
+    compiler.compile_function(mem, mem.nil(), &[], &[ast])
+}
+
+/// Compile a sequence of ASTs, such as the top level expressions of a source file, into a single
+/// Function that evaluates each in turn and returns the result of the last
+pub fn compile_all<'guard>(
+    mem: &'guard MutatorView,
+    exprs: &[TaggedScopedPtr<'guard>],
+) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+    let compiler = Compiler::new(mem, None)?;
+    compiler.compile_function(mem, mem.nil(), &[], exprs)
+}
+
+/// Compile the given AST, as `compile()` does, but in strict mode: a reference to a global that
+/// is neither bound in the given globals nor defined earlier in the AST is an error at compile
+/// time rather than when the code is run. This also rejects code that refers to a global that
+/// will be defined later, such as mutually recursive functions defined one at a time.
+pub fn compile_strict<'guard>(
+    mem: &'guard MutatorView,
+    ast: TaggedScopedPtr<'guard>,
+    globals: ScopedPtr<'guard, Dict>,
+) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+    let mut names = HashSet::new();
+    for key in globals.keys(mem)? {
+        names.insert(String::from(key.as_symbol_str()?));
+    }
+
+    let mut compiler = Compiler::new(mem, None)?;
+    compiler.vars = Variables::new_strict(names);
+    compiler.compile_function(mem, mem.nil(), &[], &[ast])
+}
+
+/// INTEGRATION TESTS
+/// TODO - move to a separate module
+#[cfg(test)]
+mod integration {
+    use super::*;
+    use crate::containers::{Container, IndexedAnyContainer};
+    use crate::error::{spos, ErrorKind};
+    use crate::memory::{Memory, Mutator};
+    use crate::number::{INLINE_NUMBER_MAX, INLINE_NUMBER_MIN};
+    use crate::pair::vec_from_pairs;
+    use crate::parser::parse;
+    use crate::printer::render;
+    use crate::taggedptr::TaggedPtr;
+    use crate::vm::Thread;
+
+    fn eval_helper<'guard>(
+        mem: &'guard MutatorView,
+        thread: ScopedPtr<'guard, Thread>,
+        code: &str,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let compiled_code = compile(mem, parse(mem, code)?)?;
+        println!("RUN CODE {}", code);
+        let result = thread.quick_vm_eval(mem, compiled_code)?;
+        println!("RUN RESULT {}", result);
+        Ok(result)
+    }
+
+    fn test_helper(test_fn: fn(&MutatorView) -> Result<(), RuntimeError>) {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = fn(&MutatorView) -> Result<(), RuntimeError>;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                test_fn: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                test_fn(mem)
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, test_fn).unwrap();
+    }
+
+    #[test]
+    fn compile_cond_first_is_true() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // testing 'cond'
+            // (nil? nil) == true, so result should be x
+            let code = "(cond (nil? nil) 'x (nil? 'a) 'y)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+
+            assert!(result == mem.lookup_sym("x"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_cond_second_is_true() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // testing 'cond'
+            // (nil? 'a) == nil, (nil? nil) == true, so result should be y
+            let code = "(cond (nil? 'a) 'x (nil? nil) 'y)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+
+            assert!(result == mem.lookup_sym("y"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_cond_none_is_true() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // testing 'cond'
+            // (nil? 'a) == nil, (nil? 'b) == nil, result should be nil
+            let code = "(cond (nil? 'a) 'x (nil? 'b) 'y)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_cond_default_expression() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a trailing lone expression is the result when no condition is true
+            let no_match = "(cond (nil? 'a) 'x (nil? 'b) 'y 'z)";
+            let match_first = "(cond (nil? nil) 'x (nil? 'b) 'y 'z)";
+            let only_default = "(cond 'z)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, no_match)?;
+            assert!(result == mem.lookup_sym("z"));
+
+            let result = eval_helper(mem, t, match_first)?;
+            assert!(result == mem.lookup_sym("x"));
+
+            let result = eval_helper(mem, t, only_default)?;
+            assert!(result == mem.lookup_sym("z"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_cond_local_variable_results() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // results that are already in a register are copied to the cond result register
+            let matched = "(let ((a 'x) (b 'y)) (cond (nil? nil) a b))";
+            let default = "(let ((a 'x) (b 'y)) (cond (nil? 'c) a b))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, matched)?;
+            assert!(result == mem.lookup_sym("x"));
+
+            let result = eval_helper(mem, t, default)?;
+            assert!(result == mem.lookup_sym("y"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_call_functions() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test calls a function from another function
+            let compare_fn = "(def is_it (ask expect) (is? ask expect))";
+            let curried_fn = "(def is_it_a (ask) (is_it ask 'a))";
+            let query1 = "(is_it_a nil)";
+            let query2 = "(is_it_a 'a)";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, compare_fn)?;
+            eval_helper(mem, t, curried_fn)?;
+
+            let result1 = eval_helper(mem, t, query1)?;
+            assert!(result1 == mem.nil());
+
+            let result2 = eval_helper(mem, t, query2)?;
+            assert!(result2 == mem.lookup_sym("true"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_map_function_over_list() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test passes a function as a parameter through recursive function calls
+            let compare_fn = "(def is_y (ask) (is? ask 'y))";
+            let map_fn =
+                "(def map (f l) (cond (nil? l) nil true (cons (f (car l)) (map f (cdr l)))))";
+
+            let query = "(map is_y '(x y z z y))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, compare_fn)?;
+            eval_helper(mem, t, map_fn)?;
+
+            let result = eval_helper(mem, t, query)?;
+
+            let result = vec_from_pairs(mem, result)?;
+            let sym_nil = mem.nil();
+            let sym_true = mem.lookup_sym("true");
+            assert!(result == &[sym_nil, sym_true, sym_nil, sym_nil, sym_true]);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_eval_nested_partials() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test evaluates nested Partial applications in function position
+            let a_fn = "(def isit (a b) (is? a b))";
+
+            let query1 = "((isit 'x) 'x)";
+            let query2 = "((isit 'x) 'y)";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, a_fn)?;
+
+            let result = eval_helper(mem, t, query1)?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, query2)?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_pass_partial_as_param() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test passes a Partial as an argument of another function that will call it
+            // with it's last argument.
+            let isit_fn = "(def isit (a b) (is? a b))";
+            let map_fn = "(def map (f v) (f v))";
+
+            let query1 = "(map (isit 'x) 'x)";
+            let query2 = "(map (isit 'x) 'y)";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, isit_fn)?;
+            eval_helper(mem, t, map_fn)?;
+
+            let result = eval_helper(mem, t, query1)?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, query2)?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_simple_let() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test compiles a basic let expression
+            let expr = "(let ((x 'y)) x)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, expr)?;
+            assert!(result == mem.lookup_sym("y"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_function_with_simple_let() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test compiles a let expression that deconstructs and reconstructs a pair list
+            let a_fn = "(def deconrecon (list) (let ((a (car list)) (b (cdr list))) (cons a b)))";
+            let query = "(deconrecon '(x y z z y))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, a_fn)?;
+
+            let result = eval_helper(mem, t, query)?;
+
+            let result = vec_from_pairs(mem, result)?;
+            let sym_x = mem.lookup_sym("x");
+            let sym_y = mem.lookup_sym("y");
+            let sym_z = mem.lookup_sym("z");
+            assert!(result == &[sym_x, sym_y, sym_z, sym_z, sym_y]);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_function_with_lambda_with_nonlocal_ref() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test compiles a function containing a lambda that references a nonlocal
+            let head_fn = "(def head (a) (let ((inner (\\ () (car a)))) (inner)))";
+            let query = "(head '(x y z z y))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, head_fn)?;
+
+            let result = eval_helper(mem, t, query)?;
+            assert!(result == mem.lookup_sym("x"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_function_returning_lambda_with_nonlocal_ref() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test compiles a function that returns a lambda that references a nonlocal
+            let head_fn = "(def head (a) (let ((inner (\\ () (car a)))) inner))";
+            let inner_fn = "(set 'inner (head '(x y z z y)))";
+            let query = "(inner)";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, head_fn)?;
+            eval_helper(mem, t, inner_fn)?;
+
+            let result = eval_helper(mem, t, query)?;
+            assert!(result == mem.lookup_sym("x"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_concat_mixed_types() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test joins symbols, strings and nil into a single Text value
+            let expr = "(concat \"foo\" 'bar \" \" (car '(baz)) nil)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, expr)?;
+            match *result {
+                Value::Text(text) => assert!(text.as_str(mem) == "foobar baznil"),
+                _ => panic!("expected Text"),
+            }
+
+            // numbers render as decimal digits
+            let number = TaggedScopedPtr::new(mem, TaggedPtr::number(-42));
+            assert!(render(*number) == "-42");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_concat_too_many_arguments() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // as many arguments as fit in the registers following the result
+            let expr = format!("(concat{})", " 'a".repeat(200));
+            let result = eval_helper(mem, t, &expr)?;
+            match *result {
+                Value::Text(text) => assert!(text.as_str(mem) == "a".repeat(200)),
+                _ => panic!("expected Text"),
+            }
+
+            // more arguments than there are registers is an error rather than an overflow
+            let expr = format!("(concat{})", " 'a".repeat(254));
+            match eval_helper(mem, t, &expr) {
+                Ok(_) => panic!("concat of 254 arguments should be an error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_cons_star() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // the leading arguments are consed onto the last, in order
+            let result = eval_helper(mem, t, "(cons* 'a 'b '(c d))")?;
+            assert!(render(*result) == "(a b c d)");
+
+            // a single argument is returned as-is
+            let result = eval_helper(mem, t, "(cons* '(a b))")?;
+            assert!(render(*result) == "(a b)");
+            let result = eval_helper(mem, t, "(cons* 'a)")?;
+            assert!(render(*result) == "a");
+
+            // a non-list tail makes an improper list
+            let result = eval_helper(mem, t, "(cons* 'a 'b 'c)")?;
+            assert!(render(*result) == "(a b . c)");
+
+            // arguments may be arbitrary expressions, including locals
+            let result = eval_helper(
+                mem,
+                t,
+                "(let ((x 'a) (y '(c))) (cons* x (car (cons 'b nil)) y))",
+            )?;
+            assert!(render(*result) == "(a b c)");
+
+            assert!(eval_helper(mem, t, "(cons*)").is_err());
+
+            // more arguments than there are registers is an error rather than an overflow
+            let expr = format!("(cons*{} nil)", " 'a".repeat(254));
+            match eval_helper(mem, t, &expr) {
+                Ok(_) => panic!("cons* of 255 arguments should be an error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+            let expr = format!("(cons*{} nil)", " 'a".repeat(200));
+            let result = eval_helper(mem, t, &expr)?;
+            assert!(render(*result) == format!("({})", vec!["a"; 200].join(" ")));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_car_cdr_lenient() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // the first and rest of the empty list are both nil
+            assert!(eval_helper(mem, t, "(car nil)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(cdr nil)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(car (cdr '(a)))")? == mem.nil());
+
+            match eval_helper(mem, t, "(car 'a)") {
+                Ok(_) => panic!("car of a symbol should be an error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_car_cdr_strict() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, "(strict-car '(a b))")?;
+            assert!(result == mem.lookup_sym("a"));
+            let result = eval_helper(mem, t, "(strict-cdr '(a b))")?;
+            assert!(render(*result) == "(b)");
+            let result = eval_helper(mem, t, "(strict-cdr '(a))")?;
+            assert!(result == mem.nil());
+
+            for code in &[
+                "(strict-car nil)",
+                "(strict-cdr nil)",
+                "(strict-car (cdr '(a)))",
+                "(strict-car 'a)",
+                "(strict-cdr \"ab\")",
+            ] {
+                match eval_helper(mem, t, code) {
+                    Ok(_) => panic!("{} should be an error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_concat_local_variables() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test passes local variables and a nested concat as arguments
+            let a_fn =
+                "(def greet (name) (let ((greeting \"hello \")) (concat greeting name (concat))))";
+            let query = "(greet 'world)";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, a_fn)?;
+
+            let result = eval_helper(mem, t, query)?;
+            match *result {
+                Value::Text(text) => assert!(text.as_str(mem) == "hello world"),
+                _ => panic!("expected Text"),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_with_lambda_with_nested_call() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // this test compiles a let containing a lambda that is referenced in a sub-let scope
+            let f = "(let ((f (\\ (a) a))) (let ((g (f 'b))) g))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, f)?;
+            assert!(result == mem.lookup_sym("b"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_star_sequential_bindings() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // each binding refers to the one before it
+            let code = "(let* ((a 'x) (b (cons a nil)) (c (cons a b))) c)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+            assert!(format!("{}", result) == "(x x)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_star_shadowing() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a later binding of the same name sees the earlier one
+            let code = "(let* ((x 'a) (x (cons x nil))) x)";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+            assert!(format!("{}", result) == "(a)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_star_vs_let_outer_binding() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // `y` is bound before `x` is rebound, so it should refer to the outer `x`. A plain
+            // `let` binds all names before evaluating any expression, so `y` sees the inner `x`
+            // before it has been assigned a value.
+            let let_star = "(let ((x 'outer)) (let* ((y x) (x 'inner)) (cons y x)))";
+            let let_plain = "(let ((x 'outer)) (let ((y x) (x 'inner)) (cons y x)))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, let_star)?;
+            assert!(format!("{}", result) == "(outer . inner)");
+
+            let result = eval_helper(mem, t, let_plain)?;
+            assert!(format!("{}", result) != "(outer . inner)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_values_from_function() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(def split (p) (values (car p) (cdr p)))")?;
+
+            let result = eval_helper(
+                mem,
+                t,
+                "(let-values (((a b) (split '(x . y))) ((c) 'z)) (list b a c))",
+            )?;
+            assert!(render(*result) == "(y x z)");
+
+            // a returned bundle is a single value until it is taken apart
+            let result = eval_helper(mem, t, "(split '(x . y))")?;
+            assert!(render(*result) == "(values x y)");
+            let result = eval_helper(mem, t, "(values 'x)")?;
+            assert!(result == mem.lookup_sym("x"));
+
+            // the bindings may be closed over
+            let result = eval_helper(
+                mem,
+                t,
+                "(let-values (((a b) (split '(x . y)))) ((\\ () (cons b a))))",
+            )?;
+            assert!(render(*result) == "(y . x)");
+
+            // no values at all
+            let result = eval_helper(mem, t, "(let-values ((() (values))) 'done)")?;
+            assert!(result == mem.lookup_sym("done"));
+
+            for code in &[
+                "(let-values (((a b c) (split '(x . y)))) a)",
+                "(let-values (((a) (split '(x . y)))) a)",
+                "(let-values (((a b) 'x)) a)",
+                "(let-values (((a b) (values 'x))) a)",
+            ] {
+                match eval_helper(mem, t, code) {
+                    Ok(_) => panic!("{} should be an error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_values_literal_does_not_allocate() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let code = "(let-values (((a b) (values 'x 'y)) ((c) 'z)) c)";
+            let function = compile(mem, parse(mem, code)?)?;
+
+            let before = mem.allocation_count();
+            let result = t.quick_vm_eval(mem, function)?;
+            assert!(mem.allocation_count() == before);
+            assert!(result == mem.lookup_sym("z"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_defstruct() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, "(defstruct point x y)")?;
+            assert!(result == mem.lookup_sym("point"));
+
+            eval_helper(mem, t, "(set 'p (make-point 'a 'b))")?;
+            assert!(render(*eval_helper(mem, t, "p")?) == "(Record point a b)");
+            assert!(eval_helper(mem, t, "(point-x p)")? == mem.lookup_sym("a"));
+            assert!(eval_helper(mem, t, "(point-y p)")? == mem.lookup_sym("b"));
+
+            assert!(eval_helper(mem, t, "(point? p)")? == mem.true_sym());
+            assert!(eval_helper(mem, t, "(point? 'p)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(point? (cons 'a 'b))")? == mem.nil());
+
+            // updating a field changes only that field, and is seen through every reference
+            let result = eval_helper(mem, t, "(set-point-x! p 'c)")?;
+            assert!(result == mem.lookup_sym("c"));
+            assert!(eval_helper(mem, t, "(point-x p)")? == mem.lookup_sym("c"));
+            assert!(eval_helper(mem, t, "(point-y p)")? == mem.lookup_sym("b"));
+            let result = eval_helper(mem, t, "(let ((q p)) (set-point-y! q 'd) (point-y p))")?;
+            assert!(result == mem.lookup_sym("d"));
+
+            // the generated functions are ordinary functions
+            let result = eval_helper(
+                mem,
+                t,
+                "(let ((get-x point-x)) (get-x (make-point (list 'e) nil)))",
+            )?;
+            assert!(render(*result) == "(e)");
+
+            // a type with the same fields is a different type
+            eval_helper(mem, t, "(defstruct vector x y)")?;
+            assert!(eval_helper(mem, t, "(point? (make-vector 'a 'b))")? == mem.nil());
+            assert!(eval_helper(mem, t, "(vector? (make-vector 'a 'b))")? == mem.true_sym());
+
+            for code in &[
+                "(point-x (make-vector 'a 'b))",
+                "(set-point-y! (make-vector 'a 'b) 'c)",
+                "(point-x 'p)",
+            ] {
+                match eval_helper(mem, t, code) {
+                    Ok(_) => panic!("{} should be a type error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                }
+            }
+
+            // as with any function, too few arguments make a Partial
+            let result = eval_helper(mem, t, "(point-y ((make-point 'a) 'b))")?;
+            assert!(result == mem.lookup_sym("b"));
+
+            // a type may have no fields
+            eval_helper(mem, t, "(defstruct unit)")?;
+            assert!(eval_helper(mem, t, "(unit? (make-unit))")? == mem.true_sym());
+
+            assert!(eval_helper(mem, t, "(defstruct)").is_err());
+            assert!(eval_helper(mem, t, "(defstruct point \"x\")").is_err());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_let_star_with_closure() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a lambda bound in let* closes over an earlier binding
+            let code = "(let* ((a 'y) (f (\\ () a))) (f))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+            assert!(result == mem.lookup_sym("y"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_case_pair_pattern_bindings() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(case (cons 'a 'b) ((x . y) (cons y x)) (_ 'none))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, code)?;
+            assert!(format!("{}", result) == "(b . a)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_case_fall_through() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let default = "(case 'atom ((x . y) x) (_ 'default))";
+            let no_match = "(case 'atom ((x . y) x) (nil 'empty))";
+
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, default)?;
+            assert!(result == mem.lookup_sym("default"));
+
+            let result = eval_helper(mem, t, no_match)?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_case_literal_and_list_patterns() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let def_fn = "(def classify (x)
+                            (case x
+                              (nil 'empty)
+                              (('foo . _) 'foo)
+                              ((a b) b)
+                              ((a . rest) rest)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, def_fn)?;
+
+            let result = eval_helper(mem, t, "(classify nil)")?;
+            assert!(result == mem.lookup_sym("empty"));
+
+            let result = eval_helper(mem, t, "(classify '(foo bar))")?;
+            assert!(result == mem.lookup_sym("foo"));
+
+            let result = eval_helper(mem, t, "(classify '(p q))")?;
+            assert!(result == mem.lookup_sym("q"));
+
+            let result = eval_helper(mem, t, "(classify '(p q r))")?;
+            assert!(format!("{}", result) == "(q r)");
+
+            let result = eval_helper(mem, t, "(classify 'x)")?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_over_apply_partial() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // `p` is a Partial awaiting one argument, which is given two. The surplus argument is
+            // applied to the function returned.
+            let def_fn = "(def f (a b) (\\ (c) (cons a (cons b c))))";
+            let over_apply = "(let ((p (f 'x))) (p 'y 'z))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, def_fn)?;
+            let result = eval_helper(mem, t, over_apply)?;
+            assert!(format!("{}", result) == "(x y . z)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_over_apply_partial_to_partial() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // the surplus argument is too few for the returned function, giving another Partial
+            let def_fn = "(def f (a b) (\\ (c d) (cons a (cons b (cons c d)))))";
+            let over_apply = "(let ((p (f 'w))) (let ((q (p 'x 'y))) (q 'z)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, def_fn)?;
+            let result = eval_helper(mem, t, over_apply)?;
+            assert!(format!("{}", result) == "(w x y . z)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    /// Compile the code and return the names of the opcodes in the resulting function
+    fn opcode_names(mem: &MutatorView, code: &str) -> Result<Vec<String>, RuntimeError> {
+        ast_opcode_names(mem, parse(mem, code)?)
+    }
+
+    /// Compile the AST and return the names of the opcodes in the resulting function
+    fn ast_opcode_names<'guard>(
+        mem: &'guard MutatorView,
+        ast: TaggedScopedPtr<'guard>,
+    ) -> Result<Vec<String>, RuntimeError> {
+        let function = compile(mem, ast)?;
+        let listing = format!("{}", function.code(mem));
+        Ok(listing
+            .lines()
+            .map(|line| line.split(' ').next().unwrap_or("").to_string())
+            .collect())
+    }
+
+    #[test]
+    fn compile_call_in_tail_position() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // the same call in tail position and in non-tail position
+            let tail = opcode_names(mem, "(f 'a)")?;
+            let non_tail = opcode_names(mem, "(cons (f 'a) nil)")?;
+
+            assert!(tail.contains(&String::from("TailCall")));
+            assert!(!tail.contains(&String::from("Call")));
+
+            assert!(non_tail.contains(&String::from("Call")));
+            assert!(!non_tail.contains(&String::from("TailCall")));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_tail_position_through_cond_and_let() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a cond expression inherits tail position, but its condition does not
+            let cond_expr = opcode_names(mem, "(cond (nil? nil) (f 'a))")?;
+            assert!(cond_expr.contains(&String::from("TailCall")));
+
+            let cond_test = opcode_names(mem, "(cond (f 'a) 'b)")?;
+            assert!(cond_test.contains(&String::from("Call")));
+            assert!(!cond_test.contains(&String::from("TailCall")));
+
+            // the last let body expression inherits tail position, binding expressions do not
+            let let_body = opcode_names(mem, "(let ((x 'a)) (f x))")?;
+            assert!(let_body.contains(&String::from("TailCall")));
+
+            let let_binding = opcode_names(mem, "(let ((x (f 'a))) x)")?;
+            assert!(let_binding.contains(&String::from("Call")));
+            assert!(!let_binding.contains(&String::from("TailCall")));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_no_tail_call_before_closing_upvalues() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a let binding captured by a closure in tail position is closed after the call
+            // returns, so the call can't be a tail call
+            for code in &[
+                "(let ((x 'a)) (f (\\ () x)))",
+                "(let* ((x 'a) (y x)) (f (\\ () y)))",
+                "(let ((x 'a)) (cond (nil? x) (f 'b) true (f (\\ () x))))",
+                "(let-values (((x y) (values 'a 'b))) (f (\\ () y)))",
+                "(case 'a (x (f (\\ () x))))",
+            ] {
+                let opcodes = opcode_names(mem, code)?;
+                assert!(opcodes.contains(&String::from("CloseUpvalues")), "{}", code);
+                assert!(opcodes.contains(&String::from("Call")), "{}", code);
+                assert!(!opcodes.contains(&String::from("TailCall")), "{}", code);
+            }
+
+            // a binding that is not captured does not stop a tail call
+            let opcodes = opcode_names(mem, "(let ((x 'a)) (g) (f (\\ (x) x)))")?;
+            assert!(opcodes.contains(&String::from("TailCall")));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_small_integer_inline() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let small = TaggedScopedPtr::new(mem, TaggedPtr::number(-1234));
+            let opcodes = ast_opcode_names(mem, small)?;
+            assert!(opcodes.contains(&String::from("LoadInteger")));
+            assert!(!opcodes.contains(&String::from("LoadLiteral")));
+
+            // an integer outside of the i16 range must still go through the literals list
+            let large = TaggedScopedPtr::new(mem, TaggedPtr::number(40000));
+            let opcodes = ast_opcode_names(mem, large)?;
+            assert!(!opcodes.contains(&String::from("LoadInteger")));
+            assert!(opcodes.contains(&String::from("LoadLiteral")));
+
+            // both evaluate to the same number
+            let t = Thread::alloc(mem)?;
+            let result = t.quick_vm_eval(mem, compile(mem, small)?)?;
+            assert!(result.as_number()? == -1234);
+            let result = t.quick_vm_eval(mem, compile(mem, large)?)?;
+            assert!(result.as_number()? == 40000);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    /// There are no integer literals in the language yet, so substitute the count into the
+    /// parsed `(dotimes (<name> <count>) ...)` form
+    fn eval_dotimes_helper<'guard>(
+        mem: &'guard MutatorView,
+        thread: ScopedPtr<'guard, Thread>,
+        code: &str,
+        count: isize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let ast = parse(mem, code)?;
+        if let Value::Pair(form) = *ast {
+            if let Value::Pair(args) = *form.second.get(mem) {
+                if let Value::Pair(binding) = *args.first.get(mem) {
+                    if let Value::Pair(count_pair) = *binding.second.get(mem) {
+                        count_pair.first.set_to_ptr(TaggedPtr::number(count));
+                    }
+                }
+            }
+        }
+        let compiled_code = compile(mem, ast)?;
+        thread.quick_vm_eval(mem, compiled_code)
+    }
+
+    #[test]
+    fn compile_dotimes_counter_values() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(dotimes (i n) (set 'acc (cons i acc)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(set 'acc nil)")?;
+            let result = eval_dotimes_helper(mem, t, code, 4)?;
+            assert!(result == mem.nil());
+
+            let acc = eval_helper(mem, t, "acc")?;
+            assert!(format!("{}", acc) == "(3 2 1 0)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_dotimes_iteration_count() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(dotimes (i n) (set 'acc (cons 'x acc)) (set 'last i))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(set 'acc nil)")?;
+            eval_dotimes_helper(mem, t, code, 5)?;
+
+            let acc = eval_helper(mem, t, "acc")?;
+            assert!(format!("{}", acc) == "(x x x x x)");
+
+            let last = eval_helper(mem, t, "last")?;
+            assert!(format!("{}", last) == "4");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_dotimes_long_running() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // enough iterations to run across many evaluation slices without restarting
+            let code = "(dotimes (i n) (set 'last (cond (nil? i) 'a (is? i 'x) 'b (atom? i) i)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_dotimes_helper(mem, t, code, 1000)?;
+
+            let last = eval_helper(mem, t, "last")?;
+            assert!(format!("{}", last) == "999");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_dotimes_zero_iterations() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(dotimes (i n) (set 'acc (cons i acc)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(set 'acc 'untouched)")?;
+            let result = eval_dotimes_helper(mem, t, code, 0)?;
+            assert!(result == mem.nil());
+
+            let acc = eval_helper(mem, t, "acc")?;
+            assert!(acc == mem.lookup_sym("untouched"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    // Replace each symbol named in `substitutes` in the parsed code with the given value, to
+    // inject values that have no literal syntax
+    fn eval_substituted_helper<'guard>(
+        mem: &'guard MutatorView,
+        thread: ScopedPtr<'guard, Thread>,
+        code: &str,
+        substitutes: &[(&str, TaggedScopedPtr<'guard>)],
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        fn substitute<'guard>(
+            mem: &'guard MutatorView,
+            node: TaggedScopedPtr<'guard>,
+            substitutes: &[(&str, TaggedScopedPtr<'guard>)],
+        ) {
+            if let Value::Pair(p) = *node {
+                for cell in &[&p.first, &p.second] {
+                    let value = cell.get(mem);
+                    match *value {
+                        Value::Symbol(s) => {
+                            for (name, replacement) in substitutes {
+                                if s.as_str(mem) == *name {
+                                    cell.set(*replacement);
+                                }
+                            }
+                        }
+                        _ => substitute(mem, value, substitutes),
+                    }
+                }
+            }
+        }
+
+        let ast = parse(mem, code)?;
+        substitute(mem, ast, substitutes);
+        let compiled_code = compile(mem, ast)?;
+        thread.quick_vm_eval(mem, compiled_code)
+    }
+
+    fn list_of_syms<'guard>(
+        mem: &'guard MutatorView,
+        names: &[&str],
+    ) -> Result<ScopedPtr<'guard, List>, RuntimeError> {
+        let syms: Vec<TaggedScopedPtr> = names.iter().map(|name| mem.lookup_sym(name)).collect();
+        List::from_slice(mem, &syms)
+    }
+
+    fn list_sym_names<'guard>(mem: &'guard MutatorView, list: ScopedPtr<'guard, List>) -> String {
+        let names: Vec<String> = (0..list.length())
+            .map(|index| render(*IndexedAnyContainer::get(&*list, mem, index).unwrap()))
+            .collect();
+        names.join(" ")
+    }
+
+    #[test]
+    fn compile_list_insert() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let lst = list_of_syms(mem, &["a", "c"])?;
+            let substitutes = [
+                ("lst", lst.as_tagged(mem)),
+                ("zero", TaggedScopedPtr::new(mem, TaggedPtr::number(0))),
+                ("one", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+                ("three", TaggedScopedPtr::new(mem, TaggedPtr::number(3))),
+            ];
+
+            // middle
+            let result = eval_substituted_helper(mem, t, "(insert lst one 'b)", &substitutes)?;
+            assert!(list_sym_names(mem, result.as_list()?) == "a b c");
+
+            // start
+            eval_substituted_helper(mem, t, "(insert lst zero 'start)", &substitutes)?;
+            assert!(list_sym_names(mem, lst) == "start a b c");
+
+            // end
+            eval_substituted_helper(mem, t, "(insert lst three 'end)", &substitutes)?;
+            assert!(list_sym_names(mem, lst) == "start a b end c");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_list_remove() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let lst = list_of_syms(mem, &["a", "b", "c", "d"])?;
+            let substitutes = [
+                ("lst", lst.as_tagged(mem)),
+                ("zero", TaggedScopedPtr::new(mem, TaggedPtr::number(0))),
+                ("one", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+                ("two", TaggedScopedPtr::new(mem, TaggedPtr::number(2))),
+            ];
+
+            // middle
+            let result = eval_substituted_helper(mem, t, "(remove lst one)", &substitutes)?;
+            assert!(result == mem.lookup_sym("b"));
+            assert!(list_sym_names(mem, lst) == "a c d");
+
+            // end
+            let result = eval_substituted_helper(mem, t, "(remove lst two)", &substitutes)?;
+            assert!(result == mem.lookup_sym("d"));
+
+            // start
+            let result = eval_substituted_helper(mem, t, "(remove lst zero)", &substitutes)?;
+            assert!(result == mem.lookup_sym("a"));
+            assert!(list_sym_names(mem, lst) == "c");
+            assert!(lst.length() == 1);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_list_insert_remove_bounds() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let lst = list_of_syms(mem, &["a", "b"])?;
+            let substitutes = [
+                ("lst", lst.as_tagged(mem)),
+                ("two", TaggedScopedPtr::new(mem, TaggedPtr::number(2))),
+                ("three", TaggedScopedPtr::new(mem, TaggedPtr::number(3))),
+                ("minus", TaggedScopedPtr::new(mem, TaggedPtr::number(-1))),
+            ];
+
+            for code in &[
+                "(insert lst three 'x)",
+                "(insert lst minus 'x)",
+                "(remove lst two)",
+                "(remove lst minus)",
+            ] {
+                match eval_substituted_helper(mem, t, code, &substitutes) {
+                    Ok(_) => panic!("{} should have been out of bounds", code),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BoundsError),
+                }
+            }
+
+            // not a list
+            match eval_substituted_helper(mem, t, "(remove 'a two)", &substitutes) {
+                Ok(_) => panic!("Remove from a symbol should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            assert!(list_sym_names(mem, lst) == "a b");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_env_keys() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let result = eval_helper(mem, t, "(env-keys)")?;
+            assert!(result == mem.nil());
+
+            eval_helper(mem, t, "(set 'b 'x)")?;
+            eval_helper(mem, t, "(def c (x) x)")?;
+            eval_helper(mem, t, "(set 'a nil)")?;
+
+            let result = eval_helper(mem, t, "(env-keys)")?;
+            assert!(format!("{}", result) == "(a b c)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_bound() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(set 'defined 'x)")?;
+            eval_helper(mem, t, "(set 'defined-nil nil)")?;
+
+            let result = eval_helper(mem, t, "(bound? 'defined)")?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, "(bound? 'defined-nil)")?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, "(bound? 'undefined)")?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_fn_params_and_name() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(def pick (a b c) b)")?;
+
+            let result = eval_helper(mem, t, "(fn-params pick)")?;
+            assert!(format!("{}", result) == "(a b c)");
+
+            let result = eval_helper(mem, t, "(fn-name pick)")?;
+            assert!(result == mem.lookup_sym("pick"));
+
+            // a Partial lists only the parameters still to be applied
+            eval_helper(mem, t, "(set 'picked (pick 'x))")?;
+
+            let result = eval_helper(mem, t, "(fn-params picked)")?;
+            assert!(format!("{}", result) == "(b c)");
+
+            let result = eval_helper(mem, t, "(fn-name picked)")?;
+            assert!(result == mem.lookup_sym("pick"));
+
+            // anonymous functions have no name
+            let result = eval_helper(mem, t, "(fn-name (lambda (x) x))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(fn-params (lambda () nil))")?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_fn_params_type_error() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            for code in &["(fn-params 'a)", "(fn-name nil)"] {
+                match eval_helper(mem, t, code) {
+                    Ok(_) => panic!("{} should be a type error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_arity() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, "(def pick (a b c) b)")?;
+
+            let result = eval_helper(mem, t, "(arity pick)")?;
+            assert!(result.as_number()? == 3);
+
+            // the remaining arity decreases as arguments are applied
+            eval_helper(mem, t, "(set 'picked (pick 'x))")?;
+            let result = eval_helper(mem, t, "(arity picked)")?;
+            assert!(result.as_number()? == 2);
+
+            eval_helper(mem, t, "(set 'picked (picked 'y))")?;
+            let result = eval_helper(mem, t, "(arity picked)")?;
+            assert!(result.as_number()? == 1);
+
+            // applying no arguments leaves the arity unchanged
+            let result = eval_helper(mem, t, "(arity (picked))")?;
+            assert!(result.as_number()? == 1);
+
+            let result = eval_helper(mem, t, "(arity (lambda () nil))")?;
+            assert!(result.as_number()? == 0);
+
+            match eval_helper(mem, t, "(arity 'pick)") {
+                Ok(_) => panic!("arity of a symbol should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_empty_predicate() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let true_sym = mem.lookup_sym("true");
+
+            let result = eval_helper(mem, t, "(empty? nil)")?;
+            assert!(result == true_sym);
+
+            let result = eval_helper(mem, t, "(empty? '(a))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(empty? (cdr '(a)))")?;
+            assert!(result == true_sym);
+
+            match eval_helper(mem, t, "(empty? 'a)") {
+                Ok(_) => panic!("empty? of a symbol should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_car_cdr_of_nil() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // nil is the empty list, so car and cdr of nil are both nil
+            let result = eval_helper(mem, t, "(car nil)")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(cdr nil)")?;
+            assert!(result == mem.nil());
+
+            // which means car alone can't tell a list starting with nil from the empty list
+            let result = eval_helper(mem, t, "(car '(nil))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(empty? '(nil))")?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_number_to_string() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                ("n", TaggedScopedPtr::new(mem, TaggedPtr::number(-255))),
+                ("zero", TaggedScopedPtr::new(mem, TaggedPtr::number(0))),
+                ("hex", TaggedScopedPtr::new(mem, TaggedPtr::number(16))),
+                ("one", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+            ];
+
+            let result = eval_substituted_helper(mem, t, "(number->string n)", &substitutes)?;
+            assert!(result.as_text_str()? == "-255");
+
+            let result = eval_substituted_helper(mem, t, "(number->string zero)", &substitutes)?;
+            assert!(result.as_text_str()? == "0");
+
+            let result = eval_substituted_helper(mem, t, "(number->string n hex)", &substitutes)?;
+            assert!(result.as_text_str()? == "-ff");
+
+            match eval_substituted_helper(mem, t, "(number->string n one)", &substitutes) {
+                Ok(_) => panic!("a radix of 1 should be an error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            match eval_helper(mem, t, "(number->string \"12\")") {
+                Ok(_) => panic!("number->string of Text should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_reuses_scratch_buffers() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            // a large function that unpacks hundreds of lists while compiling
+            let mut code = String::from("(def big (x y) (cond ");
+            for n in 0..300 {
+                code.push_str(&format!(
+                    "((is? x 'k{}) (let ((a (cons x y)) (b (car y))) (cons* a b (cdr y)))) ",
+                    n
+                ));
+            }
+            code.push_str("(true (when (nil? x) (concat 'a 'b)))))");
+            let ast = parse(mem, &code)?;
+
+            compile(mem, ast)?;
+            let created = mem.scratch_buffer_count();
+
+            // buffers are only needed for as many lists as are being unpacked at once
+            assert!(created > 0);
+            assert!(created < 10);
+
+            // and are reused by later compiles
+            compile(mem, ast)?;
+            compile(mem, ast)?;
+            assert!(mem.scratch_buffer_count() == created);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_numeric_builtins() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                ("a", TaggedScopedPtr::new(mem, TaggedPtr::number(3))),
+                ("b", TaggedScopedPtr::new(mem, TaggedPtr::number(-2))),
+                ("c", TaggedScopedPtr::new(mem, TaggedPtr::number(7))),
+                (
+                    "largest",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(INLINE_NUMBER_MAX)),
+                ),
+                (
+                    "smallest",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(INLINE_NUMBER_MIN)),
+                ),
+            ];
+            let eval = |code| -> Result<isize, RuntimeError> {
+                eval_substituted_helper(mem, t, code, &substitutes)?.as_number()
+            };
+
+            assert!(eval("(neg a)")? == -3);
+            assert!(eval("(neg b)")? == 2);
+            assert!(eval("(abs b)")? == 2);
+            assert!(eval("(abs a)")? == 3);
+            assert!(eval("(abs largest)")? == INLINE_NUMBER_MAX);
+            assert!(eval("(neg largest)")? == -INLINE_NUMBER_MAX);
+
+            // the absolute value of the smallest inline integer does not fit inline
+            for code in &["(abs smallest)", "(neg smallest)"] {
+                match eval(code) {
+                    Ok(_) => panic!("{} should overflow", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+                }
+            }
+
+            assert!(eval("(min a b c)")? == -2);
+            assert!(eval("(max a b c)")? == 7);
+            assert!(eval("(min c a)")? == 3);
+            assert!(eval("(max (neg c) b (abs b))")? == 2);
+            assert!(eval("(min a)")? == 3);
+            assert!(eval("(max smallest largest)")? == INLINE_NUMBER_MAX);
+
+            for code in &["(abs 'x)", "(neg \"12\")", "(min a b 'x)", "(max 'x)"] {
+                match eval(code) {
+                    Ok(_) => panic!("{} should be a type error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                }
+            }
+
+            match eval("(max)") {
+                Ok(_) => panic!("max requires an argument"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_random() {
+        /// Evaluate `code` `count` times, collecting the numbers it gives
+        fn draws<'guard>(
+            mem: &'guard MutatorView,
+            t: ScopedPtr<'guard, Thread>,
+            code: &str,
+            substitutes: &[(&str, TaggedScopedPtr<'guard>)],
+            count: usize,
+        ) -> Result<Vec<isize>, RuntimeError> {
+            let mut numbers = Vec::with_capacity(count);
+            for _ in 0..count {
+                numbers.push(eval_substituted_helper(mem, t, code, substitutes)?.as_number()?);
+            }
+            Ok(numbers)
+        }
+
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            let num = |n| TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+            let substitutes = [
+                ("seed", num(42)),
+                ("other-seed", num(43)),
+                ("ten", num(10)),
+                ("low", num(-5)),
+                ("high", num(5)),
+            ];
+
+            // the same seed gives the same sequence
+            let result = eval_substituted_helper(mem, t, "(seed! seed)", &substitutes)?;
+            assert!(result == mem.nil());
+            let first = draws(mem, t, "(random ten)", &substitutes, 200)?;
+
+            eval_substituted_helper(mem, t, "(seed! seed)", &substitutes)?;
+            let second = draws(mem, t, "(random ten)", &substitutes, 200)?;
+            assert!(first == second);
+
+            eval_substituted_helper(mem, t, "(seed! other-seed)", &substitutes)?;
+            let other = draws(mem, t, "(random ten)", &substitutes, 200)?;
+            assert!(first != other);
+
+            // every value is in the range, and every value in the range turns up
+            for n in 0..10 {
+                assert!(first.contains(&n));
+            }
+            assert!(first.iter().all(|n| (0..10).contains(n)));
+
+            let ranged = draws(mem, t, "(random low high)", &substitutes, 200)?;
+            for n in -5..5 {
+                assert!(ranged.contains(&n));
+            }
+            assert!(ranged.iter().all(|n| (-5..5).contains(n)));
+
+            // an empty range, non-numbers and the wrong number of arguments are errors
+            assert!(eval_substituted_helper(mem, t, "(random high low)", &substitutes).is_err());
+            assert!(eval_substituted_helper(mem, t, "(random ten ten)", &substitutes).is_err());
+            assert!(eval_helper(mem, t, "(random 'a)").is_err());
+            assert!(eval_helper(mem, t, "(random)").is_err());
+            assert!(eval_helper(mem, t, "(seed! 'a)").is_err());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_integer_division() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let number = |n| TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+            let substitutes = [
+                ("seven", number(7)),
+                ("minus-seven", number(-7)),
+                ("two", number(2)),
+                ("minus-two", number(-2)),
+                ("six", number(6)),
+                ("zero", number(0)),
+            ];
+            let eval = |code: &str| -> Result<isize, RuntimeError> {
+                eval_substituted_helper(mem, t, code, &substitutes)?.as_number()
+            };
+
+            // (numerator, denominator, quotient, remainder, floor-div, modulo)
+            let cases = [
+                ("seven", "two", 3, 1, 3, 1),
+                ("minus-seven", "two", -3, -1, -4, 1),
+                ("seven", "minus-two", -3, 1, -4, -1),
+                ("minus-seven", "minus-two", 3, -1, 3, -1),
+                ("six", "minus-two", -3, 0, -3, 0),
+                ("zero", "minus-two", 0, 0, 0, 0),
+            ];
+
+            for (num, denom, quotient, remainder, floor_div, modulo) in cases.iter() {
+                let div = eval(&format!("(quotient {} {})", num, denom))?;
+                let rem = eval(&format!("(remainder {} {})", num, denom))?;
+                let fdiv = eval(&format!("(floor-div {} {})", num, denom))?;
+                let modu = eval(&format!("(modulo {} {})", num, denom))?;
+
+                assert!(div == *quotient, "(quotient {} {}) == {}", num, denom, div);
+                assert!(
+                    rem == *remainder,
+                    "(remainder {} {}) == {}",
+                    num,
+                    denom,
+                    rem
+                );
+                assert!(
+                    fdiv == *floor_div,
+                    "(floor-div {} {}) == {}",
+                    num,
+                    denom,
+                    fdiv
+                );
+                assert!(modu == *modulo, "(modulo {} {}) == {}", num, denom, modu);
+            }
+
+            // each division and its remainder reconstruct the numerator
+            assert!(
+                eval("(+ (* (quotient minus-seven two) two) (remainder minus-seven two))")? == -7
+            );
+            assert!(
+                eval("(+ (* (floor-div minus-seven two) two) (modulo minus-seven two))")? == -7
+            );
+
+            for code in &[
+                "(quotient seven zero)",
+                "(floor-div seven zero)",
+                "(remainder seven zero)",
+                "(modulo seven zero)",
+            ] {
+                match eval(code) {
+                    Ok(_) => panic!("{} should be a division by zero error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+                }
+            }
+
+            match eval("(quotient seven 'x)") {
+                Ok(_) => panic!("quotient of a symbol should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_fold_reuses_registers() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let listing = |code: &str| -> Result<Vec<String>, RuntimeError> {
+                let function = compile(mem, parse(mem, code)?)?;
+                let listing = format!("{}", function.code(mem));
+                Ok(listing.lines().map(String::from).collect())
+            };
+
+            // every step of the chain adds the same argument register into the same accumulator
+            let code = listing("(+ a b c d e f)")?;
+            let adds: Vec<&String> = code.iter().filter(|op| op.starts_with("Add")).collect();
+            assert!(adds.len() == 5);
+            assert!(adds.iter().all(|op| *op == adds[0]));
+
+            let code = listing("(* a (+ b c) d)")?;
+            let muls: Vec<&String> = code
+                .iter()
+                .filter(|op| op.starts_with("Multiply"))
+                .collect();
+            assert!(muls.len() == 2);
+            assert!(muls[0] == muls[1]);
+
+            // no arguments is just the identity
+            assert!(listing("(+)")?[0] == "LoadInteger { dest: 2, integer: 0 }");
+            assert!(listing("(*)")?[0] == "LoadInteger { dest: 2, integer: 1 }");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_add_multiply() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                ("one", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+                ("two", TaggedScopedPtr::new(mem, TaggedPtr::number(2))),
+                ("three", TaggedScopedPtr::new(mem, TaggedPtr::number(3))),
+                ("four", TaggedScopedPtr::new(mem, TaggedPtr::number(4))),
+                (
+                    "minus-five",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(-5)),
+                ),
+                (
+                    "largest",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(INLINE_NUMBER_MAX)),
+                ),
+            ];
+            let eval = |code| -> Result<isize, RuntimeError> {
+                eval_substituted_helper(mem, t, code, &substitutes)?.as_number()
+            };
+
+            assert!(eval("(+ one two three four)")? == 10);
+            assert!(eval("(* one two three four)")? == 24);
+            assert!(eval("(+ (* two three) minus-five (+ four))")? == 5);
+            assert!(eval("(+)")? == 0);
+            assert!(eval("(*)")? == 1);
+            assert!(eval("(+ three)")? == 3);
+            assert!(eval("(* minus-five)")? == -5);
+
+            // arguments that are local variables
+            eval_helper(mem, t, "(def sum-product (a b c) (+ a (* b c) a))")?;
+            assert!(eval("(sum-product one two three)")? == 8);
+
+            // results must fit in an inline integer
+            for code in &["(+ largest one)", "(* largest two)"] {
+                match eval(code) {
+                    Ok(_) => panic!("{} should overflow", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+                }
+            }
+
+            for code in &["(+ 'x)", "(* one 'x)", "(+ one nil two)"] {
+                match eval(code) {
+                    Ok(_) => panic!("{} should be a type error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_numeric_predicates() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                ("zero", TaggedScopedPtr::new(mem, TaggedPtr::number(0))),
+                ("one", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+                ("two", TaggedScopedPtr::new(mem, TaggedPtr::number(2))),
+                (
+                    "minus-one",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(-1)),
+                ),
+                (
+                    "minus-four",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(-4)),
+                ),
+                (
+                    "smallest",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(INLINE_NUMBER_MIN)),
+                ),
+            ];
+
+            // each predicate with the values it should be true for, in the order above
+            let expected = [
+                ("zero?", [true, false, false, false, false, false]),
+                ("positive?", [false, true, true, false, false, false]),
+                ("negative?", [false, false, false, true, true, true]),
+                ("even?", [true, false, true, false, true, true]),
+                ("odd?", [false, true, false, true, false, false]),
+            ];
+
+            for (predicate, truths) in expected.iter() {
+                for ((name, _), truth) in substitutes.iter().zip(truths.iter()) {
+                    let code = format!("({} {})", predicate, name);
+                    let result = eval_substituted_helper(mem, t, &code, &substitutes)?;
+                    if *truth {
+                        assert!(result == mem.true_sym(), "{} should be true", code);
+                    } else {
+                        assert!(result == mem.nil(), "{} should be nil", code);
+                    }
+                }
+
+                for arg in &["'a", "\"1\"", "nil"] {
+                    let code = format!("({} {})", predicate, arg);
+                    match eval_helper(mem, t, &code) {
+                        Ok(_) => panic!("{} should be a type error", code),
+                        Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_char_conversions() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                (
+                    "lambda",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(0x3bb)),
+                ),
+                (
+                    "surrogate",
+                    TaggedScopedPtr::new(mem, TaggedPtr::number(0xd800)),
+                ),
+            ];
+
+            let result = eval_helper(mem, t, "(char->integer #\\a)")?;
+            assert!(result.as_number()? == 97);
+
+            let result = eval_helper(mem, t, "(char->integer #\\space)")?;
+            assert!(result.as_number()? == 32);
+
+            let result = eval_substituted_helper(mem, t, "(integer->char lambda)", &substitutes)?;
+            assert!(result.as_char()? == 'λ');
+            assert!(format!("{}", result) == "#\\λ");
+
+            let result = eval_helper(mem, t, "(integer->char (char->integer #\\newline))")?;
+            assert!(format!("{}", result) == "#\\newline");
+
+            let result = eval_helper(mem, t, "(equal? #\\x (integer->char (char->integer #\\x)))")?;
+            assert!(result == mem.true_sym());
+
+            let result = eval_helper(mem, t, "(concat \"a\" #\\space #\\b)")?;
+            assert!(result.as_text_str()? == "a b");
+
+            match eval_substituted_helper(mem, t, "(integer->char surrogate)", &substitutes) {
+                Ok(_) => panic!("a surrogate is not a valid character"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            match eval_helper(mem, t, "(char->integer \"a\")") {
+                Ok(_) => panic!("char->integer of Text should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_string_list_conversions() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // one Char per Unicode scalar value, not per byte
+            for text in &["hello, world", "héllo wörld λ→✓"] {
+                let code = format!("(string->list \"{}\")", text);
+                let chars = vec_from_pairs(mem, eval_helper(mem, t, &code)?)?;
+                assert!(chars.len() == text.chars().count());
+                for (value, c) in chars.iter().zip(text.chars()) {
+                    assert!(value.as_char()? == c);
+                }
+
+                let code = format!("(list->string (string->list \"{}\"))", text);
+                let result = eval_helper(mem, t, &code)?;
+                assert!(result.as_text_str()? == *text);
+            }
+
+            let result = eval_helper(mem, t, "(string->list \"\")")?;
+            assert!(result == mem.nil());
+            let result = eval_helper(mem, t, "(list->string nil)")?;
+            assert!(result.as_text_str()? == "");
+
+            let result = eval_helper(mem, t, "(list->string (list #\\a #\\λ #\\space))")?;
+            assert!(result.as_text_str()? == "aλ ");
+
+            for code in &[
+                "(list->string (list #\\a 'b))",
+                "(list->string (list \"a\"))",
+                "(string->list 'abc)",
+            ] {
+                match eval_helper(mem, t, code) {
+                    Ok(_) => panic!("{} should be a type error", code),
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                }
+            }
+
+            match eval_helper(mem, t, "(list->string #\\a)") {
+                Ok(_) => panic!("list->string of a Char should be an error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_string_to_number() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let substitutes = [
+                ("hex", TaggedScopedPtr::new(mem, TaggedPtr::number(16))),
+                ("n", TaggedScopedPtr::new(mem, TaggedPtr::number(1234))),
+            ];
+
+            let result = eval_helper(mem, t, "(string->number \"-42\")")?;
+            assert!(result.as_number()? == -42);
+
+            let result =
+                eval_substituted_helper(mem, t, "(string->number \"FF\" hex)", &substitutes)?;
+            assert!(result.as_number()? == 255);
+
+            // round trip through a non-decimal radix
+            let result = eval_substituted_helper(
+                mem,
+                t,
+                "(string->number (number->string n hex) hex)",
+                &substitutes,
+            )?;
+            assert!(result.as_number()? == 1234);
+
+            // invalid input parses to nil
+            let result = eval_helper(mem, t, "(string->number \"12ab\")")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(string->number \"\")")?;
+            assert!(result == mem.nil());
+
+            // a number too large to store inline is also invalid
+            let result = eval_helper(mem, t, "(string->number \"9223372036854775807\")")?;
+            assert!(result == mem.nil());
+
+            match eval_helper(mem, t, "(string->number 'a)") {
+                Ok(_) => panic!("string->number of a Symbol should be a type error"),
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_equal() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let true_sym = mem.lookup_sym("true");
+
+            let result = eval_helper(mem, t, "(equal? '(a (b c) \"d\") '(a (b c) \"d\"))")?;
+            assert!(result == true_sym);
+
+            // the same lists are not identical
+            let result = eval_helper(mem, t, "(is? '(a (b c)) '(a (b c)))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(equal? '(a (b c)) '(a (b d)))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(mem, t, "(equal? \"text\" \"text\")")?;
+            assert!(result == true_sym);
+
+            let result = eval_helper(mem, t, "(equal? 'text \"text\")")?;
+            assert!(result == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn compile_case_equal_literal_patterns() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let def_fn = "(def describe (x)
+                            (case x
+                              ('(1 2) 'quoted-list)
+                              (\"hello\" 'greeting)
+                              (_ 'other)))";
+
+            let t = Thread::alloc(mem)?;
+
+            eval_helper(mem, t, def_fn)?;
+
+            // quoted list and Text patterns match structurally equal values
+            let result = eval_helper(mem, t, "(describe '(1 2))")?;
+            assert!(result == mem.lookup_sym("quoted-list"));
+
+            let result = eval_helper(mem, t, "(describe (cons '1 '(2)))")?;
+            assert!(result == mem.lookup_sym("quoted-list"));
+
+            let result = eval_helper(mem, t, "(describe \"hello\")")?;
+            assert!(result == mem.lookup_sym("greeting"));
+
+            let result = eval_helper(mem, t, "(describe '(1 3))")?;
+            assert!(result == mem.lookup_sym("other"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
 
-/// Compile a function - parameters and expression, returning a tagged Function object
-fn compile_function<'guard, 'scope>(
-    mem: &'guard MutatorView,
-    parent: Option<&'scope Variables<'scope>>,
-    name: TaggedScopedPtr<'guard>,
-    params: &[TaggedScopedPtr<'guard>],
-    exprs: &[TaggedScopedPtr<'guard>],
-) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-    let compiler = Compiler::new(mem, parent)?;
-    Ok(compiler
-        .compile_function(mem, name, params, exprs)?
-        .as_tagged(mem))
-}
+    #[test]
+    fn compile_call_native_function() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let seven = TaggedScopedPtr::new(mem, TaggedPtr::number(7));
+            let substitutes = [("seven", seven)];
 
-/// Compile the given AST and return an anonymous Function object
-pub fn compile<'guard>(
-    mem: &'guard MutatorView,
-    ast: TaggedScopedPtr<'guard>,
-) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
-    let compiler = Compiler::new(mem, None)?;
-    compiler.compile_function(mem, mem.nil(), &[], &[ast])
-}
+            let result = eval_substituted_helper(mem, t, "(square seven)", &substitutes)?;
+            assert!(result == TaggedScopedPtr::new(mem, TaggedPtr::number(49)));
 
-/// INTEGRATION TESTS
-/// TODO - move to a separate module
-#[cfg(test)]
-mod integration {
-    use super::*;
-    use crate::memory::{Memory, Mutator};
-    use crate::parser::parse;
-    use crate::vm::Thread;
+            // a native function is a value like any other and can be passed to a lambda
+            let code = "((lambda (f x) (f (f x))) square seven)";
+            let result = eval_substituted_helper(mem, t, code, &substitutes)?;
+            assert!(result == TaggedScopedPtr::new(mem, TaggedPtr::number(2401)));
 
-    fn eval_helper<'guard>(
-        mem: &'guard MutatorView,
-        thread: ScopedPtr<'guard, Thread>,
-        code: &str,
-    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-        let compiled_code = compile(mem, parse(mem, code)?)?;
-        println!("RUN CODE {}", code);
-        let result = thread.quick_vm_eval(mem, compiled_code)?;
-        println!("RUN RESULT {}", result);
-        Ok(result)
-    }
+            let result = eval_helper(mem, t, "square")?;
+            assert!(format!("{}", result) == "(NativeFunction square)");
 
-    fn test_helper(test_fn: fn(&MutatorView) -> Result<(), RuntimeError>) {
-        let mem = Memory::new();
+            // arity is checked before the native function is called
+            assert!(eval_substituted_helper(mem, t, "(square seven seven)", &substitutes).is_err());
+
+            // an error returned by the native function is returned from evaluation
+            assert!(eval_helper(mem, t, "(square 'x)").is_err());
+
+            Ok(())
+        }
 
         struct Test {}
         impl Mutator for Test {
@@ -899,22 +4702,46 @@ mod integration {
             }
         }
 
-        let test = Test {};
-        mem.mutate(&test, test_fn).unwrap();
+        let mut mem = Memory::new();
+        mem.register_native("square", 1, |mem, args| {
+            let n = args[0].as_number()?;
+            Ok(TaggedScopedPtr::new(mem, TaggedPtr::number(n * n)))
+        });
+        mem.mutate(&Test {}, test_inner).unwrap();
     }
 
     #[test]
-    fn compile_cond_first_is_true() {
+    fn compile_text_builder() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // testing 'cond'
-            // (nil? nil) == true, so result should be x
-            let code = "(cond (nil? nil) 'x (nil? 'a) 'y)";
-
             let t = Thread::alloc(mem)?;
 
-            let result = eval_helper(mem, t, code)?;
-
-            assert!(result == mem.lookup_sym("x"));
+            eval_helper(mem, t, "(set 'sb (sb-new))")?;
+            let result = eval_helper(mem, t, "(sb-finish sb)")?;
+            assert!(result.as_text_str()?.is_empty());
+
+            // anything that can be rendered can be appended, as with concat
+            let result = eval_helper(mem, t, "(sb-finish (sb-append (sb-append sb 'a) \"b\"))")?;
+            assert!(result.as_text_str()? == "ab");
+
+            // build a long string incrementally and compare it with naive concatenation
+            eval_helper(mem, t, "(set 'sb (sb-new))")?;
+            eval_helper(mem, t, "(set 'naive \"\")")?;
+            eval_dotimes_helper(mem, t, "(dotimes (i n) (sb-append sb 'xyz))", 1000)?;
+            eval_dotimes_helper(
+                mem,
+                t,
+                "(dotimes (i n) (set 'naive (concat naive 'xyz)))",
+                1000,
+            )?;
+
+            let built = eval_helper(mem, t, "(sb-finish sb)")?;
+            let naive = eval_helper(mem, t, "naive")?;
+            assert!(built.as_text_str()? == "xyz".repeat(1000));
+            assert!(built.as_text_str()? == naive.as_text_str()?);
+
+            assert!(eval_helper(mem, t, "(sb-new 'a)").is_err());
+            assert!(eval_helper(mem, t, "(sb-append 'a 'b)").is_err());
+            assert!(eval_helper(mem, t, "(sb-finish \"a\")").is_err());
 
             Ok(())
         }
@@ -923,17 +4750,38 @@ mod integration {
     }
 
     #[test]
-    fn compile_cond_second_is_true() {
+    fn compile_list_builder() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // testing 'cond'
-            // (nil? 'a) == nil, (nil? nil) == true, so result should be y
-            let code = "(cond (nil? 'a) 'x (nil? nil) 'y)";
-
             let t = Thread::alloc(mem)?;
 
-            let result = eval_helper(mem, t, code)?;
+            let result = eval_helper(mem, t, "(list)")?;
+            assert!(result == mem.nil());
 
-            assert!(result == mem.lookup_sym("y"));
+            let result = eval_helper(mem, t, "(list 'a (car '(b)) (list 'c))")?;
+            let items = vec_from_pairs(mem, result)?;
+            assert!(items.len() == 3);
+            assert!(items[0] == mem.lookup_sym("a"));
+            assert!(items[1] == mem.lookup_sym("b"));
+            assert!(vec_from_pairs(mem, items[2])? == vec![mem.lookup_sym("c")]);
+
+            // build a long list in order by appending to its end
+            eval_helper(mem, t, "(set 'lb (lb-new))")?;
+            eval_dotimes_helper(mem, t, "(dotimes (i n) (lb-append lb i))", 1000)?;
+            let built = eval_helper(mem, t, "(lb-finish lb)")?;
+            let items = vec_from_pairs(mem, built)?;
+            assert!(items.len() == 1000);
+            for (index, item) in items.iter().enumerate() {
+                assert!(item.as_number()? == index as isize);
+            }
+
+            // finishing empties the builder, so the finished list is not appended to
+            let result = eval_helper(mem, t, "(lb-finish (lb-append lb 'z))")?;
+            assert!(vec_from_pairs(mem, result)? == vec![mem.lookup_sym("z")]);
+            assert!(vec_from_pairs(mem, built)?.len() == 1000);
+
+            assert!(eval_helper(mem, t, "(lb-new 'a)").is_err());
+            assert!(eval_helper(mem, t, "(lb-append 'a 'b)").is_err());
+            assert!(eval_helper(mem, t, "(lb-finish (sb-new))").is_err());
 
             Ok(())
         }
@@ -942,17 +4790,30 @@ mod integration {
     }
 
     #[test]
-    fn compile_cond_none_is_true() {
+    fn compile_fn_size() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // testing 'cond'
-            // (nil? 'a) == nil, (nil? 'b) == nil, result should be nil
-            let code = "(cond (nil? 'a) 'x (nil? 'b) 'y)";
-
             let t = Thread::alloc(mem)?;
 
-            let result = eval_helper(mem, t, code)?;
+            // Return
+            eval_helper(mem, t, "(def pick (a b c) b)")?;
+            let result = eval_helper(mem, t, "(fn-size pick)")?;
+            assert!(format!("{}", result) == "(1 0)");
 
-            assert!(result == mem.nil());
+            // LoadLiteral, MakePair, Return
+            eval_helper(mem, t, "(def greet (name) (cons 'hello name))")?;
+            let result = eval_helper(mem, t, "(fn-size greet)")?;
+            assert!(format!("{}", result) == "(3 1)");
+
+            // a Partial has the size of its Function
+            let result = eval_helper(mem, t, "(fn-size (pick 'x))")?;
+            assert!(format!("{}", result) == "(1 0)");
+
+            // the counts are also in the disassembly header
+            let function = eval_helper(mem, t, "greet")?;
+            assert!(format!("{:?}", function)
+                .contains("bytecode follows (3 instructions, 1 literals):"));
+
+            assert!(eval_helper(mem, t, "(fn-size 'greet)").is_err());
 
             Ok(())
         }
@@ -961,24 +4822,32 @@ mod integration {
     }
 
     #[test]
-    fn compile_call_functions() {
+    fn compile_when() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test calls a function from another function
-            let compare_fn = "(def is_it (ask expect) (is? ask expect))";
-            let curried_fn = "(def is_it_a (ask) (is_it ask 'a))";
-            let query1 = "(is_it_a nil)";
-            let query2 = "(is_it_a 'a)";
-
             let t = Thread::alloc(mem)?;
 
-            eval_helper(mem, t, compare_fn)?;
-            eval_helper(mem, t, curried_fn)?;
+            eval_helper(mem, t, "(set 'ran nil)")?;
 
-            let result1 = eval_helper(mem, t, query1)?;
-            assert!(result1 == mem.nil());
+            // the body runs in order and the result is the last expression
+            let result = eval_helper(mem, t, "(when (nil? nil) (set 'ran 'yes) 'first 'last)")?;
+            assert!(result == mem.lookup_sym("last"));
+            assert!(eval_helper(mem, t, "ran")? == mem.lookup_sym("yes"));
 
-            let result2 = eval_helper(mem, t, query2)?;
-            assert!(result2 == mem.lookup_sym("true"));
+            // the body doesn't run and the result is nil
+            eval_helper(mem, t, "(set 'ran nil)")?;
+            let result = eval_helper(mem, t, "(when (nil? 'a) (set 'ran 'yes) 'last)")?;
+            assert!(result == mem.nil());
+            assert!(eval_helper(mem, t, "ran")? == mem.nil());
+
+            // an empty body gives nil either way
+            assert!(eval_helper(mem, t, "(when (nil? nil))")? == mem.nil());
+
+            // the body may refer to a local variable
+            eval_helper(mem, t, "(def first-of (x) (when (atom? x) x))")?;
+            assert!(eval_helper(mem, t, "(first-of 'a)")? == mem.lookup_sym("a"));
+            assert!(eval_helper(mem, t, "(first-of '(a))")? == mem.nil());
+
+            assert!(eval_helper(mem, t, "(when)").is_err());
 
             Ok(())
         }
@@ -987,26 +4856,30 @@ mod integration {
     }
 
     #[test]
-    fn compile_map_function_over_list() {
+    fn compile_unless() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test passes a function as a parameter through recursive function calls
-            let compare_fn = "(def is_y (ask) (is? ask 'y))";
-            let map_fn =
-                "(def map (f l) (cond (nil? l) nil true (cons (f (car l)) (map f (cdr l)))))";
+            let t = Thread::alloc(mem)?;
 
-            let query = "(map is_y '(x y z z y))";
+            eval_helper(mem, t, "(set 'ran nil)")?;
 
-            let t = Thread::alloc(mem)?;
+            let result = eval_helper(mem, t, "(unless (nil? 'a) (set 'ran 'yes) 'last)")?;
+            assert!(result == mem.lookup_sym("last"));
+            assert!(eval_helper(mem, t, "ran")? == mem.lookup_sym("yes"));
 
-            eval_helper(mem, t, compare_fn)?;
-            eval_helper(mem, t, map_fn)?;
+            eval_helper(mem, t, "(set 'ran nil)")?;
+            let result = eval_helper(mem, t, "(unless (nil? nil) (set 'ran 'yes) 'last)")?;
+            assert!(result == mem.nil());
+            assert!(eval_helper(mem, t, "ran")? == mem.nil());
 
-            let result = eval_helper(mem, t, query)?;
+            assert!(eval_helper(mem, t, "(unless (nil? 'a))")? == mem.nil());
 
-            let result = vec_from_pairs(mem, result)?;
-            let sym_nil = mem.nil();
-            let sym_true = mem.lookup_sym("true");
-            assert!(result == &[sym_nil, sym_true, sym_nil, sym_nil, sym_true]);
+            // the last body expression may be a tail call
+            let code = "(def walk (l) (unless (nil? l) (set 'ran (car l)) (walk (cdr l))))";
+            eval_helper(mem, t, code)?;
+            assert!(eval_helper(mem, t, "(walk '(a b c))")? == mem.nil());
+            assert!(eval_helper(mem, t, "ran")? == mem.lookup_sym("c"));
+
+            assert!(eval_helper(mem, t, "(unless)").is_err());
 
             Ok(())
         }
@@ -1015,23 +4888,31 @@ mod integration {
     }
 
     #[test]
-    fn compile_eval_nested_partials() {
+    fn compile_boolean_literals() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test evaluates nested Partial applications in function position
-            let a_fn = "(def isit (a b) (is? a b))";
+            let t = Thread::alloc(mem)?;
 
-            let query1 = "((isit 'x) 'x)";
-            let query2 = "((isit 'x) 'y)";
+            assert!(eval_helper(mem, t, "#t")? == mem.true_sym());
+            assert!(eval_helper(mem, t, "#f")? == mem.nil());
+            assert!(eval_helper(mem, t, "(is? #t 'true)")? == mem.true_sym());
+            assert!(eval_helper(mem, t, "(nil? #f)")? == mem.true_sym());
+            assert!(eval_helper(mem, t, "(nil? #t)")? == mem.nil());
+            assert!(eval_helper(mem, t, "'(#t #f)")?.to_string() == "(true nil)");
 
-            let t = Thread::alloc(mem)?;
+            let result = eval_helper(mem, t, "(cond #f 'x #t 'y)")?;
+            assert!(result == mem.lookup_sym("y"));
 
-            eval_helper(mem, t, a_fn)?;
+            let result = eval_helper(mem, t, "(cond #f 'x (nil? #f) 'y)")?;
+            assert!(result == mem.lookup_sym("y"));
 
-            let result = eval_helper(mem, t, query1)?;
-            assert!(result == mem.lookup_sym("true"));
+            assert!(eval_helper(mem, t, "(when #t 'yes)")? == mem.lookup_sym("yes"));
+            assert!(eval_helper(mem, t, "(when #f 'yes)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(unless #f 'no)")? == mem.lookup_sym("no"));
+            assert!(eval_helper(mem, t, "(unless #t 'no)")? == mem.nil());
 
-            let result = eval_helper(mem, t, query2)?;
-            assert!(result == mem.nil());
+            eval_helper(mem, t, "(def is-empty (l) (cond (nil? l) #t #t #f))")?;
+            assert!(eval_helper(mem, t, "(is-empty nil)")? == mem.true_sym());
+            assert!(eval_helper(mem, t, "(is-empty '(a))")? == mem.nil());
 
             Ok(())
         }
@@ -1040,26 +4921,98 @@ mod integration {
     }
 
     #[test]
-    fn compile_pass_partial_as_param() {
+    fn compile_strict_unbound_globals() {
+        fn strict<'guard>(
+            mem: &'guard MutatorView,
+            thread: ScopedPtr<'guard, Thread>,
+            code: &str,
+        ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+            let function = compile_strict(mem, parse(mem, code)?, thread.globals(mem))?;
+            thread.quick_vm_eval(mem, function)
+        }
+
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test passes a Partial as an argument of another function that will call it
-            // with it's last argument.
-            let isit_fn = "(def isit (a b) (is? a b))";
-            let map_fn = "(def map (f v) (f v))";
+            let t = Thread::alloc(mem)?;
 
-            let query1 = "(map (isit 'x) 'x)";
-            let query2 = "(map (isit 'x) 'y)";
+            match compile_strict(mem, parse(mem, "(cons 'a never-defined)")?, t.globals(mem)) {
+                Ok(_) => panic!("never-defined should be rejected at compile time"),
+                Err(e) => assert!(
+                    *e.error_kind()
+                        == ErrorKind::EvalError(String::from(
+                            "Symbol never-defined is not bound to a value"
+                        ))
+                ),
+            }
 
-            let t = Thread::alloc(mem)?;
+            // without strict mode the error is only found when the code is run
+            let function = compile(mem, parse(mem, "(cons 'a never-defined)")?)?;
+            assert!(t.quick_vm_eval(mem, function).is_err());
+
+            // globals bound before compilation, or defined by the code itself, are allowed
+            eval_helper(mem, t, "(set 'x 'a)")?;
+            assert!(strict(mem, t, "x")? == mem.lookup_sym("a"));
+            assert!(strict(mem, t, "(cons (set 'y 'b) y)")?.to_string() == "(b . b)");
+            strict(
+                mem,
+                t,
+                "(def self-ref (l) (cond (nil? l) nil true (self-ref (cdr l))))",
+            )?;
+            assert!(strict(mem, t, "(self-ref '(a b))")? == mem.nil());
+            assert!(strict(mem, t, "(set 'z z)").is_err());
+
+            // locals, upvalues and constants are never globals
+            assert!(strict(mem, t, "(let ((a 'b)) a)")? == mem.lookup_sym("b"));
+            strict(mem, t, "(def capture (x) (lambda () x))")?;
+            assert!(strict(mem, t, "((capture 'c))")? == mem.lookup_sym("c"));
+            assert!(strict(mem, t, "(cons nil true)")?.to_string() == "(nil . true)");
+
+            // unbound references inside nested functions are found too
+            assert!(strict(mem, t, "(lambda (a) (lambda () (cons a b)))").is_err());
 
-            eval_helper(mem, t, isit_fn)?;
-            eval_helper(mem, t, map_fn)?;
+            Ok(())
+        }
 
-            let result = eval_helper(mem, t, query1)?;
-            assert!(result == mem.lookup_sym("true"));
+        test_helper(test_inner);
+    }
 
-            let result = eval_helper(mem, t, query2)?;
-            assert!(result == mem.nil());
+    #[test]
+    fn compile_let_copies() {
+        fn copies(function: TaggedScopedPtr) -> usize {
+            format!("{:?}", function).matches("CopyRegister").count()
+        }
+
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // binding and body expressions compute straight into their registers
+            eval_helper(
+                mem,
+                t,
+                "(def lets () (let ((a 'x) (b (cons 'y 'z))) (cons a b) (cons b a)))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "lets")?) == 0);
+            assert!(eval_helper(mem, t, "(lets)")?.to_string() == "((y . z) . x)");
+
+            // a binding to a value that is already in a register must be copied
+            eval_helper(mem, t, "(def rebind (p) (let ((a p)) (cons a a)))")?;
+            assert!(copies(eval_helper(mem, t, "rebind")?) == 1);
+            assert!(eval_helper(mem, t, "(rebind 'q)")?.to_string() == "(q . q)");
+
+            eval_helper(
+                mem,
+                t,
+                "(def lets* () (let* ((a 'x) (b (cons a a)) (a (cons b 'w))) (cons 'v a) a))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "lets*")?) == 1);
+            assert!(eval_helper(mem, t, "(lets*)")?.to_string() == "((x . x) . w)");
+
+            // evaluating a binding expression may use the registers of the later bindings
+            let result = eval_helper(
+                mem,
+                t,
+                "(let ((a (cons (cons 'p 'q) (cons 'r 's))) (b 't)) (cons a b))",
+            )?;
+            assert!(result.to_string() == "(((p . q) r . s) . t)");
 
             Ok(())
         }
@@ -1068,15 +5021,54 @@ mod integration {
     }
 
     #[test]
-    fn compile_simple_let() {
-        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test compiles a basic let expression
-            let expr = "(let ((x 'y)) x)";
+    fn compile_dest_copies() {
+        fn copies(function: TaggedScopedPtr) -> usize {
+            format!("{:?}", function).matches("CopyRegister").count()
+        }
 
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
             let t = Thread::alloc(mem)?;
 
-            let result = eval_helper(mem, t, expr)?;
-            assert!(result == mem.lookup_sym("y"));
+            // a cond or when as the let result writes each branch straight into the let result
+            // register, where previously the whole form's result was copied there (was 1 copy)
+            eval_helper(
+                mem,
+                t,
+                "(def let-cond (x) (let ((a (car x))) (cond (nil? a) 'none (cons a a))))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "let-cond")?) == 0);
+            assert!(eval_helper(mem, t, "(let-cond '(p))")?.to_string() == "(p . p)");
+            assert!(eval_helper(mem, t, "(let-cond '(nil))")? == mem.lookup_sym("none"));
+
+            eval_helper(
+                mem,
+                t,
+                "(def let-when (x) (let ((r (when (nil? (cdr x)) (car x)))) (cons r r)))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "let-when")?) == 0);
+            assert!(eval_helper(mem, t, "(let-when '(w))")?.to_string() == "(w . w)");
+            assert!(eval_helper(mem, t, "(let-when '(w v))")?.to_string() == "(nil)");
+
+            // case clause results are no longer copied into the result register, only the
+            // matched value and the pattern bindings are (was 6 copies)
+            eval_helper(
+                mem,
+                t,
+                "(def case-body (x) (case x ((a . b) (car a) (cons b a)) (_ 'none)))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "case-body")?) == 3);
+            assert!(eval_helper(mem, t, "(case-body '((c) . d))")?.to_string() == "(d c)");
+            assert!(eval_helper(mem, t, "(case-body nil)")? == mem.lookup_sym("none"));
+
+            // nested expressions as call arguments are evaluated into consecutive registers
+            eval_helper(mem, t, "(def pair (a b) (cons a b))")?;
+            eval_helper(
+                mem,
+                t,
+                "(def nested-args (x) (pair (cons (car x) (cdr x)) (car x)))",
+            )?;
+            assert!(copies(eval_helper(mem, t, "nested-args")?) == 0);
+            assert!(eval_helper(mem, t, "(nested-args '(e f))")?.to_string() == "((e f) . e)");
 
             Ok(())
         }
@@ -1085,23 +5077,79 @@ mod integration {
     }
 
     #[test]
-    fn compile_function_with_simple_let() {
+    fn compile_load_file() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test compiles a let expression that deconstructs and reconstructs a pair list
-            let a_fn = "(def deconrecon (list) (let ((a (car list)) (b (cdr list))) (cons a b)))";
-            let query = "(deconrecon '(x y z z y))";
-
             let t = Thread::alloc(mem)?;
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/swap.evalrus");
+            let load = format!("(load \"{}\")", path);
+            let require = format!("(require \"{}\")", path);
+
+            eval_helper(mem, t, "(set 'loads nil)")?;
+
+            // the file's result is returned and its globals can be used afterward
+            assert!(render(*eval_helper(mem, t, &load)?) == "(b . a)");
+            assert!(render(*eval_helper(mem, t, "(swap '(c . d))")?) == "(d . c)");
+            assert!(render(*eval_helper(mem, t, "loads")?) == "(loaded)");
+
+            // loading from inside a function leaves its local variables alone
+            eval_helper(
+                mem,
+                t,
+                &format!("(def load-between (a b) (cons a (cons {} b)))", load),
+            )?;
+            let result = eval_helper(mem, t, "(load-between 'x 'y)")?;
+            assert!(render(*result) == "(x (b . a) . y)");
+            assert!(render(*eval_helper(mem, t, "loads")?) == "(loaded loaded)");
+
+            // require only loads the file the first time
+            assert!(render(*eval_helper(mem, t, &require)?) == "(b . a)");
+            assert!(eval_helper(mem, t, &require)? == mem.nil());
+            assert!(render(*eval_helper(mem, t, "loads")?) == "(loaded loaded loaded)");
+
+            for code in &[
+                "(load \"no-such-file.evalrus\")",
+                "(require \"no-such-file.evalrus\")",
+            ] {
+                match eval_helper(mem, t, code) {
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::IOError(_))),
+                    Ok(_) => panic!("{} should fail", code),
+                }
+            }
+            assert!(eval_helper(mem, t, "(load 'swap)").is_err());
 
-            eval_helper(mem, t, a_fn)?;
+            Ok(())
+        }
 
-            let result = eval_helper(mem, t, query)?;
+        test_helper(test_inner);
+    }
 
-            let result = vec_from_pairs(mem, result)?;
-            let sym_x = mem.lookup_sym("x");
-            let sym_y = mem.lookup_sym("y");
-            let sym_z = mem.lookup_sym("z");
-            assert!(result == &[sym_x, sym_y, sym_z, sym_z, sym_y]);
+    #[test]
+    fn compile_load_relative_paths() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // outside of any loaded file, a relative path is relative to the working directory,
+            // which is the crate root when testing. Within a file, it is relative to the file.
+            let result = eval_helper(mem, t, "(load \"fixtures/nested/outer.evalrus\")")?;
+            assert!(render(*result) == "(sibling middle leaf)");
+
+            // once the files have been loaded, their directories no longer apply
+            assert!(eval_helper(mem, t, "(load \"sibling.evalrus\")").is_err());
+
+            let require = "(require \"fixtures/nested/sibling.evalrus\")";
+            eval_helper(mem, t, "(set 'order nil)")?;
+            eval_helper(mem, t, require)?;
+            eval_helper(mem, t, require)?;
+            assert!(render(*eval_helper(mem, t, "order")?) == "(sibling)");
+
+            // an absolute path is used as it is
+            let path = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/fixtures/nested/middle/leaf.evalrus"
+            );
+            eval_helper(mem, t, "(set 'order nil)")?;
+            let result = eval_helper(mem, t, &format!("(load \"{}\")", path))?;
+            assert!(render(*result) == "(leaf)");
 
             Ok(())
         }
@@ -1110,18 +5158,88 @@ mod integration {
     }
 
     #[test]
-    fn compile_function_with_lambda_with_nonlocal_ref() {
+    fn compile_assert() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test compiles a function containing a lambda that references a nonlocal
-            let head_fn = "(def head (a) (let ((inner (\\ () (car a)))) (inner)))";
-            let query = "(head '(x y z z y))";
+            let t = Thread::alloc(mem)?;
+            eval_helper(mem, t, "(set 'pair '(a . b))")?;
+
+            // a passing assertion evaluates to nil
+            assert!(eval_helper(mem, t, "(assert (nil? nil))")? == mem.nil());
+            assert!(eval_helper(mem, t, "(assert 'anything)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(assert-eq (car pair) 'a)")? == mem.nil());
+            assert!(eval_helper(mem, t, "(assert-eq pair (cons 'a 'b))")? == mem.nil());
+
+            // a failing one describes what was asserted and where
+            match eval_helper(mem, t, "(assert (nil? pair))") {
+                Err(e) => {
+                    assert!(e.to_string() == "Evaluation error: Assertion failed: (nil? pair)");
+                    assert!(e.error_pos() == Some(spos(1, 8)));
+                }
+                Ok(_) => panic!("a false assertion passed"),
+            }
+
+            eval_helper(mem, t, "(def check (x)\n  (assert-eq x 'b))")?;
+            match eval_helper(mem, t, "(check (cdr pair))") {
+                Err(e) => panic!("a true assertion failed: {}", e),
+                Ok(_) => (),
+            }
+            match eval_helper(mem, t, "(check (car pair))") {
+                Err(e) => {
+                    assert!(
+                        e.to_string()
+                            == "Evaluation error: Assertion failed: x is a, expected (quote b) which is b"
+                    );
+                    assert!(e.error_pos() == Some(spos(2, 13)));
+                }
+                Ok(_) => panic!("an unequal assertion passed"),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
 
+    #[test]
+    fn compile_special_form_arity() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
             let t = Thread::alloc(mem)?;
 
-            eval_helper(mem, t, head_fn)?;
+            let cases = [
+                ("(car)", "car expects 1 argument, got 0", spos(1, 1)),
+                (
+                    "(car '(a) '(b))",
+                    "car expects 1 argument, got 2",
+                    spos(1, 1),
+                ),
+                ("(cons 'a)", "cons expects 2 arguments, got 1", spos(1, 1)),
+                (
+                    "(cons 'a 'b 'c)",
+                    "cons expects 2 arguments, got 3",
+                    spos(1, 1),
+                ),
+                ("(quote a b)", "quote expects 1 argument, got 2", spos(1, 1)),
+                ("(nil? (cdr))", "cdr expects 1 argument, got 0", spos(1, 7)),
+                (
+                    "(def f (x)\n  (equal? x))",
+                    "equal? expects 2 arguments, got 1",
+                    spos(2, 3),
+                ),
+            ];
+
+            for (code, message, pos) in cases.iter() {
+                match eval_helper(mem, t, code) {
+                    Err(e) => {
+                        assert!(e.to_string() == format!("Evaluation error: {}", message));
+                        assert!(e.error_pos() == Some(*pos));
+                    }
+                    Ok(_) => panic!("{} compiled with the wrong number of arguments", code),
+                }
+            }
 
-            let result = eval_helper(mem, t, query)?;
-            assert!(result == mem.lookup_sym("x"));
+            // the right number of arguments still compiles
+            let result = eval_helper(mem, t, "(cons (car '(a)) (quote b))")?;
+            assert!(render(*result) == "(a . b)");
 
             Ok(())
         }
@@ -1130,20 +5248,155 @@ mod integration {
     }
 
     #[test]
-    fn compile_function_returning_lambda_with_nonlocal_ref() {
+    fn compile_sort() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test compiles a function that returns a lambda that references a nonlocal
-            let head_fn = "(def head (a) (let ((inner (\\ () (car a)))) inner))";
-            let inner_fn = "(set 'inner (head '(x y z z y)))";
-            let query = "(inner)";
+            let t = Thread::alloc(mem)?;
+            let substitutes: Vec<(String, TaggedScopedPtr)> = (-2..=6)
+                .map(|n| {
+                    (
+                        format!("n{}", n),
+                        TaggedScopedPtr::new(mem, TaggedPtr::number(n)),
+                    )
+                })
+                .collect();
+            let substitutes: Vec<(&str, TaggedScopedPtr)> = substitutes
+                .iter()
+                .map(|(name, n)| (name.as_str(), *n))
+                .collect();
+            let sort = |code| -> Result<String, RuntimeError> {
+                Ok(render(*eval_substituted_helper(
+                    mem,
+                    t,
+                    code,
+                    &substitutes,
+                )?))
+            };
+
+            assert!(sort("(sort '(n3 n1 n-2 n6 n1 n0))")? == "(-2 0 1 1 3 6)");
+            assert!(sort("(sort '(pear apple fig apples))")? == "(apple apples fig pear)");
+            assert!(sort("(sort '(\"b\" \"a\" \"c\"))")? == "(\"a\" \"b\" \"c\")");
+            assert!(sort("(sort nil)")? == "nil");
+
+            // a new list is returned
+            eval_helper(mem, t, "(set 'fruit '(pear apple))")?;
+            assert!(sort("(sort fruit)")? == "(apple pear)");
+            assert!(sort("fruit")? == "(pear apple)");
+
+            // values that can't be ordered
+            for code in &[
+                "(sort '(n1 a))",
+                "(sort '(a \"a\"))",
+                "(sort '((a) (b)))",
+                "(sort 'a)",
+            ] {
+                match sort(code) {
+                    Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                    Ok(_) => panic!("{} should not sort", code),
+                }
+            }
+
+            // a comparator that orders numbers in descending order
+            let code = "(sort '(n3 n1 n-2 n6 n1 n0) (\\ (a b) (negative? (+ b (neg a)))))";
+            assert!(sort(code)? == "(6 3 1 1 0 -2)");
+
+            // a comparator that puts even numbers before odd numbers, keeping the original order
+            // otherwise
+            eval_helper(
+                mem,
+                t,
+                "(def evens-first (a b) (cond (even? a) (odd? b) nil))",
+            )?;
+            let code = "(sort '(n3 n2 n5 n4 n1 n6 n0) evens-first)";
+            assert!(sort(code)? == "(2 4 6 0 3 5 1)");
+            assert!(sort("(sort nil evens-first)")? == "nil");
+
+            // a comparator can refer to local variables
+            eval_helper(
+                mem,
+                t,
+                "(def sort-by-car (l) (sort l (\\ (a b) (evens-first (car a) (car b)))))",
+            )?;
+            let code = "(sort-by-car '((n1 . a) (n2 . b) (n3 . c) (n4 . d)))";
+            assert!(sort(code)? == "((2 . b) (4 . d) (1 . a) (3 . c))");
+
+            assert!(sort("(sort)").is_err());
+            assert!(sort("(sort nil evens-first evens-first)").is_err());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
 
+    #[test]
+    fn compile_sort_with_comparator() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
             let t = Thread::alloc(mem)?;
+            eval_helper(mem, t, "(def before? (a b) (negative? (+ a (neg b))))")?;
+
+            // a long list is sorted, in order
+            let numbers: Vec<TaggedScopedPtr> = (0..2000)
+                .rev()
+                .map(|n| TaggedScopedPtr::new(mem, TaggedPtr::number(n)))
+                .collect();
+            let numbers = pairs_from_slice(mem, &numbers)?;
+            let sorted = eval_substituted_helper(
+                mem,
+                t,
+                "(sort 'numbers before?)",
+                &[("numbers", numbers)],
+            )?;
+            let sorted = vec_from_pairs(mem, sorted)?;
+            assert!(sorted.len() == 2000);
+            for (n, value) in sorted.iter().enumerate() {
+                assert!(value.get_ptr() == TaggedPtr::number(n as isize));
+            }
 
-            eval_helper(mem, t, head_fn)?;
-            eval_helper(mem, t, inner_fn)?;
+            // a List is sorted into a new List
+            let numbers: Vec<TaggedScopedPtr> = [3, 1, 2]
+                .iter()
+                .map(|n| TaggedScopedPtr::new(mem, TaggedPtr::number(*n)))
+                .collect();
+            let numbers = List::from_slice(mem, &numbers)?;
+            let sorted = eval_substituted_helper(
+                mem,
+                t,
+                "(sort 'numbers before?)",
+                &[("numbers", numbers.as_tagged(mem))],
+            )?;
+            match *sorted {
+                Value::List(list) => {
+                    for (index, n) in [1, 2, 3].iter().enumerate() {
+                        let value = IndexedAnyContainer::get(&*list, mem, index as ArraySize)?;
+                        assert!(value.get_ptr() == TaggedPtr::number(*n));
+                    }
+                }
+                _ => panic!("expected a List"),
+            }
+            let first = IndexedAnyContainer::get(&*numbers, mem, 0)?;
+            assert!(first.get_ptr() == TaggedPtr::number(3));
+
+            // a comparator can be a partial application, including one whose result is applied
+            // to the surplus argument
+            let substitutes = [
+                ("n1", TaggedScopedPtr::new(mem, TaggedPtr::number(1))),
+                ("n2", TaggedScopedPtr::new(mem, TaggedPtr::number(2))),
+            ];
+            let pairs = "'((n2 . x) (n1 . y) (n2 . z) (n1 . w))";
+            let by_key = "(\\ (key a b) (before? (key a) (key b)))";
+            let curried = "(\\ (key a) (\\ (b) (before? (key a) (key b))))";
+            for comparator in &[by_key, curried] {
+                let code = format!("(sort {} ({} (\\ (p) (car p))))", pairs, comparator);
+                let sorted = eval_substituted_helper(mem, t, &code, &substitutes)?;
+                assert!(render(*sorted) == "((1 . y) (1 . w) (2 . x) (2 . z))");
+            }
 
-            let result = eval_helper(mem, t, query)?;
-            assert!(result == mem.lookup_sym("x"));
+            // an error in the comparator is an error of the sort
+            assert!(eval_helper(mem, t, "(sort '(a b) (\\ (a b) (car a)))").is_err());
+            match eval_helper(mem, t, "(sort 'a before?)") {
+                Err(e) => assert!(matches!(e.error_kind(), ErrorKind::TypeError(_))),
+                Ok(_) => panic!("a symbol should not sort"),
+            }
 
             Ok(())
         }
@@ -1152,15 +5405,31 @@ mod integration {
     }
 
     #[test]
-    fn compile_let_with_lambda_with_nested_call() {
+    fn compile_deep_copy() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
-            // this test compiles a let containing a lambda that is referenced in a sub-let scope
-            let f = "(let ((f (\\ (a) a))) (let ((g (f 'b))) g))";
-
             let t = Thread::alloc(mem)?;
 
-            let result = eval_helper(mem, t, f)?;
-            assert!(result == mem.lookup_sym("b"));
+            eval_helper(mem, t, "(set 'tree '(a (b c) \"d\" . e))")?;
+
+            let result = eval_helper(mem, t, "(equal? tree (deep-copy tree))")?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, "(is? tree (deep-copy tree))")?;
+            assert!(result == mem.nil());
+
+            let result = eval_helper(
+                mem,
+                t,
+                "(is? (car (cdr tree)) (car (cdr (deep-copy tree))))",
+            )?;
+            assert!(result == mem.nil());
+
+            // atoms are not copied
+            let result = eval_helper(mem, t, "(is? (car tree) (car (deep-copy tree)))")?;
+            assert!(result == mem.lookup_sym("true"));
+
+            let result = eval_helper(mem, t, "(deep-copy nil)")?;
+            assert!(result == mem.nil());
 
             Ok(())
         }
@@ -112,6 +112,27 @@ pub trait IndexedContainer<T: Sized + Clone>: Container<T> {
     ) -> Result<(), RuntimeError>;
 }
 
+/// If implemented, items can be inserted into and removed from the container at arbitrary
+/// indexes, shifting the items that follow.
+pub trait InsertableContainer<T: Sized + Clone>: IndexedContainer<T> {
+    /// Insert an item before the given index, which may be one past the last item to append.
+    /// Bounds-checked. Insertion can trigger an underlying array resize, hence it requires the
+    /// ability to allocate.
+    fn insert<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        index: ArraySize,
+        item: T,
+    ) -> Result<(), RuntimeError>;
+
+    /// Move the item at the given index out to the caller. Bounds-checked.
+    fn remove<'guard>(
+        &self,
+        _guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<T, RuntimeError>;
+}
+
 /// A trait that is implemented for containers that can represent their contents as a slice.
 pub trait SliceableContainer<T: Sized + Clone>: IndexedContainer<T> {
     /// This function allows access to the interior of a container as a slice by way of a
@@ -149,6 +170,24 @@ pub trait IndexedAnyContainer: IndexedContainer<TaggedCellPtr> {
     ) -> Result<(), RuntimeError>;
 }
 
+/// Specialized insertion interface for where TaggedCellPtr is used as T
+pub trait InsertableAnyContainer: InsertableContainer<TaggedCellPtr> {
+    /// Insert an object pointer before the given index. Bounds-checked.
+    fn insert<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        index: ArraySize,
+        item: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError>;
+
+    /// Return the object pointer at the given index, removing it. Bounds-checked.
+    fn remove<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError>;
+}
+
 /// Hashable-indexed interface. Objects used as keys must implement Hashable.
 // ANCHOR: DefHashIndexedAnyContainer
 pub trait HashIndexedAnyContainer {
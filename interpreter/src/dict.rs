@@ -5,19 +5,36 @@ use std::hash::Hasher;
 
 use fnv::FnvHasher;
 
-use crate::containers::{Container, HashIndexedAnyContainer};
-use crate::error::{ErrorKind, RuntimeError};
-use crate::hashable::Hashable;
-use crate::memory::MutatorView;
+use crate::containers::{
+    Container, HashIndexedAnyContainer, IndexedAnyContainer, InsertableAnyContainer,
+    StackAnyContainer,
+};
+use crate::error::{err_eval, ErrorKind, RuntimeError};
+use crate::list::List;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::rawarray::{default_array_growth, ArraySize, RawArray};
 use crate::safeptr::{MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
+use crate::symbol::Symbol;
 use crate::taggedptr::Value;
 
-// max load factor before resizing the table
-const LOAD_FACTOR: f32 = 0.80;
+// default max load factor before resizing the table
+pub const DEFAULT_LOAD_FACTOR: f32 = 0.80;
 const TOMBSTONE: u64 = 1;
 
+/// How a Dict removes entries
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Deletion {
+    /// Mark the entry as a tombstone, which lookups scan past and insertions reuse. Deletion is
+    /// cheap but tombstones lengthen scans until the backing array is next grown, and they count
+    /// towards the load factor, so a delete-heavy dict grows even though its length doesn't.
+    Tombstone,
+    /// Blank the entry and shift back any following entries that were displaced past it, so that
+    /// the table is as if the key had never been inserted. Deletion costs a scan to the end of
+    /// the run of entries, but scans never get longer than the live entries make them.
+    Backshift,
+}
+
 /// Internal entry representation, keeping copy of hash for the key
 // ANCHOR: DefDictItem
 #[derive(Clone)]
@@ -42,20 +59,32 @@ impl DictItem {
 /// TODO move this function somewhere more suitable
 // ANCHOR: DefHashKey
 fn hash_key<'guard>(
+    _guard: &'guard dyn MutatorScope,
+    key: TaggedScopedPtr<'guard>,
+) -> Result<u64, RuntimeError> {
+    match *key {
+        Value::Symbol(s) => Ok(s.name_hash()),
+        Value::Number(n) => Ok(n as u64),
+        _ => Err(RuntimeError::new(ErrorKind::UnhashableError)),
+    }
+}
+// ANCHOR_END: DefHashKey
+
+/// Generate a hash value for a key, hashing a Symbol by its address rather than its name. Symbols
+/// are interned, so a name always maps to the same Symbol and address.
+fn hash_key_identity<'guard>(
     guard: &'guard dyn MutatorScope,
     key: TaggedScopedPtr<'guard>,
 ) -> Result<u64, RuntimeError> {
     match *key {
         Value::Symbol(s) => {
             let mut hasher = FnvHasher::default();
-            s.hash(guard, &mut hasher);
+            hasher.write_usize(&*s as *const Symbol as usize);
             Ok(hasher.finish())
         }
-        Value::Number(n) => Ok(n as u64),
-        _ => Err(RuntimeError::new(ErrorKind::UnhashableError)),
+        _ => hash_key(guard, key),
     }
 }
-// ANCHOR_END: DefHashKey
 
 // ANCHOR: DefFindEntry
 /// Given a key, generate the hash and search for an entry that either matches this hash
@@ -103,6 +132,48 @@ fn find_entry<'guard>(
 }
 // ANCHOR_END: DefFindEntry
 
+/// Blank the entry at `index`, then move back each following entry in the same run of entries
+/// whose scan from the slot its hash maps to would cross the blank, so that no scan stops short
+/// at it. Only valid for tables without tombstones.
+fn backshift_entries<'guard>(
+    _guard: &'guard dyn MutatorScope,
+    data: &RawArray<DictItem>,
+    mut index: ArraySize,
+) -> Result<(), RuntimeError> {
+    let ptr = data
+        .as_ptr()
+        .ok_or(RuntimeError::new(ErrorKind::BoundsError))?;
+
+    let capacity = data.capacity();
+    let entry_at =
+        |index: ArraySize| unsafe { &mut *(ptr.offset(index as isize) as *mut DictItem) };
+
+    let mut next = (index + 1) % capacity;
+    loop {
+        let entry = entry_at(next);
+        if entry.key.is_nil() {
+            // the end of the run: nothing further along can have been displaced past the blank
+            break;
+        }
+
+        // distances, wrapping around the end of the array, from the slot the entry's hash maps
+        // to, to the blank and to where the entry is now
+        let home = (entry.hash % capacity as u64) as ArraySize;
+        let blank_distance = (index + capacity - home) % capacity;
+        let entry_distance = (next + capacity - home) % capacity;
+
+        if blank_distance < entry_distance {
+            *entry_at(index) = entry.clone();
+            index = next;
+        }
+
+        next = (next + 1) % capacity;
+    }
+
+    *entry_at(index) = DictItem::blank();
+    Ok(())
+}
+
 /// Reset all slots to a blank entry
 fn fill_with_blank_entries<'guard>(
     _guard: &'guard dyn MutatorScope,
@@ -124,9 +195,9 @@ fn fill_with_blank_entries<'guard>(
 
 /// Returns true if the dict has reached it's defined load factor and needs to be resized before inserting
 /// a new entry.
-fn needs_to_grow(used_entries: ArraySize, capacity: ArraySize) -> bool {
+fn needs_to_grow(used_entries: ArraySize, capacity: ArraySize, load_factor: f32) -> bool {
     let ratio = (used_entries as f32) / (capacity as f32);
-    ratio > LOAD_FACTOR
+    ratio > load_factor
 }
 
 /// A mutable Dict key/value associative data structure.
@@ -138,6 +209,14 @@ pub struct Dict {
     used_entries: Cell<ArraySize>,
     /// Backing array for key/value entries
     data: Cell<RawArray<DictItem>>,
+    /// Max ratio of used entries to capacity before the backing array is grown
+    load_factor: f32,
+    /// Hash Symbol keys by address instead of by name
+    symbol_identity: bool,
+    /// How entries are removed
+    deletion: Deletion,
+    /// If the dict keeps insertion order, its keys in the order they were first associated
+    order: Option<List>,
 }
 // ANCHOR_END: DefDict
 
@@ -157,11 +236,94 @@ impl Dict {
         mem.alloc(Dict::with_capacity(mem, capacity)?)
     }
 
+    /// Allocate a new instance on the heap with pre-allocated capacity and a non-default load
+    /// factor. A lower load factor trades memory for fewer collisions.
+    pub fn alloc_with_load_factor<'guard>(
+        mem: &'guard MutatorView,
+        capacity: ArraySize,
+        load_factor: f32,
+    ) -> Result<ScopedPtr<'guard, Dict>, RuntimeError> {
+        mem.alloc(Dict::with_load_factor(mem, capacity, load_factor)?)
+    }
+
+    /// Allocate a new instance on the heap that hashes Symbol keys by their address, which is much
+    /// cheaper than hashing the name. Symbols with the same name are the same object so this makes
+    /// no difference to which key is found.
+    pub fn alloc_symbol_keyed<'guard>(
+        mem: &'guard MutatorView,
+    ) -> Result<ScopedPtr<'guard, Dict>, RuntimeError> {
+        let mut dict = Dict::new();
+        dict.symbol_identity = true;
+        mem.alloc(dict)
+    }
+
+    /// Allocate a new instance on the heap that removes entries with the given strategy rather
+    /// than with tombstones
+    pub fn alloc_with_deletion<'guard>(
+        mem: &'guard MutatorView,
+        deletion: Deletion,
+    ) -> Result<ScopedPtr<'guard, Dict>, RuntimeError> {
+        let mut dict = Dict::new();
+        dict.deletion = deletion;
+        mem.alloc(dict)
+    }
+
+    /// Allocate a new instance on the heap that iterates in insertion order rather than in the
+    /// order of the hash table, which changes as the table grows. A key that is associated again
+    /// keeps its place, and a key that is removed and associated again goes to the end.
+    ///
+    /// The order is kept in a List of the keys alongside the table, costing a word per key plus
+    /// the List's spare capacity. Removing a key also scans the List for it, so is O(n).
+    pub fn alloc_ordered<'guard>(
+        mem: &'guard MutatorView,
+    ) -> Result<ScopedPtr<'guard, Dict>, RuntimeError> {
+        let mut dict = Dict::new();
+        dict.order = Some(List::new());
+        mem.alloc(dict)
+    }
+
+    /// Generate the hash value for a key according to how this dict hashes Symbols
+    fn hash<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        key: TaggedScopedPtr<'guard>,
+    ) -> Result<u64, RuntimeError> {
+        if self.symbol_identity {
+            hash_key_identity(guard, key)
+        } else {
+            hash_key(guard, key)
+        }
+    }
+
+    /// Instantiate with pre-allocated capacity and a load factor, which must be between 0 and 1
+    /// exclusive
+    pub fn with_load_factor<'guard>(
+        mem: &'guard MutatorView,
+        capacity: ArraySize,
+        load_factor: f32,
+    ) -> Result<Dict, RuntimeError> {
+        if !(load_factor > 0.0 && load_factor < 1.0) {
+            return Err(err_eval(&format!(
+                "Dict load factor {} is not between 0 and 1",
+                load_factor
+            )));
+        }
+
+        let mut dict = Dict::with_capacity(mem, capacity)?;
+        dict.load_factor = load_factor;
+        Ok(dict)
+    }
+
     /// Scale capacity up if needed
     fn grow_capacity<'guard>(&self, mem: &'guard MutatorView) -> Result<(), RuntimeError> {
         let data = self.data.get();
 
-        let new_capacity = default_array_growth(data.capacity())?;
+        // tombstones are not copied over, so only live entries count towards the new load. A low
+        // load factor may need more than one growth step.
+        let mut new_capacity = default_array_growth(data.capacity())?;
+        while needs_to_grow(self.length.get() + 1, new_capacity, self.load_factor) {
+            new_capacity = default_array_growth(new_capacity)?;
+        }
         let new_data = RawArray::<DictItem>::with_capacity(mem, new_capacity)?;
 
         let maybe_ptr = data.as_ptr();
@@ -177,8 +339,82 @@ impl Dict {
         }
 
         self.data.set(new_data);
+        self.used_entries.set(self.length.get());
         Ok(())
     }
+
+    /// Return the value associated with the key, or `default` if there is no association. Unlike
+    /// `lookup()`, an absent key is not an error, and a key that is associated with nil gives
+    /// nil rather than the default.
+    pub fn lookup_or<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        key: TaggedScopedPtr,
+        default: TaggedScopedPtr<'guard>,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        match self.lookup(guard, key) {
+            Err(e) if *e.error_kind() == ErrorKind::KeyError => Ok(default),
+            result => result,
+        }
+    }
+
+    /// Return all keys in the dict, in insertion order if the dict keeps it and otherwise in no
+    /// particular order
+    pub fn keys<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+    ) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+        if let Some(order) = &self.order {
+            return (0..order.length())
+                .map(|index| IndexedAnyContainer::get(order, guard, index))
+                .collect();
+        }
+
+        let data = self.data.get();
+        let mut keys = Vec::with_capacity(self.length.get() as usize);
+
+        if let Some(ptr) = data.as_ptr() {
+            for index in 0..data.capacity() {
+                let entry = unsafe { &*ptr.offset(index as isize) };
+                // blank entries and tombstones both have nil keys
+                if !entry.key.is_nil() {
+                    keys.push(entry.key.get(guard));
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Return all key/value pairs in the dict, in the same order as `keys()`
+    pub fn items<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+    ) -> Vec<(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>)> {
+        if self.order.is_some() {
+            // every key in the order is associated, so neither call can fail
+            return self
+                .keys(guard)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|key| Some((key, self.lookup(guard, key).ok()?)))
+                .collect();
+        }
+
+        let data = self.data.get();
+        let mut items = Vec::with_capacity(self.length.get() as usize);
+
+        if let Some(ptr) = data.as_ptr() {
+            for index in 0..data.capacity() {
+                let entry = unsafe { &*ptr.offset(index as isize) };
+                if !entry.key.is_nil() {
+                    items.push((entry.key.get(guard), entry.value.get(guard)));
+                }
+            }
+        }
+
+        items
+    }
 }
 
 impl Container<DictItem> for Dict {
@@ -187,6 +423,10 @@ impl Container<DictItem> for Dict {
             length: Cell::new(0),
             used_entries: Cell::new(0),
             data: Cell::new(RawArray::new()),
+            load_factor: DEFAULT_LOAD_FACTOR,
+            symbol_identity: false,
+            deletion: Deletion::Tombstone,
+            order: None,
         }
     }
 
@@ -198,6 +438,10 @@ impl Container<DictItem> for Dict {
             length: Cell::new(0),
             used_entries: Cell::new(0),
             data: Cell::new(RawArray::with_capacity(mem, capacity)?),
+            load_factor: DEFAULT_LOAD_FACTOR,
+            symbol_identity: false,
+            deletion: Deletion::Tombstone,
+            order: None,
         };
 
         let data = dict.data.get();
@@ -211,6 +455,9 @@ impl Container<DictItem> for Dict {
         fill_with_blank_entries(mem, &data)?;
         self.length.set(0);
         self.used_entries.set(0);
+        if let Some(order) = &self.order {
+            order.clear(mem)?;
+        }
         Ok(())
     }
 
@@ -226,8 +473,14 @@ impl HashIndexedAnyContainer for Dict {
         guard: &'guard dyn MutatorScope,
         key: TaggedScopedPtr,
     ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-        let hash = hash_key(guard, key)?;
+        let hash = self.hash(guard, key)?;
         let data = self.data.get();
+
+        // a Dict that has never had anything inserted has no storage to search
+        if data.capacity() == 0 {
+            return Err(RuntimeError::new(ErrorKind::KeyError));
+        }
+
         let entry = find_entry(guard, &data, hash)?;
 
         if !entry.key.is_nil() {
@@ -244,11 +497,15 @@ impl HashIndexedAnyContainer for Dict {
         key: TaggedScopedPtr<'guard>,
         value: TaggedScopedPtr<'guard>,
     ) -> Result<(), RuntimeError> {
-        let hash = hash_key(mem, key)?;
+        let hash = self.hash(mem, key)?;
 
         let mut data = self.data.get();
         // check the load factor (what percentage of the capacity is or has been used)
-        if needs_to_grow(self.used_entries.get() + 1, data.capacity()) {
+        if needs_to_grow(
+            self.used_entries.get() + 1,
+            data.capacity(),
+            self.load_factor,
+        ) {
             // create a new, larger, backing array, and copy all existing entries over
             self.grow_capacity(mem)?;
             data = self.data.get();
@@ -259,6 +516,11 @@ impl HashIndexedAnyContainer for Dict {
 
         // update counters if necessary
         if entry.key.is_nil() {
+            // a new key goes at the end of the order
+            if let Some(order) = &self.order {
+                StackAnyContainer::push(order, mem, key)?;
+            }
+
             // if `key` is nil, this entry is unused: increment the length
             self.length.set(self.length.get() + 1);
             if entry.hash == 0 {
@@ -283,9 +545,13 @@ impl HashIndexedAnyContainer for Dict {
         guard: &'guard dyn MutatorScope,
         key: TaggedScopedPtr,
     ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-        let hash = hash_key(guard, key)?;
+        let hash = self.hash(guard, key)?;
 
         let data = self.data.get();
+        if data.capacity() == 0 {
+            return Err(RuntimeError::new(ErrorKind::KeyError));
+        }
+
         let entry = find_entry(guard, &data, hash)?;
 
         if entry.key.is_nil() {
@@ -293,15 +559,38 @@ impl HashIndexedAnyContainer for Dict {
             return Err(RuntimeError::new(ErrorKind::KeyError));
         }
 
-        // decrement the length but not the `used_entries` count
+        if let Some(order) = &self.order {
+            for index in 0..order.length() {
+                if self.hash(guard, IndexedAnyContainer::get(order, guard, index)?)? == hash {
+                    order.remove(guard, index)?;
+                    break;
+                }
+            }
+        }
+
+        let value = entry.value.get(guard);
         self.length.set(self.length.get() - 1);
 
-        // write the "tombstone" markers to the entry
-        entry.key.set_to_nil();
-        entry.hash = TOMBSTONE;
+        match self.deletion {
+            Deletion::Tombstone => {
+                // write the "tombstone" markers to the entry, leaving the `used_entries` count
+                // as it is
+                entry.key.set_to_nil();
+                entry.hash = TOMBSTONE;
+            }
+            Deletion::Backshift => {
+                // there are no tombstones, so every used entry is a live one
+                let base = data
+                    .as_ptr()
+                    .ok_or(RuntimeError::new(ErrorKind::BoundsError))?;
+                let index = unsafe { (entry as *const DictItem).offset_from(base) } as ArraySize;
+                backshift_entries(guard, &data, index)?;
+                self.used_entries.set(self.length.get());
+            }
+        }
 
         // return the value that was associated with the key
-        Ok(entry.value.get(guard))
+        Ok(value)
     }
     // ANCHOR_END: DefHashIndexedAnyContainerForDictDissoc
 
@@ -310,13 +599,33 @@ impl HashIndexedAnyContainer for Dict {
         guard: &'guard dyn MutatorScope,
         key: TaggedScopedPtr,
     ) -> Result<bool, RuntimeError> {
-        let hash = hash_key(guard, key)?;
+        let hash = self.hash(guard, key)?;
         let data = self.data.get();
+        if data.capacity() == 0 {
+            return Ok(false);
+        }
+
         let entry = find_entry(guard, &data, hash)?;
         Ok(!entry.key.is_nil())
     }
 }
 
+impl Trace for Dict {
+    /// Every key and value is live, along with the entry array and any key order list
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.storage(&self.data.get());
+        if let Some(order) = &self.order {
+            roots.array(guard, order);
+        }
+
+        for (key, value) in self.items(guard) {
+            roots.value(key);
+            roots.value(value);
+        }
+    }
+}
+
 impl Print for Dict {
     fn print<'guard>(
         &self,
@@ -330,10 +639,15 @@ impl Print for Dict {
 
 #[cfg(test)]
 mod test {
-    use super::{Container, Dict, HashIndexedAnyContainer};
+    use super::{hash_key, Container, Deletion, Dict, DictItem, HashIndexedAnyContainer};
     use crate::error::{ErrorKind, RuntimeError};
+    use crate::hashable::Hashable;
     use crate::memory::{Memory, Mutator, MutatorView};
     use crate::pair::Pair;
+    use crate::safeptr::TaggedScopedPtr;
+    use crate::taggedptr::{TaggedPtr, Value};
+    use fnv::FnvHasher;
+    use std::hash::Hasher;
 
     #[test]
     fn dict_empty_assoc_lookup() {
@@ -434,6 +748,54 @@ mod test {
         mem.mutate(&test, ()).unwrap();
     }
 
+    #[test]
+    fn dict_lookup_or_default() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::new();
+
+                let present = mem.lookup_sym("present");
+                let present_nil = mem.lookup_sym("present-nil");
+                let absent = mem.lookup_sym("absent");
+                let value = mem.lookup_sym("value");
+                let default = mem.lookup_sym("default");
+
+                // a Dict without storage has no keys
+                assert!(dict.lookup_or(mem, absent, default)? == default);
+
+                dict.assoc(mem, present, value)?;
+                dict.assoc(mem, present_nil, mem.nil())?;
+
+                assert!(dict.lookup_or(mem, present, default)? == value);
+                assert!(dict.lookup_or(mem, present_nil, default)? == mem.nil());
+                assert!(dict.lookup_or(mem, absent, default)? == default);
+
+                // a removed key is absent
+                dict.dissoc(mem, present)?;
+                assert!(dict.lookup_or(mem, present, default)? == default);
+
+                // errors other than a missing key are still errors
+                let unhashable = mem.alloc_tagged(Pair::new())?;
+                assert!(dict.lookup_or(mem, unhashable, default).is_err());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
     #[test]
     fn dict_dissoc_lookup() {
         let mem = Memory::new();
@@ -679,4 +1041,627 @@ mod test {
         let test = Test {};
         mem.mutate(&test, ()).unwrap();
     }
+
+    #[test]
+    fn dict_symbol_keyed() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::alloc_symbol_keyed(mem)?;
+
+                // enough keys to grow the backing array a few times
+                for num in 0..100 {
+                    let key = mem.lookup_sym(&format!("key{}", num));
+                    let val = TaggedScopedPtr::new(mem, TaggedPtr::number(num));
+                    dict.assoc(mem, key, val)?;
+                }
+                assert!(dict.length() == 100);
+
+                // looking up by name again finds the same interned Symbol
+                for num in 0..100 {
+                    let key = mem.lookup_sym(&format!("key{}", num));
+                    let lookup = dict.lookup(mem, key)?;
+                    assert!(lookup == TaggedScopedPtr::new(mem, TaggedPtr::number(num)));
+                }
+
+                assert!(!dict.exists(mem, mem.lookup_sym("missing"))?);
+                dict.dissoc(mem, mem.lookup_sym("key7"))?;
+                assert!(!dict.exists(mem, mem.lookup_sym("key7"))?);
+                assert!(dict.exists(mem, mem.lookup_sym("key8"))?);
+
+                // non-Symbol keys are hashed as usual
+                let num = TaggedScopedPtr::new(mem, TaggedPtr::number(3));
+                dict.assoc(mem, num, mem.lookup_sym("three"))?;
+                assert!(dict.lookup(mem, num)? == mem.lookup_sym("three"));
+
+                let key = mem.alloc_tagged(Pair::new())?;
+                match dict.lookup(mem, key) {
+                    Ok(_) => panic!("Key should not have been found!"),
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::UnhashableError),
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_symbol_cached_hash() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let names = ["", "a", "foo", "a-much-longer-symbol-name"];
+
+                // the hash cached at intern time matches a fresh hash of the name
+                for name in names.iter() {
+                    let sym = mem.lookup_sym(name);
+                    match *sym {
+                        Value::Symbol(s) => {
+                            let mut hasher = FnvHasher::default();
+                            s.hash(mem, &mut hasher);
+                            assert!(s.name_hash() == hasher.finish());
+                            assert!(hash_key(mem, sym)? == hasher.finish());
+                        }
+                        _ => panic!("expected Symbol"),
+                    }
+                }
+
+                let dict = Dict::alloc(mem)?;
+                for (num, name) in names.iter().enumerate() {
+                    let val = TaggedScopedPtr::new(mem, TaggedPtr::number(num as isize));
+                    dict.assoc(mem, mem.lookup_sym(name), val)?;
+                }
+                for (num, name) in names.iter().enumerate() {
+                    let lookup = dict.lookup(mem, mem.lookup_sym(name))?;
+                    assert!(lookup == TaggedScopedPtr::new(mem, TaggedPtr::number(num as isize)));
+                }
+                assert!(!dict.exists(mem, mem.lookup_sym("missing"))?);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_keys() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::new();
+                assert!(dict.keys(mem)?.is_empty());
+
+                let names = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+                for name in names.iter() {
+                    dict.assoc(mem, mem.lookup_sym(name), mem.nil())?;
+                }
+                dict.dissoc(mem, mem.lookup_sym("c"))?;
+
+                let keys = dict.keys(mem)?;
+                assert!(keys.len() == names.len() - 1);
+                for name in names.iter() {
+                    let present = keys.iter().any(|key| *key == mem.lookup_sym(name));
+                    assert!(present == (*name != "c"));
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_ordered_iteration() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::alloc_ordered(mem)?;
+                let names: Vec<String> = (0..100).rev().map(|n| format!("key{}", n)).collect();
+
+                let assert_order = |expected: &[&str]| -> Result<(), RuntimeError> {
+                    let keys = dict.keys(mem)?;
+                    assert!(keys.len() == expected.len());
+                    assert!(dict.length() as usize == expected.len());
+                    for (key, name) in keys.iter().zip(expected) {
+                        assert!(*key == mem.lookup_sym(name));
+                    }
+
+                    let items = dict.items(mem);
+                    assert!(items.len() == expected.len());
+                    for ((key, value), name) in items.iter().zip(expected) {
+                        assert!(*key == mem.lookup_sym(name));
+                        assert!(*value == dict.lookup(mem, *key)?);
+                    }
+                    Ok(())
+                };
+
+                // the table grows from empty several times on the way to 100 keys
+                for (n, name) in names.iter().enumerate() {
+                    let value = TaggedScopedPtr::new(mem, TaggedPtr::number(n as isize));
+                    dict.assoc(mem, mem.lookup_sym(name), value)?;
+                }
+                let mut expected: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                assert_order(&expected)?;
+
+                // associating an existing key again keeps its place
+                dict.assoc(mem, mem.lookup_sym("key50"), mem.nil())?;
+                assert!(dict.lookup(mem, mem.lookup_sym("key50"))? == mem.nil());
+                assert_order(&expected)?;
+
+                // a removed key leaves the order, and goes to the end if it is associated again
+                dict.dissoc(mem, mem.lookup_sym("key99"))?;
+                dict.dissoc(mem, mem.lookup_sym("key50"))?;
+                expected.retain(|name| *name != "key99" && *name != "key50");
+                assert_order(&expected)?;
+
+                dict.assoc(mem, mem.lookup_sym("key99"), mem.nil())?;
+                expected.push("key99");
+                assert_order(&expected)?;
+
+                dict.clear(mem)?;
+                assert_order(&[])?;
+                dict.assoc(mem, mem.lookup_sym("b"), mem.nil())?;
+                dict.assoc(mem, mem.lookup_sym("a"), mem.nil())?;
+                assert_order(&["b", "a"])?;
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_empty_without_storage() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                // a new Dict has no backing storage until the first insertion
+                let dict = Dict::new();
+                let key = mem.lookup_sym("a");
+
+                assert!(!dict.exists(mem, key)?);
+
+                match dict.lookup(mem, key) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::KeyError),
+                    Ok(_) => panic!("lookup in an empty Dict should fail"),
+                }
+
+                match dict.dissoc(mem, key) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::KeyError),
+                    Ok(_) => panic!("dissoc from an empty Dict should fail"),
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    // Count the entries that are not in the slot their hash maps to directly
+    fn displaced_entries(dict: &Dict) -> usize {
+        let data = dict.data.get();
+        let ptr = data.as_ptr().unwrap();
+
+        (0..data.capacity())
+            .filter(|index| {
+                let entry = unsafe { &*ptr.offset(*index as isize) as &DictItem };
+                !entry.key.is_nil() && entry.hash % data.capacity() as u64 != *index as u64
+            })
+            .count()
+    }
+
+    #[test]
+    fn dict_invalid_load_factor() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                for load_factor in &[0.0, 1.0, -0.5, 1.5, f32::NAN] {
+                    assert!(Dict::with_load_factor(mem, 16, *load_factor).is_err());
+                }
+
+                assert!(Dict::with_load_factor(mem, 16, 0.5).is_ok());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_lower_load_factor_grows_earlier() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let sparse = Dict::with_load_factor(mem, 16, 0.25)?;
+                let dense = Dict::with_load_factor(mem, 16, 0.9)?;
+
+                for n in 2..10 {
+                    let key = TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+                    sparse.assoc(mem, key, mem.nil())?;
+                    dense.assoc(mem, key, mem.nil())?;
+                }
+
+                // 8 entries is over a quarter of 16 but under 90%
+                assert!(sparse.data.get().capacity() > 16);
+                assert!(dense.data.get().capacity() == 16);
+
+                // growth keeps the load under the load factor
+                assert!(sparse.length() as f32 / sparse.data.get().capacity() as f32 <= 0.25);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_lower_load_factor_fewer_collisions() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let sparse = Dict::with_load_factor(mem, 32, 0.3)?;
+                let dense = Dict::with_load_factor(mem, 32, 0.9)?;
+
+                // number keys hash to themselves: multiples of the initial capacity all map
+                // to the same slot
+                for n in 1..25 {
+                    let key = TaggedScopedPtr::new(mem, TaggedPtr::number(n * 32));
+                    sparse.assoc(mem, key, key)?;
+                    dense.assoc(mem, key, key)?;
+                }
+
+                assert!(displaced_entries(&sparse) < displaced_entries(&dense));
+
+                // and everything can still be found
+                for n in 1..25 {
+                    let key = TaggedScopedPtr::new(mem, TaggedPtr::number(n * 32));
+                    assert!(sparse.lookup(mem, key)? == key);
+                    assert!(dense.lookup(mem, key)? == key);
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    // The following tests exercise the raw pointer arithmetic of entry scanning in find_entry()
+    // at the end of the backing array. They are kept small and fast so that they can be run
+    // under Miri to check for undefined behavior:
+    //
+    //     cargo +nightly miri test dict::
+
+    // Return the key at the given entry index as a number, or None if the entry is blank
+    fn entry_number<'guard>(mem: &'guard MutatorView, dict: &Dict, index: isize) -> Option<isize> {
+        let data = dict.data.get();
+        let entry = unsafe { &*data.as_ptr().unwrap().offset(index) as &DictItem };
+        entry.key.get(mem).as_number().ok()
+    }
+
+    #[test]
+    fn dict_find_entry_wraps_at_capacity() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::with_capacity(mem, 8)?;
+
+                // all three keys hash to the last slot, so the scan must wrap around to the
+                // start of the array
+                let keys: Vec<TaggedScopedPtr> = [7, 15, 23]
+                    .iter()
+                    .map(|n| TaggedScopedPtr::new(mem, TaggedPtr::number(*n)))
+                    .collect();
+                for key in keys.iter() {
+                    dict.assoc(mem, *key, *key)?;
+                }
+
+                assert!(dict.data.get().capacity() == 8);
+                assert!(entry_number(mem, &dict, 7) == Some(7));
+                assert!(entry_number(mem, &dict, 0) == Some(15));
+                assert!(entry_number(mem, &dict, 1) == Some(23));
+
+                for key in keys.iter() {
+                    assert!(dict.lookup(mem, *key)? == *key);
+                }
+
+                // leave a tombstone at the start of the array: the scan for 23 continues past it
+                dict.dissoc(mem, keys[1])?;
+                assert!(dict.lookup(mem, keys[2])? == keys[2]);
+                assert!(dict.lookup(mem, keys[1]).is_err());
+
+                // and a new key that collides reuses the tombstone
+                let key = TaggedScopedPtr::new(mem, TaggedPtr::number(31));
+                dict.assoc(mem, key, key)?;
+                assert!(entry_number(mem, &dict, 0) == Some(31));
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_find_entry_fills_to_load_factor() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::with_load_factor(mem, 8, 0.9)?;
+
+                // 7 of 8 slots used, all clustered at the end and wrapping around, leaving a
+                // single blank entry to terminate scans
+                for n in 0..7 {
+                    let key = TaggedScopedPtr::new(mem, TaggedPtr::number(5 + n * 8));
+                    dict.assoc(mem, key, key)?;
+                }
+                assert!(dict.data.get().capacity() == 8);
+
+                for n in 0..7 {
+                    let key = TaggedScopedPtr::new(mem, TaggedPtr::number(5 + n * 8));
+                    assert!(dict.lookup(mem, key)? == key);
+                }
+
+                // a missing key scans all the way around to the only blank entry
+                let missing = TaggedScopedPtr::new(mem, TaggedPtr::number(6));
+                assert!(dict.lookup(mem, missing).is_err());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    // Return the length of the longest run of used entries, live or tombstone, which is the
+    // longest scan that any lookup can make
+    fn longest_run(dict: &Dict) -> usize {
+        let data = dict.data.get();
+        let ptr = data.as_ptr().unwrap();
+        let capacity = data.capacity() as usize;
+
+        let mut longest = 0;
+        let mut run = 0;
+        // go around twice so that a run that wraps around the end is counted whole
+        for index in 0..capacity * 2 {
+            let entry = unsafe { &*ptr.offset((index % capacity) as isize) as &DictItem };
+            if entry.key.is_nil() && entry.hash != super::TOMBSTONE {
+                run = 0;
+            } else {
+                run += 1;
+                longest = longest.max(run);
+            }
+        }
+
+        longest.min(capacity)
+    }
+
+    #[test]
+    fn dict_backshift_wraps_at_capacity() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let dict = Dict::alloc_with_deletion(mem, Deletion::Backshift)?;
+                let number = |n| TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+
+                // fill the capacity-8 array so that 7, 15 and 23 run from the last slot around
+                // to the start, followed by 1 and 2 which are each displaced by one
+                for n in &[7, 15, 23, 1, 2] {
+                    dict.assoc(mem, number(*n), number(*n))?;
+                }
+                assert!(dict.data.get().capacity() == 8);
+                assert!(entry_number(mem, &dict, 7) == Some(7));
+                assert!(entry_number(mem, &dict, 0) == Some(15));
+                assert!(entry_number(mem, &dict, 1) == Some(23));
+                assert!(entry_number(mem, &dict, 2) == Some(1));
+                assert!(entry_number(mem, &dict, 3) == Some(2));
+
+                // removing the entry in the last slot shifts every following entry back across
+                // the end of the array, but 1 and 2 only as far as their own slots
+                assert!(dict.dissoc(mem, number(7))? == number(7));
+                assert!(entry_number(mem, &dict, 7) == Some(15));
+                assert!(entry_number(mem, &dict, 0) == Some(23));
+                assert!(entry_number(mem, &dict, 1) == Some(1));
+                assert!(entry_number(mem, &dict, 2) == Some(2));
+                assert!(entry_number(mem, &dict, 3) == None);
+                assert!(dict.used_entries.get() == 4);
+
+                for n in &[15, 23, 1, 2] {
+                    assert!(dict.lookup(mem, number(*n))? == number(*n));
+                }
+                assert!(dict.lookup(mem, number(7)).is_err());
+
+                // an entry that is already in its own slot is not moved
+                assert!(dict.dissoc(mem, number(23))? == number(23));
+                assert!(entry_number(mem, &dict, 7) == Some(15));
+                assert!(entry_number(mem, &dict, 0) == None);
+                assert!(entry_number(mem, &dict, 1) == Some(1));
+                assert!(entry_number(mem, &dict, 2) == Some(2));
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn dict_delete_heavy_probe_lengths() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let tombstone = Dict::alloc_with_deletion(mem, Deletion::Tombstone)?;
+                let backshift = Dict::alloc_with_deletion(mem, Deletion::Backshift)?;
+                let number = |n| TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+
+                // a sliding window of 8 live keys: each new key replaces the oldest
+                let live = 8;
+                for n in 0..1000 {
+                    for dict in &[&tombstone, &backshift] {
+                        dict.assoc(mem, number(n), number(n))?;
+                        if n >= live {
+                            assert!(dict.dissoc(mem, number(n - live))? == number(n - live));
+                        }
+                    }
+                }
+
+                for dict in &[&tombstone, &backshift] {
+                    assert!(dict.length() == live as u32);
+
+                    for n in 0..1000 {
+                        let found = dict.lookup(mem, number(n));
+                        if n < 1000 - live {
+                            assert!(found.is_err());
+                        } else {
+                            assert!(found? == number(n));
+                        }
+                    }
+                }
+
+                // tombstones accumulate up to the load factor between each growth of the array,
+                // so the capacity keeps growing and scans cross many tombstones
+                let tombstone_capacity = tombstone.data.get().capacity();
+                assert!(tombstone.used_entries.get() > live as u32);
+                assert!(tombstone_capacity > 64);
+
+                // without tombstones the array stays small and no scan is longer than the number
+                // of live entries
+                assert!(backshift.used_entries.get() == live as u32);
+                assert!(backshift.data.get().capacity() <= 16);
+                assert!(longest_run(&backshift) <= live as usize);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
 }
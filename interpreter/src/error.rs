@@ -1,12 +1,15 @@
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::time::Duration;
 
 use rustyline::error::ReadlineError;
 
 use blockalloc::BlockError;
 use stickyimmix::AllocError;
 
+use crate::safeptr::{MutatorScope, TaggedCellPtr, TaggedScopedPtr};
+
 /// Source code position
 // ANCHOR: DefSourcePos
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -27,13 +30,21 @@ pub enum ErrorKind {
     IOError(String),
     LexerError(String),
     ParseError(String),
+    NoExpression,
     EvalError(String),
+    TypeError(String),
     BadAllocationRequest,
     OutOfMemory,
     BoundsError,
     KeyError,
     UnhashableError,
     MutableBorrowError,
+    CollectionInProgress,
+    Timeout(Duration),
+    /// A value raised by the program itself, which may be of any type. The `Thread` the error
+    /// was raised in keeps the value rooted while the error propagates, until its next user
+    /// error.
+    UserError(TaggedCellPtr),
 }
 
 /// An Eval-rs runtime error type
@@ -41,6 +52,9 @@ pub enum ErrorKind {
 pub struct RuntimeError {
     kind: ErrorKind,
     pos: Option<SourcePos>,
+    // the position following the end of the source code the error is about, if it is known
+    end: Option<SourcePos>,
+    context: Option<String>,
 }
 
 impl RuntimeError {
@@ -48,6 +62,8 @@ impl RuntimeError {
         RuntimeError {
             kind: kind,
             pos: None,
+            end: None,
+            context: None,
         }
     }
 
@@ -55,9 +71,24 @@ impl RuntimeError {
         RuntimeError {
             kind: kind,
             pos: Some(pos),
+            end: None,
+            context: None,
         }
     }
 
+    /// Add a description of the circumstances of the error, appended to the error message
+    pub fn with_context(mut self, context: String) -> RuntimeError {
+        self.context = Some(context);
+        self
+    }
+
+    /// Record where the source code the error is about ends, so that all of it is underlined
+    /// rather than only its first character
+    pub fn with_end(mut self, end: SourcePos) -> RuntimeError {
+        self.end = Some(end);
+        self
+    }
+
     pub fn error_kind(&self) -> &ErrorKind {
         &self.kind
     }
@@ -66,24 +97,59 @@ impl RuntimeError {
         self.pos
     }
 
-    /// Given the relevant source code string, show the error in context
-    pub fn print_with_source(&self, source: &str) {
+    pub fn error_end(&self) -> Option<SourcePos> {
+        self.end
+    }
+
+    pub fn error_context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    /// Return the value carried by a user error
+    pub fn thrown_value<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+    ) -> Option<TaggedScopedPtr<'guard>> {
+        match self.kind {
+            ErrorKind::UserError(ref value) => Some(value.get(guard)),
+            _ => None,
+        }
+    }
+
+    /// Given the relevant source code string, write the error in context to the given writer
+    pub fn print_with_source(&self, source: &str, out: &mut dyn io::Write) -> io::Result<()> {
         if let Some(ref pos) = self.pos {
             let mut iter = source.lines().enumerate();
 
             while let Some((count, line)) = iter.next() {
                 // count starts at 0, line numbers start at 1
                 if count + 1 == pos.line as usize {
-                    println!("error: {}", self);
-                    println!("{:5}|{}", pos.line, line);
-                    println!("{:5}|{:width$}^", " ", " ", width = pos.column as usize);
-                    println!("{:5}|", " ");
-                    return;
+                    writeln!(out, "error: {}", self)?;
+                    writeln!(out, "{:5}|{}", pos.line, line)?;
+                    // underline to the end of the source the error is about if it is on this line
+                    let length = match self.end {
+                        Some(end) if end.line == pos.line && end.column > pos.column => {
+                            end.column - pos.column
+                        }
+                        _ => 1,
+                    };
+                    writeln!(
+                        out,
+                        "{:5}|{:width$}{}",
+                        " ",
+                        " ",
+                        "^".repeat(length as usize),
+                        width = pos.column as usize
+                    )?;
+                    writeln!(out, "{:5}|", " ")?;
+                    return Ok(());
                 }
             }
         } else {
-            println!("error: {}", self);
+            writeln!(out, "error: {}", self)?;
         }
+
+        Ok(())
     }
 }
 
@@ -93,7 +159,9 @@ impl fmt::Display for RuntimeError {
             ErrorKind::IOError(ref reason) => write!(f, "IO Error: {}", reason),
             ErrorKind::LexerError(ref reason) => write!(f, "Parse error: {}", reason),
             ErrorKind::ParseError(ref reason) => write!(f, "Parse error: {}", reason),
+            ErrorKind::NoExpression => write!(f, "No expression to evaluate"),
             ErrorKind::EvalError(ref reason) => write!(f, "Evaluation error: {}", reason),
+            ErrorKind::TypeError(ref reason) => write!(f, "Type error: {}", reason),
             ErrorKind::OutOfMemory => write!(f, "Out of memory!"),
             ErrorKind::BadAllocationRequest => {
                 write!(f, "An invalid memory size allocation was requested!")
@@ -105,7 +173,24 @@ impl fmt::Display for RuntimeError {
                 f,
                 "Attempt to modify a container that is already mutably borrowed"
             ),
+            ErrorKind::CollectionInProgress => {
+                write!(
+                    f,
+                    "Attempt to access the heap while a collection is in progress"
+                )
+            }
+            ErrorKind::Timeout(timeout) => {
+                write!(f, "Evaluation timed out after {:?}", timeout)
+            }
+            // the value can't be printed without a guard
+            ErrorKind::UserError(_) => write!(f, "Uncaught thrown value"),
+        }?;
+
+        if let Some(ref context) = self.context {
+            write!(f, " ({})", context)?;
         }
+
+        Ok(())
     }
 }
 
@@ -143,6 +228,12 @@ impl From<AllocError> for RuntimeError {
     }
 }
 
+/// Convert from AllocError, recording the size in bytes and the type of the object that could not
+/// be allocated
+pub fn err_alloc(other: AllocError, size: usize, type_name: &str) -> RuntimeError {
+    RuntimeError::from(other).with_context(format!("{} bytes requested for {}", size, type_name))
+}
+
 impl Error for RuntimeError {
     fn cause(&self) -> Option<&dyn Error> {
         None
@@ -181,3 +272,13 @@ pub fn err_parser_wpos(pos: SourcePos, reason: &str) -> RuntimeError {
 pub fn err_eval(reason: &str) -> RuntimeError {
     RuntimeError::new(ErrorKind::EvalError(String::from(reason)))
 }
+
+/// Convenience shorthand function for building an error that carries a value
+pub fn err_user(value: TaggedScopedPtr) -> RuntimeError {
+    RuntimeError::new(ErrorKind::UserError(TaggedCellPtr::new_with(value)))
+}
+
+/// Convenience shorthand function for building a type mismatch error
+pub fn err_type(reason: &str) -> RuntimeError {
+    RuntimeError::new(ErrorKind::TypeError(String::from(reason)))
+}
@@ -6,7 +6,7 @@ use crate::bytecode::ByteCode;
 use crate::containers::{Container, ContainerFromSlice, SliceableContainer, StackContainer};
 use crate::error::RuntimeError;
 use crate::list::List;
-use crate::memory::MutatorView;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
 use crate::taggedptr::Value;
@@ -23,6 +23,9 @@ pub struct Function {
     code: CellPtr<ByteCode>,
     /// Param names are stored for introspection of a function signature
     param_names: CellPtr<List>,
+    /// The name of the parameter that collects any arguments beyond `arity` into a list, or nil
+    /// if the function takes a fixed number of arguments. It is not one of `param_names`.
+    rest_param: TaggedCellPtr,
     /// List of (CallFrame-index: u8 | Window-index: u8) relative offsets from this function's
     /// declaration where nonlocal variables will be found. Needed when creating a closure. May be
     /// nil
@@ -36,10 +39,13 @@ impl Function {
     /// The nonlocal_refs arg must contain a list of 16 bit values composed of two
     /// 8 bit values: CallFrame relative offset << 8 | Window offset
     /// These values should follow the same order as given in param_names
+    ///
+    /// The rest_param arg is nil unless the function is variadic.
     pub fn alloc<'guard>(
         mem: &'guard MutatorView,
         name: TaggedScopedPtr<'guard>,
         param_names: ScopedPtr<'guard, List>,
+        rest_param: TaggedScopedPtr<'guard>,
         code: ScopedPtr<'guard, ByteCode>,
         nonlocal_refs: Option<ScopedPtr<'guard, ArrayU16>>,
     ) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
@@ -55,10 +61,16 @@ impl Function {
             arity: param_names.length() as u8,
             code: CellPtr::new_with(code),
             param_names: CellPtr::new_with(param_names),
+            rest_param: TaggedCellPtr::new_with(rest_param),
             nonlocal_refs,
         })
     }
 
+    /// Return the Function's name symbol, or nil if it is anonymous
+    pub fn name_symbol<'guard>(&self, guard: &'guard dyn MutatorScope) -> TaggedScopedPtr<'guard> {
+        self.name.get(guard)
+    }
+
     /// Return the Function's name as a string slice
     pub fn name<'guard>(&self, guard: &'guard dyn MutatorScope) -> &'guard str {
         let name = self.name.get(guard);
@@ -100,6 +112,46 @@ impl Function {
             _ => unreachable!(),
         }
     }
+
+    /// Check that the Function's bytecode only refers to registers, literals and upvalues that
+    /// exist. See `ByteCode::verify()`.
+    pub fn verify<'guard>(&self, guard: &'guard dyn MutatorScope) -> Result<(), RuntimeError> {
+        let upvalue_count = if self.is_closure() {
+            self.nonlocals(guard).length() as usize
+        } else {
+            0
+        };
+
+        self.code(guard).verify(guard, upvalue_count)
+    }
+}
+
+/// Write out parameter names as they would be written in a function definition, skipping the
+/// first `skip` of them. A rest parameter follows a `.`, as in `(a b . rest)`.
+fn param_list_string<'guard>(
+    guard: &'guard dyn MutatorScope,
+    function: &Function,
+    skip: usize,
+) -> String {
+    let params = function.param_names.get(guard);
+
+    let mut names = Vec::new();
+    params.access_slice(guard, |items| {
+        // a Partial can't have used more arguments than there are named parameters, but don't
+        // rely on it when printing
+        let start = skip.min(items.len());
+        names.extend(
+            items[start..]
+                .iter()
+                .map(|item| item.get(guard).to_string()),
+        )
+    });
+
+    if !function.rest_param.is_nil() {
+        names.push(format!(". {}", function.rest_param.get(guard)));
+    }
+
+    join(names, " ")
 }
 
 impl Print for Function {
@@ -110,12 +162,7 @@ impl Print for Function {
         f: &mut fmt::Formatter,
     ) -> fmt::Result {
         let name = self.name.get(guard);
-        let params = self.param_names.get(guard);
-
-        let mut param_string = String::new();
-        params.access_slice(guard, |items| {
-            param_string = join(items.iter().map(|item| item.get(guard)), " ")
-        });
+        let param_string = param_list_string(guard, self, 0);
 
         match *name {
             Value::Symbol(s) => write!(f, "(Function {} ({}))", s.as_str(guard), param_string),
@@ -130,8 +177,25 @@ impl Print for Function {
         f: &mut fmt::Formatter,
     ) -> fmt::Result {
         self.print(guard, f)?;
-        write!(f, "\nbytecode follows:\n")?;
-        self.code(guard).debug(guard, f)
+        let code = self.code(guard);
+        write!(
+            f,
+            "\nbytecode follows ({} instructions, {} literals):\n",
+            code.instruction_count(),
+            code.literal_count()
+        )?;
+        code.debug(guard, f)
+    }
+}
+
+impl Trace for Function {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.name.get(guard));
+        self.code.get(guard).trace(guard, roots);
+        self.param_names.get(guard).trace(guard, roots);
+        roots.value(self.rest_param.get(guard));
+        roots.value(self.nonlocal_refs.get(guard));
     }
 }
 
@@ -243,13 +307,7 @@ impl Print for Partial {
     ) -> fmt::Result {
         let function = self.func.get(guard);
         let name = function.name.get(guard);
-        let params = function.param_names.get(guard);
-
-        let mut param_string = String::new();
-        params.access_slice(guard, |items| {
-            let start = self.used as usize;
-            param_string = join(items[start..].iter().map(|item| item.get(guard)), " ")
-        });
+        let param_string = param_list_string(guard, &function, self.used as usize);
 
         match *name {
             Value::Symbol(s) => write!(f, "(Partial {} ({}))", s.as_str(guard), param_string),
@@ -264,8 +322,86 @@ impl Print for Partial {
         f: &mut fmt::Formatter,
     ) -> fmt::Result {
         self.print(guard, f)?;
-        write!(f, "\nbytecode follows:\n")?;
-        self.func.get(guard).code(guard).debug(guard, f)
+        let code = self.func.get(guard).code(guard);
+        write!(
+            f,
+            "\nbytecode follows ({} instructions, {} literals):\n",
+            code.instruction_count(),
+            code.literal_count()
+        )?;
+        code.debug(guard, f)
+    }
+}
+
+impl Trace for Partial {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        self.args.get(guard).trace(guard, roots);
+        roots.value(self.env.get(guard));
+        roots.value(self.func.get(guard).as_tagged(guard));
+    }
+}
+
+/// A function implemented in Rust, registered with `Memory::register_native`
+// ANCHOR: DefNativeFunction
+#[derive(Clone)]
+pub struct NativeFunction {
+    /// name is the Symbol the function was registered under
+    name: TaggedCellPtr,
+    /// Number of arguments required to call the function
+    arity: u8,
+    /// Index of the Rust function in the native function registry
+    id: usize,
+}
+// ANCHOR_END: DefNativeFunction
+
+impl NativeFunction {
+    /// Allocate a NativeFunction object on the heap that refers to the registered Rust function
+    /// at index `id`
+    pub fn alloc<'guard>(
+        mem: &'guard MutatorView,
+        name: TaggedScopedPtr<'guard>,
+        arity: u8,
+        id: usize,
+    ) -> Result<ScopedPtr<'guard, NativeFunction>, RuntimeError> {
+        mem.alloc(NativeFunction {
+            name: TaggedCellPtr::new_with(name),
+            arity,
+            id,
+        })
+    }
+
+    /// Return the NativeFunction's name symbol
+    pub fn name_symbol<'guard>(&self, guard: &'guard dyn MutatorScope) -> TaggedScopedPtr<'guard> {
+        self.name.get(guard)
+    }
+
+    /// Return the number of arguments the NativeFunction takes
+    pub fn arity(&self) -> u8 {
+        self.arity
+    }
+
+    /// Return the index of the Rust function in the native function registry
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl Print for NativeFunction {
+    /// Prints a string representation of the native function
+    fn print<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "(NativeFunction {})", self.name.get(guard))
+    }
+}
+
+impl Trace for NativeFunction {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.name.get(guard));
     }
 }
 
@@ -276,3 +412,73 @@ pub struct CurriedArguments {
     // The ghc runtime would push all these to the stack and then consume the stack with
     // function continuations
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::containers::StackAnyContainer;
+    use crate::memory::{Memory, Mutator};
+    use crate::printer::render;
+
+    /// A function of the given parameters that does nothing
+    fn function_of<'guard>(
+        mem: &'guard MutatorView,
+        name: TaggedScopedPtr<'guard>,
+        params: &[&str],
+        rest: Option<&str>,
+    ) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+        let param_names = List::alloc(mem)?;
+        for param in params {
+            StackAnyContainer::push(&*param_names, mem, mem.lookup_sym(param))?;
+        }
+
+        let rest = match rest {
+            Some(rest) => mem.lookup_sym(rest),
+            None => mem.nil(),
+        };
+
+        Function::alloc(mem, name, param_names, rest, ByteCode::alloc(mem)?, None)
+    }
+
+    #[test]
+    fn print_variadic_signatures() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _input: ()) -> Result<(), RuntimeError> {
+                let name = mem.lookup_sym("f");
+                let a = mem.lookup_sym("a");
+                let arg = |value| TaggedCellPtr::new_with(value);
+
+                let fixed = function_of(mem, name, &["a", "b"], None)?;
+                assert!(render(*fixed.as_tagged(mem)) == "(Function f (a b))");
+
+                let variadic = function_of(mem, name, &["a", "b"], Some("rest"))?;
+                assert!(render(*variadic.as_tagged(mem)) == "(Function f (a b . rest))");
+
+                let only_rest = function_of(mem, mem.nil(), &[], Some("rest"))?;
+                assert!(render(*only_rest.as_tagged(mem)) == "(Function (. rest))");
+
+                // the remaining signature of a partially applied variadic function keeps the
+                // rest parameter
+                let partial = Partial::alloc(mem, variadic, None, &[arg(a)])?;
+                assert!(render(*partial.as_tagged(mem)) == "(Partial f (b . rest))");
+
+                let partial = Partial::alloc_clone(mem, partial, &[arg(a)])?;
+                assert!(render(*partial.as_tagged(mem)) == "(Partial f (. rest))");
+
+                let partial = Partial::alloc(mem, fixed, None, &[arg(a)])?;
+                assert!(render(*partial.as_tagged(mem)) == "(Partial f (b))");
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+}
@@ -6,16 +6,19 @@ use stickyimmix::{
 
 use crate::array::{ArrayU16, ArrayU32, ArrayU8};
 use crate::bytecode::{ArrayOpcode, ByteCode, InstructionStream};
+use crate::character::Char;
 use crate::dict::Dict;
-use crate::function::{Function, Partial};
+use crate::function::{Function, NativeFunction, Partial};
 use crate::list::List;
 use crate::memory::HeapStorage;
 use crate::number::NumberObject;
-use crate::pair::Pair;
+use crate::pair::{ListBuilder, Pair};
 use crate::pointerops::{AsNonNull, Tagged};
+use crate::record::Record;
 use crate::symbol::Symbol;
 use crate::taggedptr::FatPtr;
-use crate::text::Text;
+use crate::text::{Text, TextBuilder};
+use crate::values::Values;
 use crate::vm::{CallFrameList, Thread, Upvalue};
 
 /// Recognized heap-allocated types.
@@ -32,17 +35,23 @@ pub enum TypeList {
     ArrayU32,
     ByteCode,
     CallFrameList,
+    Char,
     Dict,
     Function,
     InstructionStream,
     List,
+    ListBuilder,
+    NativeFunction,
     NumberObject,
     Pair,
     Partial,
+    Record,
     Symbol,
     Text,
+    TextBuilder,
     Thread,
     Upvalue,
+    Values,
 }
 
 // Mark this as a Stickyimmix type-identifier type
@@ -73,23 +82,77 @@ impl ObjectHeader {
             TypeList::ArrayU8 => FatPtr::ArrayU8(RawPtr::untag(object_addr.cast::<ArrayU8>())),
             TypeList::ArrayU16 => FatPtr::ArrayU16(RawPtr::untag(object_addr.cast::<ArrayU16>())),
             TypeList::ArrayU32 => FatPtr::ArrayU32(RawPtr::untag(object_addr.cast::<ArrayU32>())),
+            TypeList::Char => FatPtr::Char(RawPtr::untag(object_addr.cast::<Char>())),
             TypeList::Dict => FatPtr::Dict(RawPtr::untag(object_addr.cast::<Dict>())),
             TypeList::Function => FatPtr::Function(RawPtr::untag(object_addr.cast::<Function>())),
             TypeList::List => FatPtr::List(RawPtr::untag(object_addr.cast::<List>())),
+            TypeList::ListBuilder => {
+                FatPtr::ListBuilder(RawPtr::untag(object_addr.cast::<ListBuilder>()))
+            }
+            TypeList::NativeFunction => {
+                FatPtr::NativeFunction(RawPtr::untag(object_addr.cast::<NativeFunction>()))
+            }
             TypeList::NumberObject => {
                 FatPtr::NumberObject(RawPtr::untag(object_addr.cast::<NumberObject>()))
             }
             TypeList::Pair => FatPtr::Pair(RawPtr::untag(object_addr.cast::<Pair>())),
             TypeList::Partial => FatPtr::Partial(RawPtr::untag(object_addr.cast::<Partial>())),
+            TypeList::Record => FatPtr::Record(RawPtr::untag(object_addr.cast::<Record>())),
             TypeList::Symbol => FatPtr::Symbol(RawPtr::untag(object_addr.cast::<Symbol>())),
             TypeList::Text => FatPtr::Text(RawPtr::untag(object_addr.cast::<Text>())),
+            TypeList::TextBuilder => {
+                FatPtr::TextBuilder(RawPtr::untag(object_addr.cast::<TextBuilder>()))
+            }
             TypeList::Upvalue => FatPtr::Upvalue(RawPtr::untag(object_addr.cast::<Upvalue>())),
+            TypeList::Values => FatPtr::Values(RawPtr::untag(object_addr.cast::<Values>())),
 
             // Other types not represented by FatPtr are an error to id here
             _ => panic!("Invalid ObjectHeader type tag {:?}!", self.type_id),
         }
     }
     // ANCHOR_END: DefObjectHeaderGetObjectFatPtr
+
+    // The forwarding accessors are for the evacuating collector, which doesn't exist yet.
+    // An evacuated object's own storage is no longer in use, so its first word holds the address
+    // of the new copy rather than the header growing a field that every object would pay for.
+    // Every object is at least a word in size, as allocations are rounded up to a whole word.
+
+    /// Record that the object has been evacuated to `to`.
+    ///
+    /// # Safety
+    ///
+    /// The header must precede its object, as it does on the heap, and the object must not be
+    /// used again: its first word is overwritten.
+    #[allow(dead_code)]
+    pub unsafe fn set_forwarding<T: Sized>(&mut self, to: RawPtr<T>) {
+        debug_assert!(self.size_bytes > 0, "no room for a forwarding address");
+        self.mark = Mark::Forwarded;
+
+        let object = HeapStorage::get_object(self.non_null_ptr());
+        *object.cast::<*const T>().as_ptr() = to.as_ptr();
+    }
+
+    /// Return the evacuated object's new address, if it has been forwarded.
+    ///
+    /// # Safety
+    ///
+    /// The header must precede its object, as it does on the heap.
+    #[allow(dead_code)]
+    pub unsafe fn forwarding<T: Sized>(&self) -> Option<RawPtr<T>> {
+        match self.mark {
+            Mark::Forwarded => {
+                let object = HeapStorage::get_object(self.non_null_ptr());
+                Some(RawPtr::new(*object.cast::<*const T>().as_ptr()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Has the object been evacuated?
+    #[allow(dead_code)]
+    pub fn is_forwarded(&self) -> bool {
+        self.mark == Mark::Forwarded
+    }
 }
 
 impl AsNonNull for ObjectHeader {}
@@ -155,14 +218,75 @@ declare_allocobject!(ArrayU16, ArrayU16);
 declare_allocobject!(ArrayU32, ArrayU32);
 declare_allocobject!(ByteCode, ByteCode);
 declare_allocobject!(CallFrameList, CallFrameList);
+declare_allocobject!(Char, Char);
 declare_allocobject!(Dict, Dict);
 declare_allocobject!(Function, Function);
 declare_allocobject!(InstructionStream, InstructionStream);
 declare_allocobject!(List, List);
+declare_allocobject!(ListBuilder, ListBuilder);
+declare_allocobject!(NativeFunction, NativeFunction);
 declare_allocobject!(NumberObject, NumberObject);
 declare_allocobject!(Pair, Pair);
 declare_allocobject!(Partial, Partial);
+declare_allocobject!(Record, Record);
 declare_allocobject!(Symbol, Symbol);
 declare_allocobject!(Text, Text);
+declare_allocobject!(TextBuilder, TextBuilder);
 declare_allocobject!(Thread, Thread);
 declare_allocobject!(Upvalue, Upvalue);
+declare_allocobject!(Values, Values);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A header followed by its object, as they are laid out on the heap
+    #[repr(C)]
+    struct Allocation {
+        header: ObjectHeader,
+        object: u64,
+    }
+
+    #[test]
+    fn header_size_is_one_word() {
+        assert!(std::mem::size_of::<ObjectHeader>() == std::mem::size_of::<usize>());
+        assert!(std::mem::align_of::<ObjectHeader>() <= std::mem::align_of::<usize>());
+    }
+
+    #[test]
+    fn forwarding_pointer() {
+        let mut allocation = Allocation {
+            header: ObjectHeader::new::<NumberObject>(8, SizeClass::Small, Mark::Allocated),
+            object: 7,
+        };
+        let header = &mut allocation.header;
+        assert!(!header.is_forwarded());
+        assert!(unsafe { header.forwarding::<u64>() }.is_none());
+
+        let target: u64 = 42;
+        let to = RawPtr::new(&target as *const u64);
+        unsafe { header.set_forwarding(to) };
+
+        assert!(header.is_forwarded());
+        assert!(!header.is_marked());
+        assert!(unsafe { header.forwarding::<u64>() } == Some(to));
+        assert!(header.type_id() == TypeList::NumberObject);
+        assert!(header.size() == 8);
+        assert!(allocation.object == &target as *const u64 as u64);
+    }
+
+    #[test]
+    fn marking_unaffected_by_forwarding() {
+        let mut allocation = Allocation {
+            header: ObjectHeader::new_array(8, SizeClass::Small, Mark::Allocated),
+            object: 7,
+        };
+        let header = &mut allocation.header;
+        assert!(!header.is_marked());
+        header.mark();
+        assert!(header.is_marked());
+        assert!(!header.is_forwarded());
+        assert!(unsafe { header.forwarding::<u8>() }.is_none());
+        assert!(allocation.object == 7);
+    }
+}
@@ -2,7 +2,11 @@
 ///
 /// This isn't using any look-ahead yet and so always interprets
 /// (.symbol) as ( DOT SYMBOL )
+use std::str::Chars;
+
+use crate::character::char_from_name;
 use crate::error::{err_lexer, spos, RuntimeError, SourcePos};
+use crate::symbolmap::SymbolCase;
 
 // key characters
 const OPEN_PAREN: char = '(';
@@ -14,6 +18,17 @@ const LF: char = '\n';
 const DOT: char = '.';
 const DOUBLE_QUOTE: char = '"';
 const SINGLE_QUOTE: char = '\'';
+const SEMICOLON: char = ';';
+
+// a character literal begins with this prefix
+const CHAR_PREFIX: &str = "#\\";
+
+// the reason given for a string that runs to the end of the input
+pub const UNTERMINATED_STRING: &str = "Unterminated string";
+
+// boolean literals
+const TRUE_LITERAL: &str = "#t";
+const FALSE_LITERAL: &str = "#f";
 
 // ANCHOR: DefTokenType
 #[derive(Debug, PartialEq)]
@@ -24,6 +39,8 @@ pub enum TokenType {
     Dot,
     Text(String),
     Quote,
+    Char(char),
+    Bool(bool),
 }
 // ANCHOR_END: DefTokenType
 
@@ -31,150 +48,270 @@ pub enum TokenType {
 #[derive(Debug, PartialEq)]
 pub struct Token {
     pub pos: SourcePos,
+    // the position following the last character of the token
+    pub end: SourcePos,
     pub token: TokenType,
 }
 // ANCHOR_END: DefToken
 
 impl Token {
-    fn new(pos: SourcePos, token: TokenType) -> Token {
-        Token { pos, token }
+    fn new(pos: SourcePos, end: SourcePos, token: TokenType) -> Token {
+        Token { pos, end, token }
     }
 }
 
-// tokenize a String
-pub fn tokenize(input: &str) -> Result<Vec<Token>, RuntimeError> {
-    use self::TokenType::*;
+/// Whitespace is any Unicode whitespace character. Tabs are whitespace by this definition but
+/// are rejected by the lexer.
+fn is_whitespace(c: char) -> bool {
+    c.is_whitespace()
+}
 
-    // characters that terminate a symbol
-    let terminating = [OPEN_PAREN, CLOSE_PAREN, SPACE, TAB, CR, LF, DOUBLE_QUOTE];
-    let is_terminating = |c: char| terminating.iter().any(|t| c == *t);
+/// Characters that terminate a symbol: whitespace and the characters that begin another token
+fn is_terminating(c: char) -> bool {
+    is_whitespace(c) || [OPEN_PAREN, CLOSE_PAREN, DOUBLE_QUOTE, SEMICOLON].contains(&c)
+}
 
-    // return value
-    let mut tokens = Vec::new();
+/// Symbols may contain any character, including non-ASCII letters, marks and symbols, except for
+/// terminating characters and control characters
+fn is_symbol_char(c: char) -> bool {
+    !is_terminating(c) && !c.is_control()
+}
 
-    // start line numbering at 1, the first character of each line being number 0
-    let mut lineno = 1;
-    let mut charno = 0;
+/// A streaming lexer over a `&str`, yielding one token at a time so that the whole input does not
+/// have to be tokenized up front. The first error ends the stream.
+pub struct Tokens<'input> {
+    chars: Chars<'input>,
+    current: Option<char>,
+    lineno: u32,
+    charno: u32,
+    failed: bool,
+    case: SymbolCase,
+}
 
-    let mut chars = input.chars();
-    let mut current = chars.next();
+impl<'input> Tokens<'input> {
+    pub fn new(input: &'input str) -> Tokens<'input> {
+        Tokens::with_case(input, SymbolCase::default())
+    }
 
-    loop {
-        match current {
-            Some(TAB) => {
-                return Err(err_lexer(
-                    spos(lineno, charno),
-                    "tabs are not valid whitespace",
-                ));
-            }
+    /// Lex with symbol names treated according to `case`. Character literals, which are case
+    /// sensitive, and the boolean literals are read as they are.
+    pub fn with_case(input: &'input str, case: SymbolCase) -> Tokens<'input> {
+        let mut chars = input.chars();
+        let current = chars.next();
+
+        // start line numbering at 1, the first character of each line being number 0
+        Tokens {
+            chars,
+            current,
+            lineno: 1,
+            charno: 0,
+            failed: false,
+            case,
+        }
+    }
+
+    /// Make a token that began at column `begin` of the current line and ends with the current
+    /// character
+    fn token(&self, begin: u32, token: TokenType) -> Token {
+        Token::new(
+            spos(self.lineno, begin),
+            spos(self.lineno, self.charno + 1),
+            token,
+        )
+    }
+
+    /// End the stream with the given error
+    fn fail(&mut self, error: RuntimeError) -> Option<Result<Token, RuntimeError>> {
+        self.failed = true;
+        Some(Err(error))
+    }
+}
 
-            Some(SPACE) => current = chars.next(),
+impl<'input> Iterator for Tokens<'input> {
+    type Item = Result<Token, RuntimeError>;
 
-            Some(CR) => {
-                current = chars.next();
+    fn next(&mut self) -> Option<Self::Item> {
+        use self::TokenType::*;
 
-                // consume \n if it follows \r
-                if let Some(LF) = current {
-                    current = chars.next();
+        if self.failed {
+            return None;
+        }
+
+        loop {
+            let token = match self.current {
+                Some(TAB) => {
+                    return self.fail(err_lexer(
+                        spos(self.lineno, self.charno),
+                        "tabs are not valid whitespace",
+                    ));
                 }
 
-                lineno += 1;
-                charno = 0;
-                continue;
-            }
+                Some(SPACE) => {
+                    self.current = self.chars.next();
+                    None
+                }
 
-            Some(LF) => {
-                current = chars.next();
-                lineno += 1;
-                charno = 0;
-                continue;
-            }
+                Some(CR) => {
+                    self.current = self.chars.next();
 
-            // this is not correct because it doesn't allow for a . to begin a number
-            // or a symbol. Will have to fix later.
-            Some(DOT) => {
-                tokens.push(Token::new(spos(lineno, charno), Dot));
-                current = chars.next();
-            }
+                    // consume \n if it follows \r
+                    if let Some(LF) = self.current {
+                        self.current = self.chars.next();
+                    }
 
-            Some(OPEN_PAREN) => {
-                tokens.push(Token::new(spos(lineno, charno), OpenParen));
-                current = chars.next();
-            }
+                    self.lineno += 1;
+                    self.charno = 0;
+                    continue;
+                }
 
-            Some(CLOSE_PAREN) => {
-                tokens.push(Token::new(spos(lineno, charno), CloseParen));
-                current = chars.next();
-            }
+                Some(LF) => {
+                    self.current = self.chars.next();
+                    self.lineno += 1;
+                    self.charno = 0;
+                    continue;
+                }
 
-            Some(DOUBLE_QUOTE) => {
-                let text_begin = charno;
+                // this is not correct because it doesn't allow for a . to begin a number
+                // or a symbol. Will have to fix later.
+                Some(DOT) => {
+                    self.current = self.chars.next();
+                    Some(self.token(self.charno, Dot))
+                }
 
-                let mut text = String::from("");
+                Some(OPEN_PAREN) => {
+                    self.current = self.chars.next();
+                    Some(self.token(self.charno, OpenParen))
+                }
 
-                loop {
-                    current = chars.next();
-                    if let Some(c) = current {
-                        if c == DOUBLE_QUOTE {
-                            current = chars.next();
-                            charno += 1;
-                            break;
+                Some(CLOSE_PAREN) => {
+                    self.current = self.chars.next();
+                    Some(self.token(self.charno, CloseParen))
+                }
+
+                Some(DOUBLE_QUOTE) => {
+                    let text_begin = self.charno;
+
+                    let mut text = String::from("");
+
+                    loop {
+                        self.current = self.chars.next();
+                        if let Some(c) = self.current {
+                            if c == DOUBLE_QUOTE {
+                                self.current = self.chars.next();
+                                self.charno += 1;
+                                break;
+                            } else {
+                                text.push(c);
+                                self.charno += 1;
+                            }
                         } else {
-                            text.push(c);
-                            charno += 1;
+                            return self.fail(err_lexer(
+                                spos(self.lineno, self.charno),
+                                UNTERMINATED_STRING,
+                            ));
                         }
-                    } else {
-                        return Err(err_lexer(spos(lineno, charno), "Unterminated string"));
                     }
+
+                    Some(self.token(text_begin, Text(text)))
                 }
 
-                tokens.push(Token::new(spos(lineno, text_begin), Text(text)))
-            }
+                // a comment runs to the end of the line
+                Some(SEMICOLON) => loop {
+                    self.current = self.chars.next();
+                    match self.current {
+                        Some(CR) | Some(LF) | None => break None,
+                        _ => self.charno += 1,
+                    }
+                },
 
-            Some(SINGLE_QUOTE) => {
-                tokens.push(Token::new(spos(lineno, charno), Quote));
-                current = chars.next();
-            }
+                Some(SINGLE_QUOTE) => {
+                    self.current = self.chars.next();
+                    Some(self.token(self.charno, Quote))
+                }
 
-            Some(non_terminating) => {
-                let symbol_begin = charno;
+                // other whitespace that does not begin a new line
+                Some(c) if is_whitespace(c) => {
+                    self.current = self.chars.next();
+                    None
+                }
 
-                let mut symbol = String::from("");
-                symbol.push(non_terminating);
+                Some(c) if !is_symbol_char(c) => {
+                    return self.fail(err_lexer(
+                        spos(self.lineno, self.charno),
+                        "Invalid character in symbol",
+                    ));
+                }
 
-                // consume symbol
-                loop {
-                    current = chars.next();
-                    if let Some(c) = current {
-                        if is_terminating(c) {
-                            break;
+                Some(non_terminating) => {
+                    let symbol_begin = self.charno;
+
+                    let mut symbol = String::from("");
+                    symbol.push(non_terminating);
+
+                    // consume symbol. The first character following a character literal prefix
+                    // is always part of the literal, even if it would otherwise terminate a symbol
+                    loop {
+                        self.current = self.chars.next();
+                        if let Some(c) = self.current {
+                            if is_symbol_char(c) || symbol == CHAR_PREFIX {
+                                symbol.push(c);
+                                self.charno += 1;
+                            } else if is_terminating(c) {
+                                break;
+                            } else {
+                                return self.fail(err_lexer(
+                                    spos(self.lineno, self.charno + 1),
+                                    "Invalid character in symbol",
+                                ));
+                            }
                         } else {
-                            symbol.push(c);
-                            charno += 1;
+                            break;
                         }
-                    } else {
-                        break;
                     }
+
+                    // complete symbol, boolean literal or character literal
+                    let token = match symbol.strip_prefix(CHAR_PREFIX) {
+                        Some(name) => match char_from_name(name) {
+                            Some(c) => Char(c),
+                            None => {
+                                return self.fail(
+                                    err_lexer(
+                                        spos(self.lineno, symbol_begin),
+                                        "Unknown character literal",
+                                    )
+                                    .with_end(spos(self.lineno, self.charno + 1)),
+                                )
+                            }
+                        },
+                        None if symbol == TRUE_LITERAL => Bool(true),
+                        None if symbol == FALSE_LITERAL => Bool(false),
+                        None => Symbol(self.case.apply(&symbol).into_owned()),
+                    };
+                    Some(self.token(symbol_begin, token))
                 }
 
-                // complete symbol
-                tokens.push(Token::new(spos(lineno, symbol_begin), Symbol(symbol)));
-            }
+                // EOL
+                None => return None,
+            };
 
-            // EOL
-            None => break,
-        }
+            self.charno += 1;
 
-        charno += 1;
+            if let Some(token) = token {
+                return Some(Ok(token));
+            }
+        }
     }
-
-    Ok(tokens)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    // tokenize a String all at once, to compare the tokens
+    fn tokenize(input: &str) -> Result<Vec<Token>, RuntimeError> {
+        Tokens::new(input).collect()
+    }
+
     #[test]
     fn lexer_empty_string() {
         if let Ok(tokens) = tokenize("") {
@@ -184,24 +321,75 @@ mod test {
         }
     }
 
+    #[test]
+    fn lexer_comments() {
+        if let Ok(tokens) = tokenize("; leading\n(foo;trailing\n bar) ; end") {
+            assert!(tokens.len() == 4);
+            assert_eq!(
+                tokens[0],
+                Token::new(spos(2, 0), spos(2, 1), TokenType::OpenParen)
+            );
+            assert_eq!(
+                tokens[1],
+                Token::new(
+                    spos(2, 1),
+                    spos(2, 4),
+                    TokenType::Symbol(String::from("foo"))
+                )
+            );
+            assert_eq!(
+                tokens[2],
+                Token::new(
+                    spos(3, 1),
+                    spos(3, 4),
+                    TokenType::Symbol(String::from("bar"))
+                )
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(spos(3, 4), spos(3, 5), TokenType::CloseParen)
+            );
+        } else {
+            assert!(false, "unexpected error");
+        }
+    }
+
     #[test]
     fn lexer_one_line() {
         if let Ok(tokens) = tokenize("(foo bar baz)") {
             assert!(tokens.len() == 5);
-            assert_eq!(tokens[0], Token::new(spos(1, 0), TokenType::OpenParen));
+            assert_eq!(
+                tokens[0],
+                Token::new(spos(1, 0), spos(1, 1), TokenType::OpenParen)
+            );
             assert_eq!(
                 tokens[1],
-                Token::new(spos(1, 1), TokenType::Symbol(String::from("foo")))
+                Token::new(
+                    spos(1, 1),
+                    spos(1, 4),
+                    TokenType::Symbol(String::from("foo"))
+                )
             );
             assert_eq!(
                 tokens[2],
-                Token::new(spos(1, 5), TokenType::Symbol(String::from("bar")))
+                Token::new(
+                    spos(1, 5),
+                    spos(1, 8),
+                    TokenType::Symbol(String::from("bar"))
+                )
             );
             assert_eq!(
                 tokens[3],
-                Token::new(spos(1, 9), TokenType::Symbol(String::from("baz")))
+                Token::new(
+                    spos(1, 9),
+                    spos(1, 12),
+                    TokenType::Symbol(String::from("baz"))
+                )
+            );
+            assert_eq!(
+                tokens[4],
+                Token::new(spos(1, 12), spos(1, 13), TokenType::CloseParen)
             );
-            assert_eq!(tokens[4], Token::new(spos(1, 12), TokenType::CloseParen));
         } else {
             assert!(false, "unexpected error");
         }
@@ -211,20 +399,38 @@ mod test {
     fn lexer_multi_line() {
         if let Ok(tokens) = tokenize("( foo\nbar\nbaz\n)") {
             assert!(tokens.len() == 5);
-            assert_eq!(tokens[0], Token::new(spos(1, 0), TokenType::OpenParen));
+            assert_eq!(
+                tokens[0],
+                Token::new(spos(1, 0), spos(1, 1), TokenType::OpenParen)
+            );
             assert_eq!(
                 tokens[1],
-                Token::new(spos(1, 2), TokenType::Symbol(String::from("foo")))
+                Token::new(
+                    spos(1, 2),
+                    spos(1, 5),
+                    TokenType::Symbol(String::from("foo"))
+                )
             );
             assert_eq!(
                 tokens[2],
-                Token::new(spos(2, 0), TokenType::Symbol(String::from("bar")))
+                Token::new(
+                    spos(2, 0),
+                    spos(2, 3),
+                    TokenType::Symbol(String::from("bar"))
+                )
             );
             assert_eq!(
                 tokens[3],
-                Token::new(spos(3, 0), TokenType::Symbol(String::from("baz")))
+                Token::new(
+                    spos(3, 0),
+                    spos(3, 3),
+                    TokenType::Symbol(String::from("baz"))
+                )
+            );
+            assert_eq!(
+                tokens[4],
+                Token::new(spos(4, 0), spos(4, 1), TokenType::CloseParen)
             );
-            assert_eq!(tokens[4], Token::new(spos(4, 0), TokenType::CloseParen));
         } else {
             assert!(false, "unexpected error");
         }
@@ -246,10 +452,255 @@ mod test {
 
     #[test]
     fn lexer_text() {
-        if let Ok(_tokens) = tokenize("(foo \"text\" bar)") {
-            // TODO
+        if let Ok(tokens) = tokenize("(foo \"text\" bar)") {
+            assert!(tokens.len() == 5);
+            // a Text token spans its quotes
+            assert_eq!(
+                tokens[2],
+                Token::new(
+                    spos(1, 5),
+                    spos(1, 11),
+                    TokenType::Text(String::from("text"))
+                )
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(
+                    spos(1, 12),
+                    spos(1, 15),
+                    TokenType::Symbol(String::from("bar"))
+                )
+            );
         } else {
             assert!(false, "unexpected error")
         }
     }
+
+    #[test]
+    fn lexer_char_literals() {
+        if let Ok(tokens) = tokenize("(#\\a #\\( #\\space #\\newline #\\))") {
+            assert!(tokens.len() == 7);
+            assert_eq!(
+                tokens[1],
+                Token::new(spos(1, 1), spos(1, 4), TokenType::Char('a'))
+            );
+            assert_eq!(
+                tokens[2],
+                Token::new(spos(1, 5), spos(1, 8), TokenType::Char('('))
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(spos(1, 9), spos(1, 16), TokenType::Char(' '))
+            );
+            assert_eq!(
+                tokens[4],
+                Token::new(spos(1, 17), spos(1, 26), TokenType::Char('\n'))
+            );
+            assert_eq!(
+                tokens[5],
+                Token::new(spos(1, 27), spos(1, 30), TokenType::Char(')'))
+            );
+            assert_eq!(
+                tokens[6],
+                Token::new(spos(1, 30), spos(1, 31), TokenType::CloseParen)
+            );
+        } else {
+            assert!(false, "unexpected error");
+        }
+    }
+
+    #[test]
+    fn lexer_bad_char_literal() {
+        for input in &["#\\bogus", "(a #\\"] {
+            match tokenize(input) {
+                Err(e) => assert!(e.error_pos().is_some()),
+                Ok(_) => assert!(false, "expected an error for {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn lexer_boolean_literals() {
+        if let Ok(tokens) = tokenize("(#t #f #true)") {
+            assert!(tokens.len() == 5);
+            assert_eq!(
+                tokens[1],
+                Token::new(spos(1, 1), spos(1, 3), TokenType::Bool(true))
+            );
+            assert_eq!(
+                tokens[2],
+                Token::new(spos(1, 4), spos(1, 6), TokenType::Bool(false))
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(
+                    spos(1, 7),
+                    spos(1, 12),
+                    TokenType::Symbol(String::from("#true"))
+                )
+            );
+        } else {
+            assert!(false, "unexpected error");
+        }
+    }
+
+    #[test]
+    fn lexer_unicode_whitespace() {
+        // a no-break space, an em space and an ideographic space separate symbols
+        if let Ok(tokens) = tokenize("(a\u{a0}b\u{2003}c\u{3000})") {
+            assert!(tokens.len() == 5);
+            assert_eq!(
+                tokens[1],
+                Token::new(spos(1, 1), spos(1, 2), TokenType::Symbol(String::from("a")))
+            );
+            assert_eq!(
+                tokens[2],
+                Token::new(spos(1, 3), spos(1, 4), TokenType::Symbol(String::from("b")))
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(spos(1, 5), spos(1, 6), TokenType::Symbol(String::from("c")))
+            );
+            assert_eq!(
+                tokens[4],
+                Token::new(spos(1, 7), spos(1, 8), TokenType::CloseParen)
+            );
+        } else {
+            assert!(false, "unexpected error");
+        }
+    }
+
+    #[test]
+    fn lexer_unicode_symbols() {
+        // letters, combining marks and symbols outside ASCII are all valid in symbols
+        if let Ok(tokens) = tokenize("(λ café e\u{301} →)") {
+            assert!(tokens.len() == 6);
+            assert_eq!(
+                tokens[1],
+                Token::new(spos(1, 1), spos(1, 2), TokenType::Symbol(String::from("λ")))
+            );
+            assert_eq!(
+                tokens[2],
+                Token::new(
+                    spos(1, 3),
+                    spos(1, 7),
+                    TokenType::Symbol(String::from("café"))
+                )
+            );
+            assert_eq!(
+                tokens[3],
+                Token::new(
+                    spos(1, 8),
+                    spos(1, 10),
+                    TokenType::Symbol(String::from("e\u{301}"))
+                )
+            );
+            assert_eq!(
+                tokens[4],
+                Token::new(
+                    spos(1, 11),
+                    spos(1, 12),
+                    TokenType::Symbol(String::from("→"))
+                )
+            );
+        } else {
+            assert!(false, "unexpected error");
+        }
+    }
+
+    #[test]
+    fn lexer_control_characters() {
+        // inside a symbol and at the start of a token
+        for (input, column) in &[("(a\u{7}b)", 2), ("(a \u{0})", 3)] {
+            match tokenize(input) {
+                Err(e) => assert!(
+                    e.error_pos()
+                        == Some(SourcePos {
+                            line: 1,
+                            column: *column
+                        })
+                ),
+                Ok(_) => assert!(false, "expected an error for {:?}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn lexer_stream_matches_tokenize() {
+        let inputs = [
+            "",
+            "(foo bar)",
+            "; leading\n(foo;trailing\n bar) ; end",
+            "'(a . \"some text\")\r\n(#\\space #t #f)",
+            "(λ café e\u{301} →)",
+            "(def f (x)\n  (cons x '(1 2 3)))",
+        ];
+
+        for input in inputs.iter() {
+            let streamed: Vec<Token> = Tokens::new(input).map(|t| t.unwrap()).collect();
+            assert_eq!(streamed, tokenize(input).unwrap());
+        }
+    }
+
+    #[test]
+    fn lexer_stream_ends_at_error() {
+        // tokens before the error are yielded as they are reached
+        let mut tokens = Tokens::new("(foo \"unterminated");
+        assert_eq!(
+            tokens.next().unwrap().unwrap(),
+            Token::new(spos(1, 0), spos(1, 1), TokenType::OpenParen)
+        );
+        assert_eq!(
+            tokens.next().unwrap().unwrap(),
+            Token::new(
+                spos(1, 1),
+                spos(1, 4),
+                TokenType::Symbol(String::from("foo"))
+            )
+        );
+
+        let streamed_err = tokens.next().unwrap().unwrap_err();
+        let collected_err = tokenize("(foo \"unterminated").unwrap_err();
+        assert!(streamed_err.error_pos() == collected_err.error_pos());
+        assert!(streamed_err.error_kind() == collected_err.error_kind());
+
+        // and nothing after it
+        assert!(tokens.next().is_none());
+    }
+
+    #[test]
+    fn lexer_symbol_case() {
+        let input = "(Foo BAR #\\A #t)";
+        let symbols = |case| -> Vec<TokenType> {
+            Tokens::with_case(input, case)
+                .map(|t| t.unwrap().token)
+                .collect()
+        };
+
+        // case is preserved unless folding is asked for
+        assert_eq!(
+            symbols(SymbolCase::Preserve),
+            vec![
+                TokenType::OpenParen,
+                TokenType::Symbol(String::from("Foo")),
+                TokenType::Symbol(String::from("BAR")),
+                TokenType::Char('A'),
+                TokenType::Bool(true),
+                TokenType::CloseParen,
+            ]
+        );
+
+        // character literals are not folded
+        assert_eq!(
+            symbols(SymbolCase::Fold),
+            vec![
+                TokenType::OpenParen,
+                TokenType::Symbol(String::from("foo")),
+                TokenType::Symbol(String::from("bar")),
+                TokenType::Char('A'),
+                TokenType::Bool(true),
+                TokenType::CloseParen,
+            ]
+        );
+    }
 }
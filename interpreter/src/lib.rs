@@ -0,0 +1,50 @@
+//! The Eval-R-Us interpreter: a lexer, parser and bytecode compiler, and a virtual machine that
+//! runs on the Sticky Immix heap. `main.rs` builds the REPL executable on top of this library, and
+//! other programs, such as the benchmarks, can embed it through `Memory` and `Session`.
+extern crate blockalloc;
+extern crate fnv;
+extern crate itertools;
+extern crate rustyline;
+extern crate stickyimmix;
+
+mod arena;
+mod array;
+mod bytecode;
+mod character;
+mod compiler;
+mod containers;
+mod dict;
+mod error;
+mod function;
+mod hashable;
+mod headers;
+mod lexer;
+mod list;
+mod memory;
+mod number;
+mod pair;
+mod parser;
+mod pointerops;
+mod printer;
+mod rawarray;
+mod record;
+mod repl;
+mod safeptr;
+mod scratch;
+mod session;
+mod snapshot;
+mod symbol;
+mod symbolmap;
+mod taggedptr;
+mod text;
+mod traverse;
+mod values;
+mod vm;
+
+pub use crate::containers::HashIndexedAnyContainer;
+pub use crate::dict::Dict;
+pub use crate::error::{err_user, ErrorKind, RuntimeError};
+pub use crate::memory::{Memory, Mutator, MutatorView};
+pub use crate::parser::parse_all_recovering;
+pub use crate::repl::{needs_more_input, RepMaker};
+pub use crate::session::Session;
@@ -1,10 +1,7 @@
-extern crate blockalloc;
 extern crate clap;
 extern crate dirs;
-extern crate fnv;
-extern crate itertools;
+extern crate evalrus;
 extern crate rustyline;
-extern crate stickyimmix;
 
 use std::fs::File;
 use std::io;
@@ -16,36 +13,7 @@ use clap::{App, Arg};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-mod arena;
-mod array;
-mod bytecode;
-mod compiler;
-mod containers;
-mod dict;
-mod error;
-mod function;
-mod hashable;
-mod headers;
-mod lexer;
-mod list;
-mod memory;
-mod number;
-mod pair;
-mod parser;
-mod pointerops;
-mod printer;
-mod rawarray;
-mod repl;
-mod safeptr;
-mod symbol;
-mod symbolmap;
-mod taggedptr;
-mod text;
-mod vm;
-
-use crate::error::RuntimeError;
-use crate::memory::Memory;
-use crate::repl::RepMaker;
+use evalrus::{needs_more_input, Memory, RepMaker, RuntimeError};
 
 /// Read a file into a String
 fn load_file(filename: &str) -> Result<String, io::Error> {
@@ -91,15 +59,31 @@ fn read_print_loop() -> Result<(), RuntimeError> {
     let rep_maker = RepMaker {};
     let rep = mem.mutate(&rep_maker, ())?;
 
+    // lines of an expression that is not yet complete
+    let mut input = String::new();
+
     // repl
     loop {
-        let readline = reader.readline("> ");
+        let prompt = if input.is_empty() { "> " } else { "... " };
+        let readline = reader.readline(prompt);
 
         match readline {
             // valid input
             Ok(line) => {
-                reader.add_history_entry(&line);
-                mem.mutate(&rep, line)?;
+                if !input.is_empty() {
+                    input.push('\n');
+                }
+                input.push_str(&line);
+
+                // keep reading until the expression is complete
+                if needs_more_input(&input) {
+                    continue;
+                }
+
+                reader.add_history_entry(&input);
+                mem.mutate(&rep, std::mem::take(&mut input))?;
+                // the heap may only be collected between mutation sessions
+                mem.collect()?;
             }
 
             // some kind of program termination condition
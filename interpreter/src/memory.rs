@@ -2,28 +2,88 @@
 ///
 /// Defines Stack, Heap and Memory types, and a MemoryView type that gives a mutator a safe
 /// view into the stack and heap.
-use stickyimmix::{AllocObject, AllocRaw, ArraySize, RawPtr, StickyImmixHeap};
+///
+/// The mutator and the collector never run at the same time. The mutator only has access to
+/// the heap during a mutation session, `Memory::mutate()`, and the collector only runs between
+/// mutation sessions, `Memory::collect()`. No `ScopedPtr` can outlive the session it was
+/// obtained in, so there are no stale pointers held across a collection. Starting a mutation
+/// session or allocating while a collection is in progress is an error.
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::Write;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use stickyimmix::{AllocError, AllocObject, AllocRaw, ArraySize, RawPtr, StickyImmixHeap};
 
-use crate::error::RuntimeError;
+use crate::array::Array;
+use crate::error::{err_alloc, err_eval, ErrorKind, RuntimeError};
 use crate::headers::{ObjectHeader, TypeList};
 use crate::pointerops::ScopedRef;
-use crate::safeptr::{MutatorScope, ScopedPtr, TaggedScopedPtr};
-use crate::symbolmap::SymbolMap;
+use crate::rawarray::RawArray;
+use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedScopedPtr};
+use crate::scratch::{Scratch, ScratchPool};
+use crate::session::Session;
+use crate::symbolmap::{SymbolCase, SymbolMap};
 use crate::taggedptr::{FatPtr, TaggedPtr};
+use crate::text::Text;
+
+#[cfg(feature = "conservative-roots")]
+use crate::{pointerops::untag_word, vm::Thread};
+
+/// The signature of a Rust function that can be called from interpreted code. The arguments are
+/// given in call order and the returned value is the result of the call.
+pub type NativeFn = dyn for<'guard> Fn(
+    &'guard MutatorView<'guard>,
+    &[TaggedScopedPtr<'guard>],
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError>;
+
+/// Counts of garbage collection activity since the memory was created, updated by
+/// `Memory::collect()`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// The number of collections that have run
+    pub collections: usize,
+    /// The total number of bytes freed by all collections
+    pub bytes_reclaimed: usize,
+    /// How long the most recent collection took
+    pub last_duration: Duration,
+}
+
+/// A registered native function
+struct Native {
+    name: String,
+    arity: u8,
+    function: Box<NativeFn>,
+}
 
 /// This type describes the mutator's view into memory - the heap and symbol name/ptr lookup.
 ///
 /// It implements `MutatorScope` such that any `TaggedScopedPtr` or `Value` instances must be lifetime-
 /// limited to the lifetime of this instance using `&'scope dyn MutatorScope`;
+///
+/// An instance only lives for the duration of one `Memory::mutate()` call. State that must outlive
+/// it, such as a `Thread` and its globals, is kept in a `CellPtr` held by the `Mutator` and
+/// dereferenced again in the next call; see `Session`.
 // ANCHOR: DefMutatorView
 pub struct MutatorView<'memory> {
     heap: &'memory Heap,
+    output: &'memory RefCell<Box<dyn Write>>,
+    scratch: &'memory ScratchPool,
+    loaded: &'memory RefCell<HashSet<PathBuf>>,
 }
 // ANCHOR_END: DefMutatorView
 
 impl<'memory> MutatorView<'memory> {
     fn new(mem: &'memory Memory) -> MutatorView<'memory> {
-        MutatorView { heap: &mem.heap }
+        MutatorView {
+            heap: &mem.heap,
+            output: &mem.output,
+            scratch: &mem.scratch,
+            loaded: &mem.loaded,
+        }
     }
 
     /// Get a Symbol pointer from its name
@@ -33,6 +93,35 @@ impl<'memory> MutatorView<'memory> {
     }
     // ANCHOR_END: DefMutatorViewLookupSym
 
+    /// How the case of symbol names is treated when source code is read and symbols are looked up
+    pub fn symbol_case(&self) -> SymbolCase {
+        self.heap.syms.case()
+    }
+
+    /// Whether equal string literals read from source code share a single Text
+    pub fn text_interning(&self) -> bool {
+        self.heap.intern_texts
+    }
+
+    /// Return a Text holding a string literal read from source code. If text interning is on,
+    /// equal literals share a single Text, so a literal must never be modified in place.
+    pub fn text_literal(&self, content: &str) -> Result<TaggedScopedPtr<'_>, RuntimeError> {
+        if !self.heap.intern_texts {
+            return self.alloc_tagged(Text::new_from_str(self, content)?);
+        }
+
+        if let Some(text) = self.heap.texts.borrow().get(content) {
+            return Ok(TaggedScopedPtr::new(self, *text));
+        }
+
+        let text = self.alloc_tagged(Text::new_from_str(self, content)?)?;
+        self.heap
+            .texts
+            .borrow_mut()
+            .insert(String::from(content), text.get_ptr());
+        Ok(text)
+    }
+
     /// Write an object into the heap and return a scope-limited pointer to it
     // ANCHOR: DefMutatorViewAlloc
     pub fn alloc<T>(&self, object: T) -> Result<ScopedPtr<'_, T>, RuntimeError>
@@ -66,6 +155,79 @@ impl<'memory> MutatorView<'memory> {
     pub fn nil(&self) -> TaggedScopedPtr<'_> {
         TaggedScopedPtr::new(self, TaggedPtr::nil())
     }
+
+    /// Return the number of objects and arrays allocated since the heap was created
+    pub fn allocation_count(&self) -> usize {
+        self.heap.allocations.get()
+    }
+
+    /// Make the next `count` allocation requests fail as if the heap were out of memory
+    #[cfg(test)]
+    pub fn fail_next_allocations(&self, count: usize) {
+        self.heap.failing_allocations.set(count);
+    }
+
+    /// Return true if the object at the given address has survived a collection
+    #[cfg(test)]
+    pub fn is_old(&self, address: usize) -> bool {
+        self.heap.heap.is_old(RawPtr::new(address as *const u8))
+    }
+
+    /// Return the garbage collection activity counters
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.gc_stats.get()
+    }
+
+    /// Return the `true` symbol, preloaded when the heap was created
+    pub fn true_sym(&self) -> TaggedScopedPtr<'_> {
+        TaggedScopedPtr::new(self, self.heap.true_sym)
+    }
+
+    /// Return the name and arity of each registered native function. The position of each in
+    /// the iteration is the id it is called by.
+    pub fn natives(&self) -> impl Iterator<Item = (&str, u8)> {
+        self.heap
+            .natives
+            .iter()
+            .map(|native| (native.name.as_str(), native.arity))
+    }
+
+    /// Call the registered native function with the given id
+    pub fn call_native<'guard>(
+        &'guard self,
+        id: usize,
+        args: &[TaggedScopedPtr<'guard>],
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        match self.heap.natives.get(id) {
+            Some(native) => (native.function)(self, args),
+            None => Err(err_eval(&format!("No native function with id {}", id))),
+        }
+    }
+
+    /// Unpack a list of Pair instances into a buffer borrowed from a pool of reusable buffers.
+    /// Prefer this to `vec_from_pairs()` for short-lived lists that are unpacked often.
+    pub fn scratch_from_pairs<'guard>(
+        &'guard self,
+        pair_list: TaggedScopedPtr<'guard>,
+    ) -> Result<Scratch<'guard>, RuntimeError> {
+        self.scratch.take_from_pairs(self, pair_list)
+    }
+
+    /// Return the number of scratch buffers created since the memory was created
+    pub fn scratch_buffer_count(&self) -> usize {
+        self.scratch.created()
+    }
+
+    /// Record that the source file at the given canonical path has been loaded, returning false
+    /// if it already had been
+    pub fn mark_loaded(&self, path: PathBuf) -> bool {
+        self.loaded.borrow_mut().insert(path)
+    }
+
+    /// Return the writer that program and interpreter output should be sent to
+    pub fn output(&self) -> RefMut<'_, Box<dyn Write>> {
+        self.output.borrow_mut()
+    }
 }
 
 impl<'memory> MutatorScope for MutatorView<'memory> {}
@@ -80,17 +242,94 @@ pub type HeapStorage = StickyImmixHeap<ObjectHeader>;
 struct Heap {
     heap: HeapStorage,
     syms: SymbolMap,
+    true_sym: TaggedPtr,
+    /// Whether equal string literals share a single Text
+    intern_texts: bool,
+    /// The interned string literals, by content
+    texts: RefCell<HashMap<String, TaggedPtr>>,
+    natives: Vec<Native>,
+    collecting: Cell<bool>,
+    allocations: Cell<usize>,
+    gc_stats: Cell<GcStats>,
+    /// The number of upcoming allocation requests to fail, for testing out of memory handling
+    #[cfg(test)]
+    failing_allocations: Cell<usize>,
 }
 // ANCHOR_END: DefHeap
 
 impl Heap {
     fn new() -> Heap {
+        let syms = SymbolMap::new();
+        let true_sym = TaggedPtr::symbol(syms.lookup("true"));
+
         Heap {
             heap: HeapStorage::new(),
-            syms: SymbolMap::new(),
+            syms,
+            true_sym,
+            intern_texts: false,
+            texts: RefCell::new(HashMap::new()),
+            natives: Vec::new(),
+            collecting: Cell::new(false),
+            allocations: Cell::new(0),
+            gc_stats: Cell::new(GcStats::default()),
+            #[cfg(test)]
+            failing_allocations: Cell::new(0),
+        }
+    }
+
+    /// Return an error if a collection is in progress
+    fn check_not_collecting(&self) -> Result<(), RuntimeError> {
+        if self.collecting.get() {
+            Err(RuntimeError::new(ErrorKind::CollectionInProgress))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run the given function with the heap marked as being collected
+    fn collecting<F, R>(&self, f: F) -> Result<R, RuntimeError>
+    where
+        F: FnOnce() -> Result<R, RuntimeError>,
+    {
+        self.check_not_collecting()?;
+        self.collecting.set(true);
+        let result = f();
+        self.collecting.set(false);
+        result
+    }
+
+    /// Count a collection that began at `start` in the collection statistics
+    fn record_collection(&self, start: Instant, bytes_reclaimed: usize) {
+        let mut stats = self.gc_stats.get();
+        stats.collections += 1;
+        stats.bytes_reclaimed += bytes_reclaimed;
+        stats.last_duration = start.elapsed();
+        self.gc_stats.set(stats);
+    }
+
+    /// Add one to the count of allocation requests
+    fn count_allocation(&self) {
+        self.allocations.set(self.allocations.get() + 1);
+    }
+
+    /// Fail the allocation request as if the heap were out of memory if tests have asked for it
+    #[cfg(test)]
+    fn injected_failure(&self) -> Result<(), AllocError> {
+        let remaining = self.failing_allocations.get();
+        if remaining > 0 {
+            self.failing_allocations.set(remaining - 1);
+            Err(AllocError::OOM)
+        } else {
+            Ok(())
         }
     }
 
+    #[cfg(not(test))]
+    #[inline(always)]
+    fn injected_failure(&self) -> Result<(), AllocError> {
+        Ok(())
+    }
+
     /// Get a Symbol pointer from its name
     // ANCHOR: DefHeapLookupSym
     fn lookup_sym(&self, name: &str) -> TaggedPtr {
@@ -104,7 +343,11 @@ impl Heap {
     where
         T: AllocObject<TypeList>,
     {
-        Ok(self.heap.alloc(object)?)
+        self.check_not_collecting()?;
+        self.count_allocation();
+        self.injected_failure()
+            .and_then(|_| self.heap.alloc(object))
+            .map_err(|e| err_alloc(e, size_of::<T>(), &format!("{:?}", T::TYPE_ID)))
     }
     // ANCHOR_END: DefHeapAlloc
 
@@ -115,12 +358,29 @@ impl Heap {
         FatPtr: From<RawPtr<T>>,
         T: AllocObject<TypeList>,
     {
-        Ok(TaggedPtr::from(FatPtr::from(self.heap.alloc(object)?)))
+        self.check_not_collecting()?;
+        self.count_allocation();
+        let object = self
+            .injected_failure()
+            .and_then(|_| self.heap.alloc(object))
+            .map_err(|e| err_alloc(e, size_of::<T>(), &format!("{:?}", T::TYPE_ID)))?;
+        Ok(TaggedPtr::from(FatPtr::from(object)))
     }
     // ANCHOR_END: DefHeapAllocTagged
 
     fn alloc_array(&self, capacity: ArraySize) -> Result<RawPtr<u8>, RuntimeError> {
-        Ok(self.heap.alloc_array(capacity)?)
+        self.check_not_collecting()?;
+        self.count_allocation();
+        self.injected_failure()
+            .and_then(|_| self.heap.alloc_array(capacity))
+            .map_err(|e| err_alloc(e, capacity as usize, "Array"))
+    }
+
+    /// Forget every heap object, keeping the heap blocks for reuse. Symbols are not stored in
+    /// the heap and remain interned, but interned Text is forgotten with everything else.
+    fn reset(&mut self) {
+        self.heap.reset();
+        self.texts.get_mut().clear();
     }
 }
 
@@ -128,22 +388,279 @@ impl Heap {
 // ANCHOR: DefMemory
 pub struct Memory {
     heap: Heap,
+    output: RefCell<Box<dyn Write>>,
+    scratch: ScratchPool,
+    /// The canonical paths of the source files loaded by `require`
+    loaded: RefCell<HashSet<PathBuf>>,
 }
 // ANCHOR_END: DefMemory
 
 impl Memory {
-    /// Instantiate a new memory environment
+    /// Instantiate a new memory environment that writes output to stdout
     pub fn new() -> Memory {
-        Memory { heap: Heap::new() }
+        Memory::with_output(Box::new(io::stdout()))
+    }
+
+    /// Instantiate a new memory environment that writes output to the given writer
+    pub fn with_output(output: Box<dyn Write>) -> Memory {
+        Memory {
+            heap: Heap::new(),
+            output: RefCell::new(output),
+            scratch: ScratchPool::new(),
+            loaded: RefCell::new(HashSet::new()),
+        }
     }
 
     /// Run a mutator process
     // ANCHOR: DefMemoryMutate
     pub fn mutate<M: Mutator>(&self, m: &M, input: M::Input) -> Result<M::Output, RuntimeError> {
+        self.heap.check_not_collecting()?;
         let mut guard = MutatorView::new(self);
         m.run(&mut guard, input)
     }
     // ANCHOR_END: DefMemoryMutate
+
+    /// Forget every object on the heap so that an unrelated program can be run in the same
+    /// memory, reusing the already allocated heap blocks. This must happen between mutation
+    /// sessions.
+    ///
+    /// Globals and upvalues belong to a `Thread`, which is itself on the heap, so they are
+    /// discarded too. The globals defined by files loaded with `require` are gone, so those files
+    /// may be loaded again.
+    ///
+    /// # Safety
+    ///
+    /// Every heap pointer held outside of a mutation session is left dangling, and must not be
+    /// dereferenced again. That includes the `CellPtr`s held by a `Session`, a `ReadEvalPrint`
+    /// or a `CompileCache`, so all of those must be dropped before the reset, and a new
+    /// `Thread` must be allocated after it.
+    pub unsafe fn reset(&mut self) {
+        self.heap.reset();
+        self.loaded.borrow_mut().clear();
+    }
+
+    /// Set how the case of symbol names is treated when source code is read and symbols are
+    /// looked up. This should be set before anything is evaluated: symbols that were already
+    /// interned under names with upper case letters can't be found once folding is on.
+    pub fn set_symbol_case(&mut self, case: SymbolCase) {
+        self.heap.syms.set_case(case);
+    }
+
+    /// Set whether equal string literals read from source code share a single Text rather than
+    /// each allocating their own, which is off by default. Shared literals are identical, so
+    /// `is?` holds for them and `equal?` need not compare their contents. Text built at run time,
+    /// such as by `sb-finish` or `concat`, is never shared.
+    pub fn set_text_interning(&mut self, intern: bool) {
+        self.heap.intern_texts = intern;
+    }
+
+    /// Register a Rust function under the given name so that it can be called from interpreted
+    /// code. It is bound as a global in every `Thread` allocated afterwards. Registering a name
+    /// again replaces the previous function.
+    pub fn register_native<F>(&mut self, name: &str, arity: u8, function: F)
+    where
+        F: for<'guard> Fn(
+                &'guard MutatorView<'guard>,
+                &[TaggedScopedPtr<'guard>],
+            ) -> Result<TaggedScopedPtr<'guard>, RuntimeError>
+            + 'static,
+    {
+        let native = Native {
+            name: String::from(name),
+            arity,
+            function: Box::new(function),
+        };
+
+        let natives = &mut self.heap.natives;
+        match natives.iter().position(|existing| existing.name == name) {
+            Some(index) => natives[index] = native,
+            None => natives.push(native),
+        }
+    }
+
+    /// Start a persistent evaluation session, in which globals defined by one evaluation are
+    /// visible to the next
+    pub fn session(&self) -> Result<Session, RuntimeError> {
+        Session::new(self)
+    }
+
+    /// Run a garbage collection. This must happen between mutation sessions. Without any roots
+    /// to trace from nothing is collected, so this is not counted in the collection statistics;
+    /// see `collect_precise()` and `collect_conservative()`.
+    pub fn collect(&self) -> Result<(), RuntimeError> {
+        self.heap.collecting(|| {
+            // TODO there are no roots to collect from here
+            Ok(())
+        })
+    }
+
+    /// Run a minor collection whose roots are those traced from the given object, such as a
+    /// `Thread` or a `ReadEvalPrint`. Only the references the object reports as live are roots,
+    /// so a stale value it still holds, such as one left in the registers of a call frame that
+    /// has returned, does not keep anything alive. This must happen between mutation sessions.
+    ///
+    /// Every object reachable from the roots is traced in turn and survives, along with the rest
+    /// of the block it is in.
+    pub fn collect_precise<T: Trace>(&mut self, root: &T) -> Result<(), RuntimeError> {
+        let start = Instant::now();
+
+        let mut roots = Roots::new();
+        {
+            let guard = MutatorView::new(self);
+            root.trace(&guard, &mut roots);
+            roots.trace_pending(&guard);
+        }
+
+        self.heap.check_not_collecting()?;
+
+        // interned Text is not a root, so it may be swept
+        self.heap.texts.get_mut().clear();
+
+        for address in roots.addresses() {
+            self.heap.heap.mark_conservative(*address);
+        }
+        let sweep = self.heap.heap.minor_sweep();
+
+        self.heap.record_collection(start, sweep.bytes_freed);
+        Ok(())
+    }
+
+    /// Run a minor collection whose roots are found by scanning the thread's register stack and
+    /// call frames conservatively: every word in them that, untagged, points into a heap block
+    /// keeps that block alive. This must happen between mutation sessions.
+    ///
+    /// The objects the scan finds are then traced, treating every register as live, so that
+    /// everything reachable from them, such as the thread's globals, survives too.
+    #[cfg(feature = "conservative-roots")]
+    pub fn collect_conservative(&mut self, thread: &CellPtr<Thread>) -> Result<(), RuntimeError> {
+        let start = Instant::now();
+
+        let mut roots = Roots::new();
+        let words = {
+            let guard = MutatorView::new(self);
+            let thread = thread.get(&guard);
+            thread.trace_conservative(&guard, &mut roots);
+            roots.trace_pending(&guard);
+            thread.conservative_roots(&guard)
+        };
+
+        self.heap.check_not_collecting()?;
+
+        // interned Text is not a root, so it may be swept: literals read from now on are
+        // allocated and interned afresh
+        self.heap.texts.get_mut().clear();
+
+        for word in words {
+            self.heap.heap.mark_conservative(untag_word(word));
+        }
+        for address in roots.addresses() {
+            self.heap.heap.mark_conservative(*address);
+        }
+        let sweep = self.heap.heap.minor_sweep();
+
+        self.heap.record_collection(start, sweep.bytes_freed);
+        Ok(())
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+/// The addresses of the heap objects found by tracing, which must survive a collection
+#[derive(Default)]
+pub struct Roots {
+    addresses: Vec<usize>,
+    /// Every address added so far, so that each object is traced once however many references
+    /// to it there are, and cycles terminate
+    seen: HashSet<usize>,
+    /// Values whose objects have been added but not yet traced
+    pending: Vec<TaggedPtr>,
+}
+
+impl Roots {
+    pub fn new() -> Roots {
+        Roots {
+            addresses: Vec::new(),
+            seen: HashSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Add a heap object that is referred to directly, such as through a `CellPtr`, rather than
+    /// by a tagged pointer. The caller traces the object's own references.
+    pub fn object<T>(&mut self, object: &T) {
+        self.address(object as *const T as usize);
+    }
+
+    /// Add the heap object a value points to, to be traced by `trace_pending()`. Nil, inline
+    /// numbers and Symbols are not stored in the heap and are ignored.
+    pub fn value(&mut self, value: TaggedScopedPtr) {
+        if let Some(address) = value.get_ptr().heap_address() {
+            if self.address(address) {
+                self.pending.push(value.get_ptr());
+            }
+        }
+    }
+
+    /// Add an array and its backing storage, which is allocated separately. The array's items
+    /// are not added: the caller knows which of them are live. The storage is added even when
+    /// the array is empty, as it may have room for items pushed later.
+    pub fn array<T: Sized + Clone>(&mut self, guard: &dyn MutatorScope, array: &Array<T>) {
+        self.object(array);
+
+        let items = unsafe { array.as_capacity_slice(guard) };
+        if !items.is_empty() {
+            self.address(items.as_ptr() as usize);
+        }
+    }
+
+    /// Add the backing storage of an object that holds a `RawArray` directly
+    pub fn storage<T: Sized>(&mut self, storage: &RawArray<T>) {
+        if let Some(ptr) = storage.as_ptr() {
+            self.address(ptr as usize);
+        }
+    }
+
+    /// Trace every object whose value was added, and every object found by tracing those, until
+    /// there is nothing left to trace
+    pub fn trace_pending(&mut self, guard: &dyn MutatorScope) {
+        while let Some(ptr) = self.pending.pop() {
+            let value = TaggedScopedPtr::new(guard, ptr);
+            (*value).trace(guard, self);
+        }
+    }
+
+    /// Return the addresses of the objects found
+    pub fn addresses(&self) -> &[usize] {
+        &self.addresses
+    }
+
+    /// Record an address, returning false if it had already been recorded
+    fn address(&mut self, address: usize) -> bool {
+        let new = self.seen.insert(address);
+        if new {
+            self.addresses.push(address);
+        }
+        new
+    }
+}
+
+/// Implemented by types that refer to heap objects that must survive a collection. Only the
+/// references that are live are reported, so that the collector is given a precise root set.
+pub trait Trace {
+    /// Add the heap storage of `self`, and every heap object its live parts refer to directly.
+    /// Objects added as values are traced in turn by `Roots::trace_pending()`.
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots);
+}
+
+/// A heap object held outside of a mutation session is traced as the object itself
+impl<T: Trace> Trace for CellPtr<T> {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        self.get(guard).trace(guard, roots);
+    }
 }
 
 /// Defines the interface a heap-mutating type must use to be allowed access to the heap
@@ -158,3 +675,270 @@ pub trait Mutator: Sized {
     // function to return iterator that iterates over roots
 }
 // ANCHOR_END: DefMutator
+
+#[cfg(test)]
+mod test {
+    use super::{Memory, Mutator, MutatorView};
+    use crate::compiler::compile;
+    use crate::error::{ErrorKind, RuntimeError};
+    use crate::pair::Pair;
+    use crate::parser::parse;
+    use crate::printer::render;
+    use crate::safeptr::CellPtr;
+    use crate::text::Text;
+    use crate::vm::Thread;
+
+    struct Alloc {}
+    impl Mutator for Alloc {
+        type Input = ();
+        type Output = ();
+
+        fn run(&self, mem: &MutatorView, _input: ()) -> Result<(), RuntimeError> {
+            mem.alloc(Text::new_empty())?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn no_mutation_during_collection() {
+        let mem = Memory::new();
+
+        // a mutation session cannot be started, and so nothing can be allocated, from within
+        // a collection
+        let result = mem.heap.collecting(|| mem.mutate(&Alloc {}, ()));
+        match result {
+            Err(e) => assert!(*e.error_kind() == ErrorKind::CollectionInProgress),
+            Ok(_) => panic!("mutation session started during a collection"),
+        }
+
+        // allocating directly on the heap is also refused
+        assert!(mem
+            .heap
+            .collecting(|| mem.heap.alloc(Text::new_empty()))
+            .is_err());
+
+        // a collection cannot be started from within a collection
+        assert!(mem.heap.collecting(|| mem.collect()).is_err());
+
+        // after the collection, mutation continues as normal
+        mem.mutate(&Alloc {}, ()).unwrap();
+        mem.collect().unwrap();
+        mem.mutate(&Alloc {}, ()).unwrap();
+    }
+
+    struct Oversized {}
+    impl Mutator for Oversized {
+        type Input = ();
+        type Output = ();
+
+        fn run(&self, mem: &MutatorView, _input: ()) -> Result<(), RuntimeError> {
+            // larger than a block, which the heap cannot allocate
+            match mem.alloc_array(1 << 20) {
+                Err(e) => {
+                    assert!(*e.error_kind() == ErrorKind::BadAllocationRequest);
+                    assert!(e.error_context() == Some("1048576 bytes requested for Array"));
+                    assert!(e.to_string().contains("1048576 bytes requested for Array"));
+                }
+                Ok(_) => panic!("allocated an array larger than a block"),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn alloc_error_reports_request() {
+        let mem = Memory::new();
+        mem.mutate(&Oversized {}, ()).unwrap();
+    }
+
+    struct InjectedFailures {}
+    impl Mutator for InjectedFailures {
+        type Input = ();
+        type Output = ();
+
+        fn run(&self, mem: &MutatorView, _input: ()) -> Result<(), RuntimeError> {
+            mem.fail_next_allocations(3);
+
+            let e = mem
+                .alloc(Text::new_empty())
+                .err()
+                .expect("allocation should fail");
+            assert!(*e.error_kind() == ErrorKind::OutOfMemory);
+            assert!(e.to_string().contains("bytes requested for Text"));
+
+            let e = mem
+                .alloc_tagged(Pair::new())
+                .err()
+                .expect("allocation should fail");
+            assert!(*e.error_kind() == ErrorKind::OutOfMemory);
+
+            let e = mem.alloc_array(64).err().expect("allocation should fail");
+            assert!(*e.error_kind() == ErrorKind::OutOfMemory);
+            assert!(e.error_context() == Some("64 bytes requested for Array"));
+
+            // only as many requests as asked for fail
+            mem.alloc(Text::new_empty())?;
+            mem.alloc_tagged(Pair::new())?;
+            mem.alloc_array(64)?;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn injected_alloc_failures() {
+        let mem = Memory::new();
+        mem.mutate(&InjectedFailures {}, ()).unwrap();
+    }
+
+    struct TrueSym {}
+    impl Mutator for TrueSym {
+        type Input = ();
+        type Output = ();
+
+        fn run(&self, mem: &MutatorView, _input: ()) -> Result<(), RuntimeError> {
+            assert!(mem.true_sym() == mem.lookup_sym("true"));
+            assert!(mem.true_sym() != mem.lookup_sym("false"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn true_sym_is_interned_true() {
+        let mut mem = Memory::new();
+        mem.mutate(&TrueSym {}, ()).unwrap();
+
+        // symbols are not heap allocated so the cached pointer survives a reset
+        unsafe { mem.reset() };
+        mem.mutate(&TrueSym {}, ()).unwrap();
+    }
+
+    /// Allocate a marker object, then evaluate each expression in a new Thread. Returns the
+    /// address of the marker and the rendered result of the last expression.
+    struct Program {}
+    impl Mutator for Program {
+        type Input = &'static [&'static str];
+        type Output = (usize, String);
+
+        fn run(
+            &self,
+            mem: &MutatorView,
+            exprs: &'static [&'static str],
+        ) -> Result<(usize, String), RuntimeError> {
+            let marker = mem.alloc(Text::new_empty())?;
+            let thread = Thread::alloc(mem)?;
+
+            let mut result = String::from("nil");
+            for expr in exprs {
+                let function = compile(mem, parse(mem, expr)?)?;
+                result = render(*thread.quick_vm_eval(mem, function)?);
+            }
+
+            Ok((&*marker as *const Text as usize, result))
+        }
+    }
+
+    #[test]
+    fn reset_between_programs() {
+        let mut mem = Memory::new();
+
+        let (first_marker, result) = mem
+            .mutate(&Program {}, &["(def answer () 'yes)", "(answer)"])
+            .unwrap();
+        assert!(result == "yes");
+
+        unsafe { mem.reset() };
+
+        // the heap blocks are reused from the start, so the first object is allocated at the
+        // same address, and the global defined by the previous program is gone
+        let (second_marker, result) = mem.mutate(&Program {}, &["(bound? 'answer)"]).unwrap();
+        assert!(second_marker == first_marker);
+        assert!(result == "nil");
+
+        // and the heap continues to work as normal
+        let (_, result) = mem
+            .mutate(&Program {}, &["(def answer () 'again)", "(answer)"])
+            .unwrap();
+        assert!(result == "again");
+    }
+
+    #[test]
+    fn text_interning_shares_literals() {
+        let same_literals: &[&str] = &["(set 'a \"abc\")", "(is? a \"abc\")"];
+
+        let mut mem = Memory::new();
+        let (_, result) = mem.mutate(&Program {}, same_literals).unwrap();
+        assert!(result == "nil");
+
+        mem.set_text_interning(true);
+        let (_, result) = mem.mutate(&Program {}, same_literals).unwrap();
+        assert!(result == "true");
+        let (_, result) = mem.mutate(&Program {}, &["(is? \"abc\" \"abd\")"]).unwrap();
+        assert!(result == "nil");
+
+        // text built at run time is not interned, but is still equal to the literal
+        let (_, result) = mem
+            .mutate(&Program {}, &["(is? \"ab\" (concat \"a\" \"b\"))"])
+            .unwrap();
+        assert!(result == "nil");
+        let (_, result) = mem
+            .mutate(&Program {}, &["(equal? \"ab\" (concat \"a\" \"b\"))"])
+            .unwrap();
+        assert!(result == "true");
+
+        // the interned literals are forgotten along with the rest of the heap
+        unsafe { mem.reset() };
+        assert!(mem.heap.texts.borrow().is_empty());
+        let (_, result) = mem.mutate(&Program {}, same_literals).unwrap();
+        assert!(result == "true");
+        assert!(mem.heap.texts.borrow().len() == 1);
+    }
+
+    /// Allocate enough short-lived Text objects to fill several heap blocks, returning a new
+    /// Thread to collect from, which refers to none of them
+    struct Garbage {}
+    impl Mutator for Garbage {
+        type Input = ();
+        type Output = CellPtr<Thread>;
+
+        fn run(&self, mem: &MutatorView, _input: ()) -> Result<CellPtr<Thread>, RuntimeError> {
+            for _ in 0..10000 {
+                mem.alloc(Text::new_empty())?;
+            }
+            Ok(CellPtr::new_with(Thread::alloc(mem)?))
+        }
+    }
+
+    #[test]
+    fn gc_stats_count_collections() {
+        let mut mem = Memory::new();
+        assert!(mem.heap.gc_stats.get().collections == 0);
+
+        let (_, result) = mem.mutate(&Program {}, &["(gc-stats)"]).unwrap();
+        assert!(result == "((collections . 0) (bytes-reclaimed . 0) (last-collection-us . 0))");
+
+        // collecting without roots does nothing, so it is not counted
+        mem.collect().unwrap();
+        assert!(mem.heap.gc_stats.get().collections == 0);
+
+        let thread = mem.mutate(&Garbage {}, ()).unwrap();
+        mem.collect_precise(&thread).unwrap();
+
+        let stats = mem.heap.gc_stats.get();
+        assert!(stats.collections == 1);
+        assert!(stats.bytes_reclaimed > 0);
+
+        let (_, result) = mem
+            .mutate(&Program {}, &["(cdr (car (gc-stats)))"])
+            .unwrap();
+        assert!(result == "1");
+        let (_, result) = mem
+            .mutate(&Program {}, &["(cdr (car (cdr (gc-stats))))"])
+            .unwrap();
+        assert!(result == stats.bytes_reclaimed.to_string());
+
+        // a collection that could not run is not counted
+        assert!(mem.heap.collecting(|| mem.collect()).is_err());
+        assert!(mem.heap.gc_stats.get().collections == 1);
+    }
+}
@@ -2,14 +2,74 @@
 use std::fmt;
 
 use crate::array::Array;
+use crate::containers::Container;
+use crate::error::{err_eval, RuntimeError};
+use crate::memory::{Roots, Trace};
 use crate::printer::Print;
 use crate::safeptr::MutatorScope;
 
+/// The largest integer that can be stored inline in a tagged pointer, which loses two bits to
+/// the tag
+pub const INLINE_NUMBER_MAX: isize = isize::MAX >> 2;
+/// The smallest integer that can be stored inline in a tagged pointer
+pub const INLINE_NUMBER_MIN: isize = isize::MIN >> 2;
+
+/// Convert a runtime radix value to a radix in the range 2..=36 that can be used for conversions
+pub fn check_radix(radix: isize) -> Result<u32, RuntimeError> {
+    if (2..=36).contains(&radix) {
+        Ok(radix as u32)
+    } else {
+        Err(err_eval(&format!(
+            "Radix must be between 2 and 36, got {}",
+            radix
+        )))
+    }
+}
+
+/// Render an integer in the given radix, using lowercase letters for digits above 9
+pub fn number_to_string(value: isize, radix: u32) -> String {
+    let mut digits = Vec::new();
+    let mut remaining = value.unsigned_abs();
+
+    loop {
+        let digit = (remaining % radix as usize) as u32;
+        digits.push(std::char::from_digit(digit, radix).unwrap());
+
+        remaining /= radix as usize;
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    if value < 0 {
+        digits.push('-');
+    }
+
+    digits.iter().rev().collect()
+}
+
+/// Parse an integer in the given radix. Returns None if the string is not a valid integer or the
+/// integer can't be stored inline.
+pub fn parse_number(from_str: &str, radix: u32) -> Option<isize> {
+    isize::from_str_radix(from_str, radix)
+        .ok()
+        .filter(|value| (INLINE_NUMBER_MIN..=INLINE_NUMBER_MAX).contains(value))
+}
+
 /// TODO A heap-allocated number
 pub struct NumberObject {
     _value: Array<u64>,
 }
 
+impl NumberObject {
+    /// Instantiate a number with a value of zero
+    pub fn zero() -> NumberObject {
+        NumberObject {
+            _value: Array::new(),
+        }
+    }
+}
+
 impl Print for NumberObject {
     fn print<'guard>(
         &self,
@@ -20,3 +80,10 @@ impl Print for NumberObject {
         write!(f, "NumberObject(nan)")
     }
 }
+
+impl Trace for NumberObject {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.array(guard, &self._value);
+    }
+}
@@ -2,10 +2,10 @@ use std::cell::Cell;
 use std::fmt;
 
 use crate::error::{err_eval, RuntimeError, SourcePos};
-use crate::memory::MutatorView;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::safeptr::{MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
-use crate::taggedptr::Value;
+use crate::taggedptr::{TaggedPtr, Value};
 
 /// A Pair of pointers, like a Cons cell of old
 // ANCHOR: DefPair
@@ -105,6 +105,14 @@ impl Print for Pair {
     }
 }
 
+impl Trace for Pair {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.first.get(guard));
+        roots.value(self.second.get(guard));
+    }
+}
+
 /// Link the two values `head` and `rest` into a Pair instance
 // ANCHOR: DefCons
 pub fn cons<'guard>(
@@ -119,15 +127,100 @@ pub fn cons<'guard>(
 }
 // ANCHOR_END: DefCons
 
-/// Unpack a list of Pair instances into a Vec
-pub fn vec_from_pairs<'guard>(
+/// Builds a list of Pair instances in order by appending to its end. The last Pair is kept so
+/// that each append is a single allocation, where consing onto the front builds the list in
+/// reverse and walking to the end each time is O(n).
+#[derive(Clone)]
+pub struct ListBuilder {
+    head: TaggedCellPtr,
+    tail: TaggedCellPtr,
+}
+
+impl ListBuilder {
+    /// Allocate an empty ListBuilder on the heap
+    pub fn alloc<'guard>(
+        mem: &'guard MutatorView,
+    ) -> Result<ScopedPtr<'guard, ListBuilder>, RuntimeError> {
+        mem.alloc(ListBuilder {
+            head: TaggedCellPtr::new_nil(),
+            tail: TaggedCellPtr::new_nil(),
+        })
+    }
+
+    /// Append a value to the end of the list
+    pub fn push<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        value: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        let new_tail = match *self.tail.get(mem) {
+            Value::Pair(tail) => tail.append(mem, value)?,
+            _ => {
+                let pair = cons(mem, value, mem.nil())?;
+                self.head.set(pair);
+                pair
+            }
+        };
+        self.tail.set(new_tail);
+        Ok(())
+    }
+
+    /// Return the first and last Pairs of the list so far, both nil if nothing has been appended
+    pub fn ends<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+    ) -> (TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>) {
+        (self.head.get(guard), self.tail.get(guard))
+    }
+
+    /// Return the list built so far, leaving the builder empty. The list is handed over rather
+    /// than copied, so anything appended afterwards starts a new list.
+    pub fn take_list<'guard>(&self, guard: &'guard dyn MutatorScope) -> TaggedScopedPtr<'guard> {
+        let list = self.head.get(guard);
+        self.head.set_to_nil();
+        self.tail.set_to_nil();
+        list
+    }
+}
+
+impl Print for ListBuilder {
+    fn print<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "(ListBuilder {})", self.head.get(guard))
+    }
+}
+
+impl Trace for ListBuilder {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.head.get(guard));
+        roots.value(self.tail.get(guard));
+    }
+}
+
+/// Pack a slice of values into a nil-terminated list of Pair instances
+pub fn pairs_from_slice<'guard>(
+    mem: &'guard MutatorView,
+    values: &[TaggedScopedPtr<'guard>],
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+    let mut list = mem.nil();
+    for value in values.iter().rev() {
+        list = cons(mem, *value, list)?;
+    }
+    Ok(list)
+}
+
+/// Unpack a list of Pair instances onto the end of a Vec
+pub fn extend_from_pairs<'guard>(
     guard: &'guard dyn MutatorScope,
     pair_list: TaggedScopedPtr<'guard>,
-) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+    result: &mut Vec<TaggedScopedPtr<'guard>>,
+) -> Result<(), RuntimeError> {
     match *pair_list {
         Value::Pair(pair) => {
-            let mut result = Vec::new();
-
             result.push(pair.first.get(guard));
 
             let mut next = pair.second.get(guard);
@@ -138,15 +231,58 @@ pub fn vec_from_pairs<'guard>(
 
             // we've terminated the list, but correctly?
             match *next {
-                Value::Nil => Ok(result),
+                Value::Nil => Ok(()),
                 _ => Err(err_eval("Incorrectly terminated Pair list")),
             }
         }
-        Value::Nil => Ok(Vec::new()),
+        Value::Nil => Ok(()),
         _ => Err(err_eval("Expected a Pair")),
     }
 }
 
+/// Unpack a list of Pair instances into a Vec
+pub fn vec_from_pairs<'guard>(
+    guard: &'guard dyn MutatorScope,
+    pair_list: TaggedScopedPtr<'guard>,
+) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+    let mut result = Vec::new();
+    extend_from_pairs(guard, pair_list, &mut result)?;
+    Ok(result)
+}
+
+/// Unpack a list of exactly N Pair instances into an array, without allocating
+fn array_from_pairs<'guard, const N: usize>(
+    guard: &'guard dyn MutatorScope,
+    pair_list: TaggedScopedPtr<'guard>,
+) -> Result<[TaggedScopedPtr<'guard>; N], RuntimeError> {
+    let mut result = [TaggedScopedPtr::new(guard, TaggedPtr::nil()); N];
+    let mut count = 0;
+
+    let mut next = pair_list;
+    while let Value::Pair(pair) = *next {
+        if count < N {
+            result[count] = pair.first.get(guard);
+        }
+        count += 1;
+        next = pair.second.get(guard);
+    }
+
+    match *next {
+        Value::Nil => (),
+        _ if count == 0 => return Err(err_eval("Expected a Pair")),
+        _ => return Err(err_eval("Incorrectly terminated Pair list")),
+    }
+
+    if count != N {
+        return Err(err_eval(&format!(
+            "Pair list has {} items, expected {}",
+            count, N
+        )));
+    }
+
+    Ok(result)
+}
+
 /// Unpack a list of Pair instances into a Vec, expecting n values
 pub fn vec_from_n_pairs<'guard>(
     guard: &'guard dyn MutatorScope,
@@ -171,15 +307,8 @@ pub fn value_from_1_pair<'guard>(
     guard: &'guard dyn MutatorScope,
     pair_list: TaggedScopedPtr<'guard>,
 ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-    let result = vec_from_pairs(guard, pair_list)?;
-
-    match result.as_slice() {
-        [first] => Ok(*first),
-        _ => Err(err_eval(&format!(
-            "Pair list has {} items, expected 1",
-            result.len()
-        ))),
-    }
+    let [first] = array_from_pairs(guard, pair_list)?;
+    Ok(first)
 }
 
 /// Convenience function for unpacking a list of Pair instances into two values
@@ -187,15 +316,8 @@ pub fn values_from_2_pairs<'guard>(
     guard: &'guard dyn MutatorScope,
     pair_list: TaggedScopedPtr<'guard>,
 ) -> Result<(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>), RuntimeError> {
-    let result = vec_from_pairs(guard, pair_list)?;
-
-    match result.as_slice() {
-        [first, second] => Ok((*first, *second)),
-        _ => Err(err_eval(&format!(
-            "Pair list has {} items, expected 2",
-            result.len()
-        ))),
-    }
+    let [first, second] = array_from_pairs(guard, pair_list)?;
+    Ok((first, second))
 }
 
 /// Convenience function for unpacking a list of Pair instances into three values
@@ -210,15 +332,8 @@ pub fn values_from_3_pairs<'guard>(
     ),
     RuntimeError,
 > {
-    let result = vec_from_pairs(guard, pair_list)?;
-
-    match result.as_slice() {
-        [first, second, third] => Ok((*first, *second, *third)),
-        _ => Err(err_eval(&format!(
-            "Pair list has {} items, expected 3",
-            result.len()
-        ))),
-    }
+    let [first, second, third] = array_from_pairs(guard, pair_list)?;
+    Ok((first, second, third))
 }
 
 #[cfg(test)]
@@ -248,6 +363,42 @@ mod test {
         mem.mutate(&test, test_fn).unwrap();
     }
 
+    #[test]
+    fn list_builder_appends_in_order() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let builder = ListBuilder::alloc(mem)?;
+            assert!(builder.take_list(mem) == mem.nil());
+
+            // each append allocates one Pair, however long the list already is
+            for n in 0..1000 {
+                let before = mem.allocation_count();
+                builder.push(mem, TaggedScopedPtr::new(mem, TaggedPtr::number(n)))?;
+                assert!(mem.allocation_count() - before == 1);
+            }
+
+            let (_, tail) = builder.ends(mem);
+            let list = builder.take_list(mem);
+            let items = vec_from_pairs(mem, list)?;
+            assert!(items.len() == 1000);
+            for (index, item) in items.iter().enumerate() {
+                assert!(item.as_number()? == index as isize);
+            }
+
+            // the builder kept hold of the last Pair rather than walking to it
+            match *tail {
+                Value::Pair(last) => {
+                    assert!(last.first.get(mem).as_number()? == 999);
+                    assert!(last.second.get(mem) == mem.nil());
+                }
+                _ => panic!("expected the tail to be a Pair"),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner)
+    }
+
     #[test]
     fn unpack_pair_list_bad() {
         fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
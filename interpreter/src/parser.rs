@@ -1,13 +1,14 @@
+use std::cell::Cell;
 use std::iter::Peekable;
 use std::marker::PhantomData;
 
-use crate::error::{err_parser, err_parser_wpos, RuntimeError, SourcePos};
-use crate::lexer::{tokenize, Token, TokenType};
+use crate::character;
+use crate::error::{err_parser, err_parser_wpos, ErrorKind, RuntimeError, SourcePos};
+use crate::lexer::{Token, TokenType, Tokens};
 use crate::memory::MutatorView;
 use crate::pair::Pair;
 use crate::safeptr::{MutatorScope, TaggedCellPtr, TaggedScopedPtr};
 use crate::taggedptr::Value;
-use crate::text;
 
 // A linked list, internal to the parser to simplify the code and is stored on the Rust stack
 struct PairList<'guard> {
@@ -57,7 +58,10 @@ impl<'guard> PairList<'guard> {
         Ok(())
     }
 
-    /// Apply dot-notation to set the second value of the last pair of the list
+    /// Apply dot-notation to set the second value of the last pair of the list.
+    /// The value is stored as-is, so dotted structure is never reshaped here: `(a . (b . c))`
+    /// is the same structure as `(a b . c)` and is printed in that canonical form.
+    /// `parse_list()` rejects a dot directly after an open-paren, so the list is never empty.
     fn dot(
         &mut self,
         guard: &'guard dyn MutatorScope,
@@ -90,30 +94,35 @@ impl<'guard> PairList<'guard> {
 // If a list token is:
 //  * a Dot, it must be followed by an s-expression and a CloseParen
 //
-fn parse_list<'guard, 'i, I: 'i>(
+// The s-expression following a Dot becomes the second value of the last pair
+// unchanged, whether it is a symbol, nil or another (possibly dotted) list.
+//
+fn parse_list<'guard, I>(
     mem: &'guard MutatorView,
     tokens: &mut Peekable<I>,
 ) -> Result<TaggedScopedPtr<'guard>, RuntimeError>
 where
-    I: Iterator<Item = &'i Token>,
+    I: Iterator<Item = Token>,
 {
     use self::TokenType::*;
 
     // peek at very first token after the open-paren
     match tokens.peek() {
-        Some(&&Token {
-            token: CloseParen,
-            pos: _,
+        Some(&Token {
+            token: CloseParen, ..
         }) => {
             tokens.next();
             return Ok(mem.nil());
         }
 
-        Some(&&Token { token: Dot, pos }) => {
-            return Err(err_parser_wpos(
-                pos,
-                "Unexpected '.' dot after open-parenthesis",
-            ));
+        Some(&Token {
+            token: Dot,
+            pos,
+            end,
+        }) => {
+            return Err(
+                err_parser_wpos(pos, "Unexpected '.' dot after open-parenthesis").with_end(end),
+            );
         }
 
         _ => (),
@@ -123,57 +132,79 @@ where
     let mut list = PairList::open(mem);
     loop {
         match tokens.peek() {
-            Some(&&Token {
+            Some(&Token {
                 token: OpenParen,
                 pos,
+                ..
             }) => {
                 tokens.next();
                 list.push(mem, parse_list(mem, tokens)?, pos)?;
             }
 
-            Some(&&Token {
+            Some(&Token {
                 token: Symbol(_),
                 pos,
+                ..
             }) => {
                 list.push(mem, parse_sexpr(mem, tokens)?, pos)?;
             }
 
-            Some(&&Token {
+            Some(&Token {
                 token: Text(_),
                 pos,
+                ..
+            }) => {
+                list.push(mem, parse_sexpr(mem, tokens)?, pos)?;
+            }
+
+            Some(&Token {
+                token: TokenType::Char(_),
+                pos,
+                ..
+            }) => {
+                list.push(mem, parse_sexpr(mem, tokens)?, pos)?;
+            }
+
+            Some(&Token {
+                token: Bool(_),
+                pos,
+                ..
             }) => {
                 list.push(mem, parse_sexpr(mem, tokens)?, pos)?;
             }
 
-            Some(&&Token { token: Quote, pos }) => {
+            Some(&Token {
+                token: Quote, pos, ..
+            }) => {
                 list.push(mem, parse_sexpr(mem, tokens)?, pos)?;
             }
 
-            Some(&&Token { token: Dot, pos }) => {
+            Some(&Token {
+                token: Dot, pos, ..
+            }) => {
                 tokens.next();
                 list.dot(mem, parse_sexpr(mem, tokens)?, pos);
 
                 // the only valid sequence here on out is Dot s-expression CloseParen
                 match tokens.peek() {
-                    Some(&&Token {
-                        token: CloseParen,
-                        pos: _,
+                    Some(&Token {
+                        token: CloseParen, ..
                     }) => (),
 
-                    Some(&&Token { token: _, pos }) => {
+                    Some(&Token { pos, end, .. }) => {
                         return Err(err_parser_wpos(
                             pos,
                             "Dotted pair must be closed by a ')' close-parenthesis",
-                        ));
+                        )
+                        .with_end(end));
                     }
 
                     None => return Err(err_parser("Unexpected end of code stream")),
                 }
             }
 
-            Some(&&Token {
-                token: CloseParen,
-                pos: _,
+            Some(&Token {
+                token: CloseParen, ..
             }) => {
                 tokens.next();
                 break;
@@ -193,50 +224,59 @@ where
 //
 // Must be a
 //  * symbol
+//  * text
+//  * character
+//  * boolean
 //  * or a list
 //
-fn parse_sexpr<'guard, 'i, I: 'i>(
+fn parse_sexpr<'guard, I>(
     mem: &'guard MutatorView,
     tokens: &mut Peekable<I>,
 ) -> Result<TaggedScopedPtr<'guard>, RuntimeError>
 where
-    I: Iterator<Item = &'i Token>,
+    I: Iterator<Item = Token>,
 {
     use self::TokenType::*;
 
-    match tokens.peek() {
-        Some(&&Token {
-            token: OpenParen,
-            pos: _,
-        }) => {
-            tokens.next();
-            parse_list(mem, tokens)
-        }
+    match tokens.next() {
+        Some(Token {
+            token: OpenParen, ..
+        }) => parse_list(mem, tokens),
 
-        Some(&&Token {
-            token: Symbol(ref name),
-            pos: _,
+        Some(Token {
+            token: Symbol(name),
+            ..
         }) => {
-            tokens.next();
             // the symbol 'nil' is reinterpreted as a literal nil value
             if name == "nil" {
                 Ok(mem.nil())
             } else {
-                Ok(mem.lookup_sym(name))
+                Ok(mem.lookup_sym(&name))
             }
         }
 
-        Some(&&Token {
-            token: Text(ref string),
-            pos: _,
-        }) => {
-            tokens.next();
-            let text = mem.alloc_tagged(text::Text::new_from_str(mem, &string)?)?;
-            Ok(text)
+        Some(Token {
+            token: Text(string),
+            ..
+        }) => mem.text_literal(&string),
+
+        Some(Token {
+            token: TokenType::Char(c),
+            ..
+        }) => Ok(mem.alloc_tagged(character::Char::new(c))?),
+
+        // boolean literals are the same values as the symbol 'true' and nil
+        Some(Token { token: Bool(b), .. }) => {
+            if b {
+                Ok(mem.true_sym())
+            } else {
+                Ok(mem.nil())
+            }
         }
 
-        Some(&&Token { token: Quote, pos }) => {
-            tokens.next();
+        Some(Token {
+            token: Quote, pos, ..
+        }) => {
             // create a (quote x) pair here
             // parse_sexpr() for x
             let mut list = PairList::open(mem);
@@ -246,26 +286,149 @@ where
             Ok(list.close(mem))
         }
 
-        Some(&&Token { token: Dot, pos }) => Err(err_parser_wpos(pos, "Invalid symbol '.'")),
+        Some(Token {
+            token: Dot,
+            pos,
+            end,
+        }) => Err(err_parser_wpos(pos, "Invalid symbol '.'").with_end(end)),
 
-        Some(&&Token {
+        Some(Token {
             token: CloseParen,
             pos,
-        }) => Err(err_parser_wpos(pos, "Unmatched close parenthesis")),
+            end,
+        }) => Err(err_parser_wpos(pos, "Unmatched close parenthesis").with_end(end)),
 
-        None => {
-            tokens.next();
-            Ok(mem.nil())
+        None => Err(err_parser("Unexpected end of code stream")),
+    }
+}
+
+fn parse_tokens<'guard, I>(
+    mem: &'guard MutatorView,
+    tokens: I,
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut tokenstream = tokens.peekable();
+
+    // input that is empty, or only whitespace and comments, is not the same as `nil`
+    if tokenstream.peek().is_none() {
+        return Err(RuntimeError::new(ErrorKind::NoExpression));
+    }
+
+    let expr = parse_sexpr(mem, &mut tokenstream)?;
+
+    // only one expression at a time is parsed
+    if let Some(token) = tokenstream.peek() {
+        return Err(err_parser_wpos(
+            token.pos,
+            "Unexpected input after the end of the expression",
+        )
+        .with_end(token.end));
+    }
+
+    Ok(expr)
+}
+
+fn parse_tokens_all<'guard, I>(
+    mem: &'guard MutatorView,
+    tokens: I,
+) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut tokenstream = tokens.peekable();
+
+    let mut exprs = Vec::new();
+    while tokenstream.peek().is_some() {
+        exprs.push(parse_sexpr(mem, &mut tokenstream)?);
+    }
+
+    Ok(exprs)
+}
+
+/// Passes tokens through unchanged, counting how deeply nested in parentheses the tokens taken so
+/// far leave the stream, so that parsing can resume at the next top level expression after an
+/// error
+struct NestedTokens<'depth, I> {
+    tokens: I,
+    depth: &'depth Cell<usize>,
+}
+
+impl<'depth, I> Iterator for NestedTokens<'depth, I>
+where
+    I: Iterator<Item = Token>,
+{
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.next()?;
+
+        match token.token {
+            TokenType::OpenParen => self.depth.set(self.depth.get() + 1),
+            // an unmatched close paren at the top level is an error, and leaves the depth at 0
+            TokenType::CloseParen => self.depth.set(self.depth.get().saturating_sub(1)),
+            _ => (),
         }
+
+        Some(token)
     }
 }
 
-fn parse_tokens<'guard>(
+fn parse_tokens_recovering<'guard, I>(
     mem: &'guard MutatorView,
-    tokens: Vec<Token>,
-) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-    let mut tokenstream = tokens.iter().peekable();
-    parse_sexpr(mem, &mut tokenstream)
+    tokens: I,
+) -> (Vec<TaggedScopedPtr<'guard>>, Vec<RuntimeError>)
+where
+    I: Iterator<Item = Token>,
+{
+    let depth = Cell::new(0);
+    let mut tokenstream = NestedTokens {
+        tokens,
+        depth: &depth,
+    }
+    .peekable();
+
+    let mut exprs = Vec::new();
+    let mut errors = Vec::new();
+    while tokenstream.peek().is_some() {
+        match parse_sexpr(mem, &mut tokenstream) {
+            Ok(expr) => exprs.push(expr),
+
+            Err(e) => {
+                errors.push(e);
+
+                // skip the rest of the malformed expression, up to the close paren that balances
+                // its open paren. The error may have been found by peeking at a token, which has
+                // already been counted, so it is skipped too if the expression is still open.
+                while depth.get() > 0 && tokenstream.next().is_some() {}
+            }
+        }
+    }
+
+    (exprs, errors)
+}
+
+/// Adapts the lexer's stream of token results to the stream of tokens the parser consumes. A lexer
+/// error ends the stream early and is kept to be reported in place of whatever parse result the
+/// truncated stream gave.
+struct TokenStream<'input> {
+    tokens: Tokens<'input>,
+    error: Option<RuntimeError>,
+}
+
+impl<'input> Iterator for TokenStream<'input> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.tokens.next()? {
+            Ok(token) => Some(token),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
 }
 
 /// Parse the given string into an AST
@@ -274,15 +437,83 @@ pub fn parse<'guard>(
     mem: &'guard MutatorView,
     input: &str,
 ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
-    parse_tokens(mem, tokenize(input)?)
+    let mut stream = TokenStream {
+        tokens: Tokens::with_case(input, mem.symbol_case()),
+        error: None,
+    };
+    let result = parse_tokens(mem, &mut stream);
+
+    // a lexer error anywhere in the input takes precedence over a parse error, as it would if the
+    // input had been tokenized up front, so lex the rest of the input to find one
+    if result.is_err() {
+        stream.by_ref().for_each(drop);
+    }
+
+    match stream.error {
+        Some(e) => Err(e),
+        None => result,
+    }
 }
 // ANCHOR_END: DefParse
 
+/// Parse the given string into a sequence of ASTs, one for each top level expression, as a source
+/// file is. Input that is empty, or only whitespace and comments, gives an empty sequence.
+pub fn parse_all<'guard>(
+    mem: &'guard MutatorView,
+    input: &str,
+) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+    let mut stream = TokenStream {
+        tokens: Tokens::with_case(input, mem.symbol_case()),
+        error: None,
+    };
+    let result = parse_tokens_all(mem, &mut stream);
+
+    // as in `parse()`, a lexer error takes precedence
+    if result.is_err() {
+        stream.by_ref().for_each(drop);
+    }
+
+    match stream.error {
+        Some(e) => Err(e),
+        None => result,
+    }
+}
+
+/// Parse the given string into a sequence of ASTs, as `parse_all()` does, but carry on after a
+/// malformed top level expression: it is reported and skipped, and parsing resumes at the next
+/// top level expression. This returns the well formed expressions and an error for each malformed
+/// one, so that all the errors in a source file can be shown at once. The lexer cannot carry on
+/// after an error, so a lexer error is the last one reported.
+pub fn parse_all_recovering<'guard>(
+    mem: &'guard MutatorView,
+    input: &str,
+) -> (Vec<TaggedScopedPtr<'guard>>, Vec<RuntimeError>) {
+    let mut stream = TokenStream {
+        tokens: Tokens::with_case(input, mem.symbol_case()),
+        error: None,
+    };
+    let (exprs, mut errors) = parse_tokens_recovering(mem, &mut stream);
+
+    if let Some(e) = stream.error {
+        // the only parse error without a position is reaching the end of the input, which here
+        // was cut short by the lexer error, so an expression left unfinished is reported as the
+        // lexer error alone
+        if errors.last().is_some_and(|last| last.error_pos().is_none()) {
+            errors.pop();
+        }
+        errors.push(e);
+    }
+
+    (exprs, errors)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::error::spos;
     use crate::memory::{Memory, Mutator, MutatorView};
     use crate::printer::print;
+    use crate::symbolmap::SymbolCase;
 
     fn check(input: &str, expect: &str) {
         let mem = Memory::new();
@@ -312,6 +543,94 @@ mod test {
         mem.mutate(&test, ()).unwrap();
     }
 
+    fn parse_error(input: &str) -> RuntimeError {
+        let mem = Memory::new();
+
+        struct Test<'a> {
+            input: &'a str,
+        }
+
+        impl<'a> Mutator for Test<'a> {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                parse(mem, self.input).map(|_| ())
+            }
+        }
+
+        let test = Test { input };
+        match mem.mutate(&test, ()) {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error parsing {:?}", input),
+        }
+    }
+
+    fn check_error(input: &str, expect: ErrorKind) {
+        assert!(*parse_error(input).error_kind() == expect);
+    }
+
+    #[test]
+    fn parse_empty_string() {
+        check_error("", ErrorKind::NoExpression);
+    }
+
+    #[test]
+    fn parse_whitespace_only() {
+        check_error("  \n\r\n ", ErrorKind::NoExpression);
+    }
+
+    #[test]
+    fn parse_comment_only() {
+        check_error("; nothing to see here", ErrorKind::NoExpression);
+        check_error("; one\n  ; two\n", ErrorKind::NoExpression);
+    }
+
+    #[test]
+    fn parse_comment_after_expression() {
+        check("(a b) ; the list", "(a b)");
+    }
+
+    #[test]
+    fn parse_quote_at_end() {
+        check_error(
+            "'",
+            ErrorKind::ParseError(String::from("Unexpected end of code stream")),
+        );
+    }
+
+    #[test]
+    fn parse_trailing_input() {
+        check_error(
+            "(a) b",
+            ErrorKind::ParseError(String::from(
+                "Unexpected input after the end of the expression",
+            )),
+        );
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        // the whole of the offending token is underlined
+        let input = "(a) bcd";
+        let e = parse_error(input);
+        assert!(e.error_pos() == Some(spos(1, 4)));
+        assert!(e.error_end() == Some(spos(1, 7)));
+
+        let mut out = Vec::new();
+        e.print_with_source(input, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().nth(2) == Some("     |    ^^^"));
+
+        let e = parse_error("(a . b c)");
+        assert!(e.error_pos() == Some(spos(1, 7)));
+        assert!(e.error_end() == Some(spos(1, 8)));
+
+        let e = parse_error("#\\bogus");
+        assert!(e.error_pos() == Some(spos(1, 0)));
+        assert!(e.error_end() == Some(spos(1, 7)));
+    }
+
     #[test]
     fn parse_empty_list() {
         let input = String::from("()");
@@ -388,4 +707,203 @@ mod test {
         let expect = String::from("(a)");
         check(&input, &expect);
     }
+
+    #[test]
+    fn parse_char_literals() {
+        check("#\\a", "#\\a");
+        check("(#\\( #\\) #\\.)", "(#\\( #\\) #\\.)");
+        check(
+            "(a #\\space #\\newline #\\tab)",
+            "(a #\\space #\\newline #\\tab)",
+        );
+        check("'#\\z", "(quote #\\z)");
+    }
+
+    #[test]
+    fn parse_dot_notation_nested_first() {
+        check("(((a . b) . c) . d)", "(((a . b) . c) . d)");
+        check("((a . b) (c . d))", "((a . b) (c . d))");
+    }
+
+    #[test]
+    fn parse_dot_notation_nested_second() {
+        // a dotted list in second position continues the list
+        check("(a . (b . c))", "(a b . c)");
+        check("(a . (b . (c . nil)))", "(a b c)");
+        check("(a b . (c d))", "(a b c d)");
+        check("(a . ((b . c)))", "(a (b . c))");
+    }
+
+    #[test]
+    fn parse_dot_notation_errors() {
+        check_error(
+            "(. a)",
+            ErrorKind::ParseError(String::from("Unexpected '.' dot after open-parenthesis")),
+        );
+        check_error(
+            "(a . b c)",
+            ErrorKind::ParseError(String::from(
+                "Dotted pair must be closed by a ')' close-parenthesis",
+            )),
+        );
+        check_error(
+            "(a . b . c)",
+            ErrorKind::ParseError(String::from(
+                "Dotted pair must be closed by a ')' close-parenthesis",
+            )),
+        );
+        check_error(
+            "(a . )",
+            ErrorKind::ParseError(String::from("Unmatched close parenthesis")),
+        );
+        check_error(
+            "(a . . b)",
+            ErrorKind::ParseError(String::from("Invalid symbol '.'")),
+        );
+    }
+
+    #[test]
+    fn parse_all_top_level_expressions() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let exprs = parse_all(mem, "(a b) c ; comment\n(d . e)")?;
+                let printed: Vec<String> = exprs.iter().map(|expr| print(**expr)).collect();
+                assert!(printed == vec!["(a b)", "c", "(d . e)"]);
+
+                assert!(parse_all(mem, "; nothing to see here")?.is_empty());
+                assert!(parse_all(mem, "a (b").is_err());
+
+                Ok(())
+            }
+        }
+
+        mem.mutate(&Test {}, ()).unwrap();
+    }
+
+    #[test]
+    fn parse_all_recovering_from_errors() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let parse = |input| {
+                    let (exprs, errors) = parse_all_recovering(mem, input);
+                    let printed: Vec<String> = exprs.iter().map(|expr| print(**expr)).collect();
+                    (printed, errors)
+                };
+                let is_parse_error = |e: &RuntimeError, reason: &str, pos: Option<SourcePos>| {
+                    *e.error_kind() == ErrorKind::ParseError(String::from(reason))
+                        && e.error_pos() == pos
+                };
+                let dot_not_closed = "Dotted pair must be closed by a ')' close-parenthesis";
+                let dot_after_paren = "Unexpected '.' dot after open-parenthesis";
+
+                // well formed input parses as parse_all() does
+                let (exprs, errors) = parse("(a b) c\n(d . e)");
+                assert!(exprs == vec!["(a b)", "c", "(d . e)"]);
+                assert!(errors.is_empty());
+
+                // one broken expression among good ones, nested parens and all, is skipped
+                let (exprs, errors) = parse("(a b)\n(c . d e (f g) h)\n'(i j)");
+                assert!(exprs == vec!["(a b)", "(quote (i j))"]);
+                assert!(errors.len() == 1);
+                assert!(is_parse_error(&errors[0], dot_not_closed, Some(spos(2, 7))));
+
+                // every error is reported, including stray close parens between expressions
+                let (exprs, errors) = parse("(. a) b ) (c . d . e) (f)");
+                assert!(exprs == vec!["b", "(f)"]);
+                assert!(errors.len() == 3);
+                assert!(is_parse_error(
+                    &errors[0],
+                    dot_after_paren,
+                    Some(spos(1, 1))
+                ));
+                assert!(is_parse_error(
+                    &errors[1],
+                    "Unmatched close parenthesis",
+                    Some(spos(1, 8))
+                ));
+                assert!(is_parse_error(
+                    &errors[2],
+                    dot_not_closed,
+                    Some(spos(1, 17))
+                ));
+
+                // an unfinished expression at the end is reported without a position
+                let (exprs, errors) = parse("a (b (c)");
+                assert!(exprs == vec!["a"]);
+                assert!(errors.len() == 1);
+                assert!(is_parse_error(
+                    &errors[0],
+                    "Unexpected end of code stream",
+                    None
+                ));
+
+                // the lexer cannot carry on after an error, so the expressions before it are kept
+                // and the one it cuts short is reported only as the lexer error
+                let (exprs, errors) = parse("(a) (. b) (c\t d)");
+                assert!(exprs == vec!["(a)"]);
+                assert!(errors.len() == 2);
+                assert!(is_parse_error(
+                    &errors[0],
+                    dot_after_paren,
+                    Some(spos(1, 5))
+                ));
+                assert!(matches!(errors[1].error_kind(), ErrorKind::LexerError(_)));
+
+                Ok(())
+            }
+        }
+
+        mem.mutate(&Test {}, ()).unwrap();
+    }
+
+    #[test]
+    fn parse_symbol_case() {
+        struct Test {}
+        impl Mutator for Test {
+            type Input = SymbolCase;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                case: SymbolCase,
+            ) -> Result<Self::Output, RuntimeError> {
+                let exprs = parse_all(mem, "Foo foo #\\A")?;
+                assert!(print(*exprs[2]) == "#\\A");
+
+                match case {
+                    SymbolCase::Preserve => {
+                        assert!(exprs[0] != exprs[1]);
+                        assert!(print(*exprs[0]) == "Foo");
+                    }
+                    SymbolCase::Fold => {
+                        assert!(exprs[0] == exprs[1]);
+                        assert!(print(*exprs[0]) == "foo");
+                        // symbols looked up outside the reader are folded in the same way
+                        assert!(mem.lookup_sym("FOO") == exprs[1]);
+                    }
+                }
+
+                Ok(())
+            }
+        }
+
+        for case in &[SymbolCase::Preserve, SymbolCase::Fold] {
+            let mut mem = Memory::new();
+            mem.set_symbol_case(*case);
+            mem.mutate(&Test {}, *case).unwrap();
+        }
+    }
 }
@@ -27,6 +27,11 @@ pub fn get_tag(tagged_word: usize) -> usize {
     tagged_word & TAG_MASK
 }
 
+/// Remove the tag from the given word, leaving the address it would point to
+pub fn untag_word(tagged_word: usize) -> usize {
+    tagged_word & PTR_MASK
+}
+
 /// Pointer tagging operations on RawPtr<T>
 // ANCHOR: DefTagged
 pub trait Tagged<T> {
@@ -53,6 +58,7 @@ pub trait ScopedRef<T> {
 
 impl<T> ScopedRef<T> for RawPtr<T> {
     fn scoped_ref<'scope>(&self, _guard: &'scope dyn MutatorScope) -> &'scope T {
+        self.check_deref();
         unsafe { &*self.as_ptr() }
     }
 }
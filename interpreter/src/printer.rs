@@ -1,9 +1,19 @@
 use std::fmt;
 //use std::io;
 
+use crate::containers::SliceableContainer;
 use crate::safeptr::MutatorScope;
 use crate::taggedptr::Value;
 
+/// The line width that `pretty_print()` is usually given
+pub const PRETTY_WIDTH: usize = 80;
+
+/// The container nesting depth that `print_bounded()` is usually given
+pub const PRINT_DEPTH: usize = 32;
+
+/// The number of values that `print_bounded()` is usually given
+pub const PRINT_ELEMENTS: usize = 1000;
+
 /// Trait for using a `Value` lifted pointer in the `Display` trait
 pub trait Print {
     fn print<'guard>(
@@ -36,3 +46,468 @@ pub fn print(value: Value) -> String {
 pub fn debug(value: Value) -> String {
     format!("{:?}", value)
 }
+
+/// Render a value as output text: the same as `print()` except that Text values are rendered
+/// without surrounding quotes and Char values as the bare character
+pub fn render(value: Value) -> String {
+    match value {
+        Value::Text(t) => String::from(t.as_str(&value)),
+        Value::Char(c) => c.value().to_string(),
+        _ => print(value),
+    }
+}
+
+/// Render a value as text laid out over multiple lines so that, where possible, no line is longer
+/// than `width` characters. A list that does not fit on the rest of the line has each element
+/// after the first on its own line. The elements are aligned with the first if it is a list. If
+/// the first is an atom, such as a function or form name, they are indented by two spaces instead
+/// and the second element stays on the first line if it fits. `(quote x)` is written as `'x`.
+pub fn pretty_print(value: Value, width: usize) -> String {
+    let mut out = String::new();
+    pretty(&value, value, 0, width, &mut out);
+    out
+}
+
+/// Render a value as `print()` does, but with at most `max_elements` values in total and with
+/// Pairs, Lists and Dicts nested at most `max_depth` deep. Anything past either limit is written
+/// as `...`. Every value printed counts against the limit, so printing a cyclic structure
+/// terminates.
+pub fn print_bounded(value: Value, max_depth: usize, max_elements: usize) -> String {
+    let mut printer = BoundedPrinter::new(max_depth, max_elements);
+    printer.value(&value, value, 0);
+    printer.out
+}
+
+/// Whether `print_bounded()` given the same limits would render all of a value
+pub fn within_bounds(value: Value, max_depth: usize, max_elements: usize) -> bool {
+    let mut printer = BoundedPrinter::new(max_depth, max_elements);
+    printer.value(&value, value, 0);
+    !printer.truncated
+}
+
+/// Output state for `print_bounded()`
+struct BoundedPrinter {
+    max_depth: usize,
+    // the number of values that may still be printed
+    budget: usize,
+    truncated: bool,
+    out: String,
+}
+
+impl BoundedPrinter {
+    fn new(max_depth: usize, max_elements: usize) -> BoundedPrinter {
+        BoundedPrinter {
+            max_depth,
+            budget: max_elements,
+            truncated: false,
+            out: String::new(),
+        }
+    }
+
+    fn elide(&mut self) {
+        self.truncated = true;
+        self.out.push_str("...");
+    }
+
+    /// Write a value that is nested inside `depth` containers. Returns false if the element
+    /// budget had already run out, in which case the enclosing container should be closed
+    /// without printing anything more.
+    fn value<'guard>(
+        &mut self,
+        guard: &'guard dyn MutatorScope,
+        value: Value<'guard>,
+        depth: usize,
+    ) -> bool {
+        if self.budget == 0 {
+            self.elide();
+            return false;
+        }
+        self.budget -= 1;
+
+        match value {
+            Value::Pair(_) | Value::List(_) | Value::Dict(_) if depth >= self.max_depth => {
+                self.elide()
+            }
+
+            Value::Pair(pair) => {
+                self.out.push('(');
+                let mut tail = pair;
+                if self.value(guard, *tail.first.get(guard), depth + 1) {
+                    loop {
+                        match *tail.second.get(guard) {
+                            Value::Nil => break,
+                            Value::Pair(next) => {
+                                self.out.push(' ');
+                                if !self.value(guard, *next.first.get(guard), depth + 1) {
+                                    break;
+                                }
+                                tail = next;
+                            }
+                            rest => {
+                                self.out.push_str(" . ");
+                                self.value(guard, rest, depth + 1);
+                                break;
+                            }
+                        }
+                    }
+                }
+                self.out.push(')');
+            }
+
+            Value::List(list) => {
+                let items: Vec<Value<'guard>> = list.access_slice(guard, |items| {
+                    items.iter().map(|item| *item.get(guard)).collect()
+                });
+
+                self.out.push('[');
+                for (index, item) in items.into_iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(", ");
+                    }
+                    if !self.value(guard, item, depth + 1) {
+                        break;
+                    }
+                }
+                self.out.push(']');
+            }
+
+            Value::Dict(dict) => {
+                self.out.push_str("Dict[");
+                for (index, (key, value)) in dict.items(guard).into_iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(", ");
+                    }
+                    if !self.value(guard, *key, depth + 1) {
+                        break;
+                    }
+                    self.out.push_str(": ");
+                    if !self.value(guard, *value, depth + 1) {
+                        break;
+                    }
+                }
+                self.out.push(']');
+            }
+
+            _ => self.out.push_str(&print(value)),
+        }
+
+        true
+    }
+}
+
+/// Return the quoted value if `value` is a `(quote x)` form
+fn quoted<'guard>(guard: &'guard dyn MutatorScope, value: Value<'guard>) -> Option<Value<'guard>> {
+    if let Value::Pair(p) = value {
+        if let (Value::Symbol(s), Value::Pair(rest)) = (*p.first.get(guard), *p.second.get(guard)) {
+            if s.as_str(guard) == "quote" && rest.second.is_nil() {
+                return Some(*rest.first.get(guard));
+            }
+        }
+    }
+    None
+}
+
+/// Split a list into its elements and whatever terminates it, which is nil for a proper list
+fn list_parts<'guard>(
+    guard: &'guard dyn MutatorScope,
+    value: Value<'guard>,
+) -> (Vec<Value<'guard>>, Value<'guard>) {
+    let mut items = Vec::new();
+    let mut tail = value;
+    while let Value::Pair(p) = tail {
+        items.push(*p.first.get(guard));
+        tail = *p.second.get(guard);
+    }
+    (items, tail)
+}
+
+/// Render a value on a single line, with quote sugar
+fn flat<'guard>(guard: &'guard dyn MutatorScope, value: Value<'guard>) -> String {
+    if let Some(inner) = quoted(guard, value) {
+        return format!("'{}", flat(guard, inner));
+    }
+
+    match value {
+        Value::Pair(_) => {
+            let (items, tail) = list_parts(guard, value);
+            let mut out = String::from("(");
+            for (index, item) in items.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&flat(guard, item));
+            }
+            if !matches!(tail, Value::Nil) {
+                out.push_str(" . ");
+                out.push_str(&flat(guard, tail));
+            }
+            out.push(')');
+            out
+        }
+        _ => print(value),
+    }
+}
+
+/// Render a value starting at column `indent`, breaking it over lines if it is too wide
+fn pretty<'guard>(
+    guard: &'guard dyn MutatorScope,
+    value: Value<'guard>,
+    indent: usize,
+    width: usize,
+    out: &mut String,
+) {
+    let flat_form = flat(guard, value);
+    if indent + flat_form.chars().count() <= width {
+        out.push_str(&flat_form);
+        return;
+    }
+
+    if let Some(inner) = quoted(guard, value) {
+        out.push('\'');
+        pretty(guard, inner, indent + 1, width, out);
+        return;
+    }
+
+    match value {
+        Value::Pair(_) => {
+            let (items, tail) = list_parts(guard, value);
+
+            // the column following an atom at the head of the list, e.g. `(def `
+            let head_atom_end = match items[0] {
+                Value::Pair(_) => None,
+                head => Some(indent + 1 + flat(guard, head).chars().count() + 1),
+            };
+            let item_indent = match head_atom_end {
+                Some(_) => indent + 2,
+                None => indent + 1,
+            };
+            let newline = |out: &mut String| {
+                out.push('\n');
+                out.push_str(&" ".repeat(item_indent));
+            };
+
+            out.push('(');
+            for (index, item) in items.into_iter().enumerate() {
+                match (index, head_atom_end) {
+                    (0, _) => pretty(guard, item, indent + 1, width, out),
+
+                    // keep the first argument of a form on the same line as the form name if
+                    // it fits there
+                    (1, Some(column)) if column + flat(guard, item).chars().count() <= width => {
+                        out.push(' ');
+                        pretty(guard, item, column, width, out);
+                    }
+
+                    _ => {
+                        newline(out);
+                        pretty(guard, item, item_indent, width, out);
+                    }
+                }
+            }
+            if !matches!(tail, Value::Nil) {
+                newline(out);
+                out.push_str(". ");
+                pretty(guard, tail, item_indent + 2, width, out);
+            }
+            out.push(')');
+        }
+        _ => out.push_str(&flat_form),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pretty_print, print_bounded, within_bounds, PRETTY_WIDTH};
+    use crate::containers::{HashIndexedAnyContainer, StackAnyContainer};
+    use crate::dict::Dict;
+    use crate::error::RuntimeError;
+    use crate::list::List;
+    use crate::memory::{Memory, Mutator, MutatorView};
+    use crate::parser::parse;
+    use crate::taggedptr::Value;
+
+    fn test_helper(test_fn: fn(&MutatorView) -> Result<(), RuntimeError>) {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = fn(&MutatorView) -> Result<(), RuntimeError>;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                test_fn: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                test_fn(mem)
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, test_fn).unwrap();
+    }
+
+    fn pretty(mem: &MutatorView, code: &str, width: usize) -> Result<String, RuntimeError> {
+        Ok(pretty_print(*parse(mem, code)?, width))
+    }
+
+    #[test]
+    fn pretty_print_fits_on_one_line() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            assert!(pretty(mem, "(a (b c) . d)", PRETTY_WIDTH)? == "(a (b c) . d)");
+            assert!(pretty(mem, "(a '(b c) (quote d))", PRETTY_WIDTH)? == "(a '(b c) 'd)");
+            assert!(pretty(mem, "(quote a b)", PRETTY_WIDTH)? == "(quote a b)");
+            assert!(pretty(mem, "atom", 1)? == "atom");
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn pretty_print_nested() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(def fact (n acc) (cond (is? n 'zero) acc (fact (dec n) (mul n acc))))";
+            let expected = "\
+(def fact
+  (n acc)
+  (cond (is? n 'zero)
+    acc
+    (fact (dec n)
+      (mul n acc))))";
+            assert!(pretty(mem, code, 24)? == expected);
+
+            // a list whose first element is a list aligns the remaining elements with it
+            let code = "((lambda (x) (cons x x)) 'argument)";
+            let expected = "\
+((lambda (x)
+   (cons x x))
+ 'argument)";
+            assert!(pretty(mem, code, 16)? == expected);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn pretty_print_quote_and_dot() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "'(first-element second-element . third-element)";
+            let expected = "\
+'(first-element
+   second-element
+   . third-element)";
+            assert!(pretty(mem, code, 20)? == expected);
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    fn bounded(
+        mem: &MutatorView,
+        code: &str,
+        max_depth: usize,
+        max_elements: usize,
+    ) -> Result<String, RuntimeError> {
+        Ok(print_bounded(*parse(mem, code)?, max_depth, max_elements))
+    }
+
+    #[test]
+    fn print_bounded_depth() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let code = "(a (b (c (d))) . e)";
+            assert!(bounded(mem, code, 2, 100)? == "(a (b ...) . e)");
+            assert!(bounded(mem, code, 0, 100)? == "...");
+            assert!(bounded(mem, code, 4, 100)? == "(a (b (c (d))) . e)");
+
+            let value = *parse(mem, code)?;
+            assert!(!within_bounds(value, 3, 100));
+            assert!(within_bounds(value, 4, 100));
+
+            // atoms aren't containers so are never cut off by depth
+            assert!(bounded(mem, "atom", 0, 100)? == "atom");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn print_bounded_elements() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            assert!(bounded(mem, "(a b c d e)", 10, 3)? == "(a b ...)");
+            assert!(bounded(mem, "((a b c) d)", 10, 3)? == "((a ...) ...)");
+
+            // the root and every element count, so a list of two fits in three
+            assert!(bounded(mem, "(a b)", 10, 3)? == "(a b)");
+            assert!(within_bounds(*parse(mem, "(a b)")?, 10, 3));
+            assert!(!within_bounds(*parse(mem, "(a b c)")?, 10, 3));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn print_bounded_cycle() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let list = parse(mem, "(a b)")?;
+            match *list {
+                Value::Pair(first) => match *first.second.get(mem) {
+                    Value::Pair(second) => second.second.set(list),
+                    _ => panic!("list is too short"),
+                },
+                _ => panic!("list is not a Pair"),
+            }
+
+            assert!(print_bounded(*list, 10, 5) == "(a b a b ...)");
+
+            // a cycle through the first member instead nests ever deeper
+            let list = parse(mem, "(a)")?;
+            match *list {
+                Value::Pair(first) => first.first.set(list),
+                _ => panic!("list is not a Pair"),
+            }
+
+            assert!(print_bounded(*list, 3, 100) == "(((...)))");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn print_bounded_list_and_dict() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+
+            let list = List::alloc(mem)?;
+            StackAnyContainer::push(&*list, mem, a)?;
+            StackAnyContainer::push(&*list, mem, parse(mem, "(b c)")?)?;
+            StackAnyContainer::push(&*list, mem, a)?;
+            let list = list.as_tagged(mem);
+
+            assert!(print_bounded(*list, 10, 100) == "[a, (b c), a]");
+            assert!(print_bounded(*list, 1, 100) == "[a, ..., a]");
+            assert!(print_bounded(*list, 10, 4) == "[a, (b ...), ...]");
+
+            let dict = Dict::alloc(mem)?;
+            dict.assoc(mem, a, list)?;
+            let dict = dict.as_tagged(mem);
+
+            assert!(print_bounded(*dict, 10, 100) == "Dict[a: [a, (b c), a]]");
+            assert!(print_bounded(*dict, 1, 100) == "Dict[a: ...]");
+            assert!(print_bounded(*dict, 10, 2) == "Dict[a: ...]");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+}
@@ -1,6 +1,5 @@
 use std::mem::size_of;
-use std::ptr::NonNull;
-use std::slice::from_raw_parts_mut;
+use std::ptr::{copy_nonoverlapping, NonNull};
 
 pub use stickyimmix::ArraySize;
 
@@ -10,13 +9,15 @@ use crate::memory::MutatorView;
 /// Arrays start out at this size by default
 pub const DEFAULT_ARRAY_SIZE: ArraySize = 8;
 
-/// Arrays grow at this rate by default
+/// Arrays grow at this rate by default. Growth that would overflow `ArraySize` is an error rather
+/// than wrapping around to a smaller capacity.
 pub fn default_array_growth(capacity: ArraySize) -> Result<ArraySize, RuntimeError> {
     if capacity == 0 {
         Ok(DEFAULT_ARRAY_SIZE)
     } else {
+        // always grow by at least one, or a capacity of 1 would never grow
         capacity
-            .checked_add(capacity / 2)
+            .checked_add((capacity / 2).max(1))
             .ok_or(RuntimeError::new(ErrorKind::BadAllocationRequest))
     }
 }
@@ -103,17 +104,15 @@ impl<T: Sized> RawArray<T> {
 
                 let new_ptr = mem.alloc_array(new_capacity_bytes)?.as_ptr() as *mut T;
 
-                // create a pair of slices from the raw pointers and byte sizes
-                let (old_slice, new_slice) = unsafe {
-                    (
-                        from_raw_parts_mut(old_ptr as *mut u8, old_capacity_bytes as usize),
-                        from_raw_parts_mut(new_ptr as *mut u8, new_capacity_bytes as usize),
-                    )
-                };
-
-                // Copy content from old to new array
-                for (src, dest) in old_slice.iter().zip(new_slice) {
-                    *dest = *src;
+                // Copy content from old to new array. This must be an untyped copy: objects may
+                // contain padding bytes, which are uninitialized and can't be read as `u8`s.
+                let copy_bytes = old_capacity_bytes.min(new_capacity_bytes);
+                unsafe {
+                    copy_nonoverlapping(
+                        old_ptr as *const u8,
+                        new_ptr as *mut u8,
+                        copy_bytes as usize,
+                    );
                 }
 
                 self.ptr = NonNull::new(new_ptr);
@@ -147,3 +146,165 @@ impl<T: Sized> RawArray<T> {
     }
     // ANCHOR_END: DefRawArrayAsPtr
 }
+
+#[cfg(test)]
+mod test {
+    use super::{default_array_growth, ArraySize, RawArray, DEFAULT_ARRAY_SIZE};
+    use crate::error::{ErrorKind, RuntimeError};
+    use crate::memory::{Memory, Mutator, MutatorView};
+
+    /// Write `count` sequential values into the array starting at `base`
+    fn write_sequence(array: &RawArray<u64>, count: u32, base: u64) {
+        let ptr = array.as_ptr().unwrap() as *mut u64;
+        for index in 0..count {
+            unsafe { *ptr.offset(index as isize) = base + index as u64 };
+        }
+    }
+
+    /// Assert that the first `count` values are the sequence written by `write_sequence()`
+    fn assert_sequence(array: &RawArray<u64>, count: u32, base: u64) {
+        let ptr = array.as_ptr().unwrap();
+        for index in 0..count {
+            assert!(unsafe { *ptr.offset(index as isize) } == base + index as u64);
+        }
+    }
+
+    #[test]
+    fn rawarray_grow_preserves_data() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let mut array: RawArray<u64> = RawArray::new();
+                array.resize(view, DEFAULT_ARRAY_SIZE)?;
+                assert!(array.capacity() == DEFAULT_ARRAY_SIZE);
+
+                let mut filled = array.capacity();
+                write_sequence(&array, filled, 1000);
+
+                // grow a number of times, checking the growth rate and that previously written
+                // values were copied over each time
+                for _ in 0..10 {
+                    let old_ptr = array.as_ptr();
+                    let expected_capacity = default_array_growth(array.capacity())?;
+
+                    array.resize(view, expected_capacity)?;
+
+                    assert!(array.capacity() == expected_capacity);
+                    assert!(array.as_ptr() != old_ptr);
+                    assert_sequence(&array, filled, 1000);
+
+                    filled = array.capacity();
+                    write_sequence(&array, filled, 1000);
+                }
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn rawarray_growth_rate() {
+        assert!(default_array_growth(0).unwrap() == DEFAULT_ARRAY_SIZE);
+        assert!(default_array_growth(1).unwrap() == 2);
+        assert!(default_array_growth(8).unwrap() == 12);
+        assert!(default_array_growth(12).unwrap() == 18);
+        assert!(default_array_growth(18).unwrap() == 27);
+        assert!(default_array_growth(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn rawarray_growth_overflow() {
+        // the largest capacity that can still grow by half without overflowing
+        let largest = ArraySize::MAX / 3 * 2;
+        assert!(default_array_growth(largest).unwrap() == ArraySize::MAX);
+
+        for capacity in &[largest + 1, largest + 2, ArraySize::MAX - 1] {
+            match default_array_growth(*capacity) {
+                Err(e) => assert!(*e.error_kind() == ErrorKind::BadAllocationRequest),
+                Ok(grown) => panic!("growth of {} wrapped to {}", capacity, grown),
+            }
+        }
+    }
+
+    #[test]
+    fn rawarray_capacity_bytes_overflow() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                // a capacity that fits in ArraySize may still overflow it when converted to bytes
+                let capacity = default_array_growth(ArraySize::MAX / 3 * 2)?;
+
+                match RawArray::<u64>::with_capacity(view, capacity) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BadAllocationRequest),
+                    Ok(_) => panic!("allocation size should have overflowed"),
+                }
+
+                let mut array: RawArray<u64> = RawArray::with_capacity(view, 8)?;
+                match array.resize(view, capacity) {
+                    Err(e) => assert!(*e.error_kind() == ErrorKind::BadAllocationRequest),
+                    Ok(_) => panic!("allocation size should have overflowed"),
+                }
+                assert!(array.capacity() == 8);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn rawarray_shrink_preserves_prefix() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let mut array: RawArray<u64> = RawArray::with_capacity(view, 32)?;
+                write_sequence(&array, 32, 0);
+
+                array.resize(view, 5)?;
+                assert!(array.capacity() == 5);
+                assert_sequence(&array, 5, 0);
+
+                array.resize(view, 0)?;
+                assert!(array.capacity() == 0);
+                assert!(array.as_ptr().is_none());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use crate::array::ArraySize;
+use crate::containers::{Container, IndexedAnyContainer, StackAnyContainer};
+use crate::error::{err_type, RuntimeError};
+use crate::list::List;
+use crate::memory::{MutatorView, Roots, Trace};
+use crate::printer::Print;
+use crate::safeptr::{MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
+
+/// An instance of a record type declared with `defstruct`: the Symbol naming the type and a fixed
+/// number of fields. Fields may be updated but never added or removed.
+#[derive(Clone)]
+pub struct Record {
+    type_name: TaggedCellPtr,
+    fields: List,
+}
+
+impl Record {
+    /// Allocate a Record of the named type with the given field values
+    pub fn alloc<'guard>(
+        mem: &'guard MutatorView,
+        type_name: TaggedScopedPtr<'guard>,
+        fields: &[TaggedScopedPtr<'guard>],
+    ) -> Result<ScopedPtr<'guard, Record>, RuntimeError> {
+        type_name.as_symbol_str()?;
+
+        let items: List = Container::with_capacity(mem, fields.len() as ArraySize)?;
+        for field in fields {
+            StackAnyContainer::push(&items, mem, *field)?;
+        }
+
+        mem.alloc(Record {
+            type_name: TaggedCellPtr::new_with(type_name),
+            fields: items,
+        })
+    }
+
+    /// Return the Symbol naming the record's type
+    pub fn type_name<'guard>(&self, guard: &'guard dyn MutatorScope) -> TaggedScopedPtr<'guard> {
+        self.type_name.get(guard)
+    }
+
+    /// Return a type error if the record is not of the named type
+    pub fn check_type<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        type_name: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        let own_type = self.type_name(guard);
+        if own_type == type_name {
+            Ok(())
+        } else {
+            Err(err_type(&format!(
+                "Expected a {} record but got a {} record",
+                type_name, own_type
+            )))
+        }
+    }
+
+    /// Return the number of fields
+    pub fn field_count(&self) -> ArraySize {
+        self.fields.length()
+    }
+
+    /// Return the value of the field at the given index
+    pub fn field<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        IndexedAnyContainer::get(&self.fields, guard, index)
+    }
+
+    /// Set the value of the field at the given index
+    pub fn set_field<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        index: ArraySize,
+        value: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        IndexedAnyContainer::set(&self.fields, mem, index, value)
+    }
+}
+
+impl Print for Record {
+    fn print<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "(Record {}", self.type_name(guard))?;
+        for index in 0..self.field_count() {
+            write!(f, " {}", self.field(guard, index)?)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Trace for Record {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.type_name.get(guard));
+        self.fields.trace(guard, roots);
+    }
+}
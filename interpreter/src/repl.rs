@@ -1,10 +1,134 @@
-use crate::compiler::compile;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+
+use crate::compiler::{compile, compile_strict};
+use crate::containers::HashIndexedAnyContainer;
+use crate::dict::Dict;
 use crate::error::{ErrorKind, RuntimeError};
-use crate::memory::{Mutator, MutatorView};
+use crate::function::Function;
+use crate::lexer::{TokenType, Tokens, UNTERMINATED_STRING};
+use crate::memory::{Mutator, MutatorView, Roots, Trace};
 use crate::parser::parse;
-use crate::safeptr::{CellPtr, TaggedScopedPtr};
+use crate::printer::{
+    pretty_print, print_bounded, within_bounds, PRETTY_WIDTH, PRINT_DEPTH, PRINT_ELEMENTS,
+};
+use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedScopedPtr};
+use crate::symbolmap::SymbolCase;
 use crate::vm::Thread;
 
+/// The most functions a `CompileCache` holds. Once it is full, the oldest entry is forgotten
+/// to make room for each new one.
+const COMPILE_CACHE_CAPACITY: usize = 256;
+
+/// Everything that decides what a piece of source code compiles to: the reader options in effect
+/// and whether it was compiled strictly, as well as the source itself
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    source: String,
+    symbol_case: SymbolCase,
+    text_interning: bool,
+    strict: bool,
+}
+
+/// A cache of compiled top-level expressions keyed by their source text, so that entering the
+/// same line again does not allocate another identical `Function`.
+///
+/// Compiled code refers to globals by name and looks them up when it is run, so a cached
+/// `Function` stays correct when globals are redefined. Entries point into the heap, so the cache
+/// must be dropped before the `Memory` is reset, and its entries are roots when it is traced.
+pub struct CompileCache {
+    entries: RefCell<HashMap<CacheKey, CellPtr<Function>>>,
+    /// The keys of the entries, oldest first
+    order: RefCell<VecDeque<CacheKey>>,
+}
+
+impl CompileCache {
+    pub fn new() -> CompileCache {
+        CompileCache {
+            entries: RefCell::new(HashMap::new()),
+            order: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Return the `Function` previously compiled from identical source with the same options, or
+    /// parse and compile the source and remember the result. If `globals` is given, the source is
+    /// compiled strictly against them, as by `compile_strict()`. Errors are not cached.
+    pub fn compile<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        source: &str,
+        globals: Option<ScopedPtr<'guard, Dict>>,
+    ) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+        let key = CacheKey {
+            source: String::from(source),
+            symbol_case: mem.symbol_case(),
+            text_interning: mem.text_interning(),
+            strict: globals.is_some(),
+        };
+
+        if let Some(function) = self.entries.borrow().get(&key) {
+            return Ok(function.get(mem));
+        }
+
+        let ast = parse(mem, source)?;
+        let function = match globals {
+            Some(globals) => compile_strict(mem, ast, globals)?,
+            None => compile(mem, ast)?,
+        };
+
+        let mut entries = self.entries.borrow_mut();
+        let mut order = self.order.borrow_mut();
+        if entries.len() == COMPILE_CACHE_CAPACITY {
+            if let Some(oldest) = order.pop_front() {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key.clone(), CellPtr::new_with(function));
+        order.push_back(key);
+
+        Ok(function)
+    }
+}
+
+impl Default for CompileCache {
+    fn default() -> CompileCache {
+        CompileCache::new()
+    }
+}
+
+impl Trace for CompileCache {
+    /// Every cached Function is live
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        for function in self.entries.borrow().values() {
+            roots.value(function.get(guard).as_tagged(guard));
+        }
+    }
+}
+
+/// Return true if the input is an incomplete expression that continues on the next line: it has
+/// more open parentheses than close parentheses, or it ends inside a string. Parentheses in
+/// strings, comments and character literals are not counted. Any other lexer error, or more
+/// close parentheses than open, is left for the parser to report.
+pub fn needs_more_input(input: &str) -> bool {
+    let mut depth = 0;
+
+    for token in Tokens::new(input) {
+        match token {
+            Ok(token) => match token.token {
+                TokenType::OpenParen => depth += 1,
+                TokenType::CloseParen => depth -= 1,
+                _ => (),
+            },
+            Err(e) => {
+                return matches!(e.error_kind(), ErrorKind::LexerError(reason) if reason == UNTERMINATED_STRING)
+            }
+        }
+    }
+
+    depth > 0
+}
+
 /// A mutator that returns a Repl instance
 pub struct RepMaker {}
 
@@ -20,16 +144,26 @@ impl Mutator for RepMaker {
 /// Mutator that implements the VM
 pub struct ReadEvalPrint {
     main_thread: CellPtr<Thread>,
+    cache: CompileCache,
 }
 
 impl ReadEvalPrint {
     pub fn alloc(mem: &MutatorView) -> Result<ReadEvalPrint, RuntimeError> {
         Ok(ReadEvalPrint {
             main_thread: CellPtr::new_with(Thread::alloc(mem)?),
+            cache: CompileCache::new(),
         })
     }
 }
 
+impl Trace for ReadEvalPrint {
+    /// The roots of the repl are those of its thread and its compiled lines
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        self.main_thread.get(guard).trace(guard, roots);
+        self.cache.trace(guard, roots);
+    }
+}
+
 impl Mutator for ReadEvalPrint {
     type Input = String;
     type Output = ();
@@ -38,46 +172,68 @@ impl Mutator for ReadEvalPrint {
         let thread = self.main_thread.get(mem);
 
         // If the first 2 chars of the line are ":d", then the user has requested a debug
-        // representation
-        let (line, debug) = if line.starts_with(":d ") {
-            (&line[3..], true)
+        // representation. If they are ":pp", the result is pretty printed.
+        let (line, debug, pretty) = if line.starts_with(":d ") {
+            (&line[3..], true, false)
+        } else if line.starts_with(":pp ") {
+            (&line[4..], false, true)
         } else {
-            (line.as_str(), false)
+            (line.as_str(), false, false)
         };
 
         match (|mem, line| -> Result<TaggedScopedPtr, RuntimeError> {
-            let value = parse(mem, line)?;
-
-            if debug {
-                println!(
-                    "# Debug\n## Input:\n```\n{}\n```\n## Parsed:\n```\n{:?}\n```",
-                    line, value
-                );
+            // debug output shows every compilation stage, so bypasses the cache
+            if !debug {
+                let function = self.cache.compile(mem, line, None)?;
+                return thread.quick_vm_eval(mem, function);
             }
 
-            let function = compile(mem, value)?;
+            let value = parse(mem, line)?;
+            writeln!(
+                mem.output(),
+                "# Debug\n## Input:\n```\n{}\n```\n## Parsed:\n```\n{:?}\n```",
+                line,
+                value
+            )?;
 
-            if debug {
-                println!("## Compiled:\n```\n{:?}\n```", function);
-            }
+            let function = compile(mem, value)?;
+            writeln!(mem.output(), "## Compiled:\n```\n{:?}\n```", function)?;
 
             let value = thread.quick_vm_eval(mem, function)?;
-
-            if debug {
-                println!("## Evaluated:\n```\n{:?}\n```\n", value);
-            }
+            writeln!(mem.output(), "## Evaluated:\n```\n{:?}\n```\n", value)?;
 
             Ok(value)
         })(mem, &line)
         {
-            Ok(value) => println!("{}", value),
+            Ok(value) => {
+                // results are printed with limits so that large or cyclic structures can't
+                // flood or hang the repl. Only values that fit within them are pretty printed.
+                if pretty && within_bounds(*value, PRINT_DEPTH, PRINT_ELEMENTS) {
+                    writeln!(mem.output(), "{}", pretty_print(*value, PRETTY_WIDTH))?;
+                } else {
+                    let output = print_bounded(*value, PRINT_DEPTH, PRINT_ELEMENTS);
+                    writeln!(mem.output(), "{}", output)?;
+                }
+
+                // the most recent result can be referred to as `_`
+                thread.globals(mem).assoc(mem, mem.lookup_sym("_"), value)?;
+            }
 
             Err(e) => {
                 match e.error_kind() {
                     // non-fatal repl errors
-                    ErrorKind::LexerError(_) => e.print_with_source(&line),
-                    ErrorKind::ParseError(_) => e.print_with_source(&line),
-                    ErrorKind::EvalError(_) => e.print_with_source(&line),
+                    ErrorKind::LexerError(_) => e.print_with_source(line, &mut *mem.output())?,
+                    ErrorKind::ParseError(_) => e.print_with_source(line, &mut *mem.output())?,
+                    ErrorKind::EvalError(_) => e.print_with_source(line, &mut *mem.output())?,
+                    ErrorKind::TypeError(_) => e.print_with_source(line, &mut *mem.output())?,
+                    // such as a missing file given to `load` or `require`. If it was writing the
+                    // output that failed, printing the error fails too and ends the repl.
+                    ErrorKind::IOError(_) => e.print_with_source(line, &mut *mem.output())?,
+                    ErrorKind::UserError(value) => {
+                        writeln!(mem.output(), "error: uncaught {}", value.get(mem))?
+                    }
+                    // a blank line has nothing to print
+                    ErrorKind::NoExpression => (),
                     _ => return Err(e),
                 }
             }
@@ -86,3 +242,306 @@ impl Mutator for ReadEvalPrint {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::memory::Memory;
+    use crate::pair::cons;
+
+    /// A writer that appends to a buffer that remains readable by the test
+    #[derive(Clone)]
+    struct SharedBuffer {
+        buffer: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SharedBuffer {
+        fn new() -> SharedBuffer {
+            SharedBuffer {
+                buffer: Rc::new(RefCell::new(Vec::new())),
+            }
+        }
+
+        fn contents(&self) -> String {
+            String::from_utf8(self.buffer.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_lines(lines: &[&str]) -> String {
+        let output = SharedBuffer::new();
+        let mem = Memory::with_output(Box::new(output.clone()));
+
+        let rep = mem.mutate(&RepMaker {}, ()).unwrap();
+        for line in lines {
+            mem.mutate(&rep, String::from(*line)).unwrap();
+        }
+
+        output.contents()
+    }
+
+    #[test]
+    fn repl_captures_program_output() {
+        let output = run_lines(&["(print \"hello \")", "(println 'world)"]);
+        // each line's own output is followed by the repl printing the nil result
+        assert_eq!(output, "hello nil\nworld\nnil\n");
+    }
+
+    #[test]
+    fn repl_captures_results_and_errors() {
+        let output = run_lines(&["(concat 'a \"b\")", "(car 'a)"]);
+        assert_eq!(
+            output,
+            "\"ab\"\nerror: Evaluation error: Parameter to FirstOfPair is not a list\n"
+        );
+    }
+
+    #[test]
+    fn repl_continues_after_type_errors() {
+        let output = run_lines(&["(+ 'a 'b)", "'c"]);
+        assert_eq!(
+            output,
+            "error: Type error: expected Number, got Symbol\nc\n"
+        );
+    }
+
+    #[test]
+    fn repl_continues_after_missing_files() {
+        let output = run_lines(&["(load \"no-such-file.lisp\")", "'c"]);
+        assert!(output.starts_with("error: IO Error: "));
+        assert!(output.contains("no-such-file.lisp"));
+        assert!(output.ends_with("\nc\n"));
+    }
+
+    #[test]
+    fn repl_pretty_prints() {
+        let long = "'((alpha-alpha-alpha beta-beta-beta) (gamma-gamma-gamma delta-delta-delta) \
+                    (epsilon-epsilon-epsilon zeta-zeta-zeta))";
+        let output = run_lines(&[&format!(":pp {}", long), ":pp '(a b)"]);
+        assert_eq!(
+            output,
+            "((alpha-alpha-alpha beta-beta-beta)\n \
+             (gamma-gamma-gamma delta-delta-delta)\n \
+             (epsilon-epsilon-epsilon zeta-zeta-zeta))\n\
+             (a b)\n"
+        );
+    }
+
+    #[test]
+    fn repl_skips_empty_input() {
+        let output = run_lines(&["", "   ", "; just a comment", "'a"]);
+        assert_eq!(output, "a\n");
+    }
+
+    #[test]
+    fn repl_input_balance() {
+        // complete expressions
+        for input in &[
+            "",
+            "a",
+            "(a b)",
+            "(def f (x)\n  (cons x nil))",
+            "'(a (b) c)",
+            "\"(\"",
+            "(concat \"(\" \"((\")",
+            "(print \")\")",
+            "(a) ; (",
+            "(integer->char #\\()",
+            // too many close parentheses is a parse error, not a continuation
+            "(a))",
+            // other lexer errors are reported rather than read past
+            "(a \t",
+        ] {
+            assert!(!needs_more_input(input), "{:?} is complete", input);
+        }
+
+        // incomplete expressions
+        for input in &[
+            "(",
+            "(def f (x)",
+            "(def f (x)\n  (cons x",
+            "'(a (b)",
+            "(print \")\"",
+            "(concat \"a",
+            "(concat \"a)",
+            "\"((",
+            "(a ; )",
+            "(list #\\)",
+        ] {
+            assert!(needs_more_input(input), "{:?} is incomplete", input);
+        }
+    }
+
+    #[test]
+    fn repl_evaluates_multiple_lines() {
+        let output = run_lines(&["(def f (x)\n  (cons x\n        \"(\"))", "(f 'a)"]);
+        assert_eq!(output, "(Function f (x))\n(a . \"(\")\n");
+    }
+
+    #[test]
+    fn repl_binds_last_result() {
+        let output = run_lines(&["(cons 'a 'b)", "(cdr _)", "_", "(car 'a)", "_"]);
+        // an error leaves `_` as the last successful result
+        assert_eq!(
+            output,
+            "(a . b)\nb\nb\nerror: Evaluation error: Parameter to FirstOfPair is not a list\nb\n"
+        );
+    }
+
+    #[test]
+    fn compile_cache_reuses_functions() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let cache = CompileCache::new();
+                let cached = || cache.entries.borrow().len();
+                let source = "(def pair-of (x) (cons x (cons x nil)))";
+
+                let before = mem.allocation_count();
+                let first = cache.compile(mem, source, None)?;
+                let first_allocs = mem.allocation_count() - before;
+
+                let before = mem.allocation_count();
+                let second = cache.compile(mem, source, None)?;
+                let second_allocs = mem.allocation_count() - before;
+
+                assert!(first.as_tagged(mem) == second.as_tagged(mem));
+                assert!(first_allocs > 0);
+                assert!(second_allocs < first_allocs);
+                assert!(second_allocs == 0);
+                assert!(cached() == 1);
+
+                let other = cache.compile(mem, "(pair-of 'a)", None)?;
+                assert!(other.as_tagged(mem) != first.as_tagged(mem));
+                assert!(cached() == 2);
+
+                // errors are not cached
+                assert!(cache.compile(mem, "(a . )", None).is_err());
+                assert!(cached() == 2);
+
+                // the same source compiled strictly is a different entry
+                let globals = Dict::alloc(mem)?;
+                assert!(cache.compile(mem, "(pair-of 'a)", Some(globals)).is_err());
+                globals.assoc(mem, mem.lookup_sym("pair-of"), first.as_tagged(mem))?;
+                let strict = cache.compile(mem, "(pair-of 'a)", Some(globals))?;
+                assert!(strict.as_tagged(mem) != other.as_tagged(mem));
+                assert!(cached() == 3);
+
+                // the cache is bounded, forgetting the oldest entries first
+                for index in 0..COMPILE_CACHE_CAPACITY {
+                    cache.compile(mem, &format!("'{}", index), None)?;
+                }
+                assert!(cached() == COMPILE_CACHE_CAPACITY);
+                let again = cache.compile(mem, source, None)?;
+                assert!(again.as_tagged(mem) != first.as_tagged(mem));
+                assert!(cached() == COMPILE_CACHE_CAPACITY);
+
+                Ok(())
+            }
+        }
+
+        mem.mutate(&Test {}, ()).unwrap();
+    }
+
+    #[test]
+    fn repl_cache_respects_symbol_case() {
+        let output = SharedBuffer::new();
+        let mut mem = Memory::with_output(Box::new(output.clone()));
+
+        let rep = mem.mutate(&RepMaker {}, ()).unwrap();
+        mem.mutate(&rep, String::from("'Foo")).unwrap();
+        mem.set_symbol_case(SymbolCase::Fold);
+        mem.mutate(&rep, String::from("'Foo")).unwrap();
+
+        assert_eq!(output.contents(), "Foo\nfoo\n");
+    }
+
+    #[test]
+    fn repl_cache_entries_are_roots() {
+        /// Allocate enough short-lived Pairs to fill several heap blocks
+        struct Garbage {}
+        impl Mutator for Garbage {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                for _ in 0..5000 {
+                    cons(mem, mem.lookup_sym("garbage"), mem.nil())?;
+                }
+                Ok(())
+            }
+        }
+
+        /// Return whether the cached functions are in blocks that survived a collection
+        struct CacheIsOld<'cache>(&'cache CompileCache);
+        impl Mutator for CacheIsOld<'_> {
+            type Input = ();
+            type Output = Vec<bool>;
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                Ok(self
+                    .0
+                    .entries
+                    .borrow()
+                    .values()
+                    .map(|function| mem.is_old(&*function.get(mem) as *const Function as usize))
+                    .collect())
+            }
+        }
+
+        let output = SharedBuffer::new();
+        let mut mem = Memory::with_output(Box::new(output.clone()));
+
+        // a debug line is not cached. The first one leaves the thread with the storage it needs
+        // to evaluate a line allocated, and the last leaves the thread's instruction stream
+        // referring to its own code rather than the cached line.
+        let rep = mem.mutate(&RepMaker {}, ()).unwrap();
+        mem.mutate(&rep, String::from(":d nil")).unwrap();
+        mem.mutate(&Garbage {}, ()).unwrap();
+        mem.mutate(&rep, String::from("'a")).unwrap();
+        mem.mutate(&Garbage {}, ()).unwrap();
+        mem.mutate(&rep, String::from(":d nil")).unwrap();
+        mem.mutate(&Garbage {}, ()).unwrap();
+
+        // only the cache refers to the compiled line
+        mem.collect_precise(&rep).unwrap();
+        assert!(mem.mutate(&CacheIsOld(&rep.cache), ()).unwrap() == vec![true]);
+        mem.mutate(&Garbage {}, ()).unwrap();
+        mem.mutate(&rep, String::from("'a")).unwrap();
+
+        assert!(output.contents().ends_with("\na\n"));
+    }
+
+    #[test]
+    fn repl_cached_lines_see_redefined_globals() {
+        let output = run_lines(&[
+            "(def pick (x) 'first)",
+            "(pick 'a)",
+            "(def pick (x) 'second)",
+            "(pick 'a)",
+        ]);
+        assert_eq!(
+            output,
+            "(Function pick (x))\nfirst\n(Function pick (x))\nsecond\n"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use std::cell::Cell;
 use std::fmt;
 use std::ops::Deref;
+use std::ptr;
 
 use stickyimmix::{AllocObject, RawPtr};
 
@@ -190,7 +191,7 @@ impl<'guard> PartialEq for TaggedScopedPtr<'guard> {
 /// A wrapper around the runtime typed `TaggedPtr` for storing pointers in data structures with
 /// interior mutability, allowing pointers to be updated to point at different target objects.
 // ANCHOR: DefTaggedCellPtr
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TaggedCellPtr {
     inner: Cell<TaggedPtr>,
 }
@@ -237,6 +238,21 @@ impl TaggedCellPtr {
         self.inner.set(other.inner.get());
     }
 
+    /// Copy all the pointers in `src` to the start of `dest` at once, as `copy_from_slice()` does
+    /// for a mutable slice. The two slices may overlap, so this can also move a run of registers
+    /// along the register window.
+    pub fn copy_slice(dest: &[TaggedCellPtr], src: &[TaggedCellPtr]) {
+        assert!(
+            src.len() <= dest.len(),
+            "source slice is longer than the destination"
+        );
+
+        // A TaggedCellPtr is nothing but a Cell, so its content may be written through a shared
+        // reference, and it has no destructor, so a bitwise copy is a valid copy. `ptr::copy`
+        // allows the ranges to overlap.
+        unsafe { ptr::copy(src.as_ptr(), dest.as_ptr() as *mut TaggedCellPtr, src.len()) }
+    }
+
     /// Return true if the pointer is nil
     pub fn is_nil(&self) -> bool {
         self.inner.get().is_nil()
@@ -263,3 +279,50 @@ impl From<TaggedScopedPtr<'_>> for TaggedCellPtr {
         TaggedCellPtr::new_with(ptr)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::TaggedCellPtr;
+    use crate::taggedptr::TaggedPtr;
+
+    fn numbers(values: &[isize]) -> Vec<TaggedCellPtr> {
+        values
+            .iter()
+            .map(|n| TaggedCellPtr::new_ptr(TaggedPtr::number(*n)))
+            .collect()
+    }
+
+    fn assert_numbers(cells: &[TaggedCellPtr], values: &[isize]) {
+        let expected = numbers(values);
+        assert!(cells.len() == expected.len());
+        for (cell, expect) in cells.iter().zip(expected.iter()) {
+            assert!(cell.get_ptr() == expect.get_ptr());
+        }
+    }
+
+    #[test]
+    fn copy_slice_overlapping() {
+        // shift right, as call args are shunted along to make space for partial args
+        let cells = numbers(&[0, 1, 2, 3, 4, 5]);
+        TaggedCellPtr::copy_slice(&cells[2..], &cells[0..3]);
+        assert_numbers(&cells, &[0, 1, 0, 1, 2, 5]);
+
+        // shift left
+        let cells = numbers(&[0, 1, 2, 3, 4, 5]);
+        TaggedCellPtr::copy_slice(&cells[0..], &cells[2..5]);
+        assert_numbers(&cells, &[2, 3, 4, 3, 4, 5]);
+
+        // separate slices
+        let cells = numbers(&[0, 1, 2, 3]);
+        let other = numbers(&[7, 8]);
+        TaggedCellPtr::copy_slice(&cells[1..], &other);
+        assert_numbers(&cells, &[0, 7, 8, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_slice_too_long() {
+        let cells = numbers(&[0, 1, 2]);
+        TaggedCellPtr::copy_slice(&cells[2..], &cells[0..2]);
+    }
+}
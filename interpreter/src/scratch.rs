@@ -0,0 +1,96 @@
+/// Reusable buffers for temporary lists of values, such as the compiler's unpacked expressions
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+use crate::error::RuntimeError;
+use crate::pair::extend_from_pairs;
+use crate::safeptr::{MutatorScope, TaggedScopedPtr};
+
+/// A pool of empty buffers owned by `Memory`, so that buffers are reused across every compile in
+/// every mutation session rather than allocated and freed for each list that is unpacked.
+///
+/// Buffers are stored with a `'static` lifetime parameter and are only ever handed out empty,
+/// scoped to the mutation session that takes them.
+pub struct ScratchPool {
+    free: RefCell<Vec<Vec<TaggedScopedPtr<'static>>>>,
+    created: Cell<usize>,
+}
+
+impl ScratchPool {
+    pub fn new() -> ScratchPool {
+        ScratchPool {
+            free: RefCell::new(Vec::new()),
+            created: Cell::new(0),
+        }
+    }
+
+    /// Take an empty buffer from the pool, creating a new one if none are free
+    pub fn take<'guard>(&'guard self, _guard: &'guard dyn MutatorScope) -> Scratch<'guard> {
+        let items = match self.free.borrow_mut().pop() {
+            Some(items) => items,
+            None => {
+                self.created.set(self.created.get() + 1);
+                Vec::new()
+            }
+        };
+
+        // Safe because the buffer is empty: no value outlives the scope it was created in
+        let items = unsafe {
+            mem::transmute::<Vec<TaggedScopedPtr<'static>>, Vec<TaggedScopedPtr<'guard>>>(items)
+        };
+
+        Scratch { items, pool: self }
+    }
+
+    /// Take a buffer from the pool and unpack a list of Pair instances into it
+    pub fn take_from_pairs<'guard>(
+        &'guard self,
+        guard: &'guard dyn MutatorScope,
+        pair_list: TaggedScopedPtr<'guard>,
+    ) -> Result<Scratch<'guard>, RuntimeError> {
+        let mut scratch = self.take(guard);
+        extend_from_pairs(guard, pair_list, &mut scratch.items)?;
+        Ok(scratch)
+    }
+
+    /// Return the number of buffers that have been created, which only grows when more buffers
+    /// are in use at once than ever before
+    pub fn created(&self) -> usize {
+        self.created.get()
+    }
+}
+
+/// A buffer on loan from a `ScratchPool`, which is cleared and returned to the pool when dropped
+pub struct Scratch<'guard> {
+    items: Vec<TaggedScopedPtr<'guard>>,
+    pool: &'guard ScratchPool,
+}
+
+impl<'guard> Deref for Scratch<'guard> {
+    type Target = Vec<TaggedScopedPtr<'guard>>;
+
+    fn deref(&self) -> &Vec<TaggedScopedPtr<'guard>> {
+        &self.items
+    }
+}
+
+impl<'guard> DerefMut for Scratch<'guard> {
+    fn deref_mut(&mut self) -> &mut Vec<TaggedScopedPtr<'guard>> {
+        &mut self.items
+    }
+}
+
+impl<'guard> Drop for Scratch<'guard> {
+    fn drop(&mut self) {
+        let mut items = mem::take(&mut self.items);
+        items.clear();
+
+        // Safe because the buffer is empty, as above
+        let items = unsafe {
+            mem::transmute::<Vec<TaggedScopedPtr<'guard>>, Vec<TaggedScopedPtr<'static>>>(items)
+        };
+
+        self.pool.free.borrow_mut().push(items);
+    }
+}
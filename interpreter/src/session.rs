@@ -0,0 +1,218 @@
+/// A persistent evaluation session for embedding the interpreter
+use std::cell::Cell;
+use std::time::Duration;
+
+use crate::compiler::compile;
+use crate::error::RuntimeError;
+use crate::memory::{Memory, Mutator, MutatorView};
+use crate::parser::parse;
+use crate::printer::render;
+use crate::safeptr::CellPtr;
+use crate::snapshot::heap_snapshot;
+use crate::vm::Thread;
+
+/// A mutator that allocates a new Session
+struct SessionMaker {}
+
+impl Mutator for SessionMaker {
+    type Input = ();
+    type Output = Session;
+
+    fn run(&self, mem: &MutatorView, _input: ()) -> Result<Session, RuntimeError> {
+        Ok(Session {
+            thread: CellPtr::new_with(Thread::alloc(mem)?),
+            trace: Cell::new(false),
+            timeout: Cell::new(None),
+        })
+    }
+}
+
+/// A mutator that renders a snapshot of the heap objects reachable from a session's globals
+struct SessionSnapshot<'session> {
+    session: &'session Session,
+}
+
+impl Mutator for SessionSnapshot<'_> {
+    type Input = ();
+    type Output = String;
+
+    fn run(&self, mem: &MutatorView, _input: ()) -> Result<String, RuntimeError> {
+        let globals = self.session.thread.get(mem).globals(mem);
+        heap_snapshot(mem, &[globals.as_tagged(mem)])
+    }
+}
+
+/// Evaluates a sequence of programs with shared global state, each in its own call to
+/// `Memory::mutate()`.
+///
+/// Heap pointers obtained through a `MutatorView` are limited to the mutation session they were
+/// obtained in, so nothing allocated by one `mutate()` call can be used in the next through a
+/// `ScopedPtr`. A `Session` instead holds its `Thread`, and through it the globals dict, in a
+/// `CellPtr`, which is not lifetime-limited, and only dereferences it again from within a later
+/// mutation session. Globals defined in one `eval()` are therefore visible to the next, as in the
+/// REPL.
+///
+/// The `Thread` lives on the heap, so a `Session` must be dropped before `Memory::reset()`.
+pub struct Session {
+    thread: CellPtr<Thread>,
+    /// Whether opcodes are traced to the output as they are executed
+    trace: Cell<bool>,
+    /// The longest each evaluation may run for
+    timeout: Cell<Option<Duration>>,
+}
+
+impl Session {
+    /// Start a new session with a fresh `Thread` and its own globals
+    pub fn new(mem: &Memory) -> Result<Session, RuntimeError> {
+        mem.mutate(&SessionMaker {}, ())
+    }
+
+    /// Parse, compile and evaluate the source in this session, returning the rendered result
+    pub fn eval(&self, mem: &Memory, source: &str) -> Result<String, RuntimeError> {
+        mem.mutate(self, String::from(source))
+    }
+
+    /// Turn execution tracing on or off for the evaluations that follow. While on, every opcode
+    /// is written to the output before it is executed.
+    pub fn set_trace(&self, enabled: bool) {
+        self.trace.set(enabled);
+    }
+
+    /// Limit the time each evaluation that follows may take, or remove the limit with `None`. An
+    /// evaluation that runs over is halted with a timeout error, and the session can still be
+    /// used afterwards.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+    }
+
+    /// Render a snapshot of every heap object reachable from this session's globals, one line
+    /// per object, as described for `heap_snapshot()`. Taking snapshots before and after a
+    /// change shows what it allocated or left unreachable.
+    pub fn heap_snapshot(&self, mem: &Memory) -> Result<String, RuntimeError> {
+        mem.mutate(&SessionSnapshot { session: self }, ())
+    }
+}
+
+impl Mutator for Session {
+    type Input = String;
+    type Output = String;
+
+    fn run(&self, mem: &MutatorView, source: String) -> Result<String, RuntimeError> {
+        let thread = self.thread.get(mem);
+        thread.set_trace(self.trace.get());
+        thread.set_timeout(self.timeout.get());
+        let function = compile(mem, parse(mem, &source)?)?;
+        Ok(render(*thread.quick_vm_eval(mem, function)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::Write;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::Session;
+    use crate::error::ErrorKind;
+    use crate::memory::Memory;
+
+    /// A writer that appends to a buffer that remains readable by the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn session_globals_survive() {
+        let mem = Memory::new();
+        let session = mem.session().unwrap();
+
+        // each eval is a separate mutation session
+        session
+            .eval(&mem, "(def swap (p) (cons (cdr p) (car p)))")
+            .unwrap();
+        session.eval(&mem, "(set 'pair '(a . b))").unwrap();
+        assert!(session.eval(&mem, "(swap pair)").unwrap() == "(b . a)");
+
+        // an error does not end the session
+        match session.eval(&mem, "(undefined)") {
+            Err(e) => assert!(matches!(e.error_kind(), ErrorKind::EvalError(_))),
+            Ok(_) => panic!("undefined function was called"),
+        }
+        assert!(session.eval(&mem, "(swap pair)").unwrap() == "(b . a)");
+
+        // another session has its own globals
+        let other = Session::new(&mem).unwrap();
+        assert!(other.eval(&mem, "(swap pair)").is_err());
+        other.eval(&mem, "(set 'pair 'c)").unwrap();
+        assert!(session.eval(&mem, "pair").unwrap() == "(a . b)");
+    }
+
+    #[test]
+    fn session_heap_snapshot() {
+        let mem = Memory::new();
+        let session = mem.session().unwrap();
+
+        let before = session.heap_snapshot(&mem).unwrap();
+        assert!(before.starts_with("0x"));
+        assert!(before.lines().next().unwrap().contains(" Dict "));
+        assert!(!before.contains(" Pair "));
+
+        session.eval(&mem, "(set 'pair '(a . b))").unwrap();
+        let after = session.heap_snapshot(&mem).unwrap();
+        assert!(after.lines().filter(|line| line.contains(" Pair ")).count() == 1);
+    }
+
+    #[test]
+    fn session_timeout() {
+        // the error traceback is not of interest
+        let mem = Memory::with_output(Box::new(io::sink()));
+        let session = mem.session().unwrap();
+        session
+            .eval(&mem, "(set 'many (string->number \"1000000000000\"))")
+            .unwrap();
+        session
+            .eval(&mem, "(def forever () (dotimes (i many) nil))")
+            .unwrap();
+
+        let timeout = Duration::from_millis(20);
+        session.set_timeout(Some(timeout));
+        match session.eval(&mem, "(forever)") {
+            Err(e) => assert!(*e.error_kind() == ErrorKind::Timeout(timeout)),
+            Ok(_) => panic!("a long loop returned"),
+        }
+        assert!(session.eval(&mem, "(cons 'a 'b)").unwrap() == "(a . b)");
+
+        session.set_timeout(None);
+        assert!(session.eval(&mem, "(car '(c))").unwrap() == "c");
+    }
+
+    #[test]
+    fn session_traces_opcodes() {
+        let buffer = SharedBuffer::default();
+        let mem = Memory::with_output(Box::new(buffer.clone()));
+        let session = mem.session().unwrap();
+
+        session.eval(&mem, "'a").unwrap();
+        assert!(buffer.0.borrow().is_empty());
+
+        session.set_trace(true);
+        session.eval(&mem, "'a").unwrap();
+        let traced = buffer.0.borrow().len();
+        assert!(traced > 0);
+
+        session.set_trace(false);
+        session.eval(&mem, "'a").unwrap();
+        assert!(buffer.0.borrow().len() == traced);
+    }
+}
@@ -1,9 +1,12 @@
 /// A Symbol type
+use std::cmp::Ordering;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::slice;
 use std::str;
 
+use fnv::FnvHasher;
+
 use crate::hashable::Hashable;
 use crate::printer::Print;
 use crate::safeptr::MutatorScope;
@@ -11,22 +14,33 @@ use crate::safeptr::MutatorScope;
 /// A Symbol is a unique object that has a unique name string. The backing storage for the
 /// underlying str data must have a lifetime of at least that of the Symbol instance to
 /// prevent use-after-free.
+/// The name is immutable, so its hash is computed once on creation and cached.
 /// See `SymbolMap`
 // ANCHOR: DefSymbol
 #[derive(Copy, Clone)]
 pub struct Symbol {
     name_ptr: *const u8,
     name_len: usize,
+    hash: u64,
 }
 // ANCHOR_END: DefSymbol
 
 impl Symbol {
-    /// The originating &str must be owned by a SymbolMap hash table
-    pub fn new(name: &str) -> Symbol {
-        Symbol {
+    /// The originating &str must be owned by a SymbolMap hash table. Symbols should only be
+    /// created through `SymbolMap::lookup()` so that each name maps to exactly one Symbol.
+    /// The cached hash is the one `Hashable::hash()` produces with an `FnvHasher`.
+    pub fn new<'guard>(guard: &'guard dyn MutatorScope, name: &str) -> Symbol {
+        let mut symbol = Symbol {
             name_ptr: name.as_ptr(),
             name_len: name.len(),
-        }
+            hash: 0,
+        };
+
+        let mut hasher = FnvHasher::default();
+        symbol.hash(guard, &mut hasher);
+        symbol.hash = hasher.finish();
+
+        symbol
     }
 
     /// Unsafe because Symbol does not own the &str nor can it know anything about the actual lifetime
@@ -42,6 +56,23 @@ impl Symbol {
         unsafe { self.unguarded_as_str() }
     }
     // ANCHOR_END: DefSymbolAsStr
+
+    /// Return the FNV hash of the name, as computed when the Symbol was created
+    pub fn name_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Compare two Symbols by name rather than by identity
+    pub fn eq_by_name<'guard>(&self, guard: &'guard dyn MutatorScope, other: &Symbol) -> bool {
+        self.as_str(guard) == other.as_str(guard)
+    }
+
+    /// Order two Symbols lexicographically by name, comparing the names byte by byte, which for
+    /// UTF-8 is the same as comparing them by Unicode code point. This does not depend on when
+    /// either Symbol was interned, so sorting by it gives the same order in every run.
+    pub fn cmp_by_name<'guard>(&self, guard: &'guard dyn MutatorScope, other: &Symbol) -> Ordering {
+        self.as_str(guard).cmp(other.as_str(guard))
+    }
 }
 
 impl Print for Symbol {
@@ -62,3 +93,49 @@ impl Hashable for Symbol {
     }
 }
 // ANCHOR_END: DefImplHashableForSymbol
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use crate::error::RuntimeError;
+    use crate::memory::{Memory, Mutator, MutatorView};
+    use crate::taggedptr::Value;
+
+    #[test]
+    fn symbol_ordering_by_name() {
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                // interned in an order that is not the sorted order
+                let names = ["pear", "apple", "Zebra", "apples", "app", "pear", "λ", "z"];
+                let mut symbols: Vec<_> = names
+                    .iter()
+                    .map(|name| match *mem.lookup_sym(name) {
+                        Value::Symbol(s) => s,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+
+                symbols.sort_by(|a, b| a.cmp_by_name(mem, b));
+                let sorted: Vec<&str> = symbols.iter().map(|s| s.as_str(mem)).collect();
+                assert!(
+                    sorted == vec!["Zebra", "app", "apple", "apples", "pear", "pear", "z", "λ"]
+                );
+
+                // equal names compare equal
+                assert!(symbols[4].cmp_by_name(mem, &symbols[5]) == Ordering::Equal);
+                assert!(symbols[0].cmp_by_name(mem, &symbols[1]) == Ordering::Less);
+                assert!(symbols[7].cmp_by_name(mem, &symbols[6]) == Ordering::Greater);
+
+                Ok(())
+            }
+        }
+
+        let mem = Memory::new();
+        mem.mutate(&Test {}, ()).unwrap();
+    }
+}
@@ -1,12 +1,36 @@
 /// Implements str interning for mapping Symbol names to unique pointers
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 
 use stickyimmix::{AllocRaw, RawPtr};
 
 use crate::arena::Arena;
+use crate::safeptr::MutatorScope;
 use crate::symbol::Symbol;
 
+/// How the case of symbol names is treated when they are read and interned
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SymbolCase {
+    /// Names are kept as they are written, so `Foo` and `foo` are different symbols
+    #[default]
+    Preserve,
+    /// Names are converted to lower case, so `Foo` and `foo` are the same symbol
+    Fold,
+}
+
+impl SymbolCase {
+    /// Return the name as a symbol of that name is known under this treatment
+    pub fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            SymbolCase::Fold if name.chars().any(char::is_uppercase) => {
+                Cow::Owned(name.to_lowercase())
+            }
+            _ => Cow::Borrowed(name),
+        }
+    }
+}
+
 /// A mapping of symbol names (Strings) to Symbol pointers. Only one copy of the symbol
 /// name String is kept; a Symbol resides in managed memory with a raw pointer to the
 /// String. Thus the lifetime of the SymbolMap must be at least the lifetime of the
@@ -18,22 +42,43 @@ use crate::symbol::Symbol;
 pub struct SymbolMap {
     map: RefCell<HashMap<String, RawPtr<Symbol>>>,
     arena: Arena,
+    case: SymbolCase,
 }
 // ANCHOR_END: DefSymbolMap
 
+/// Symbol names are owned by the map and never freed, so they can be read for as long as the
+/// map is borrowed
+impl MutatorScope for SymbolMap {}
+
 impl SymbolMap {
     pub fn new() -> SymbolMap {
         SymbolMap {
             map: RefCell::new(HashMap::new()),
             arena: Arena::new(),
+            case: SymbolCase::default(),
         }
     }
 
+    /// How symbol names are treated before they are looked up
+    pub fn case(&self) -> SymbolCase {
+        self.case
+    }
+
+    /// Change how symbol names are treated before they are looked up. Symbols that were already
+    /// interned keep their names, so with folding on, one with upper case letters in its name
+    /// can no longer be looked up.
+    pub fn set_case(&mut self, case: SymbolCase) {
+        self.case = case;
+    }
+
     // Can't take a map.entry(name) without providing an owned String, i.e. cloning 'name'
     // Can't insert a new entry with just a reference without hashing twice, and cloning 'name'
     // The common case, lookups, should be fast, inserts can be slower.
     // ANCHOR: DefSymbolMapLookup
     pub fn lookup(&self, name: &str) -> RawPtr<Symbol> {
+        let name = self.case.apply(name);
+        let name: &str = &name;
+
         {
             if let Some(ptr) = self.map.borrow().get(name) {
                 return *ptr;
@@ -41,9 +86,90 @@ impl SymbolMap {
         }
 
         let name = String::from(name);
-        let ptr = self.arena.alloc(Symbol::new(&name)).unwrap();
-        self.map.borrow_mut().insert(name, ptr);
+        let ptr = self.arena.alloc(Symbol::new(self, &name)).unwrap();
+        let previous = self.map.borrow_mut().insert(name, ptr);
+        debug_assert!(previous.is_none(), "symbol interned twice");
         ptr
     }
     // ANCHOR_END: DefSymbolMapLookup
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::RuntimeError;
+    use crate::memory::{Memory, Mutator, MutatorView};
+    use crate::taggedptr::TaggedPtr;
+
+    #[test]
+    fn lookup_same_name_returns_same_ptr() {
+        let syms = SymbolMap::new();
+
+        let first = syms.lookup("foo");
+        let second = syms.lookup("foo");
+        let other = syms.lookup("bar");
+
+        assert!(first == second);
+        assert!(first != other);
+    }
+
+    #[test]
+    fn lookup_stress_same_name() {
+        let syms = SymbolMap::new();
+
+        let first = syms.lookup("stress");
+
+        for n in 0..10000 {
+            // interleave other names to force the underlying HashMap to resize
+            syms.lookup(&format!("other{}", n));
+            assert!(syms.lookup("stress") == first);
+        }
+    }
+
+    #[test]
+    fn lookup_sym_same_ptr_across_mutators() {
+        struct Lookup {}
+        impl Mutator for Lookup {
+            type Input = &'static str;
+            type Output = TaggedPtr;
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                name: &'static str,
+            ) -> Result<TaggedPtr, RuntimeError> {
+                Ok(mem.lookup_sym(name).get_ptr())
+            }
+        }
+
+        let mem = Memory::new();
+        let lookup = Lookup {};
+
+        let first = mem.mutate(&lookup, "foo").unwrap();
+        let second = mem.mutate(&lookup, "foo").unwrap();
+        let other = mem.mutate(&lookup, "bar").unwrap();
+
+        assert!(first == second);
+        assert!(first != other);
+    }
+
+    #[test]
+    fn lookup_folds_case() {
+        let mut syms = SymbolMap::new();
+
+        // case is preserved by default
+        let upper = syms.lookup("Foo");
+        assert!(upper != syms.lookup("foo"));
+        assert!(syms.lookup("Foo") == upper);
+
+        syms.set_case(SymbolCase::Fold);
+        let folded = syms.lookup("FOO");
+        assert!(folded == syms.lookup("foo"));
+        assert!(folded == syms.lookup("fOo"));
+        assert!(folded != upper);
+        assert!(unsafe { folded.as_ref().unguarded_as_str() } == "foo");
+
+        // non-ASCII letters are folded too
+        assert!(syms.lookup("ÉTÉ") == syms.lookup("été"));
+    }
+}
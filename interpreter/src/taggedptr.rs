@@ -17,17 +17,23 @@ use std::ptr::NonNull;
 use stickyimmix::{AllocRaw, RawPtr};
 
 use crate::array::{ArrayU16, ArrayU32, ArrayU8};
+use crate::character::Char;
 use crate::dict::Dict;
-use crate::function::{Function, Partial};
+use crate::error::{err_type, RuntimeError};
+use crate::function::{Function, NativeFunction, Partial};
 use crate::list::List;
-use crate::memory::HeapStorage;
+use crate::memory::{HeapStorage, Roots, Trace};
 use crate::number::NumberObject;
-use crate::pair::Pair;
-use crate::pointerops::{get_tag, ScopedRef, Tagged, TAG_NUMBER, TAG_OBJECT, TAG_PAIR, TAG_SYMBOL};
+use crate::pair::{ListBuilder, Pair};
+use crate::pointerops::{
+    get_tag, untag_word, ScopedRef, Tagged, TAG_NUMBER, TAG_OBJECT, TAG_PAIR, TAG_SYMBOL,
+};
 use crate::printer::Print;
+use crate::record::Record;
 use crate::safeptr::{MutatorScope, ScopedPtr};
 use crate::symbol::Symbol;
-use crate::text::Text;
+use crate::text::{Text, TextBuilder};
+use crate::values::Values;
 use crate::vm::Upvalue;
 
 /// A safe interface to GC-heap managed objects. The `'guard` lifetime must be a safe lifetime for
@@ -39,17 +45,23 @@ pub enum Value<'guard> {
     ArrayU8(ScopedPtr<'guard, ArrayU8>),
     ArrayU16(ScopedPtr<'guard, ArrayU16>),
     ArrayU32(ScopedPtr<'guard, ArrayU32>),
+    Char(ScopedPtr<'guard, Char>),
     Dict(ScopedPtr<'guard, Dict>),
     Function(ScopedPtr<'guard, Function>),
     List(ScopedPtr<'guard, List>),
+    ListBuilder(ScopedPtr<'guard, ListBuilder>),
+    NativeFunction(ScopedPtr<'guard, NativeFunction>),
     Nil,
     Number(isize),
     NumberObject(ScopedPtr<'guard, NumberObject>),
     Pair(ScopedPtr<'guard, Pair>),
     Partial(ScopedPtr<'guard, Partial>),
+    Record(ScopedPtr<'guard, Record>),
     Symbol(ScopedPtr<'guard, Symbol>),
     Text(ScopedPtr<'guard, Text>),
+    TextBuilder(ScopedPtr<'guard, TextBuilder>),
     Upvalue(ScopedPtr<'guard, Upvalue>),
+    Values(ScopedPtr<'guard, Values>),
 }
 // ANCHOR_END: DefValue
 
@@ -61,15 +73,21 @@ impl<'guard> fmt::Display for Value<'guard> {
             Value::Pair(p) => p.print(self, f),
             Value::Symbol(s) => s.print(self, f),
             Value::Number(n) => write!(f, "{}", *n),
+            Value::Char(c) => c.print(self, f),
             Value::Text(t) => t.print(self, f),
+            Value::TextBuilder(t) => t.print(self, f),
             Value::List(a) => a.print(self, f),
+            Value::ListBuilder(b) => b.print(self, f),
             Value::ArrayU8(a) => a.print(self, f),
             Value::ArrayU16(a) => a.print(self, f),
             Value::ArrayU32(a) => a.print(self, f),
             Value::Dict(d) => d.print(self, f),
             Value::Function(n) => n.print(self, f),
+            Value::NativeFunction(n) => n.print(self, f),
             Value::Partial(p) => p.print(self, f),
+            Value::Record(r) => r.print(self, f),
             Value::Upvalue(_) => write!(f, "Upvalue"),
+            Value::Values(v) => v.print(self, f),
             _ => write!(f, "<unidentified-object-type>"),
         }
     }
@@ -81,16 +99,22 @@ impl<'guard> fmt::Debug for Value<'guard> {
             Value::ArrayU8(a) => a.debug(self, f),
             Value::ArrayU16(a) => a.debug(self, f),
             Value::ArrayU32(a) => a.debug(self, f),
+            Value::Char(c) => c.debug(self, f),
             Value::Dict(d) => d.debug(self, f),
             Value::Function(n) => n.debug(self, f),
             Value::List(a) => a.debug(self, f),
+            Value::ListBuilder(b) => b.debug(self, f),
+            Value::NativeFunction(n) => n.debug(self, f),
             Value::Nil => write!(f, "nil"),
             Value::Number(n) => write!(f, "{}", *n),
             Value::Pair(p) => p.debug(self, f),
             Value::Partial(p) => p.debug(self, f),
+            Value::Record(r) => r.debug(self, f),
             Value::Symbol(s) => s.debug(self, f),
             Value::Text(t) => t.debug(self, f),
+            Value::TextBuilder(t) => t.debug(self, f),
             Value::Upvalue(_) => write!(f, "Upvalue"),
+            Value::Values(v) => v.debug(self, f),
             _ => write!(f, "<unidentified-object-type>"),
         }
     }
@@ -98,6 +122,157 @@ impl<'guard> fmt::Debug for Value<'guard> {
 
 impl<'guard> MutatorScope for Value<'guard> {}
 
+/// Trace the object a value points to according to its type
+impl<'guard> Trace for Value<'guard> {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        match self {
+            Value::ArrayU8(a) => a.trace(guard, roots),
+            Value::ArrayU16(a) => a.trace(guard, roots),
+            Value::ArrayU32(a) => a.trace(guard, roots),
+            Value::Char(c) => c.trace(guard, roots),
+            Value::Dict(d) => d.trace(guard, roots),
+            Value::Function(f) => f.trace(guard, roots),
+            Value::List(l) => l.trace(guard, roots),
+            Value::ListBuilder(b) => b.trace(guard, roots),
+            Value::NativeFunction(n) => n.trace(guard, roots),
+            Value::NumberObject(n) => n.trace(guard, roots),
+            Value::Pair(p) => p.trace(guard, roots),
+            Value::Partial(p) => p.trace(guard, roots),
+            Value::Record(r) => r.trace(guard, roots),
+            Value::Text(t) => t.trace(guard, roots),
+            Value::TextBuilder(t) => t.trace(guard, roots),
+            Value::Upvalue(u) => u.trace(guard, roots),
+            Value::Values(v) => v.trace(guard, roots),
+            // not stored in the heap
+            Value::Nil | Value::Number(_) | Value::Symbol(_) => (),
+        }
+    }
+}
+
+/// Extraction of native Rust values, returning a type error if the Value is not of the
+/// expected type
+impl<'guard> Value<'guard> {
+    /// Return the name of the type of this value, for use in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::ArrayU8(_) => "ArrayU8",
+            Value::ArrayU16(_) => "ArrayU16",
+            Value::ArrayU32(_) => "ArrayU32",
+            Value::Char(_) => "Char",
+            Value::Dict(_) => "Dict",
+            Value::Function(_) => "Function",
+            Value::List(_) => "List",
+            Value::ListBuilder(_) => "ListBuilder",
+            Value::NativeFunction(_) => "NativeFunction",
+            Value::Nil => "Nil",
+            Value::Number(_) => "Number",
+            Value::NumberObject(_) => "NumberObject",
+            Value::Pair(_) => "Pair",
+            Value::Partial(_) => "Partial",
+            Value::Record(_) => "Record",
+            Value::Symbol(_) => "Symbol",
+            Value::Text(_) => "Text",
+            Value::TextBuilder(_) => "TextBuilder",
+            Value::Upvalue(_) => "Upvalue",
+            Value::Values(_) => "Values",
+        }
+    }
+
+    /// Return the name of the type of this value as the `type-of` form gives it. Both kinds of
+    /// number are a `number`.
+    pub fn type_symbol_name(&self) -> &'static str {
+        match self {
+            Value::ArrayU8(_) => "array-u8",
+            Value::ArrayU16(_) => "array-u16",
+            Value::ArrayU32(_) => "array-u32",
+            Value::Char(_) => "char",
+            Value::Dict(_) => "dict",
+            Value::Function(_) => "function",
+            Value::List(_) => "list",
+            Value::ListBuilder(_) => "list-builder",
+            Value::NativeFunction(_) => "native-function",
+            Value::Nil => "nil",
+            Value::Number(_) | Value::NumberObject(_) => "number",
+            Value::Pair(_) => "pair",
+            Value::Partial(_) => "partial",
+            Value::Record(_) => "record",
+            Value::Symbol(_) => "symbol",
+            Value::Text(_) => "text",
+            Value::TextBuilder(_) => "text-builder",
+            Value::Upvalue(_) => "upvalue",
+            Value::Values(_) => "values",
+        }
+    }
+
+    /// Return the integer of a Number value
+    pub fn as_number(&self) -> Result<isize, RuntimeError> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            _ => Err(self.type_mismatch("Number")),
+        }
+    }
+
+    /// Return the character of a Char value
+    pub fn as_char(&self) -> Result<char, RuntimeError> {
+        match self {
+            Value::Char(c) => Ok(c.value()),
+            _ => Err(self.type_mismatch("Char")),
+        }
+    }
+
+    /// Return a pointer to a List value
+    pub fn as_list(&self) -> Result<ScopedPtr<'guard, List>, RuntimeError> {
+        match self {
+            Value::List(l) => Ok(*l),
+            _ => Err(self.type_mismatch("List")),
+        }
+    }
+
+    /// Return a pointer to a ListBuilder value
+    pub fn as_list_builder(&self) -> Result<ScopedPtr<'guard, ListBuilder>, RuntimeError> {
+        match self {
+            Value::ListBuilder(b) => Ok(*b),
+            _ => Err(self.type_mismatch("ListBuilder")),
+        }
+    }
+
+    /// Return a pointer to a Record value
+    pub fn as_record(&self) -> Result<ScopedPtr<'guard, Record>, RuntimeError> {
+        match self {
+            Value::Record(r) => Ok(*r),
+            _ => Err(self.type_mismatch("Record")),
+        }
+    }
+
+    /// Return a pointer to a TextBuilder value
+    pub fn as_text_builder(&self) -> Result<ScopedPtr<'guard, TextBuilder>, RuntimeError> {
+        match self {
+            Value::TextBuilder(b) => Ok(*b),
+            _ => Err(self.type_mismatch("TextBuilder")),
+        }
+    }
+
+    /// Return the name of a Symbol value
+    pub fn as_symbol_str(&self) -> Result<&str, RuntimeError> {
+        match self {
+            Value::Symbol(s) => Ok(s.as_str(self)),
+            _ => Err(self.type_mismatch("Symbol")),
+        }
+    }
+
+    /// Return the content of a Text value
+    pub fn as_text_str(&self) -> Result<&str, RuntimeError> {
+        match self {
+            Value::Text(t) => Ok(t.as_str(self)),
+            _ => Err(self.type_mismatch("Text")),
+        }
+    }
+
+    fn type_mismatch(&self, expected: &str) -> RuntimeError {
+        err_type(&format!("expected {}, got {}", expected, self.type_name()))
+    }
+}
+
 /// An unpacked tagged Fat Pointer that carries the type information in the enum structure.
 /// This should represent every type native to the runtime.
 // ANCHOR: DefFatPtr
@@ -106,17 +281,23 @@ pub enum FatPtr {
     ArrayU8(RawPtr<ArrayU8>),
     ArrayU16(RawPtr<ArrayU16>),
     ArrayU32(RawPtr<ArrayU32>),
+    Char(RawPtr<Char>),
     Dict(RawPtr<Dict>),
     Function(RawPtr<Function>),
     List(RawPtr<List>),
+    ListBuilder(RawPtr<ListBuilder>),
+    NativeFunction(RawPtr<NativeFunction>),
     Nil,
     Number(isize),
     NumberObject(RawPtr<NumberObject>),
     Pair(RawPtr<Pair>),
     Partial(RawPtr<Partial>),
+    Record(RawPtr<Record>),
     Symbol(RawPtr<Symbol>),
     Text(RawPtr<Text>),
+    TextBuilder(RawPtr<TextBuilder>),
     Upvalue(RawPtr<Upvalue>),
+    Values(RawPtr<Values>),
 }
 // ANCHOR_END: DefFatPtr
 
@@ -135,11 +316,18 @@ impl FatPtr {
             FatPtr::ArrayU32(raw_ptr) => {
                 Value::ArrayU32(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
             }
+            FatPtr::Char(raw_ptr) => Value::Char(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard))),
             FatPtr::Dict(raw_ptr) => Value::Dict(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard))),
             FatPtr::Function(raw_ptr) => {
                 Value::Function(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
             }
             FatPtr::List(raw_ptr) => Value::List(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard))),
+            FatPtr::ListBuilder(raw_ptr) => {
+                Value::ListBuilder(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
+            }
+            FatPtr::NativeFunction(raw_ptr) => {
+                Value::NativeFunction(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
+            }
             FatPtr::Nil => Value::Nil,
             FatPtr::Number(num) => Value::Number(*num),
             FatPtr::NumberObject(raw_ptr) => {
@@ -149,13 +337,22 @@ impl FatPtr {
             FatPtr::Partial(raw_ptr) => {
                 Value::Partial(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
             }
+            FatPtr::Record(raw_ptr) => {
+                Value::Record(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
+            }
             FatPtr::Symbol(raw_ptr) => {
                 Value::Symbol(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
             }
             FatPtr::Text(raw_ptr) => Value::Text(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard))),
+            FatPtr::TextBuilder(raw_ptr) => {
+                Value::TextBuilder(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
+            }
             FatPtr::Upvalue(raw_ptr) => {
                 Value::Upvalue(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
             }
+            FatPtr::Values(raw_ptr) => {
+                Value::Values(ScopedPtr::new(guard, raw_ptr.scoped_ref(guard)))
+            }
         }
     }
     // ANCHOR_END: DefFatPtrAsValue
@@ -175,15 +372,21 @@ macro_rules! fatptr_from_rawptr {
 fatptr_from_rawptr!(ArrayU8, ArrayU8);
 fatptr_from_rawptr!(ArrayU16, ArrayU16);
 fatptr_from_rawptr!(ArrayU32, ArrayU32);
+fatptr_from_rawptr!(Char, Char);
 fatptr_from_rawptr!(Dict, Dict);
 fatptr_from_rawptr!(Function, Function);
 fatptr_from_rawptr!(List, List);
+fatptr_from_rawptr!(ListBuilder, ListBuilder);
+fatptr_from_rawptr!(NativeFunction, NativeFunction);
 fatptr_from_rawptr!(NumberObject, NumberObject);
 fatptr_from_rawptr!(Pair, Pair);
 fatptr_from_rawptr!(Partial, Partial);
+fatptr_from_rawptr!(Record, Record);
 fatptr_from_rawptr!(Symbol, Symbol);
 fatptr_from_rawptr!(Text, Text);
+fatptr_from_rawptr!(TextBuilder, TextBuilder);
 fatptr_from_rawptr!(Upvalue, Upvalue);
+fatptr_from_rawptr!(Values, Values);
 
 /// Conversion from an integer type
 impl From<isize> for FatPtr {
@@ -213,6 +416,7 @@ impl PartialEq for FatPtr {
             (Symbol(p), Symbol(q)) => p == q,
             (Number(i), Number(j)) => i == j,
             (NumberObject(p), NumberObject(q)) => p == q,
+            (NativeFunction(p), NativeFunction(q)) => p == q,
             _ => false,
         }
     }
@@ -243,6 +447,17 @@ impl TaggedPtr {
         unsafe { self.tag == 0 }
     }
 
+    /// Return the address of the heap object pointed to, or None for nil, inline numbers and
+    /// Symbols, which are not stored in the heap
+    pub fn heap_address(&self) -> Option<usize> {
+        unsafe {
+            match get_tag(self.tag) {
+                TAG_PAIR | TAG_OBJECT => Some(untag_word(self.tag)),
+                _ => None,
+            }
+        }
+    }
+
     /// Construct a generic object TaggedPtr
     fn object<T>(ptr: RawPtr<T>) -> TaggedPtr {
         TaggedPtr {
@@ -318,17 +533,23 @@ impl From<FatPtr> for TaggedPtr {
             FatPtr::ArrayU8(raw) => TaggedPtr::object(raw),
             FatPtr::ArrayU16(raw) => TaggedPtr::object(raw),
             FatPtr::ArrayU32(raw) => TaggedPtr::object(raw),
+            FatPtr::Char(raw) => TaggedPtr::object(raw),
             FatPtr::Dict(raw) => TaggedPtr::object(raw),
             FatPtr::Function(raw) => TaggedPtr::object(raw),
             FatPtr::List(raw) => TaggedPtr::object(raw),
+            FatPtr::ListBuilder(raw) => TaggedPtr::object(raw),
+            FatPtr::NativeFunction(raw) => TaggedPtr::object(raw),
             FatPtr::Nil => TaggedPtr::nil(),
             FatPtr::Number(value) => TaggedPtr::number(value),
             FatPtr::NumberObject(raw) => TaggedPtr::object(raw),
             FatPtr::Pair(raw) => TaggedPtr::pair(raw),
             FatPtr::Partial(raw) => TaggedPtr::object(raw),
+            FatPtr::Record(raw) => TaggedPtr::object(raw),
             FatPtr::Text(raw) => TaggedPtr::object(raw),
+            FatPtr::TextBuilder(raw) => TaggedPtr::object(raw),
             FatPtr::Symbol(raw) => TaggedPtr::symbol(raw),
             FatPtr::Upvalue(raw) => TaggedPtr::object(raw),
+            FatPtr::Values(raw) => TaggedPtr::object(raw),
         }
     }
 }
@@ -340,3 +561,280 @@ impl PartialEq for TaggedPtr {
         unsafe { self.tag == other.tag }
     }
 }
+
+/// The raw word, as the pointer can't be followed without a guard
+impl fmt::Debug for TaggedPtr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TaggedPtr({:#x})", unsafe { self.tag })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FatPtr, TaggedPtr, Value};
+    use crate::array::{ArrayU16, ArrayU32, ArrayU8};
+    use crate::character::Char;
+    use crate::compiler::compile;
+    use crate::dict::Dict;
+    use crate::error::{ErrorKind, RuntimeError};
+    use crate::function::{NativeFunction, Partial};
+    use crate::list::List;
+    use crate::memory::{Memory, Mutator, MutatorView};
+    use crate::number::NumberObject;
+    use crate::pair::{ListBuilder, Pair};
+    use crate::parser::parse;
+    use crate::pointerops::{get_tag, TAG_NUMBER, TAG_OBJECT, TAG_PAIR, TAG_SYMBOL};
+    use crate::record::Record;
+    use crate::safeptr::TaggedScopedPtr;
+    use crate::text::{Text, TextBuilder};
+    use crate::values::Values;
+    use crate::vm::Upvalue;
+
+    /// Return the address of the object a FatPtr points to, or None for inline values
+    fn fat_address(fat: FatPtr) -> Option<usize> {
+        match fat {
+            FatPtr::ArrayU8(raw) => Some(raw.as_word()),
+            FatPtr::ArrayU16(raw) => Some(raw.as_word()),
+            FatPtr::ArrayU32(raw) => Some(raw.as_word()),
+            FatPtr::Char(raw) => Some(raw.as_word()),
+            FatPtr::Dict(raw) => Some(raw.as_word()),
+            FatPtr::Function(raw) => Some(raw.as_word()),
+            FatPtr::List(raw) => Some(raw.as_word()),
+            FatPtr::ListBuilder(raw) => Some(raw.as_word()),
+            FatPtr::NativeFunction(raw) => Some(raw.as_word()),
+            FatPtr::Nil => None,
+            FatPtr::Number(_) => None,
+            FatPtr::NumberObject(raw) => Some(raw.as_word()),
+            FatPtr::Pair(raw) => Some(raw.as_word()),
+            FatPtr::Partial(raw) => Some(raw.as_word()),
+            FatPtr::Record(raw) => Some(raw.as_word()),
+            FatPtr::Symbol(raw) => Some(raw.as_word()),
+            FatPtr::Text(raw) => Some(raw.as_word()),
+            FatPtr::TextBuilder(raw) => Some(raw.as_word()),
+            FatPtr::Upvalue(raw) => Some(raw.as_word()),
+            FatPtr::Values(raw) => Some(raw.as_word()),
+        }
+    }
+
+    #[test]
+    fn tagging_round_trip_all_types() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let function = compile(view, parse(view, "(cons 'a 'b)")?)?;
+                let name = view.lookup_sym("native");
+
+                // one of each Value type, with the type name and low bit tag expected of it
+                let values = [
+                    (ArrayU8::alloc(view)?.as_tagged(view), "ArrayU8"),
+                    (ArrayU16::alloc(view)?.as_tagged(view), "ArrayU16"),
+                    (ArrayU32::alloc(view)?.as_tagged(view), "ArrayU32"),
+                    (view.alloc_tagged(Char::new('c'))?, "Char"),
+                    (Dict::alloc(view)?.as_tagged(view), "Dict"),
+                    (function.as_tagged(view), "Function"),
+                    (List::alloc(view)?.as_tagged(view), "List"),
+                    (ListBuilder::alloc(view)?.as_tagged(view), "ListBuilder"),
+                    (
+                        NativeFunction::alloc(view, name, 0, 0)?.as_tagged(view),
+                        "NativeFunction",
+                    ),
+                    (view.nil(), "Nil"),
+                    (TaggedScopedPtr::new(view, TaggedPtr::number(-3)), "Number"),
+                    (view.alloc_tagged(NumberObject::zero())?, "NumberObject"),
+                    (view.alloc_tagged(Pair::new())?, "Pair"),
+                    (
+                        Partial::alloc(view, function, None, &[])?.as_tagged(view),
+                        "Partial",
+                    ),
+                    (
+                        Record::alloc(view, view.lookup_sym("point"), &[view.nil()])?
+                            .as_tagged(view),
+                        "Record",
+                    ),
+                    (view.lookup_sym("sym"), "Symbol"),
+                    (
+                        view.alloc_tagged(Text::new_from_str(view, "text")?)?,
+                        "Text",
+                    ),
+                    (TextBuilder::alloc(view)?.as_tagged(view), "TextBuilder"),
+                    (Upvalue::alloc(view, 0)?.as_tagged(view), "Upvalue"),
+                    (
+                        Values::alloc_from_slice(view, &[view.nil()])?.as_tagged(view),
+                        "Values",
+                    ),
+                ];
+
+                for (value, type_name) in values.iter() {
+                    let tagged = value.get_ptr();
+                    let word = unsafe { tagged.tag };
+
+                    let expected_tag = match *type_name {
+                        "Nil" => None,
+                        "Number" => Some(TAG_NUMBER),
+                        "Pair" => Some(TAG_PAIR),
+                        "Symbol" => Some(TAG_SYMBOL),
+                        _ => Some(TAG_OBJECT),
+                    };
+                    match expected_tag {
+                        Some(tag) => assert!(get_tag(word) == tag, "{} tag", type_name),
+                        None => assert!(word == 0),
+                    }
+
+                    // TaggedPtr -> FatPtr -> TaggedPtr preserves identity
+                    let fat = FatPtr::from(tagged);
+                    assert!(TaggedPtr::from(fat) == tagged, "{} round trip", type_name);
+                    assert!(fat.as_value(view).type_name() == *type_name);
+
+                    // the object address must leave the tag bits free, and removing the tag
+                    // gives the address back
+                    if let Some(address) = fat_address(fat) {
+                        assert!(address & 0x3 == 0, "{} is not 4-byte aligned", type_name);
+                        assert!(word & !0x3 == address, "{} address", type_name);
+                    }
+                }
+
+                assert!(
+                    FatPtr::from(values[10].0.get_ptr())
+                        .as_value(view)
+                        .as_number()?
+                        == -3
+                );
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn value_extract_native() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let number = TaggedScopedPtr::new(view, TaggedPtr::number(-7));
+                assert!(number.as_number()? == -7);
+
+                let symbol = view.lookup_sym("foo");
+                assert!(symbol.as_symbol_str()? == "foo");
+
+                let text = view.alloc_tagged(Text::new_from_str(view, "bar")?)?;
+                assert!(text.as_text_str()? == "bar");
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn value_extract_type_mismatch() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let symbol = view.lookup_sym("foo");
+                let nil = view.nil();
+
+                match symbol.as_number() {
+                    Err(e) => assert!(
+                        *e.error_kind()
+                            == ErrorKind::TypeError(String::from("expected Number, got Symbol"))
+                    ),
+                    Ok(_) => panic!("a Symbol is not a Number"),
+                }
+
+                assert!(nil.as_symbol_str().is_err());
+                assert!(symbol.as_text_str().is_err());
+                assert!(Value::Nil.as_number().is_err());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+
+    #[test]
+    fn native_function_round_trip() {
+        let mut mem = Memory::new();
+        mem.register_native("negate", 1, |mem, args| {
+            let n = args[0].as_number()?;
+            Ok(TaggedScopedPtr::new(mem, TaggedPtr::number(-n)))
+        });
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let name = view.lookup_sym("negate");
+                let native = NativeFunction::alloc(view, name, 1, 0)?;
+
+                // ScopedPtr -> TaggedPtr -> FatPtr -> Value and back again
+                let tagged = native.as_tagged(view);
+                let fat = FatPtr::from(tagged.get_ptr());
+                assert!(fat == FatPtr::from(tagged.get_ptr()));
+                assert!(TaggedPtr::from(fat) == tagged.get_ptr());
+
+                match fat.as_value(view) {
+                    Value::NativeFunction(f) => {
+                        assert!(f.arity() == 1);
+                        assert!(f.id() == 0);
+
+                        let arg = TaggedScopedPtr::new(view, TaggedPtr::number(5));
+                        let result = view.call_native(f.id(), &[arg])?;
+                        assert!(result.as_number()? == -5);
+                    }
+                    _ => panic!("expected a NativeFunction"),
+                }
+
+                assert!(tagged.type_name() == "NativeFunction");
+                assert!(format!("{}", tagged) == "(NativeFunction negate)");
+
+                // there is no native function registered with this id
+                assert!(view.call_native(1, &[]).is_err());
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
+}
@@ -4,12 +4,14 @@ use std::hash::{Hash, Hasher};
 use std::slice;
 use std::str;
 
+use crate::array::ArrayU8;
+use crate::containers::{Container, SliceableContainer, StackContainer};
 use crate::error::{ErrorKind, RuntimeError};
 use crate::hashable::Hashable;
-use crate::memory::MutatorView;
+use crate::memory::{MutatorView, Roots, Trace};
 use crate::printer::Print;
 use crate::rawarray::{ArraySize, RawArray};
-use crate::safeptr::MutatorScope;
+use crate::safeptr::{MutatorScope, ScopedPtr};
 
 /// While Text is somewhat similar to Symbol, it is instead garbage-collected heap allocated and not interned.
 #[derive(Copy, Clone)]
@@ -73,15 +75,79 @@ impl Print for Text {
     }
 }
 
+impl Trace for Text {
+    fn trace(&self, _guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.storage(&self.content);
+    }
+}
+
 impl Hashable for Text {
     fn hash<'guard, H: Hasher>(&self, guard: &'guard dyn MutatorScope, h: &mut H) {
         self.as_str(guard).hash(h)
     }
 }
 
+/// A growable buffer for building a string a piece at a time. Appending copies only the appended
+/// string, where concatenating to a Text copies the whole string so far into a new Text each time.
+#[derive(Clone)]
+pub struct TextBuilder {
+    content: ArrayU8,
+}
+
+impl TextBuilder {
+    /// Allocate an empty TextBuilder on the heap
+    pub fn alloc<'guard>(
+        mem: &'guard MutatorView,
+    ) -> Result<ScopedPtr<'guard, TextBuilder>, RuntimeError> {
+        mem.alloc(TextBuilder {
+            content: ArrayU8::new(),
+        })
+    }
+
+    /// Append a copy of the given &str slice to the content
+    pub fn push_str<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        from_str: &str,
+    ) -> Result<(), RuntimeError> {
+        for byte in from_str.bytes() {
+            self.content.push(mem, byte)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the content so far into a new Text object. The builder can continue to be appended to.
+    pub fn to_text<'guard>(&self, mem: &'guard MutatorView) -> Result<Text, RuntimeError> {
+        self.content.access_slice(mem, |bytes| {
+            // only whole &str slices are ever appended, so the content is always valid utf-8
+            Text::new_from_str(mem, str::from_utf8(bytes).unwrap())
+        })
+    }
+}
+
+impl Print for TextBuilder {
+    fn print<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        self.content.access_slice(guard, |bytes| {
+            write!(f, "(TextBuilder \"{}\")", str::from_utf8(bytes).unwrap())
+        })
+    }
+}
+
+impl Trace for TextBuilder {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.array(guard, &self.content);
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Text;
+    use super::{Text, TextBuilder};
     use crate::error::RuntimeError;
     use crate::memory::{Memory, Mutator, MutatorView};
 
@@ -170,4 +236,43 @@ mod test {
         let test = Test {};
         mem.mutate(&test, ()).unwrap();
     }
+
+    #[test]
+    fn text_builder_append() {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(
+                &self,
+                view: &MutatorView,
+                _input: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                let builder = TextBuilder::alloc(view)?;
+                assert!(builder.to_text(view)?.as_str(view).is_empty());
+
+                let mut expected = String::new();
+                for _ in 0..1000 {
+                    builder.push_str(view, "こん")?;
+                    expected.push_str("こん");
+                }
+                builder.push_str(view, "")?;
+
+                assert!(builder.to_text(view)?.as_str(view) == expected);
+
+                // the builder can be appended to after a Text has been made from it
+                builder.push_str(view, "!")?;
+                expected.push('!');
+                assert!(builder.to_text(view)?.as_str(view) == expected);
+
+                Ok(())
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, ()).unwrap();
+    }
 }
@@ -0,0 +1,640 @@
+/// Generic traversal of the graph of objects reachable from a value.
+///
+/// Features that need to walk a whole structure - copying, comparing, hashing, printing - share
+/// the walk itself and the cycle protection by implementing `Visitor` and calling `traverse()`.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::array::ArraySize;
+use crate::containers::{Container, FillAnyContainer, IndexedAnyContainer, SliceableContainer};
+use crate::error::RuntimeError;
+use crate::list::List;
+use crate::memory::MutatorView;
+use crate::pair::Pair;
+use crate::safeptr::{MutatorScope, TaggedScopedPtr};
+use crate::taggedptr::Value;
+
+/// What the traversal should do after a Visitor has been shown a node
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Walk {
+    /// Go on to visit the node's children
+    Descend,
+    /// Don't visit the node's children but carry on with the rest of the graph
+    Skip,
+    /// End the traversal
+    Stop,
+}
+
+/// Something that can be traversed: it may have an identity and children.
+///
+/// A single value is a node, and so is a pair of values, which walks two graphs in lockstep to
+/// compare them.
+pub trait Node<'guard>: Copy {
+    /// The identity of a node that can have children
+    type Key: Eq + Hash;
+
+    /// Return the identity of this node if it can have children, so that it is only visited once
+    /// however many times it is reached. Nodes that can't have children have no identity and are
+    /// visited each time they are reached.
+    fn key(&self) -> Option<Self::Key>;
+
+    /// Append the children of this node, in order, to `children`
+    fn children(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        children: &mut Vec<Self>,
+    ) -> Result<(), RuntimeError>;
+}
+
+/// The heap address of a Pair or a List, the only types that are traversed into
+fn container_key(value: TaggedScopedPtr) -> Option<usize> {
+    match *value {
+        Value::Pair(pair) => Some(&*pair as *const Pair as usize),
+        Value::List(list) => Some(&*list as *const List as usize),
+        _ => None,
+    }
+}
+
+/// Pairs have two children, first and second, and Lists have their items as children. Any other
+/// type is a leaf.
+impl<'guard> Node<'guard> for TaggedScopedPtr<'guard> {
+    type Key = usize;
+
+    fn key(&self) -> Option<usize> {
+        container_key(*self)
+    }
+
+    fn children(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        children: &mut Vec<Self>,
+    ) -> Result<(), RuntimeError> {
+        match **self {
+            Value::Pair(pair) => {
+                children.push(pair.first.get(guard));
+                children.push(pair.second.get(guard));
+            }
+            Value::List(list) => {
+                list.access_slice(guard, |items| {
+                    children.extend(items.iter().map(|item| item.get(guard)))
+                });
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// A pair of values has children only if both are Pairs or both are Lists of the same length, in
+/// which case the children are the corresponding children of each value paired up.
+impl<'guard> Node<'guard> for (TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>) {
+    type Key = (usize, usize);
+
+    fn key(&self) -> Option<(usize, usize)> {
+        match (container_key(self.0), container_key(self.1)) {
+            (Some(left), Some(right)) => Some((left, right)),
+            _ => None,
+        }
+    }
+
+    fn children(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        children: &mut Vec<Self>,
+    ) -> Result<(), RuntimeError> {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+
+        match (*self.0, *self.1) {
+            (Value::Pair(_), Value::Pair(_)) | (Value::List(_), Value::List(_)) => {
+                self.0.children(guard, &mut left)?;
+                self.1.children(guard, &mut right)?;
+            }
+            _ => (),
+        }
+
+        if left.len() == right.len() {
+            children.extend(left.into_iter().zip(right));
+        }
+
+        Ok(())
+    }
+}
+
+/// Callbacks for `traverse()`
+pub trait Visitor<'guard, N: Node<'guard>> {
+    /// Called once for each node with an identity that is reachable from the root, and each time
+    /// a node without one is reached, in depth-first order. The return value decides whether
+    /// the node's children are visited next.
+    fn node(&mut self, node: N) -> Result<Walk, RuntimeError>;
+
+    /// Called for each child of a node that is descended into, before the child itself is
+    /// visited. This is also called for children that have already been visited, so that shared
+    /// structure and cycles can be reconstructed.
+    fn child(&mut self, _parent: N, _index: usize, _child: N) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+/// Walk the graph of nodes reachable from `root`, visiting each node with an identity only once.
+///
+/// An explicit work list is used rather than recursion so that long lists can't overflow the
+/// native stack, and remembering which nodes have been visited ensures that cyclic structures
+/// terminate.
+pub fn traverse<'guard, N, V>(
+    guard: &'guard dyn MutatorScope,
+    root: N,
+    visitor: &mut V,
+) -> Result<(), RuntimeError>
+where
+    N: Node<'guard>,
+    V: Visitor<'guard, N>,
+{
+    let mut visited = HashSet::new();
+    if let Some(key) = root.key() {
+        visited.insert(key);
+    }
+
+    let mut pending = vec![root];
+    let mut children = Vec::new();
+
+    while let Some(node) = pending.pop() {
+        match visitor.node(node)? {
+            Walk::Descend => (),
+            Walk::Skip => continue,
+            Walk::Stop => break,
+        }
+
+        children.clear();
+        node.children(guard, &mut children)?;
+
+        for (index, child) in children.iter().enumerate() {
+            visitor.child(node, index, *child)?;
+        }
+
+        // pushed in reverse so that they are popped in order
+        for child in children.iter().rev() {
+            let unvisited = match child.key() {
+                Some(key) => visited.insert(key),
+                None => true,
+            };
+
+            if unvisited {
+                pending.push(*child);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies each Pair and List reachable from a value, preserving shared structure and cycles
+struct DeepCopy<'guard> {
+    mem: &'guard MutatorView<'guard>,
+    copies: HashMap<usize, TaggedScopedPtr<'guard>>,
+}
+
+impl<'guard> DeepCopy<'guard> {
+    /// Return the copy of the given value, allocating it with nil children if this is the first
+    /// time it has been reached. Values that aren't traversed into are not copied.
+    fn copy_of(
+        &mut self,
+        value: TaggedScopedPtr<'guard>,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let key = match value.key() {
+            Some(key) => key,
+            None => return Ok(value),
+        };
+
+        if let Some(copy) = self.copies.get(&key) {
+            return Ok(*copy);
+        }
+
+        let copy = match *value {
+            Value::Pair(pair) => {
+                let copy = Pair::new();
+                copy.first_pos.set(pair.first_pos.get());
+                copy.second_pos.set(pair.second_pos.get());
+                self.mem.alloc_tagged(copy)?
+            }
+            Value::List(list) => {
+                let length = list.length();
+                let copy = List::alloc_with_capacity(self.mem, length)?;
+                copy.fill(self.mem, length, self.mem.nil())?;
+                copy.as_tagged(self.mem)
+            }
+            _ => value,
+        };
+
+        self.copies.insert(key, copy);
+        Ok(copy)
+    }
+}
+
+impl<'guard> Visitor<'guard, TaggedScopedPtr<'guard>> for DeepCopy<'guard> {
+    fn node(&mut self, node: TaggedScopedPtr<'guard>) -> Result<Walk, RuntimeError> {
+        self.copy_of(node)?;
+        Ok(Walk::Descend)
+    }
+
+    fn child(
+        &mut self,
+        parent: TaggedScopedPtr<'guard>,
+        index: usize,
+        child: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        let parent = self.copy_of(parent)?;
+        let child = self.copy_of(child)?;
+
+        match *parent {
+            Value::Pair(pair) if index == 0 => pair.first.set(child),
+            Value::Pair(pair) => pair.second.set(child),
+            Value::List(list) => {
+                IndexedAnyContainer::set(&*list, self.mem, index as ArraySize, child)?
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Return a copy of a value in which every Pair and List reachable from it is newly allocated.
+/// Any other objects, such as Symbols, Text and Functions, are shared with the original.
+pub fn deep_copy<'guard>(
+    mem: &'guard MutatorView,
+    value: TaggedScopedPtr<'guard>,
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+    let mut visitor = DeepCopy {
+        mem,
+        copies: HashMap::new(),
+    };
+
+    traverse(mem, value, &mut visitor)?;
+    visitor.copy_of(value)
+}
+
+/// Compares two value graphs in lockstep, stopping at the first difference
+struct StructuralEquality<'guard> {
+    guard: &'guard dyn MutatorScope,
+    equal: bool,
+}
+
+impl<'guard> Visitor<'guard, (TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>)>
+    for StructuralEquality<'guard>
+{
+    fn node(
+        &mut self,
+        (left, right): (TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>),
+    ) -> Result<Walk, RuntimeError> {
+        if left == right {
+            return Ok(Walk::Skip);
+        }
+
+        let guard = self.guard;
+        self.equal = match (*left, *right) {
+            (Value::Symbol(s1), Value::Symbol(s2)) => s1.eq_by_name(guard, &s2),
+            (Value::Text(t1), Value::Text(t2)) => t1.as_str(guard) == t2.as_str(guard),
+            (Value::Char(c1), Value::Char(c2)) => c1.value() == c2.value(),
+            (Value::Pair(_), Value::Pair(_)) => return Ok(Walk::Descend),
+            // Lists of different lengths can't be equal, so only compare the items of Lists of
+            // the same length, pairwise
+            (Value::List(l1), Value::List(l2)) => {
+                if l1.length() == l2.length() {
+                    return Ok(Walk::Descend);
+                }
+                false
+            }
+            // inline Numbers with equal values are identical, which was checked above
+            _ => false,
+        };
+
+        if self.equal {
+            Ok(Walk::Skip)
+        } else {
+            Ok(Walk::Stop)
+        }
+    }
+}
+
+/// Structural equality: numbers and Chars are compared by value, Text by content, Symbols by
+/// identity, Pairs by recursively comparing their members and Lists by comparing their items
+/// element-wise. Any other type is compared by identity.
+///
+/// Each pair of Pairs or Lists is only compared once, which ensures that comparing cyclic
+/// structures terminates: a cycle that reaches containers already being compared does not add
+/// any new differences.
+pub fn values_equal<'guard>(
+    guard: &'guard dyn MutatorScope,
+    left: TaggedScopedPtr<'guard>,
+    right: TaggedScopedPtr<'guard>,
+) -> bool {
+    let mut visitor = StructuralEquality { guard, equal: true };
+
+    traverse(guard, (left, right), &mut visitor).is_ok() && visitor.equal
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::containers::StackAnyContainer;
+    use crate::memory::{Memory, Mutator};
+    use crate::pair::{cons, pairs_from_slice};
+    use crate::printer::render;
+
+    fn test_helper(test_fn: fn(&MutatorView) -> Result<(), RuntimeError>) {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = fn(&MutatorView) -> Result<(), RuntimeError>;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                test_fn: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                test_fn(mem)
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, test_fn).unwrap();
+    }
+
+    /// Records every node and child reference it is shown
+    struct Recorder<'guard> {
+        nodes: Vec<TaggedScopedPtr<'guard>>,
+        children: Vec<(TaggedScopedPtr<'guard>, usize, TaggedScopedPtr<'guard>)>,
+    }
+
+    impl<'guard> Visitor<'guard, TaggedScopedPtr<'guard>> for Recorder<'guard> {
+        fn node(&mut self, node: TaggedScopedPtr<'guard>) -> Result<Walk, RuntimeError> {
+            self.nodes.push(node);
+            Ok(Walk::Descend)
+        }
+
+        fn child(
+            &mut self,
+            parent: TaggedScopedPtr<'guard>,
+            index: usize,
+            child: TaggedScopedPtr<'guard>,
+        ) -> Result<(), RuntimeError> {
+            self.children.push((parent, index, child));
+            Ok(())
+        }
+    }
+
+    fn record<'guard>(
+        mem: &'guard MutatorView,
+        root: TaggedScopedPtr<'guard>,
+    ) -> Result<Recorder<'guard>, RuntimeError> {
+        let mut recorder = Recorder {
+            nodes: Vec::new(),
+            children: Vec::new(),
+        };
+        traverse(mem, root, &mut recorder)?;
+        Ok(recorder)
+    }
+
+    /// Make a cyclic list by pointing the last Pair of the list back at the first
+    fn make_cycle<'guard>(
+        mem: &'guard MutatorView,
+        values: &[TaggedScopedPtr<'guard>],
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let head = pairs_from_slice(mem, values)?;
+
+        let mut last = head;
+        while let Value::Pair(pair) = *last {
+            let next = pair.second.get(mem);
+            if next == mem.nil() {
+                pair.second.set(head);
+                break;
+            }
+            last = next;
+        }
+
+        Ok(head)
+    }
+
+    #[test]
+    fn traverse_nested() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+            let nil = mem.nil();
+
+            // (a (b) . [a b])
+            let list = List::alloc(mem)?;
+            StackAnyContainer::push(&*list, mem, a)?;
+            StackAnyContainer::push(&*list, mem, b)?;
+            let list = list.as_tagged(mem);
+            let inner = pairs_from_slice(mem, &[b])?;
+            let rest = cons(mem, inner, list)?;
+            let root = cons(mem, a, rest)?;
+
+            let recorder = record(mem, root)?;
+
+            assert!(recorder.nodes == vec![root, a, rest, inner, b, nil, list, a, b]);
+
+            assert!(
+                recorder.children
+                    == vec![
+                        (root, 0, a),
+                        (root, 1, rest),
+                        (rest, 0, inner),
+                        (rest, 1, list),
+                        (inner, 0, b),
+                        (inner, 1, nil),
+                        (list, 0, a),
+                        (list, 1, b),
+                    ]
+            );
+
+            // an atom is the only node
+            let recorder = record(mem, a)?;
+            assert!(recorder.nodes == vec![a]);
+            assert!(recorder.children.is_empty());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn traverse_shared_and_cyclic() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+            let nil = mem.nil();
+
+            // a Pair that is reached twice is only visited once
+            let shared = pairs_from_slice(mem, &[a])?;
+            let root = cons(mem, shared, shared)?;
+            let recorder = record(mem, root)?;
+            assert!(recorder.nodes == vec![root, shared, a, nil]);
+            assert!(recorder.children.len() == 4);
+
+            // a cycle terminates, but the reference back to the start is still shown
+            let cycle = make_cycle(mem, &[a, b])?;
+            let second = match *cycle {
+                Value::Pair(pair) => pair.second.get(mem),
+                _ => panic!("cycle is not a Pair"),
+            };
+            let recorder = record(mem, cycle)?;
+            assert!(recorder.nodes == vec![cycle, a, second, b]);
+            assert!(recorder.children[3] == (second, 1, cycle));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn traverse_skip_and_stop() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            struct Counter {
+                count: usize,
+                walk: Walk,
+            }
+
+            impl<'guard> Visitor<'guard, TaggedScopedPtr<'guard>> for Counter {
+                fn node(&mut self, _node: TaggedScopedPtr<'guard>) -> Result<Walk, RuntimeError> {
+                    self.count += 1;
+                    Ok(self.walk)
+                }
+            }
+
+            let a = mem.lookup_sym("a");
+            let list = pairs_from_slice(mem, &[a, a, a])?;
+
+            let mut counter = Counter {
+                count: 0,
+                walk: Walk::Skip,
+            };
+            traverse(mem, list, &mut counter)?;
+            assert!(counter.count == 1);
+
+            let mut counter = Counter {
+                count: 0,
+                walk: Walk::Stop,
+            };
+            traverse(mem, cons(mem, list, list)?, &mut counter)?;
+            assert!(counter.count == 1);
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn deep_copy_preserves_structure() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+
+            let list = List::alloc(mem)?;
+            StackAnyContainer::push(&*list, mem, a)?;
+            let shared = pairs_from_slice(mem, &[a, b])?;
+            StackAnyContainer::push(&*list, mem, shared)?;
+            let root = cons(mem, shared, list.as_tagged(mem))?;
+
+            let copy = deep_copy(mem, root)?;
+            assert!(copy != root);
+            assert!(render(*copy) == render(*root));
+
+            // every Pair and List is new, and structure that was shared is still shared
+            let copied_list = match *copy {
+                Value::Pair(pair) => {
+                    let copied_shared = pair.first.get(mem);
+                    assert!(copied_shared != shared);
+
+                    let copied_list = pair.second.get(mem).as_list()?;
+                    assert!(IndexedAnyContainer::get(&*copied_list, mem, 1)? == copied_shared);
+                    copied_list
+                }
+                _ => panic!("copy is not a Pair"),
+            };
+            assert!(copied_list.as_tagged(mem) != list.as_tagged(mem));
+
+            // cycles are copied as cycles
+            let cycle = make_cycle(mem, &[a, b])?;
+            let copy = deep_copy(mem, cycle)?;
+            assert!(copy != cycle);
+            assert!(values_equal(mem, copy, cycle));
+            match *copy {
+                Value::Pair(pair) => match *pair.second.get(mem) {
+                    Value::Pair(pair) => assert!(pair.second.get(mem) == copy),
+                    _ => panic!("copy is not a cycle"),
+                },
+                _ => panic!("copy is not a Pair"),
+            }
+
+            // atoms are returned as they are
+            assert!(deep_copy(mem, a)? == a);
+            assert!(deep_copy(mem, mem.nil())? == mem.nil());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn values_equal_lists() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+
+            let make_list = |items: &[TaggedScopedPtr<'_>]| -> Result<_, RuntimeError> {
+                let list = List::alloc(mem)?;
+                for item in items {
+                    StackAnyContainer::push(&*list, mem, *item)?;
+                }
+                Ok(list.as_tagged(mem))
+            };
+
+            // items are compared structurally rather than by identity
+            let inner1 = pairs_from_slice(mem, &[a, b])?;
+            let inner2 = pairs_from_slice(mem, &[a, b])?;
+            let list1 = make_list(&[a, inner1])?;
+            let list2 = make_list(&[a, inner2])?;
+            assert!(list1 != list2);
+            assert!(values_equal(mem, list1, list2));
+
+            // Lists nested in Lists
+            let outer1 = make_list(&[list1, b])?;
+            let outer2 = make_list(&[list2, b])?;
+            assert!(values_equal(mem, outer1, outer2));
+
+            // a differing item
+            let list3 = make_list(&[b, inner2])?;
+            assert!(!values_equal(mem, list1, list3));
+
+            // differing lengths, including a prefix and an empty List
+            let prefix = make_list(&[a])?;
+            let empty = make_list(&[])?;
+            assert!(!values_equal(mem, list1, prefix));
+            assert!(!values_equal(mem, prefix, list1));
+            assert!(!values_equal(mem, empty, prefix));
+            assert!(values_equal(mem, empty, make_list(&[])?));
+
+            // a List is never equal to a Pair list with the same members
+            assert!(!values_equal(
+                mem,
+                list1,
+                pairs_from_slice(mem, &[a, inner1])?
+            ));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+use crate::array::ArraySize;
+use crate::containers::{Container, IndexedAnyContainer, StackAnyContainer};
+use crate::error::{err_eval, RuntimeError};
+use crate::list::List;
+use crate::memory::{MutatorView, Roots, Trace};
+use crate::printer::Print;
+use crate::safeptr::{MutatorScope, ScopedPtr, TaggedScopedPtr};
+
+/// A bundle of the values given to the `values` form, which `let-values` takes apart again. A
+/// Values object is never modified after it is allocated.
+#[derive(Clone)]
+pub struct Values {
+    items: List,
+}
+
+impl Values {
+    /// Allocate a Values object holding a copy of the given values
+    pub fn alloc_from_slice<'guard>(
+        mem: &'guard MutatorView,
+        values: &[TaggedScopedPtr<'guard>],
+    ) -> Result<ScopedPtr<'guard, Values>, RuntimeError> {
+        let items: List = Container::with_capacity(mem, values.len() as ArraySize)?;
+        for value in values {
+            StackAnyContainer::push(&items, mem, *value)?;
+        }
+
+        mem.alloc(Values { items })
+    }
+
+    /// Return the number of values in the bundle
+    pub fn length(&self) -> ArraySize {
+        self.items.length()
+    }
+
+    /// Return the value at the given index
+    pub fn get<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        index: ArraySize,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        IndexedAnyContainer::get(&self.items, guard, index)
+    }
+
+    /// Return all the values, checking that there are as many as expected
+    pub fn expect<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        count: ArraySize,
+    ) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+        if self.length() != count {
+            return Err(err_eval(&format!(
+                "Expected {} values but {} were given",
+                count,
+                self.length()
+            )));
+        }
+
+        (0..count).map(|index| self.get(guard, index)).collect()
+    }
+}
+
+impl Print for Values {
+    fn print<'guard>(
+        &self,
+        guard: &'guard dyn MutatorScope,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "(values")?;
+        for index in 0..self.length() {
+            write!(f, " {}", self.get(guard, index)?)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Trace for Values {
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        self.items.trace(guard, roots);
+    }
+}
@@ -1,24 +1,50 @@
 use std::cell::Cell;
+use std::cmp::{min, Ordering};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Write;
+use std::mem::swap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::array::{Array, ArraySize};
-use crate::bytecode::{ByteCode, InstructionStream, Opcode};
+use crate::bytecode::{ByteCode, InstructionStream, Opcode, Register, REGISTER_COUNT};
+use crate::character::{char_from_codepoint, Char};
+use crate::compiler::compile_all;
 use crate::containers::{
-    Container, FillAnyContainer, HashIndexedAnyContainer, IndexedAnyContainer, IndexedContainer,
-    SliceableContainer, StackAnyContainer, StackContainer,
+    Container, ContainerFromSlice, FillAnyContainer, HashIndexedAnyContainer, IndexedAnyContainer,
+    IndexedContainer, InsertableAnyContainer, SliceableContainer, StackAnyContainer,
+    StackContainer,
 };
-use crate::dict::Dict;
-use crate::error::{err_eval, RuntimeError};
-use crate::function::{Function, Partial};
+use crate::dict::{Deletion, Dict};
+use crate::error::{err_eval, err_type, ErrorKind, RuntimeError};
+use crate::function::{Function, NativeFunction, Partial};
 use crate::list::List;
-use crate::memory::MutatorView;
-use crate::pair::Pair;
+use crate::memory::{MutatorView, Roots, Trace};
+use crate::number::{
+    check_radix, number_to_string, parse_number, INLINE_NUMBER_MAX, INLINE_NUMBER_MIN,
+};
+use crate::pair::{
+    cons, pairs_from_slice, value_from_1_pair, values_from_2_pairs, vec_from_pairs, ListBuilder,
+    Pair,
+};
+use crate::parser::parse_all;
+use crate::printer::render;
+use crate::record::Record;
 use crate::safeptr::{CellPtr, MutatorScope, ScopedPtr, TaggedCellPtr, TaggedScopedPtr};
 use crate::taggedptr::{TaggedPtr, Value};
+use crate::text::{Text, TextBuilder};
+use crate::traverse::{deep_copy, values_equal};
+use crate::values::Values;
 
 pub const RETURN_REG: usize = 0;
 pub const ENV_REG: usize = 1;
 pub const FIRST_ARG_REG: usize = 2;
 
+/// The number of call frames a new Thread has room for before its frame list is reallocated
+pub const DEFAULT_FRAME_CAPACITY: ArraySize = 16;
+
 /// Evaluation control flow flags
 #[derive(PartialEq)]
 pub enum EvalStatus<'guard> {
@@ -26,6 +52,13 @@ pub enum EvalStatus<'guard> {
     Pending,
     /// Eval is complete, here is the resulting value
     Return(TaggedScopedPtr<'guard>),
+    /// A `SortListWith` instruction must call its comparator, which it can only do once the
+    /// register window is released
+    SortListWith {
+        dest: Register,
+        list: Register,
+        less: Register,
+    },
 }
 
 /// A call frame, separate from the register stack
@@ -38,6 +71,11 @@ pub struct CallFrame {
     ip: Cell<ArraySize>,
     /// Stack base - index into the register stack where register window for this function begins
     base: ArraySize,
+    /// Arguments left over from an over-applied call, to be applied to the return value. A List,
+    /// or nil if there are none.
+    surplus_args: TaggedCellPtr,
+    /// For the top level code of a loaded file, the Text directory the file is in. Otherwise nil.
+    source_dir: TaggedCellPtr,
 }
 // ANCHOR_END: DefCallFrame
 
@@ -48,20 +86,28 @@ impl CallFrame {
             function: CellPtr::new_with(main_fn),
             ip: Cell::new(0),
             base: 0,
+            surplus_args: TaggedCellPtr::new_nil(),
+            source_dir: TaggedCellPtr::new_nil(),
         }
     }
 
     /// Instantiate a new stack frame for the given function, beginning execution at the given
-    /// instruction pointer and a register window at `base`
+    /// instruction pointer and a register window at `base`. Any `surplus_args` will be applied to
+    /// the function's return value. A `source_dir` Text marks the frame as the top level of a
+    /// loaded file.
     fn new<'guard>(
         function: ScopedPtr<'guard, Function>,
         ip: ArraySize,
         base: ArraySize,
+        surplus_args: TaggedScopedPtr<'guard>,
+        source_dir: TaggedScopedPtr<'guard>,
     ) -> CallFrame {
         CallFrame {
             function: CellPtr::new_with(function),
             ip: Cell::new(ip),
             base,
+            surplus_args: TaggedCellPtr::new_with(surplus_args),
+            source_dir: TaggedCellPtr::new_with(source_dir),
         }
     }
 
@@ -97,7 +143,7 @@ pub struct Upvalue {
 impl Upvalue {
     /// Allocate a new Upvalue on the heap. The absolute stack index of the object must be
     /// provided.
-    fn alloc<'guard>(
+    pub fn alloc<'guard>(
         mem: &'guard MutatorView,
         location: ArraySize,
     ) -> Result<ScopedPtr<'guard, Upvalue>, RuntimeError> {
@@ -108,48 +154,64 @@ impl Upvalue {
         })
     }
 
-    /// Dereference the upvalue
-    fn get<'guard>(
+    /// Return the value of a closed upvalue. An open upvalue refers to a stack slot instead and
+    /// has no value of its own.
+    pub fn closed_value<'guard>(
         &self,
         guard: &'guard dyn MutatorScope,
-        stack: ScopedPtr<'guard, List>,
-    ) -> Result<TaggedPtr, RuntimeError> {
+    ) -> Option<TaggedScopedPtr<'guard>> {
+        match self.closed.get() {
+            true => Some(self.value.get(guard)),
+            false => None,
+        }
+    }
+
+    /// Return the stack slot of an open upvalue
+    fn stack_slot<'stack>(
+        &self,
+        stack: &'stack [TaggedCellPtr],
+    ) -> Result<&'stack TaggedCellPtr, RuntimeError> {
+        stack
+            .get(self.location as usize)
+            .ok_or_else(|| RuntimeError::new(ErrorKind::BoundsError))
+    }
+
+    /// Dereference the upvalue. The stack is passed as a slice as upvalues are accessed while the
+    /// VM holds the stack register window.
+    fn get(&self, stack: &[TaggedCellPtr]) -> Result<TaggedPtr, RuntimeError> {
         match self.closed.get() {
             true => Ok(self.value.get_ptr()),
-            false => Ok(IndexedContainer::get(&*stack, guard, self.location)?.get_ptr()),
+            false => Ok(self.stack_slot(stack)?.get_ptr()),
         }
     }
 
     /// Write a new value to the Upvalue, placing it here or on the stack depending on the
     /// closedness of it.
-    fn set<'guard>(
-        &self,
-        guard: &'guard dyn MutatorScope,
-        stack: ScopedPtr<'guard, List>,
-        ptr: TaggedPtr,
-    ) -> Result<(), RuntimeError> {
+    fn set(&self, stack: &[TaggedCellPtr], ptr: TaggedPtr) -> Result<(), RuntimeError> {
         match self.closed.get() {
             true => self.value.set_to_ptr(ptr),
-            false => {
-                IndexedContainer::set(&*stack, guard, self.location, TaggedCellPtr::new_ptr(ptr))?
-            }
+            false => self.stack_slot(stack)?.set_to_ptr(ptr),
         };
         Ok(())
     }
 
     /// Close the upvalue, copying the stack variable value into the Upvalue
-    fn close<'guard>(
-        &self,
-        guard: &'guard dyn MutatorScope,
-        stack: ScopedPtr<'guard, List>,
-    ) -> Result<(), RuntimeError> {
-        let ptr = IndexedContainer::get(&*stack, guard, self.location)?.get_ptr();
+    fn close(&self, stack: &[TaggedCellPtr]) -> Result<(), RuntimeError> {
+        let ptr = self.stack_slot(stack)?.get_ptr();
         self.value.set_to_ptr(ptr);
         self.closed.set(true);
         Ok(())
     }
 }
 
+impl Trace for Upvalue {
+    /// The value is only held by the Upvalue once it is closed. Until then it is in a register.
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+        roots.value(self.value.get(guard));
+    }
+}
+
 /// Get the Upvalue for the index into the given closure environment.
 /// Function will panic if types are not as expected.
 fn env_upvalue_lookup<'guard>(
@@ -170,6 +232,242 @@ fn env_upvalue_lookup<'guard>(
     }
 }
 
+/// Return the Function of a Function or Partial value, along with the count of arguments that have
+/// already been applied to it
+fn function_of<'guard>(
+    guard: &'guard dyn MutatorScope,
+    value: TaggedScopedPtr<'guard>,
+) -> Result<(ScopedPtr<'guard, Function>, u8), RuntimeError> {
+    match *value {
+        Value::Function(function) => Ok((function, 0)),
+        Value::Partial(partial) => Ok((partial.function(guard), partial.used())),
+        _ => Err(err_type(&format!(
+            "expected Function or Partial, got {}",
+            value.type_name()
+        ))),
+    }
+}
+
+/// The natural ordering of values for sorting: numbers are ordered by value and Symbols and Text
+/// lexicographically by name. Values of any other type, or of two different types, can't be
+/// ordered.
+fn compare_values<'guard>(
+    guard: &'guard dyn MutatorScope,
+    left: TaggedScopedPtr<'guard>,
+    right: TaggedScopedPtr<'guard>,
+) -> Result<Ordering, RuntimeError> {
+    match (*left, *right) {
+        (Value::Number(l), Value::Number(r)) => Ok(l.cmp(&r)),
+        (Value::Symbol(l), Value::Symbol(r)) => Ok(l.cmp_by_name(guard, &r)),
+        (Value::Text(l), Value::Text(r)) => Ok(l.as_str(guard).cmp(r.as_str(guard))),
+        _ => Err(err_type(&format!(
+            "cannot order {} and {}",
+            left.type_name(),
+            right.type_name()
+        ))),
+    }
+}
+
+/// Sort values in place by their natural ordering, as given by `compare_values()`. The sort is
+/// stable.
+fn sort_values<'guard>(
+    guard: &'guard dyn MutatorScope,
+    values: &mut [TaggedScopedPtr<'guard>],
+) -> Result<(), RuntimeError> {
+    // the first error is kept; the order doesn't matter once there is one
+    let mut error = None;
+    values.sort_by(|left, right| {
+        compare_values(guard, *left, *right).unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            Ordering::Equal
+        })
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Sort values in place with a merge sort, calling `less` to order them. `less(a, b)` is true if
+/// `a` belongs before `b`. Values that are in order are left in their original order, so the sort
+/// is stable. Adjacent runs are merged in passes, each merging runs twice as long as the last, so
+/// `less` is called O(n log n) times.
+fn merge_sort<'guard, F>(
+    values: &mut Vec<TaggedScopedPtr<'guard>>,
+    mut less: F,
+) -> Result<(), RuntimeError>
+where
+    F: FnMut(TaggedScopedPtr<'guard>, TaggedScopedPtr<'guard>) -> Result<bool, RuntimeError>,
+{
+    let len = values.len();
+    let mut merged = Vec::with_capacity(len);
+
+    let mut width = 1;
+    while width < len {
+        merged.clear();
+
+        for start in (0..len).step_by(2 * width) {
+            let middle = min(start + width, len);
+            let end = min(start + 2 * width, len);
+
+            // a value is only taken from the right run if it belongs strictly before the left
+            let (mut left, mut right) = (start, middle);
+            while left < middle && right < end {
+                if less(values[right], values[left])? {
+                    merged.push(values[right]);
+                    right += 1;
+                } else {
+                    merged.push(values[left]);
+                    left += 1;
+                }
+            }
+            merged.extend_from_slice(&values[left..middle]);
+            merged.extend_from_slice(&values[right..end]);
+        }
+
+        swap(values, &mut merged);
+        width *= 2;
+    }
+
+    Ok(())
+}
+
+/// The elements of the pair list or List to be sorted
+fn sortable_values<'guard>(
+    guard: &'guard dyn MutatorScope,
+    list: TaggedScopedPtr<'guard>,
+) -> Result<Vec<TaggedScopedPtr<'guard>>, RuntimeError> {
+    match *list {
+        Value::Nil => Ok(Vec::new()),
+        Value::Pair(_) => vec_from_pairs(guard, list),
+        Value::List(list) => Ok(list.access_slice(guard, |items| {
+            items.iter().map(|item| item.get(guard)).collect()
+        })),
+        _ => Err(err_type(&format!("cannot sort {}", list.type_name()))),
+    }
+}
+
+/// A new sequence of the sorted `values` of the same type as the `original` one
+fn sorted_like<'guard>(
+    mem: &'guard MutatorView,
+    original: TaggedScopedPtr<'guard>,
+    values: &[TaggedScopedPtr<'guard>],
+) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+    match *original {
+        Value::List(_) => {
+            let sorted = List::alloc_with_capacity(mem, values.len() as ArraySize)?;
+            for value in values {
+                StackAnyContainer::push(&*sorted, mem, *value)?;
+            }
+            Ok(sorted.as_tagged(mem))
+        }
+        _ => pairs_from_slice(mem, values),
+    }
+}
+
+/// Read, parse and compile the source file at the given path into a Function that expects no
+/// arguments. An empty file compiles to a Function that returns nil.
+fn compile_file<'guard>(
+    mem: &'guard MutatorView,
+    path: &Path,
+) -> Result<ScopedPtr<'guard, Function>, RuntimeError> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| RuntimeError::from(e).with_context(path.display().to_string()))?;
+
+    let mut exprs = parse_all(mem, &source)?;
+    if exprs.is_empty() {
+        exprs.push(mem.nil());
+    }
+
+    compile_all(mem, &exprs)
+}
+
+/// Build the error for a failed assertion. The source position of the asserted form, if the
+/// parser recorded one, is the position of the error.
+fn assertion_failed(form: TaggedScopedPtr, message: String) -> RuntimeError {
+    let kind = ErrorKind::EvalError(format!("Assertion failed: {}", message));
+
+    match *form {
+        Value::Pair(pair) => match pair.first_pos.get() {
+            Some(pos) => RuntimeError::with_pos(kind, pos),
+            None => RuntimeError::new(kind),
+        },
+        _ => RuntimeError::new(kind),
+    }
+}
+
+/// Convert the result of a checked integer operation to an inline number, returning an overflow
+/// error naming the operation if there is no result or it does not fit inline. There is no
+/// heap-allocated number type to promote to yet.
+fn inline_number(result: Option<isize>, op: &str) -> Result<TaggedPtr, RuntimeError> {
+    match result {
+        Some(n) if (INLINE_NUMBER_MIN..=INLINE_NUMBER_MAX).contains(&n) => Ok(TaggedPtr::number(n)),
+        _ => Err(err_eval(&format!("Integer overflow in {}", op))),
+    }
+}
+
+/// Divide `num` by `denom`, returning the quotient and remainder. The quotient is rounded toward
+/// zero, as Rust's `/` does, or toward negative infinity if `floor` is set. Either way
+/// `num == quotient * denom + remainder`, so the remainder takes the sign of `num` when truncating
+/// and the sign of `denom` when flooring.
+fn divide_integer(
+    num: isize,
+    denom: isize,
+    floor: bool,
+    op: &str,
+) -> Result<(TaggedPtr, TaggedPtr), RuntimeError> {
+    if denom == 0 {
+        return Err(err_eval(&format!("Division by zero in {}", op)));
+    }
+
+    // neither can fail with a non-zero denominator and inline numbers
+    let quotient = num / denom;
+    let remainder = num % denom;
+
+    let (quotient, remainder) = if floor && remainder != 0 && (remainder < 0) != (denom < 0) {
+        (quotient - 1, remainder + denom)
+    } else {
+        (quotient, remainder)
+    };
+
+    Ok((
+        inline_number(Some(quotient), op)?,
+        TaggedPtr::number(remainder),
+    ))
+}
+
+/// A seed for a new Thread's random number generator that differs from run to run
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Return the range of `count` registers beginning at register `start`, or an error if they would
+/// run past the end of the register window.
+fn register_range(start: usize, count: usize) -> Result<Range<usize>, RuntimeError> {
+    match start.checked_add(count) {
+        Some(end) if end <= REGISTER_COUNT => Ok(start..end),
+        _ => Err(err_eval(&format!(
+            "{} arguments from register {} would overflow the register window",
+            count, start
+        ))),
+    }
+}
+
+/// Convert a Number value to a List index. Negative and oversized numbers are out of bounds.
+fn list_index(value: TaggedScopedPtr) -> Result<ArraySize, RuntimeError> {
+    let index = value.as_number()?;
+
+    if index < 0 || index > ArraySize::MAX as isize {
+        Err(RuntimeError::new(ErrorKind::BoundsError))
+    } else {
+        Ok(index as ArraySize)
+    }
+}
+
 /// An execution Thread object.
 /// It is composed of all the data structures required for execution of a bytecode stream -
 /// register stack, call frames, closure upvalues, thread-local global associations and the current
@@ -178,7 +476,9 @@ fn env_upvalue_lookup<'guard>(
 pub struct Thread {
     /// An array of CallFrames
     frames: CellPtr<CallFrameList>,
-    /// An array of pointers any object type
+    /// An array of pointers any object type. It must never be resized while the register window
+    /// slice into it is held: growing it may reallocate the backing storage, leaving the slice
+    /// dangling.
     stack: CellPtr<List>,
     /// The current stack base pointer
     stack_base: Cell<ArraySize>,
@@ -189,6 +489,19 @@ pub struct Thread {
     globals: CellPtr<Dict>,
     /// The current instruction location
     instr: CellPtr<InstructionStream>,
+    /// An opcode to execute before fetching the next one from the instruction stream
+    next_opcode: Cell<Option<Opcode>>,
+    /// When set, each opcode is written to the output before it is executed
+    trace: Cell<bool>,
+    /// The longest an evaluation may run for before it is halted
+    timeout: Cell<Option<Duration>>,
+    /// The time at which the current evaluation is halted
+    deadline: Cell<Option<Instant>>,
+    /// The value carried by the most recent user error, held so that it stays reachable while
+    /// the error propagates
+    thrown: TaggedCellPtr,
+    /// The state of the pseudo-random number generator used by `random`
+    random_state: Cell<u64>,
 }
 // ANCHOR_END: DefThread
 
@@ -197,19 +510,36 @@ impl Thread {
     /// bytecode yet.
     pub fn alloc<'guard>(
         mem: &'guard MutatorView,
+    ) -> Result<ScopedPtr<'guard, Thread>, RuntimeError> {
+        Thread::alloc_with_frame_capacity(mem, DEFAULT_FRAME_CAPACITY)
+    }
+
+    /// Allocate a new Thread as `alloc()` does, but with room for `frame_capacity` call frames
+    /// before the frame list must grow. Deeply recursive programs reallocate the frame list less
+    /// often with a larger capacity.
+    pub fn alloc_with_frame_capacity<'guard>(
+        mem: &'guard MutatorView,
+        frame_capacity: ArraySize,
     ) -> Result<ScopedPtr<'guard, Thread>, RuntimeError> {
         // create an empty stack frame array
-        let frames = CallFrameList::alloc_with_capacity(mem, 16)?;
+        let frames = CallFrameList::alloc_with_capacity(mem, frame_capacity)?;
 
         // create a minimal value stack
         let stack = List::alloc_with_capacity(mem, 256)?;
         stack.fill(mem, 256, mem.nil())?;
 
-        // create an empty upvalue stack->heap mapping
-        let upvalues = Dict::alloc(mem)?;
-
-        // create an empty globals dict
-        let globals = Dict::alloc(mem)?;
+        // create an empty upvalue stack->heap mapping. Upvalues are removed as soon as they are
+        // closed, so entries come and go constantly: backshift rather than leave tombstones
+        let upvalues = Dict::alloc_with_deletion(mem, Deletion::Backshift)?;
+
+        // create a globals dict containing the registered native functions. Globals are always
+        // keyed by Symbol, so hash them by address
+        let globals = Dict::alloc_symbol_keyed(mem)?;
+        for (id, (name, arity)) in mem.natives().enumerate() {
+            let name = mem.lookup_sym(name);
+            let native = NativeFunction::alloc(mem, name, arity, id)?;
+            globals.assoc(mem, name, native.as_tagged(mem))?;
+        }
 
         // create an empty instruction stream
         let blank_code = ByteCode::alloc(mem)?;
@@ -222,9 +552,108 @@ impl Thread {
             upvalues: CellPtr::new_with(upvalues),
             globals: CellPtr::new_with(globals),
             instr: CellPtr::new_with(instr),
+            next_opcode: Cell::new(None),
+            trace: Cell::new(false),
+            timeout: Cell::new(None),
+            deadline: Cell::new(None),
+            thrown: TaggedCellPtr::new_nil(),
+            random_state: Cell::new(clock_seed()),
         })
     }
 
+    /// Return every word that might be a pointer to a live object directly referred to by the
+    /// thread's execution state: the Thread itself, its register stack and call frame arrays
+    /// and their backing storage, and every word held in that storage. Nothing is known of what
+    /// the words are, so any of them may be an untagged pointer, a tagged pointer or a number.
+    /// The most recently thrown value is included too.
+    #[cfg(feature = "conservative-roots")]
+    pub fn conservative_roots(&self, guard: &dyn MutatorScope) -> Vec<usize> {
+        /// Add the address of the array, its backing storage and the words in the storage
+        fn array_words<T: Sized + Clone>(
+            guard: &dyn MutatorScope,
+            array: &Array<T>,
+            words: &mut Vec<usize>,
+        ) {
+            words.push(array as *const Array<T> as usize);
+
+            let items = unsafe { array.as_slice(guard) };
+            words.push(items.as_ptr() as usize);
+
+            let word_count = std::mem::size_of_val(items) / std::mem::size_of::<usize>();
+            let storage =
+                unsafe { std::slice::from_raw_parts(items.as_ptr() as *const usize, word_count) };
+            words.extend_from_slice(storage);
+        }
+
+        let mut words = vec![self as *const Thread as usize];
+        array_words(guard, &*self.stack.get(guard), &mut words);
+        array_words(guard, &*self.frames.get(guard), &mut words);
+        words.push(unsafe { *(&self.thrown as *const TaggedCellPtr as *const usize) });
+        words
+    }
+
+    /// Return the value carried by the most recent user error raised in this thread, or nil if
+    /// there has not been one. The value stays rooted by the thread until the next user error.
+    #[cfg(test)]
+    pub fn thrown_value<'guard>(&self, guard: &'guard dyn MutatorScope) -> TaggedScopedPtr<'guard> {
+        self.thrown.get(guard)
+    }
+
+    /// Restart the pseudo-random number sequence. The same seed always gives the same sequence.
+    pub fn seed_random(&self, seed: u64) {
+        self.random_state.set(seed);
+    }
+
+    /// Return the next number in the pseudo-random sequence. This is SplitMix64, which is small
+    /// and fast and has good enough statistical quality for games and simulations, but must not
+    /// be used for anything security-related.
+    fn next_random(&self) -> u64 {
+        let state = self.random_state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.random_state.set(state);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Return the dict of global bindings
+    pub fn globals<'guard>(&self, guard: &'guard dyn MutatorScope) -> ScopedPtr<'guard, Dict> {
+        self.globals.get(guard)
+    }
+
+    /// Turn execution tracing on or off. While on, every opcode is written to the output, along
+    /// with its instruction pointer and the stack base, before it is executed.
+    pub fn set_trace(&self, enabled: bool) {
+        self.trace.set(enabled);
+    }
+
+    /// Limit the wall-clock time each subsequent evaluation may take, or remove the limit with
+    /// `None`. An evaluation that runs over is halted with a timeout error. The time is only
+    /// checked between batches of instructions, so the limit may be overrun by the time taken to
+    /// execute one batch.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+    }
+
+    /// Write a human-readable trace line for an opcode about to be executed
+    fn trace_opcode(
+        &self,
+        mem: &MutatorView,
+        ip: ArraySize,
+        stack_base: usize,
+        opcode: &Opcode,
+    ) -> Result<(), RuntimeError> {
+        writeln!(
+            mem.output(),
+            "trace: ip={} base={} {:?}",
+            ip,
+            stack_base,
+            opcode
+        )?;
+        Ok(())
+    }
+
     /// Retrieve an Upvalue for the given absolute stack offset.
     fn upvalue_lookup<'guard>(
         &self,
@@ -271,6 +700,83 @@ impl Thread {
         }
     }
 
+    /// Save the return ip in the current call frame and push a new call frame for the given
+    /// function, with a register window beginning at the `dest` register, switching the
+    /// instruction stream to the function's code. Any `surplus_args` are applied to the return
+    /// value, and a `source_dir` Text is the directory that relative paths are loaded from while
+    /// the function runs.
+    fn enter_function<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        function: ScopedPtr<'guard, Function>,
+        dest: Register,
+        surplus_args: TaggedScopedPtr<'guard>,
+        source_dir: TaggedScopedPtr<'guard>,
+    ) -> Result<(), RuntimeError> {
+        let frames = self.frames.get(mem);
+        let instr = self.instr.get(mem);
+
+        // Modify the current call frame, saving the return ip
+        let current_frame_ip = instr.get_next_ip();
+        frames.access_slice(mem, |f| {
+            f.last()
+                .expect("No CallFrames in slice!")
+                .ip
+                .set(current_frame_ip)
+        });
+
+        // Create a new call frame, pushing it to the frame stack
+        let new_stack_base = self.stack_base.get() + dest as ArraySize;
+        let frame = CallFrame::new(function, 0, new_stack_base, surplus_args, source_dir);
+        frames.push(mem, frame)?;
+
+        // Update the instruction stream to point to the new function
+        let code = function.code(mem);
+        self.stack_base.set(new_stack_base);
+        instr.switch_frame(code, 0);
+
+        Ok(())
+    }
+
+    /// Compile the source file at the given path and enter its code, recording its directory in
+    /// the new call frame
+    fn enter_source_file<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        path: &Path,
+        dest: Register,
+    ) -> Result<(), RuntimeError> {
+        let function = compile_file(mem, path)?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let source_dir = mem.alloc_tagged(Text::new_from_str(mem, &dir.to_string_lossy())?)?;
+
+        self.enter_function(mem, function, dest, mem.nil(), source_dir)
+    }
+
+    /// Resolve a path given to `load` or `require`. A relative path is relative to the directory
+    /// of the innermost file being loaded, or, outside of any loaded file, to the current working
+    /// directory.
+    fn resolve_source_path(&self, mem: &MutatorView, path: &str) -> PathBuf {
+        let frames = self.frames.get(mem);
+
+        let source_dir = frames.access_slice(mem, |frames| {
+            frames
+                .iter()
+                .rev()
+                .find_map(|frame| match *frame.source_dir.get(mem) {
+                    Value::Text(dir) => Some(PathBuf::from(dir.as_str(mem))),
+                    _ => None,
+                })
+        });
+
+        match source_dir {
+            // joining an absolute path replaces the directory
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
     // ANCHOR: ThreadEvalNextInstr
     /// Execute the next instruction in the current instruction stream
     fn eval_next_instr<'guard>(
@@ -284,13 +790,35 @@ impl Thread {
         let globals = self.globals.get(mem);
         let instr = self.instr.get(mem);
 
+        // A call or return may have moved the stack base: ensure the stack has 256 registers
+        // allocated above it. This may reallocate the stack so it must happen before the
+        // register window is taken.
+        // TODO reset to nil to avoid accidental leakage of previous call values
+        stack.fill(
+            mem,
+            self.stack_base.get() + REGISTER_COUNT as ArraySize,
+            mem.nil(),
+        )?;
+
         // Establish a 256-register window into the stack from the stack base
-        stack.access_slice(mem, |full_stack| {
+        let status = stack.access_slice(mem, |full_stack| {
+            // Registers are only ever written to through `TaggedCellPtr` interior mutability, so
+            // a shared slice is sufficient. This allows Upvalues to access the full stack while
+            // the window is held.
+            let full_stack: &[TaggedCellPtr] = full_stack;
             let stack_base = self.stack_base.get() as usize;
-            let window = &mut full_stack[stack_base..stack_base + 256];
+            let window = &full_stack[stack_base..stack_base + REGISTER_COUNT];
+
+            // Fetch the next instruction and identify it. A pending opcode takes precedence.
+            let ip = instr.get_next_ip();
+            let opcode = match self.next_opcode.take() {
+                Some(opcode) => opcode,
+                None => instr.get_next_opcode(mem)?,
+            };
 
-            // Fetch the next instruction and identify it
-            let opcode = instr.get_next_opcode(mem)?;
+            if self.trace.get() {
+                self.trace_opcode(mem, ip, stack_base, &opcode)?;
+            }
 
             match opcode {
                 // Do nothing.
@@ -306,7 +834,7 @@ impl Thread {
                     window[RETURN_REG].set_to_ptr(result);
 
                     // remove this function's stack frame
-                    frames.pop(mem)?;
+                    let returning = frames.pop(mem)?;
 
                     // if we just returned from the last stack frame, program evaluation is complete
                     if frames.length() == 0 {
@@ -316,6 +844,26 @@ impl Thread {
                         let frame = frames.top(mem)?;
                         self.stack_base.set(frame.base);
                         instr.switch_frame(frame.function.get(mem).code(mem), frame.ip.get());
+
+                        // If the returning function was over-applied, call the return value with
+                        // the surplus arguments. The returning function's register window begins
+                        // at the previous frame's call `dest` register, so the call is set up
+                        // using the same register layout as the compiler would.
+                        if let Value::List(surplus) = *returning.surplus_args.get(mem) {
+                            let arg_count = surplus.length() as usize;
+
+                            surplus.access_slice(mem, |items| {
+                                TaggedCellPtr::copy_slice(&window[FIRST_ARG_REG..], items)
+                            });
+                            window[FIRST_ARG_REG + arg_count].copy_from(&window[RETURN_REG]);
+
+                            let dest = (returning.base - frame.base) as Register;
+                            self.next_opcode.set(Some(Opcode::Call {
+                                function: dest + (FIRST_ARG_REG + arg_count) as Register,
+                                dest,
+                                arg_count: arg_count as Register,
+                            }));
+                        }
                     }
                 }
 
@@ -331,7 +879,7 @@ impl Thread {
                     let test_val = window[test as usize].get(mem);
 
                     match *test_val {
-                        Value::Nil => window[dest as usize].set(mem.lookup_sym("true")),
+                        Value::Nil => window[dest as usize].set(mem.true_sym()),
                         _ => window[dest as usize].set_to_nil(),
                     }
                 }
@@ -345,11 +893,32 @@ impl Thread {
                         Value::Pair(_) => window[dest as usize].set_to_nil(),
                         Value::Nil => window[dest as usize].set_to_nil(),
                         // TODO what other types?
-                        _ => window[dest as usize].set(mem.lookup_sym("true")),
+                        _ => window[dest as usize].set(mem.true_sym()),
+                    }
+                }
+
+                // Evaluate whether the `test` register contains the empty list. There is no
+                // distinct empty list value: `nil` is the empty list, so this is "true" for `nil`
+                // and `nil` for a Pair. Anything else is not a list at all and is a type error
+                // rather than quietly non-empty.
+                Opcode::IsEmpty { dest, test } => {
+                    let test_val = window[test as usize].get(mem);
+
+                    match *test_val {
+                        Value::Nil => window[dest as usize].set(mem.true_sym()),
+                        Value::Pair(_) => window[dest as usize].set_to_nil(),
+                        _ => {
+                            return Err(err_type(&format!(
+                                "expected a list, got {}",
+                                test_val.type_name()
+                            )))
+                        }
                     }
                 }
 
-                // CAR - get the first value of a Pair object
+                // CAR - get the first value of a Pair object. Since `nil` is the empty list, the
+                // first value of `nil` is `nil`, which can't be told apart from a list whose first
+                // value is `nil`. Use `empty?` to distinguish them.
                 Opcode::FirstOfPair { dest, reg } => {
                     let reg_val = window[reg as usize].get(mem);
 
@@ -360,7 +929,8 @@ impl Thread {
                     }
                 }
 
-                // CDR - get the second value of a Pair object
+                // CDR - get the second value of a Pair object. As with CAR, the rest of the empty
+                // list `nil` is `nil`.
                 Opcode::SecondOfPair { dest, reg } => {
                     let reg_val = window[reg as usize].get(mem);
 
@@ -371,6 +941,28 @@ impl Thread {
                     }
                 }
 
+                // Strict CAR - as CAR, but the empty list `nil` has no first value
+                Opcode::StrictFirstOfPair { dest, reg } => {
+                    let reg_val = window[reg as usize].get(mem);
+
+                    match *reg_val {
+                        Value::Pair(p) => window[dest as usize].set_to_ptr(p.first.get_ptr()),
+                        Value::Nil => return Err(err_eval("Parameter to strict-car is nil")),
+                        _ => return Err(err_eval("Parameter to strict-car is not a list")),
+                    }
+                }
+
+                // Strict CDR - as CDR, but the empty list `nil` has no rest
+                Opcode::StrictSecondOfPair { dest, reg } => {
+                    let reg_val = window[reg as usize].get(mem);
+
+                    match *reg_val {
+                        Value::Pair(p) => window[dest as usize].set_to_ptr(p.second.get_ptr()),
+                        Value::Nil => return Err(err_eval("Parameter to strict-cdr is nil")),
+                        _ => return Err(err_eval("Parameter to strict-cdr is not a list")),
+                    }
+                }
+
                 // CONS - create a Pair, pointing to `reg1` and `reg2`
                 Opcode::MakePair { dest, reg1, reg2 } => {
                     let reg1_val = window[reg1 as usize].get_ptr();
@@ -383,6 +975,19 @@ impl Thread {
                     window[dest as usize].set(mem.alloc_tagged(new_pair)?);
                 }
 
+                // Structural comparison - if `test1` and `test2` are equal values, set `dest` to the
+                // symbol "true"
+                Opcode::Equal { dest, test1, test2 } => {
+                    let test1_val = window[test1 as usize].get(mem);
+                    let test2_val = window[test2 as usize].get(mem);
+
+                    if values_equal(mem, test1_val, test2_val) {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
                 // Identity comparison - if `test1` and `test2` are identical pointers, set `dest`
                 // to the symbol "true"
                 Opcode::IsIdentical { dest, test1, test2 } => {
@@ -391,25 +996,93 @@ impl Thread {
                     let test2_val = window[test2 as usize].get_ptr();
 
                     if test1_val == test2_val {
-                        window[dest as usize].set(mem.lookup_sym("true"));
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set(mem.nil());
+                    }
+                }
+
+                // Integer comparison - if `test1` is less than `test2`, set `dest` to the
+                // symbol "true"
+                Opcode::IsLessThan { dest, test1, test2 } => {
+                    let n1 = window[test1 as usize].get(mem).as_number()?;
+                    let n2 = window[test2 as usize].get(mem).as_number()?;
+
+                    if n1 < n2 {
+                        window[dest as usize].set(mem.true_sym());
                     } else {
                         window[dest as usize].set(mem.nil());
                     }
                 }
 
+                // Integer test - if `test` is zero, set `dest` to the symbol "true"
+                Opcode::IsZero { dest, test } => {
+                    let n = window[test as usize].get(mem).as_number()?;
+
+                    if n == 0 {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
+                // Integer test - if `test` is greater than zero, set `dest` to the symbol "true"
+                Opcode::IsPositive { dest, test } => {
+                    let n = window[test as usize].get(mem).as_number()?;
+
+                    if n > 0 {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
+                // Integer test - if `test` is less than zero, set `dest` to the symbol "true"
+                Opcode::IsNegative { dest, test } => {
+                    let n = window[test as usize].get(mem).as_number()?;
+
+                    if n < 0 {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
+                // Integer test - if `test` is even, set `dest` to the symbol "true"
+                Opcode::IsEven { dest, test } => {
+                    let n = window[test as usize].get(mem).as_number()?;
+
+                    if n % 2 == 0 {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
+                // Integer test - if `test` is odd, set `dest` to the symbol "true"
+                Opcode::IsOdd { dest, test } => {
+                    let n = window[test as usize].get(mem).as_number()?;
+
+                    if n % 2 != 0 {
+                        window[dest as usize].set(mem.true_sym());
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
+
                 // Unconditional jump - advance the instruction pointer by `offset`
                 Opcode::Jump { offset } => {
-                    instr.jump(offset);
+                    instr.jump(mem, offset)?;
                 }
 
                 // Jump if the `test` register contains the symbol "true"
                 Opcode::JumpIfTrue { test, offset } => {
                     let test_val = window[test as usize].get(mem);
 
-                    let true_sym = mem.lookup_sym("true"); // TODO preload keyword syms
+                    let true_sym = mem.true_sym();
 
                     if test_val == true_sym {
-                        instr.jump(offset)
+                        instr.jump(mem, offset)?;
                     }
                 }
 
@@ -417,10 +1090,10 @@ impl Thread {
                 Opcode::JumpIfNotTrue { test, offset } => {
                     let test_val = window[test as usize].get(mem);
 
-                    let true_sym = mem.lookup_sym("true");
+                    let true_sym = mem.true_sym();
 
                     if test_val != true_sym {
-                        instr.jump(offset)
+                        instr.jump(mem, offset)?;
                     }
                 }
 
@@ -456,6 +1129,99 @@ impl Thread {
                     }
                 }
 
+                // Put a list of all globally bound symbols, sorted by name, in the register `dest`
+                Opcode::LoadGlobalNames { dest } => {
+                    let mut names = globals.keys(mem)?;
+                    names.sort_by_cached_key(|name| format!("{}", name));
+
+                    window[dest as usize].set(pairs_from_slice(mem, &names)?);
+                }
+
+                // Load an association list of the garbage collection counters into `dest`
+                Opcode::LoadGcStats { dest } => {
+                    let stats = mem.gc_stats();
+                    let counters = [
+                        ("collections", stats.collections as u128),
+                        ("bytes-reclaimed", stats.bytes_reclaimed as u128),
+                        ("last-collection-us", stats.last_duration.as_micros()),
+                    ];
+
+                    let mut entries = Vec::with_capacity(counters.len());
+                    for (name, count) in counters.iter() {
+                        let count = inline_number(isize::try_from(*count).ok(), "gc-stats")?;
+                        entries.push(cons(
+                            mem,
+                            mem.lookup_sym(name),
+                            TaggedScopedPtr::new(mem, count),
+                        )?);
+                    }
+
+                    window[dest as usize].set(pairs_from_slice(mem, &entries)?);
+                }
+
+                // Evaluate whether the symbol in the `name` register has a global binding. Set the
+                // `dest` register to "true" or `nil`.
+                Opcode::IsBound { dest, name } => {
+                    let name_val = window[name as usize].get(mem);
+
+                    if let Value::Symbol(_) = *name_val {
+                        if globals.exists(mem, name_val)? {
+                            window[dest as usize].set(mem.true_sym());
+                        } else {
+                            window[dest as usize].set_to_nil();
+                        }
+                    } else {
+                        return Err(err_eval("Cannot test binding of non-symbol type"));
+                    }
+                }
+
+                // Put a list of the parameter names of the Function or Partial in the `function`
+                // register in `dest`. For a Partial, only the parameters that have not been
+                // applied yet are listed.
+                Opcode::FunctionParams { dest, function } => {
+                    let (function, used) = function_of(mem, window[function as usize].get(mem))?;
+
+                    let mut names = Vec::new();
+                    function.param_names(mem).access_slice(mem, |items| {
+                        for item in &items[used as usize..] {
+                            names.push(item.get(mem));
+                        }
+                    });
+
+                    window[dest as usize].set(pairs_from_slice(mem, &names)?);
+                }
+
+                // Put the name symbol of the Function or Partial in the `function` register in
+                // `dest`, or nil if it is anonymous
+                Opcode::FunctionName { dest, function } => {
+                    let (function, _) = function_of(mem, window[function as usize].get(mem))?;
+                    window[dest as usize].set(function.name_symbol(mem));
+                }
+
+                // Put the number of arguments still needed to call the Function or Partial in the
+                // `function` register in `dest`
+                Opcode::FunctionArity { dest, function } => {
+                    let (function, used) = function_of(mem, window[function as usize].get(mem))?;
+                    let arity = (function.arity() - used) as isize;
+                    window[dest as usize].set_to_ptr(TaggedPtr::number(arity));
+                }
+
+                // Put a list of the number of instructions and the number of literals in the
+                // bytecode of the Function or Partial in the `function` register in `dest`
+                Opcode::FunctionSize { dest, function } => {
+                    let (function, _) = function_of(mem, window[function as usize].get(mem))?;
+                    let code = function.code(mem);
+
+                    let counts = [
+                        TaggedScopedPtr::new(
+                            mem,
+                            TaggedPtr::number(code.instruction_count() as isize),
+                        ),
+                        TaggedScopedPtr::new(mem, TaggedPtr::number(code.literal_count() as isize)),
+                    ];
+                    window[dest as usize].set(pairs_from_slice(mem, &counts)?);
+                }
+
                 // Bind a symbol to the `src` register in the globals dict
                 Opcode::StoreGlobal { src, name } => {
                     let name_val = window[name as usize].get(mem);
@@ -470,49 +1236,37 @@ impl Thread {
                 // Call the function referred to by the `function` register, put the result in the
                 // `dest` register.
                 //
-                // The function can be a Function object or a Partial.
+                // The function can be a Function object, a Partial or a NativeFunction. A
+                // NativeFunction must be given exactly as many arguments as its arity: the
+                // arguments are passed to the Rust function and its return value is put in the
+                // `dest` register.
                 //
-                // If the arg_count is less than the function arity, return a Partial instead of
+                // For a Function or Partial, if the arg_count is less than the function arity, return a Partial instead of
                 // entering the function.
                 //
                 // If the arg_count is equal to the Function or Partial arity, enter the Function
                 // object code.
+                //
+                // A TailCall is a call in tail position. It is currently executed exactly as a
+                // Call, pushing a new call frame.
+                // TODO reuse the current call frame for a TailCall
                 Opcode::Call {
                     function,
                     dest,
                     arg_count,
+                }
+                | Opcode::TailCall {
+                    function,
+                    dest,
+                    arg_count,
                 } => {
                     let binding = window[function as usize].get(mem);
+                    let args = register_range(dest as usize + FIRST_ARG_REG, arg_count as usize)?;
 
                     // To avoid duplicating code in function and partial application cases,
                     // this is declared as a closure so it can access local variables
-                    let new_call_frame = |function| -> Result<(), RuntimeError> {
-                        // Modify the current call frame, saving the return ip
-                        let current_frame_ip = instr.get_next_ip();
-                        frames.access_slice(mem, |f| {
-                            f.last()
-                                .expect("No CallFrames in slice!")
-                                .ip
-                                .set(current_frame_ip)
-                        });
-
-                        // Create a new call frame, pushing it to the frame stack
-                        let new_stack_base = self.stack_base.get() + dest as ArraySize;
-                        let frame = CallFrame::new(function, 0, new_stack_base);
-                        frames.push(mem, frame)?;
-
-                        // Update the instruction stream to point to the new function
-                        let code = function.code(mem);
-                        self.stack_base.set(new_stack_base);
-                        instr.switch_frame(code, 0);
-
-                        // Ensure the stack has 256 registers allocated
-                        // TODO reset to nil to avoid accidental leakage of previous call values
-                        // TODO Ruh-roh we shouldn't be able to modify the stack size from
-                        // within an access_slice() call :grimace:
-                        stack.fill(mem, new_stack_base + 256, mem.nil())?;
-
-                        Ok(())
+                    let new_call_frame = |function, surplus_args| -> Result<(), RuntimeError> {
+                        self.enter_function(mem, function, dest, surplus_args, mem.nil())
                     };
 
                     // Handle the two similar-but-different cases: this might be a Function object
@@ -523,15 +1277,7 @@ impl Thread {
 
                             if arg_count < arity {
                                 // Too few args, return a Partial object
-                                let args_start = dest as usize + FIRST_ARG_REG;
-                                let args_end = args_start + arg_count as usize;
-
-                                let partial = Partial::alloc(
-                                    mem,
-                                    function,
-                                    None,
-                                    &window[args_start..args_end],
-                                )?;
+                                let partial = Partial::alloc(mem, function, None, &window[args])?;
 
                                 window[dest as usize].set(partial.as_tagged(mem));
 
@@ -546,7 +1292,7 @@ impl Thread {
                                 )));
                             }
 
-                            new_call_frame(function)?;
+                            new_call_frame(function, mem.nil())?;
                         }
 
                         Value::Partial(partial) => {
@@ -560,50 +1306,70 @@ impl Thread {
                             } else if arg_count < arity {
                                 // Too few args, bake a new Partial from the existing one, adding the new
                                 // arguments
-                                let args_start = dest as usize + FIRST_ARG_REG;
-                                let args_end = args_start + arg_count as usize;
-
-                                let new_partial = Partial::alloc_clone(
-                                    mem,
-                                    partial,
-                                    &window[args_start..args_end],
-                                )?;
+                                let new_partial =
+                                    Partial::alloc_clone(mem, partial, &window[args])?;
 
                                 window[dest as usize].set(new_partial.as_tagged(mem));
 
                                 return Ok(EvalStatus::Pending);
-                            } else if arg_count > arity {
-                                // Too many args, we haven't got a continuations stack
-                                return Err(err_eval(&format!(
-                                    "Partial {} expected {} arguments, got {}",
-                                    binding,
-                                    partial.arity(),
-                                    arg_count
-                                )));
                             }
 
+                            // Too many args: set aside the surplus args to be applied to the
+                            // return value of the function, and enter the function with the
+                            // args it needs
+                            let (arg_count, surplus_args) = if arg_count > arity {
+                                let surplus_start = args.start + arity as usize;
+                                let surplus =
+                                    List::from_slice(mem, &window[surplus_start..args.end])?;
+                                (arity, surplus.as_tagged(mem))
+                            } else {
+                                (arg_count, mem.nil())
+                            };
+
+                            // The partially applied args go before the call args, and together
+                            // they must fit in the register window
+                            let push_dist = partial.used();
+                            let all_args = register_range(
+                                args.start,
+                                push_dist as usize + arg_count as usize,
+                            )?;
+
                             // Copy closure env pointer
-                            window[dest as usize + ENV_REG] = partial.closure_env();
+                            window[dest as usize + ENV_REG].copy_from(&partial.closure_env());
 
                             // Shunt _call_ args back into the window to make space for the
                             // partially applied args
-                            let push_dist = partial.used();
-                            let from_reg = dest as usize + FIRST_ARG_REG;
-                            let to_reg = from_reg + push_dist as usize;
-                            for index in (0..arg_count as usize).rev() {
-                                window[to_reg + index] = window[from_reg + index].clone();
-                            }
+                            let to_reg = all_args.start + push_dist as usize;
+                            TaggedCellPtr::copy_slice(
+                                &window[to_reg..all_args.end],
+                                &window[args.start..args.start + arg_count as usize],
+                            );
 
                             // copy args from Partial to the register window
-                            let args = partial.args(mem);
-                            let start_reg = dest as usize + FIRST_ARG_REG;
-                            args.access_slice(mem, |items| {
-                                for (index, item) in items.iter().enumerate() {
-                                    window[start_reg + index] = item.clone();
-                                }
+                            let partial_args = partial.args(mem);
+                            partial_args.access_slice(mem, |items| {
+                                TaggedCellPtr::copy_slice(&window[all_args], items)
                             });
 
-                            new_call_frame(partial.function(mem))?;
+                            new_call_frame(partial.function(mem), surplus_args)?;
+                        }
+
+                        Value::NativeFunction(native) => {
+                            if arg_count != native.arity() {
+                                return Err(err_eval(&format!(
+                                    "Function {} expected {} arguments, got {}",
+                                    binding,
+                                    native.arity(),
+                                    arg_count
+                                )));
+                            }
+
+                            // No call frame is needed: the Rust function runs to completion here
+                            let args: Vec<TaggedScopedPtr> =
+                                window[args].iter().map(|arg| arg.get(mem)).collect();
+
+                            let result = mem.call_native(native.id(), &args)?;
+                            window[dest as usize].set(result);
                         }
 
                         _ => return Err(err_eval("Type is not callable")),
@@ -662,34 +1428,132 @@ impl Thread {
 
                 // Simple copy of one register to another
                 Opcode::CopyRegister { dest, src } => {
-                    window[dest as usize] = window[src as usize].clone();
+                    window[dest as usize].copy_from(&window[src as usize]);
                 }
 
-                // TODO
-                Opcode::Add { dest, reg1, reg2 } => unimplemented!(),
+                // Add the integers in `reg1` and `reg2`, putting the sum in `dest`
+                Opcode::Add { dest, reg1, reg2 } => {
+                    let n1 = window[reg1 as usize].get(mem).as_number()?;
+                    let n2 = window[reg2 as usize].get(mem).as_number()?;
+
+                    window[dest as usize].set_to_ptr(inline_number(n1.checked_add(n2), "Add")?);
+                }
 
                 // TODO
                 Opcode::Subtract { dest, left, right } => unimplemented!(),
 
-                // TODO
-                Opcode::Multiply { dest, reg1, reg2 } => unimplemented!(),
+                // Multiply the integers in `reg1` and `reg2`, putting the product in `dest`
+                Opcode::Multiply { dest, reg1, reg2 } => {
+                    let n1 = window[reg1 as usize].get(mem).as_number()?;
+                    let n2 = window[reg2 as usize].get(mem).as_number()?;
 
-                // TODO
-                Opcode::DivideInteger { dest, num, denom } => unimplemented!(),
+                    window[dest as usize]
+                        .set_to_ptr(inline_number(n1.checked_mul(n2), "Multiply")?);
+                }
 
-                // Follow the indirection of an Upvalue to retrieve the value, copy the value to a
+                // Divide the integer in `num` by the integer in `denom`, rounding toward zero,
+                // putting the quotient in `dest`
+                Opcode::DivideInteger { dest, num, denom } => {
+                    let n = window[num as usize].get(mem).as_number()?;
+                    let d = window[denom as usize].get(mem).as_number()?;
+                    let (quotient, _) = divide_integer(n, d, false, "DivideInteger")?;
+                    window[dest as usize].set_to_ptr(quotient);
+                }
+
+                // Divide the integer in `num` by the integer in `denom`, rounding toward negative
+                // infinity, putting the quotient in `dest`
+                Opcode::FloorDivideInteger { dest, num, denom } => {
+                    let n = window[num as usize].get(mem).as_number()?;
+                    let d = window[denom as usize].get(mem).as_number()?;
+                    let (quotient, _) = divide_integer(n, d, true, "FloorDivideInteger")?;
+                    window[dest as usize].set_to_ptr(quotient);
+                }
+
+                // Put the remainder of `DivideInteger` of `num` by `denom` in `dest`. It has the
+                // sign of `num`.
+                Opcode::Remainder { dest, num, denom } => {
+                    let n = window[num as usize].get(mem).as_number()?;
+                    let d = window[denom as usize].get(mem).as_number()?;
+                    let (_, remainder) = divide_integer(n, d, false, "Remainder")?;
+                    window[dest as usize].set_to_ptr(remainder);
+                }
+
+                // Put the remainder of `FloorDivideInteger` of `num` by `denom` in `dest`. It has
+                // the sign of `denom`.
+                Opcode::Modulo { dest, num, denom } => {
+                    let n = window[num as usize].get(mem).as_number()?;
+                    let d = window[denom as usize].get(mem).as_number()?;
+                    let (_, remainder) = divide_integer(n, d, true, "Modulo")?;
+                    window[dest as usize].set_to_ptr(remainder);
+                }
+
+                // Put a pseudo-random integer from `low` up to but not including `high` in `dest`
+                Opcode::Random { dest, low, high } => {
+                    let low = window[low as usize].get(mem).as_number()?;
+                    let high = window[high as usize].get(mem).as_number()?;
+                    if high <= low {
+                        return Err(err_eval(&format!(
+                            "Random range from {} to {} is empty",
+                            low, high
+                        )));
+                    }
+
+                    // scale the random bits to the range rather than taking a remainder, which
+                    // would favor low numbers more. The result lies between two inline numbers so
+                    // is one itself.
+                    let range = (high as i128 - low as i128) as u128;
+                    let offset = (self.next_random() as u128 * range) >> 64;
+                    let n = (low as i128 + offset as i128) as isize;
+                    window[dest as usize].set_to_ptr(TaggedPtr::number(n));
+                }
+
+                // Restart the random number sequence from the integer in `seed`, setting `dest`
+                // to nil
+                Opcode::SeedRandom { dest, seed } => {
+                    let seed = window[seed as usize].get(mem).as_number()?;
+                    self.seed_random(seed as u64);
+                    window[dest as usize].set_to_nil();
+                }
+
+                // Negate the integer in `src`, putting the result in `dest`
+                Opcode::Negate { dest, src } => {
+                    let n = window[src as usize].get(mem).as_number()?;
+                    window[dest as usize].set_to_ptr(inline_number(n.checked_neg(), "Negate")?);
+                }
+
+                // Put the absolute value of the integer in `src` in `dest`
+                Opcode::Abs { dest, src } => {
+                    let n = window[src as usize].get(mem).as_number()?;
+                    window[dest as usize].set_to_ptr(inline_number(n.checked_abs(), "Abs")?);
+                }
+
+                // Put the lesser of the integers in `reg1` and `reg2` in `dest`
+                Opcode::Min { dest, reg1, reg2 } => {
+                    let n1 = window[reg1 as usize].get(mem).as_number()?;
+                    let n2 = window[reg2 as usize].get(mem).as_number()?;
+                    window[dest as usize].set_to_ptr(TaggedPtr::number(n1.min(n2)));
+                }
+
+                // Put the greater of the integers in `reg1` and `reg2` in `dest`
+                Opcode::Max { dest, reg1, reg2 } => {
+                    let n1 = window[reg1 as usize].get(mem).as_number()?;
+                    let n2 = window[reg2 as usize].get(mem).as_number()?;
+                    window[dest as usize].set_to_ptr(TaggedPtr::number(n1.max(n2)));
+                }
+
+                // Follow the indirection of an Upvalue to retrieve the value, copy the value to a
                 // local register
                 Opcode::GetUpvalue { dest, src } => {
                     let closure_env = window[ENV_REG].get(mem);
                     let upvalue = env_upvalue_lookup(mem, closure_env, src)?;
-                    window[dest as usize].set_to_ptr(upvalue.get(mem, stack)?);
+                    window[dest as usize].set_to_ptr(upvalue.get(full_stack)?);
                 }
 
                 // Follow the indirection of an Upvalue to set the value from a local register
                 Opcode::SetUpvalue { dest, src } => {
                     let closure_env = window[ENV_REG].get(mem);
                     let upvalue = env_upvalue_lookup(mem, closure_env, dest)?;
-                    upvalue.set(mem, stack, window[src as usize].get_ptr())?;
+                    upvalue.set(full_stack, window[src as usize].get_ptr())?;
                 }
 
                 // Move up to 3 stack register values to the Upvalue objects referring to them
@@ -702,29 +1566,468 @@ impl Thread {
                             // find the Upvalue object by location
                             let (location_ptr, upvalue) = self.upvalue_lookup(mem, location)?;
                             // close it and unanchor from the Thread
-                            upvalue.close(mem, stack)?;
+                            upvalue.close(full_stack)?;
                             self.upvalues.get(mem).dissoc(mem, location_ptr)?;
                         }
                     }
                 }
+
+                // Render each of `count` registers starting at `first` to a string and
+                // concatenate them into a new Text object
+                Opcode::Concat { dest, first, count } => {
+                    let start = first as usize;
+                    let end = start + count as usize;
+
+                    let mut joined = String::new();
+                    for reg in &window[start..end] {
+                        joined.push_str(&render(*reg.get(mem)));
+                    }
+
+                    let text = Text::new_from_str(mem, &joined)?;
+                    window[dest as usize].set(mem.alloc_tagged(text)?);
+                }
+
+                // Render the integer in `number` as a new Text object in the radix in `radix`
+                Opcode::NumberToText {
+                    dest,
+                    number,
+                    radix,
+                } => {
+                    let value = window[number as usize].get(mem).as_number()?;
+                    let radix = check_radix(window[radix as usize].get(mem).as_number()?)?;
+
+                    let text = Text::new_from_str(mem, &number_to_string(value, radix))?;
+                    window[dest as usize].set(mem.alloc_tagged(text)?);
+                }
+
+                // Parse the Text in `text` as an integer in the radix in `radix`. If the Text is
+                // not a valid integer, `dest` is set to nil.
+                Opcode::TextToNumber { dest, text, radix } => {
+                    let radix = check_radix(window[radix as usize].get(mem).as_number()?)?;
+                    let value = window[text as usize].get(mem);
+
+                    match parse_number(value.as_text_str()?, radix) {
+                        Some(number) => window[dest as usize].set_to_ptr(TaggedPtr::number(number)),
+                        None => window[dest as usize].set_to_nil(),
+                    }
+                }
+
+                // Put a new, empty TextBuilder in the `dest` register
+                Opcode::MakeTextBuilder { dest } => {
+                    let builder = TextBuilder::alloc(mem)?;
+                    window[dest as usize].set(builder.as_tagged(mem));
+                }
+
+                // Render the `value` register to a string, as `Concat` does, and append it to the
+                // TextBuilder in the `builder` register. The TextBuilder is also put in `dest`.
+                Opcode::AppendText {
+                    dest,
+                    builder,
+                    value,
+                } => {
+                    let builder_val = window[builder as usize].get(mem);
+                    let value = window[value as usize].get(mem);
+
+                    builder_val
+                        .as_text_builder()?
+                        .push_str(mem, &render(*value))?;
+                    window[dest as usize].set(builder_val);
+                }
+
+                // Copy the content of the TextBuilder in the `builder` register into a new Text
+                // object
+                Opcode::BuildText { dest, builder } => {
+                    let builder = window[builder as usize].get(mem).as_text_builder()?;
+                    let text = builder.to_text(mem)?;
+                    window[dest as usize].set(mem.alloc_tagged(text)?);
+                }
+
+                // Put a new, empty ListBuilder in the `dest` register
+                Opcode::MakeListBuilder { dest } => {
+                    let builder = ListBuilder::alloc(mem)?;
+                    window[dest as usize].set(builder.as_tagged(mem));
+                }
+
+                // Append the `value` register to the end of the list in the ListBuilder in the
+                // `builder` register. The ListBuilder is also put in `dest`.
+                Opcode::ListAppend {
+                    dest,
+                    builder,
+                    value,
+                } => {
+                    let builder_val = window[builder as usize].get(mem);
+                    let value = window[value as usize].get(mem);
+
+                    builder_val.as_list_builder()?.push(mem, value)?;
+                    window[dest as usize].set(builder_val);
+                }
+
+                // Move the list out of the ListBuilder in the `builder` register into `dest`,
+                // leaving the builder empty
+                Opcode::BuildList { dest, builder } => {
+                    let builder = window[builder as usize].get(mem).as_list_builder()?;
+                    window[dest as usize].set(builder.take_list(mem));
+                }
+
+                // Bundle the `count` registers starting at `first` into a new Values object in
+                // `dest`
+                Opcode::MakeValues { dest, first, count } => {
+                    let first = first as usize;
+                    let values: Vec<TaggedScopedPtr> = window[first..first + count as usize]
+                        .iter()
+                        .map(|reg| reg.get(mem))
+                        .collect();
+                    window[dest as usize]
+                        .set(Values::alloc_from_slice(mem, &values)?.as_tagged(mem));
+                }
+
+                // Take apart the Values object in `src` into the `count` registers starting at
+                // `dest`. The number of values must match. Any other value is a single value.
+                Opcode::UnpackValues { dest, count, src } => {
+                    let bundle = window[src as usize].get(mem);
+                    match *bundle {
+                        Value::Values(values) => {
+                            let values = values.expect(mem, count as ArraySize)?;
+                            for (offset, value) in values.into_iter().enumerate() {
+                                window[dest as usize + offset].set(value);
+                            }
+                        }
+                        _ if count == 1 => window[dest as usize].set(bundle),
+                        _ => {
+                            return Err(err_eval(&format!(
+                                "Expected {} values but 1 was given",
+                                count
+                            )))
+                        }
+                    }
+                }
+
+                // Make a new Record of the type named by the Symbol in `first`, with the `count`
+                // registers following it as its fields, in `dest`
+                Opcode::MakeRecord { dest, first, count } => {
+                    let first = first as usize;
+                    let type_name = window[first].get(mem);
+                    let fields: Vec<TaggedScopedPtr> = window
+                        [first + 1..first + 1 + count as usize]
+                        .iter()
+                        .map(|reg| reg.get(mem))
+                        .collect();
+                    let record = Record::alloc(mem, type_name, &fields)?;
+                    window[dest as usize].set(record.as_tagged(mem));
+                }
+
+                // Set `dest` to true if the `test` register contains a Record of the type named
+                // in `type_name`, nil otherwise
+                Opcode::IsRecordOf {
+                    dest,
+                    test,
+                    type_name,
+                } => {
+                    let type_name = window[type_name as usize].get(mem);
+
+                    match *window[test as usize].get(mem) {
+                        Value::Record(r) if r.type_name(mem) == type_name => {
+                            window[dest as usize].set(mem.true_sym())
+                        }
+                        _ => window[dest as usize].set_to_nil(),
+                    }
+                }
+
+                // Raise a type error unless the `record` register contains a Record of the type
+                // named in `type_name`
+                Opcode::CheckRecord { record, type_name } => {
+                    let record = window[record as usize].get(mem).as_record()?;
+                    record.check_type(mem, window[type_name as usize].get(mem))?;
+                }
+
+                // Put the field at `index` of the Record in `record` into `dest`
+                Opcode::RecordGet {
+                    dest,
+                    record,
+                    index,
+                } => {
+                    let record = window[record as usize].get(mem).as_record()?;
+                    window[dest as usize].set(record.field(mem, index as ArraySize)?);
+                }
+
+                // Set the field at `index` of the Record in `record` to the `value` register
+                Opcode::RecordSet {
+                    record,
+                    index,
+                    value,
+                } => {
+                    let record = window[record as usize].get(mem).as_record()?;
+                    record.set_field(mem, index as ArraySize, window[value as usize].get(mem))?;
+                }
+
+                // Put a Symbol naming the type of the value in `src` into `dest`
+                Opcode::TypeOf { dest, src } => {
+                    let name = window[src as usize].get(mem).type_symbol_name();
+                    window[dest as usize].set(mem.lookup_sym(name));
+                }
+
+                // Put the Unicode code point of the Char in `src` into `dest`
+                Opcode::CharToNumber { dest, src } => {
+                    let c = window[src as usize].get(mem).as_char()?;
+                    window[dest as usize].set_to_ptr(TaggedPtr::number(c as isize));
+                }
+
+                // Put a new Char for the Unicode code point in `src` into `dest`
+                Opcode::NumberToChar { dest, src } => {
+                    let value = window[src as usize].get(mem).as_number()?;
+                    let c = char_from_codepoint(value)?;
+                    window[dest as usize].set(mem.alloc_tagged(Char::new(c))?);
+                }
+
+                // Put a list of a new Char for each Unicode scalar value of the Text in `text`
+                // into `dest`
+                Opcode::TextToChars { dest, text } => {
+                    let chars: Vec<char> = window[text as usize]
+                        .get(mem)
+                        .as_text_str()?
+                        .chars()
+                        .collect();
+
+                    let mut values = Vec::with_capacity(chars.len());
+                    for c in chars {
+                        values.push(mem.alloc_tagged(Char::new(c))?);
+                    }
+                    window[dest as usize].set(pairs_from_slice(mem, &values)?);
+                }
+
+                // Put a new Text made of the Chars in the list in `list` into `dest`. Any other
+                // type of element is a type error.
+                Opcode::CharsToText { dest, list } => {
+                    let mut string = String::new();
+                    for value in vec_from_pairs(mem, window[list as usize].get(mem))? {
+                        string.push(value.as_char()?);
+                    }
+
+                    let text = Text::new_from_str(mem, &string)?;
+                    window[dest as usize].set(mem.alloc_tagged(text)?);
+                }
+
+                // Write the rendered value of the `src` register to the output, setting `dest`
+                // to nil
+                Opcode::Print { dest, src } => {
+                    let value = window[src as usize].get(mem);
+                    write!(mem.output(), "{}", render(*value))?;
+                    window[dest as usize].set_to_nil();
+                }
+
+                // As Print but terminates the output with a newline
+                Opcode::PrintLine { dest, src } => {
+                    let value = window[src as usize].get(mem);
+                    writeln!(mem.output(), "{}", render(*value))?;
+                    window[dest as usize].set_to_nil();
+                }
+
+                // Insert the value in `item` into the List in `list` before the index in `index`
+                Opcode::ListInsert { list, index, item } => {
+                    let list = window[list as usize].get(mem).as_list()?;
+                    let index = list_index(window[index as usize].get(mem))?;
+                    list.insert(mem, index, window[item as usize].get(mem))?;
+                }
+
+                // Remove the value at the index in `index` from the List in `list`, putting it in
+                // `dest`
+                Opcode::ListRemove { dest, list, index } => {
+                    let list = window[list as usize].get(mem).as_list()?;
+                    let index = list_index(window[index as usize].get(mem))?;
+                    window[dest as usize].set(list.remove(mem, index)?);
+                }
+
+                // Raise an error if the `test` register is nil. The `form` register holds the
+                // assertion's argument list as it was written, to describe the failure.
+                Opcode::Assert { form, test } => {
+                    if let Value::Nil = *window[test as usize].get(mem) {
+                        let form = window[form as usize].get(mem);
+                        let expr = value_from_1_pair(mem, form)?;
+                        return Err(assertion_failed(form, render(*expr)));
+                    }
+                }
+
+                // Raise an error if the values in the `first` and `second` registers are not
+                // structurally equal, as Equal compares them. The `form` register holds the
+                // assertion's argument list as it was written, to describe the failure.
+                Opcode::AssertEqual {
+                    form,
+                    first,
+                    second,
+                } => {
+                    let first = window[first as usize].get(mem);
+                    let second = window[second as usize].get(mem);
+
+                    if !values_equal(mem, first, second) {
+                        let form = window[form as usize].get(mem);
+                        let (first_expr, second_expr) = values_from_2_pairs(mem, form)?;
+                        return Err(assertion_failed(
+                            form,
+                            format!(
+                                "{} is {}, expected {} which is {}",
+                                render(*first_expr),
+                                render(*first),
+                                render(*second_expr),
+                                render(*second)
+                            ),
+                        ));
+                    }
+                }
+
+                // Put a new, sorted, copy of the pair list or List in `list` into `dest`. See
+                // `compare_values()` for the ordering.
+                Opcode::SortList { dest, list } => {
+                    let list = window[list as usize].get(mem);
+
+                    let mut values = sortable_values(mem, list)?;
+                    sort_values(mem, &mut values)?;
+                    window[dest as usize].set(sorted_like(mem, list, &values)?);
+                }
+
+                // As SortList, but ordering the values by calling the comparator function in
+                // `less`. See `sort_list_with()`.
+                Opcode::SortListWith { dest, list, less } => {
+                    return Ok(EvalStatus::SortListWith { dest, list, less });
+                }
+
+                // Put a copy of the value in `src` into `dest` that shares no Pairs or Lists with
+                // the original
+                Opcode::DeepCopy { dest, src } => {
+                    let copy = deep_copy(mem, window[src as usize].get(mem))?;
+                    window[dest as usize].set(copy);
+                }
+
+                // Read and compile the source file at the path in the Text in `path` and evaluate
+                // it in this thread, so that it shares the globals. The file's code is entered as
+                // a call to a Function of no arguments, in a new call frame whose register window
+                // begins at `dest`, and its result is returned to `dest`. While it runs, relative
+                // paths are loaded from the file's directory.
+                Opcode::LoadFile { dest, path } => {
+                    let path = window[path as usize].get(mem);
+                    let path = self.resolve_source_path(mem, path.as_text_str()?);
+
+                    self.enter_source_file(mem, &path, dest)?;
+                }
+
+                // As LoadFile, but only if the file has not been required before, by any thread.
+                // Otherwise `dest` is set to nil. A file is recorded as required before it is
+                // evaluated, so files that require each other are each only loaded once.
+                Opcode::RequireFile { dest, path } => {
+                    let path = window[path as usize].get(mem);
+                    let path = self.resolve_source_path(mem, path.as_text_str()?);
+                    let canonical = fs::canonicalize(&path).map_err(|e| {
+                        RuntimeError::from(e).with_context(path.display().to_string())
+                    })?;
+
+                    if mem.mark_loaded(canonical) {
+                        self.enter_source_file(mem, &path, dest)?;
+                    } else {
+                        window[dest as usize].set_to_nil();
+                    }
+                }
             }
 
             Ok(EvalStatus::Pending)
-        })
+        })?;
+
+        if let EvalStatus::SortListWith { dest, list, less } = status {
+            self.sort_list_with(mem, dest, list, less)?;
+            return Ok(EvalStatus::Pending);
+        }
+
+        Ok(status)
     }
 
-    /// Given ByteCode, execute up to max_instr more instructions
+    /// Put a new, sorted, copy of the pair list or List in `list` into `dest`, ordering the values
+    /// with a stable merge sort that calls the comparator function in `less`. Calling back into
+    /// the VM may reallocate the register stack, so this must not run while the register window
+    /// is held.
+    fn sort_list_with<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        dest: Register,
+        list: Register,
+        less: Register,
+    ) -> Result<(), RuntimeError> {
+        let stack = self.stack.get(mem);
+        let base = self.stack_base.get();
+
+        let list = IndexedAnyContainer::get(&*stack, mem, base + list as ArraySize)?;
+        let less = IndexedAnyContainer::get(&*stack, mem, base + less as ArraySize)?;
+
+        let mut values = sortable_values(mem, list)?;
+        merge_sort(&mut values, |left, right| {
+            let result = self.call_function(mem, dest, less, &[left, right])?;
+            Ok(!matches!(*result, Value::Nil))
+        })?;
+
+        let sorted = sorted_like(mem, list, &values)?;
+        IndexedAnyContainer::set(&*stack, mem, base + dest as ArraySize, sorted)
+    }
+
+    /// Call a function from within an instruction and run it to completion, returning its
+    /// result. The arguments and function are put in the registers following `dest` as the
+    /// compiler lays out a call whose result register is `dest`, and the call is made by a
+    /// `Call` instruction, so any registers beyond `dest` are overwritten. Calling back into the
+    /// VM may reallocate the register stack, so this must not run while the register window is
+    /// held.
+    fn call_function<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        dest: Register,
+        function: TaggedScopedPtr<'guard>,
+        args: &[TaggedScopedPtr<'guard>],
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let stack = self.stack.get(mem);
+        let frames = self.frames.get(mem);
+        let base = self.stack_base.get() + dest as ArraySize;
+
+        for (index, arg) in args.iter().enumerate() {
+            let reg = (FIRST_ARG_REG + index) as ArraySize;
+            IndexedAnyContainer::set(&*stack, mem, base + reg, *arg)?;
+        }
+        let function_reg = (FIRST_ARG_REG + args.len()) as Register;
+        IndexedAnyContainer::set(&*stack, mem, base + function_reg as ArraySize, function)?;
+
+        self.next_opcode.set(Some(Opcode::Call {
+            function: dest + function_reg,
+            dest,
+            arg_count: args.len() as Register,
+        }));
+
+        // run until the function has returned to this frame, along with any call of its result
+        // with surplus arguments, checking the deadline once per batch of instructions
+        let depth = frames.length();
+        let mut count = 0;
+        while self.next_opcode.get().is_some() || frames.length() > depth {
+            self.eval_next_instr(mem)?;
+
+            count += 1;
+            if count % 1024 == 0 {
+                self.check_deadline()?;
+            }
+        }
+
+        IndexedAnyContainer::get(&*stack, mem, base)
+    }
+
+    /// Return a timeout error if the current evaluation has run past its deadline
+    fn check_deadline(&self) -> Result<(), RuntimeError> {
+        match self.deadline.get() {
+            Some(deadline) if Instant::now() >= deadline => {
+                let timeout = self.timeout.get().unwrap_or_default();
+                Err(RuntimeError::new(ErrorKind::Timeout(timeout)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Continue executing the current instruction stream for up to max_instr more instructions
     fn vm_eval_stream<'guard>(
         &self,
         mem: &'guard MutatorView,
-        code: ScopedPtr<'guard, ByteCode>,
         max_instr: ArraySize,
     ) -> Result<EvalStatus<'guard>, RuntimeError> {
-        let instr = self.instr.get(mem);
-        // TODO this is broken logic, this function shouldn't switch back to this code object every
-        // time it is called
-        instr.switch_frame(code, 0);
-
         for _ in 0..max_instr {
             match self.eval_next_instr(mem) {
                 // Evaluation paused or completed without error
@@ -734,31 +2037,54 @@ impl Thread {
                 },
 
                 // Evaluation hit an error
-                Err(rt_error) => {
-                    // unwind the stack, printing a trace
-                    let frames = self.frames.get(mem);
-
-                    // Print a stack trace if the error is multiple call frames deep
-                    frames.access_slice(mem, |window| {
-                        if window.len() > 1 {
-                            println!("Error traceback:");
-                        }
+                Err(rt_error) => return Err(self.unwind(mem, rt_error)?),
+            }
+        }
 
-                        for frame in &window[1..] {
-                            println!("  {}", frame.as_string(mem));
-                        }
-                    });
+        // reading the clock once per batch of instructions keeps the timeout cheap
+        if let Err(timeout) = self.check_deadline() {
+            return Err(self.unwind(mem, timeout)?);
+        }
 
-                    // Unwind by clearing all frames from the stack
-                    frames.clear(mem)?;
-                    self.stack_base.set(0);
+        Ok(EvalStatus::Pending)
+    }
 
-                    return Err(rt_error);
-                }
+    /// Print a stack trace and clear all call frames after an error, returning the error
+    fn unwind<'guard>(
+        &self,
+        mem: &'guard MutatorView,
+        rt_error: RuntimeError,
+    ) -> Result<RuntimeError, RuntimeError> {
+        let frames = self.frames.get(mem);
+
+        // Print a stack trace if the error is multiple call frames deep
+        frames.access_slice(mem, |window| -> Result<(), RuntimeError> {
+            let mut output = mem.output();
+
+            if window.len() > 1 {
+                writeln!(output, "Error traceback:")?;
             }
+
+            for frame in &window[1..] {
+                writeln!(output, "  {}", frame.as_string(mem))?;
+            }
+
+            Ok(())
+        })?;
+
+        // The error is about to leave the frames and registers that referred to a thrown value,
+        // so root the value in the thread itself
+        if let ErrorKind::UserError(ref value) = *rt_error.error_kind() {
+            self.thrown.copy_from(value);
         }
 
-        Ok(EvalStatus::Pending)
+        // Unwind by clearing all frames from the stack, along with any call that was about to be
+        // made by a returning frame
+        frames.clear(mem)?;
+        self.stack_base.set(0);
+        self.next_opcode.set(None);
+
+        Ok(rt_error)
     }
 
     /// Evaluate a Function completely, returning the result. The Function passed in should expect
@@ -770,13 +2096,20 @@ impl Thread {
     ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
         let mut status = EvalStatus::Pending;
 
+        // nothing left over from a previous evaluation may run before the new function
+        self.next_opcode.set(None);
+
         let frames = self.frames.get(mem);
         frames.push(mem, CallFrame::new_main(function))?;
 
         let code = function.code(mem);
+        self.instr.get(mem).switch_frame(code, 0);
+
+        self.deadline
+            .set(self.timeout.get().map(|timeout| Instant::now() + timeout));
 
         while status == EvalStatus::Pending {
-            status = self.vm_eval_stream(mem, code, 1024)?;
+            status = self.vm_eval_stream(mem, 1024)?;
             match status {
                 EvalStatus::Return(value) => return Ok(value),
                 _ => (),
@@ -786,3 +2119,845 @@ impl Thread {
         Err(err_eval("Unexpected end of evaluation"))
     }
 }
+
+impl Thread {
+    /// Add the register stack and the values in the given number of registers from the bottom
+    fn trace_registers(&self, guard: &dyn MutatorScope, roots: &mut Roots, count: usize) {
+        let stack = self.stack.get(guard);
+        roots.array(guard, &*stack);
+        stack.access_slice(guard, |registers| {
+            for register in registers.iter().take(count) {
+                roots.value(register.get(guard));
+            }
+        });
+    }
+
+    /// Trace the thread as a conservative scan would find it: every register is treated as live,
+    /// including those left behind by call frames that have returned. Registers only ever hold
+    /// tagged pointers, so the objects they point to can be traced by type.
+    #[cfg(feature = "conservative-roots")]
+    pub fn trace_conservative(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        self.trace(guard, roots);
+        self.trace_registers(guard, roots, usize::MAX);
+    }
+}
+
+impl Trace for Thread {
+    /// The roots of a Thread are the values in its live registers and call frames, its upvalues
+    /// and globals, the most recently thrown value and the arrays and dicts holding them
+    fn trace(&self, guard: &dyn MutatorScope, roots: &mut Roots) {
+        roots.object(self);
+
+        // only the registers up to the top of the current register window are live. Those above
+        // it were left behind by call frames that have returned.
+        self.trace_registers(
+            guard,
+            roots,
+            self.stack_base.get() as usize + REGISTER_COUNT,
+        );
+
+        let frames = self.frames.get(guard);
+        roots.array(guard, &*frames);
+        frames.access_slice(guard, |frames| {
+            for frame in frames.iter() {
+                roots.value(frame.function.get(guard).as_tagged(guard));
+                roots.value(frame.surplus_args.get(guard));
+                roots.value(frame.source_dir.get(guard));
+            }
+        });
+
+        self.upvalues.get(guard).trace(guard, roots);
+        self.globals.get(guard).trace(guard, roots);
+        self.instr.get(guard).trace(guard, roots);
+        roots.value(self.thrown.get(guard));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::array::{ArrayU16, ArrayU32, ArrayU8};
+    use crate::compiler::compile;
+    use crate::error::err_user;
+    use crate::memory::{Memory, Mutator};
+    use crate::number::NumberObject;
+    use crate::pair::cons;
+    use crate::parser::parse;
+
+    fn test_helper(test_fn: fn(&MutatorView) -> Result<(), RuntimeError>) {
+        let mem = Memory::new();
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = fn(&MutatorView) -> Result<(), RuntimeError>;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                test_fn: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                test_fn(mem)
+            }
+        }
+
+        let test = Test {};
+        mem.mutate(&test, test_fn).unwrap();
+    }
+
+    fn eval<'guard>(
+        mem: &'guard MutatorView,
+        thread: ScopedPtr<'guard, Thread>,
+        code: &str,
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let function = compile(mem, parse(mem, code)?)?;
+        thread.quick_vm_eval(mem, function)
+    }
+
+    #[test]
+    fn eval_timeout() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let many = TaggedScopedPtr::new(mem, TaggedPtr::number(1 << 40));
+            t.globals(mem).assoc(mem, mem.lookup_sym("many"), many)?;
+            eval(mem, t, "(def forever () (dotimes (i many) nil))")?;
+
+            let timeout = Duration::from_millis(20);
+            t.set_timeout(Some(timeout));
+            let start = Instant::now();
+            match eval(mem, t, "(forever)") {
+                Err(e) => assert!(*e.error_kind() == ErrorKind::Timeout(timeout)),
+                Ok(_) => panic!("a long loop returned"),
+            }
+            assert!(start.elapsed() >= timeout);
+            assert!(start.elapsed() < Duration::from_secs(5));
+
+            // the thread is unwound and can be used again, and the timeout applies to each
+            // evaluation separately
+            assert!(eval(mem, t, "(cons 'a 'b)")?.to_string() == "(a . b)");
+            assert!(eval(mem, t, "(forever)").is_err());
+
+            t.set_timeout(None);
+            assert!(eval(mem, t, "(car '(c))")? == mem.lookup_sym("c"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn eval_discards_pending_opcode() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // an unwound error takes a pending call with it
+            let function = compile(mem, parse(mem, "nil")?)?;
+            t.frames.get(mem).push(mem, CallFrame::new_main(function))?;
+            t.next_opcode.set(Some(Opcode::NoOp));
+            t.unwind(mem, err_eval("stopped"))?;
+            assert!(t.next_opcode.take().is_none());
+
+            // and a new evaluation starts with its own first instruction
+            t.next_opcode.set(Some(Opcode::Return { reg: 0 }));
+            assert!(eval(mem, t, "'a")? == mem.lookup_sym("a"));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn trace_opcodes() {
+        /// A writer that appends to a buffer that remains readable by the test
+        #[derive(Clone)]
+        struct TraceBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+        impl Write for TraceBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = TraceBuffer(Default::default());
+        let mem = Memory::with_output(Box::new(buffer.clone()));
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = ();
+            type Output = ();
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let t = Thread::alloc(mem)?;
+
+                // nothing is traced while tracing is off
+                eval(mem, t, "'a")?;
+
+                t.set_trace(true);
+                let result = eval(mem, t, "(cons 'a (car '(b)))")?;
+                assert!(result.to_string() == "(a . b)");
+                t.set_trace(false);
+
+                eval(mem, t, "'a")?;
+
+                Ok(())
+            }
+        }
+
+        mem.mutate(&Test {}, ()).unwrap();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        let names: Vec<&str> = lines
+            .iter()
+            .map(|line| line.split(' ').nth(3).unwrap())
+            .collect();
+        assert!(
+            names
+                == [
+                    "LoadLiteral",
+                    "LoadLiteral",
+                    "FirstOfPair",
+                    "MakePair",
+                    "Return"
+                ]
+        );
+        assert!(lines[0].starts_with("trace: ip=0 base=0 LoadLiteral { dest: "));
+        assert!(lines[4].starts_with("trace: ip=4 base=0 Return { reg: "));
+    }
+
+    #[test]
+    fn deep_calls_grow_stack() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let initial_length = t.stack.get(mem).length();
+
+            let make_list = |length| -> Result<TaggedScopedPtr, RuntimeError> {
+                let values: Vec<TaggedScopedPtr> = (0..length)
+                    .map(|n| TaggedScopedPtr::new(mem, TaggedPtr::number(n)))
+                    .collect();
+                pairs_from_slice(mem, &values)
+            };
+
+            // a list long enough that recursing over it moves the stack base well beyond the
+            // initial stack allocation, reallocating the stack several times
+            let globals = t.globals.get(mem);
+            globals.assoc(mem, mem.lookup_sym("long"), make_list(400)?)?;
+
+            // each call returns into a frame whose registers must have survived the stack being
+            // reallocated beneath it
+            let code = "(def copy (l) (cond (nil? l) nil (cons (car l) (copy (cdr l)))))";
+            eval(mem, t, code)?;
+            let result = eval(mem, t, "(equal? (copy long) long)")?;
+            assert!(result == mem.true_sym());
+            assert!(t.stack.get(mem).length() > initial_length * 4);
+
+            // an open upvalue refers to its variable by absolute stack position, so it remains
+            // valid after the stack is reallocated
+            eval(
+                mem,
+                t,
+                "(def deep (l f) (cond (nil? l) (f) (deep (cdr l) f)))",
+            )?;
+            let code = "(def outer (l) (let ((x 'found)) (deep l (lambda () x))))";
+            eval(mem, t, code)?;
+            let result = eval(mem, t, "(outer long)")?;
+            assert!(result == mem.lookup_sym("found"));
+
+            // the stack can't grow beyond the largest object the heap can allocate, which is an
+            // error rather than a crash, and the thread can still be used afterwards
+            globals.assoc(mem, mem.lookup_sym("longer"), make_list(5000)?)?;
+            match eval(mem, t, "(copy longer)") {
+                Err(e) => assert!(*e.error_kind() == ErrorKind::BadAllocationRequest),
+                Ok(_) => panic!("the stack grew beyond the largest allocation"),
+            }
+            let result = eval(mem, t, "(equal? (copy long) long)")?;
+            assert!(result == mem.true_sym());
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn deep_calls_grow_frames() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let numbers: Vec<TaggedScopedPtr> = (0..100)
+                .map(|n| TaggedScopedPtr::new(mem, TaggedPtr::number(n)))
+                .collect();
+            let list = pairs_from_slice(mem, &numbers)?;
+
+            // each call saves its caller's ip and stack base in its frame, so a frame that was
+            // corrupted while the frame list grew would return to the wrong place or read its
+            // local `x` from the wrong registers
+            let code = "(def build (l) \
+                          (cond (nil? l) nil \
+                                true (let ((x (car l))) (cons (cons x x) (build (cdr l))))))";
+            let code_explode = "(def explode (l) \
+                                  (cond (nil? l) (car 'boom) \
+                                        true (cons (car l) (explode (cdr l)))))";
+
+            for capacity in &[0, 1, DEFAULT_FRAME_CAPACITY, 200] {
+                let t = Thread::alloc_with_frame_capacity(mem, *capacity)?;
+                t.globals(mem).assoc(mem, mem.lookup_sym("numbers"), list)?;
+                eval(mem, t, code)?;
+                eval(mem, t, code_explode)?;
+
+                for _ in 0..2 {
+                    let result = vec_from_pairs(mem, eval(mem, t, "(build numbers)")?)?;
+                    assert!(result.len() == numbers.len());
+                    for (item, n) in result.iter().zip(0..) {
+                        match **item {
+                            Value::Pair(p) => {
+                                assert!(p.first.get(mem).as_number()? == n);
+                                assert!(p.second.get(mem).as_number()? == n);
+                            }
+                            _ => panic!("expected a Pair"),
+                        }
+                    }
+                    assert!(t.frames.get(mem).length() == 0);
+
+                    // unwinding from the bottom of the recursion clears every frame, leaving the
+                    // list ready for the next evaluation
+                    assert!(eval(mem, t, "(explode numbers)").is_err());
+                    assert!(t.frames.get(mem).length() == 0);
+                    assert!(t.stack_base.get() == 0);
+                }
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    /// Make a cyclic list by pointing the last Pair of the list back at the first
+    fn make_cycle<'guard>(
+        mem: &'guard MutatorView,
+        values: &[TaggedScopedPtr<'guard>],
+    ) -> Result<TaggedScopedPtr<'guard>, RuntimeError> {
+        let head = pairs_from_slice(mem, values)?;
+
+        let mut last = head;
+        while let Value::Pair(pair) = *last {
+            let next = pair.second.get(mem);
+            if next == mem.nil() {
+                pair.second.set(head);
+                break;
+            }
+            last = next;
+        }
+
+        Ok(head)
+    }
+
+    #[test]
+    fn equal_atoms() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let number = |n| TaggedScopedPtr::new(mem, TaggedPtr::number(n));
+            assert!(values_equal(mem, number(42), number(42)));
+            assert!(!values_equal(mem, number(42), number(-42)));
+
+            let text1 = mem.alloc_tagged(Text::new_from_str(mem, "same")?)?;
+            let text2 = mem.alloc_tagged(Text::new_from_str(mem, "same")?)?;
+            let text3 = mem.alloc_tagged(Text::new_from_str(mem, "different")?)?;
+            assert!(text1 != text2);
+            assert!(values_equal(mem, text1, text2));
+            assert!(!values_equal(mem, text1, text3));
+
+            assert!(values_equal(mem, mem.lookup_sym("a"), mem.lookup_sym("a")));
+            assert!(!values_equal(mem, mem.lookup_sym("a"), mem.lookup_sym("b")));
+
+            assert!(values_equal(mem, mem.nil(), mem.nil()));
+
+            // values of different types are never equal
+            assert!(!values_equal(mem, mem.lookup_sym("same"), text1));
+            assert!(!values_equal(mem, number(0), mem.nil()));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn equal_pairs() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+            let text = mem.alloc_tagged(Text::new_from_str(mem, "c")?)?;
+
+            let list1 = pairs_from_slice(mem, &[a, b, text])?;
+            let list2 = pairs_from_slice(mem, &[a, b, text])?;
+            assert!(values_equal(mem, list1, list2));
+
+            let shorter = pairs_from_slice(mem, &[a, b])?;
+            assert!(!values_equal(mem, list1, shorter));
+
+            let different = pairs_from_slice(mem, &[a, a, text])?;
+            assert!(!values_equal(mem, list1, different));
+
+            // nested lists
+            let nested1 = cons(mem, list1, cons(mem, a, b)?)?;
+            let nested2 = cons(mem, list2, cons(mem, a, b)?)?;
+            assert!(values_equal(mem, nested1, nested2));
+
+            let nested3 = cons(mem, shorter, cons(mem, a, b)?)?;
+            assert!(!values_equal(mem, nested1, nested3));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn equal_cyclic_pairs() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let a = mem.lookup_sym("a");
+            let b = mem.lookup_sym("b");
+
+            // (a b a b ...) compared with a separately allocated (a b a b ...)
+            let cycle1 = make_cycle(mem, &[a, b])?;
+            let cycle2 = make_cycle(mem, &[a, b])?;
+            assert!(values_equal(mem, cycle1, cycle2));
+
+            // cycles of different lengths that unroll to the same infinite list
+            let cycle3 = make_cycle(mem, &[a, b, a, b])?;
+            assert!(values_equal(mem, cycle1, cycle3));
+
+            // cycles that differ
+            let cycle4 = make_cycle(mem, &[a, a])?;
+            assert!(!values_equal(mem, cycle1, cycle4));
+
+            let cycle5 = make_cycle(mem, &[a, b, b])?;
+            assert!(!values_equal(mem, cycle1, cycle5));
+
+            // a cycle is not equal to a finite list with the same prefix
+            let list = pairs_from_slice(mem, &[a, b, a, b])?;
+            assert!(!values_equal(mem, cycle1, list));
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn partial_args_shuffle() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            eval(
+                mem,
+                t,
+                "(def six (a b c d e f) (cons a (cons b (cons c (cons d (cons e (cons f nil)))))))",
+            )?;
+
+            // the partially applied args are placed before the call args, in order
+            eval(mem, t, "(set 'two (six 'a 'b))")?;
+            eval(mem, t, "(set 'three (two 'c))")?;
+            assert!(eval(mem, t, "(three 'd 'e 'f)")?.to_string() == "(a b c d e f)");
+            assert!(eval(mem, t, "(two 'c 'd 'e 'f)")?.to_string() == "(a b c d e f)");
+
+            // surplus args are applied to the return value, in order
+            eval(mem, t, "(def make (a) (\\ (b c) (cons a (cons b c))))")?;
+            eval(mem, t, "(set 'made (make))")?;
+            assert!(eval(mem, t, "(made 'x 'y 'z)")?.to_string() == "(x y . z)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn call_overflowing_register_window() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // a function of 200 parameters, partially applied to all but one of them
+            let params: Vec<String> = (0..200).map(|n| format!("p{}", n)).collect();
+            eval(mem, t, &format!("(def wide ({}) p199)", params.join(" ")))?;
+            let args = vec!["nil"; 199].join(" ");
+            eval(mem, t, &format!("(set 'almost (wide {}))", args))?;
+
+            // called where few registers are in use, the arguments fit in the window
+            assert!(eval(mem, t, "(almost 'last)")? == mem.lookup_sym("last"));
+
+            // called after 60 registers are in use, the partially applied arguments would be
+            // placed beyond the end of the register window
+            let locals: Vec<String> = (0..60).map(|n| format!("(v{} nil)", n)).collect();
+            let code = format!("(let ({}) (almost 'last))", locals.join(" "));
+            match eval(mem, t, &code) {
+                Err(e) => match e.error_kind() {
+                    ErrorKind::EvalError(message) => {
+                        assert!(message.ends_with("would overflow the register window"))
+                    }
+                    _ => panic!("unexpected error {}", e),
+                },
+                Ok(_) => panic!("call should overflow the register window"),
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn out_of_memory_is_an_error() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            let code = parse(mem, "(cons 'a 'b)")?;
+
+            mem.fail_next_allocations(1);
+            let error = compile(mem, code).err().expect("compiling should fail");
+            assert!(*error.error_kind() == ErrorKind::OutOfMemory);
+
+            // evaluation fails if the program's Pair can't be allocated
+            let function = compile(mem, code)?;
+            mem.fail_next_allocations(1);
+            let error = t.quick_vm_eval(mem, function).unwrap_err();
+            assert!(*error.error_kind() == ErrorKind::OutOfMemory);
+
+            // the thread was unwound and can carry on
+            let result = t.quick_vm_eval(mem, function)?;
+            assert!(render(*result) == "(a . b)");
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn user_error_carries_value() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+            assert!(t.thrown_value(mem) == mem.nil());
+
+            // raised inside a function, so the error unwinds a call frame
+            eval(mem, t, "(def raise-list (x) (raise (cons x '(b (c d)))))")?;
+            let error = eval(mem, t, "(raise-list 'a)").unwrap_err();
+
+            let thrown = error.thrown_value(mem).expect("expected a user error");
+            assert!(values_equal(mem, thrown, parse(mem, "(a b (c d))")?));
+            // the thread roots the same value
+            assert!(t.thrown_value(mem) == thrown);
+
+            // evaluation can carry on afterwards
+            assert!(eval(mem, t, "'ok")? == mem.lookup_sym("ok"));
+
+            // other errors carry no value
+            let error = eval(mem, t, "(car 'a)").unwrap_err();
+            assert!(error.thrown_value(mem).is_none());
+            assert!(t.thrown_value(mem) == thrown);
+
+            Ok(())
+        }
+
+        struct Test {}
+        impl Mutator for Test {
+            type Input = fn(&MutatorView) -> Result<(), RuntimeError>;
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                test_fn: Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                test_fn(mem)
+            }
+        }
+
+        let mut mem = Memory::new();
+        mem.register_native("raise", 1, |_mem, args| Err(err_user(args[0])));
+        mem.mutate(&Test {}, test_inner).unwrap();
+    }
+
+    #[test]
+    fn type_of_each_value_kind() {
+        fn test_inner(mem: &MutatorView) -> Result<(), RuntimeError> {
+            let t = Thread::alloc(mem)?;
+
+            // values the language can make for itself
+            for (code, type_name) in &[
+                ("nil", "nil"),
+                ("'a", "symbol"),
+                ("(cons 'a 'b)", "pair"),
+                ("(list 'a 'b)", "pair"),
+                ("\"text\"", "text"),
+                ("#\\a", "char"),
+                ("(string->number \"12\")", "number"),
+                ("(lambda (x) x)", "function"),
+                ("((lambda (x y) x) 'a)", "partial"),
+                ("(record-new 'point 'a)", "record"),
+                ("(values 'a 'b)", "values"),
+                ("(lb-new)", "list-builder"),
+                ("(sb-new)", "text-builder"),
+                ("(type-of 'a)", "symbol"),
+            ] {
+                let code = format!("(type-of {})", code);
+                let result = eval(mem, t, &code)?;
+                assert!(result == mem.lookup_sym(type_name), "{}", code);
+            }
+
+            // values that are made natively, passed in as globals
+            let name = mem.lookup_sym("native");
+            let natives = [
+                (ArrayU8::alloc(mem)?.as_tagged(mem), "array-u8"),
+                (ArrayU16::alloc(mem)?.as_tagged(mem), "array-u16"),
+                (ArrayU32::alloc(mem)?.as_tagged(mem), "array-u32"),
+                (Dict::alloc(mem)?.as_tagged(mem), "dict"),
+                (List::alloc(mem)?.as_tagged(mem), "list"),
+                (
+                    NativeFunction::alloc(mem, name, 0, 0)?.as_tagged(mem),
+                    "native-function",
+                ),
+                (mem.alloc_tagged(NumberObject::zero())?, "number"),
+                (Upvalue::alloc(mem, 0)?.as_tagged(mem), "upvalue"),
+            ];
+            for (value, type_name) in natives.iter() {
+                t.globals(mem).assoc(mem, mem.lookup_sym("x"), *value)?;
+                let result = eval(mem, t, "(type-of x)")?;
+                assert!(result == mem.lookup_sym(type_name), "{}", type_name);
+            }
+
+            Ok(())
+        }
+
+        test_helper(test_inner);
+    }
+
+    #[test]
+    fn precise_collection_keeps_live_registers_only() {
+        /// Allocate enough short-lived Pairs to fill several heap blocks
+        fn garbage(mem: &MutatorView) -> Result<(), RuntimeError> {
+            for _ in 0..5000 {
+                cons(mem, mem.lookup_sym("garbage"), mem.nil())?;
+            }
+            Ok(())
+        }
+
+        struct Setup {}
+        impl Mutator for Setup {
+            type Input = ();
+            type Output = (CellPtr<Thread>, usize, usize);
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let thread = Thread::alloc(mem)?;
+
+                // a call frame whose register window began at 256 has returned, leaving its
+                // registers on the stack above the current window
+                let stack = thread.stack.get(mem);
+                stack.fill(mem, 512, mem.nil())?;
+                garbage(mem)?;
+
+                // each Pair is only reachable through a register, in a block of its own
+                let kept = cons(mem, mem.lookup_sym("kept"), mem.nil())?;
+                IndexedAnyContainer::set(&*stack, mem, 10, kept)?;
+                garbage(mem)?;
+                let dropped = cons(mem, mem.lookup_sym("dropped"), mem.nil())?;
+                IndexedAnyContainer::set(&*stack, mem, 256 + 10, dropped)?;
+                garbage(mem)?;
+
+                let kept = kept.get_ptr().heap_address().unwrap();
+                let dropped = dropped.get_ptr().heap_address().unwrap();
+
+                let mut roots = Roots::new();
+                thread.trace(mem, &mut roots);
+                assert!(roots.addresses().contains(&kept));
+                assert!(!roots.addresses().contains(&dropped));
+
+                Ok((CellPtr::new_with(thread), kept, dropped))
+            }
+        }
+
+        struct Check {}
+        impl Mutator for Check {
+            type Input = (CellPtr<Thread>, usize, usize);
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                (thread, kept, dropped): Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                assert!(mem.is_old(kept));
+                assert!(!mem.is_old(dropped));
+
+                let stack = thread.get(mem).stack.get(mem);
+                match *IndexedAnyContainer::get(&*stack, mem, 10)? {
+                    Value::Pair(pair) => {
+                        assert!(pair.first.get(mem) == mem.lookup_sym("kept"));
+                        assert!(pair.second.get(mem) == mem.nil());
+                    }
+                    _ => panic!("expected the register to hold a Pair"),
+                }
+
+                assert!(mem.gc_stats().collections == 1);
+                Ok(())
+            }
+        }
+
+        let mut mem = Memory::new();
+        let setup = mem.mutate(&Setup {}, ()).unwrap();
+        mem.collect_precise(&setup.0).unwrap();
+        mem.mutate(&Check {}, setup).unwrap();
+    }
+
+    #[test]
+    fn precise_collection_traces_globals() {
+        /// Allocate enough short-lived Pairs to fill several heap blocks
+        fn garbage(mem: &MutatorView) -> Result<(), RuntimeError> {
+            for _ in 0..5000 {
+                cons(mem, mem.lookup_sym("garbage"), mem.nil())?;
+            }
+            Ok(())
+        }
+
+        struct Setup {}
+        impl Mutator for Setup {
+            type Input = ();
+            type Output = (CellPtr<Thread>, Vec<usize>);
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let thread = Thread::alloc(mem)?;
+                eval(mem, thread, "(def pick () (cdr '(x y z)))")?;
+                garbage(mem)?;
+
+                // each Pair of the list is in a block of its own, so only the head is in a block
+                // that the global refers to directly
+                let mut list = mem.nil();
+                let mut pairs = Vec::new();
+                for name in &["c", "b", "a"] {
+                    list = cons(mem, mem.lookup_sym(name), list)?;
+                    pairs.push(list.get_ptr().heap_address().unwrap());
+                    garbage(mem)?;
+                }
+                thread
+                    .globals(mem)
+                    .assoc(mem, mem.lookup_sym("kept"), list)?;
+
+                Ok((CellPtr::new_with(thread), pairs))
+            }
+        }
+
+        struct Check {}
+        impl Mutator for Check {
+            type Input = (CellPtr<Thread>, Vec<usize>);
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                (thread, pairs): Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                for pair in pairs {
+                    assert!(mem.is_old(pair));
+                }
+
+                // new objects go into the freed blocks, not over the survivors
+                let thread = thread.get(mem);
+                garbage(mem)?;
+                assert!(render(*eval(mem, thread, "kept")?) == "(a b c)");
+                assert!(render(*eval(mem, thread, "(pick)")?) == "(y z)");
+
+                Ok(())
+            }
+        }
+
+        let mut mem = Memory::new();
+        let setup = mem.mutate(&Setup {}, ()).unwrap();
+        mem.collect_precise(&setup.0).unwrap();
+        mem.mutate(&Check {}, setup).unwrap();
+    }
+
+    #[cfg(feature = "conservative-roots")]
+    #[test]
+    fn conservative_collection_keeps_register_values() {
+        /// Allocate enough short-lived Pairs to fill several heap blocks
+        fn garbage(mem: &MutatorView) -> Result<(), RuntimeError> {
+            for _ in 0..5000 {
+                cons(mem, mem.lookup_sym("garbage"), mem.nil())?;
+            }
+            Ok(())
+        }
+
+        struct Setup {}
+        impl Mutator for Setup {
+            type Input = ();
+            type Output = (CellPtr<Thread>, usize);
+
+            fn run(&self, mem: &MutatorView, _: Self::Input) -> Result<Self::Output, RuntimeError> {
+                let thread = Thread::alloc(mem)?;
+                garbage(mem)?;
+
+                // the Pair is only reachable through a register, in a block of its own
+                let kept = cons(mem, mem.lookup_sym("kept"), mem.nil())?;
+                IndexedAnyContainer::set(&*thread.stack.get(mem), mem, 10, kept)?;
+                garbage(mem)?;
+
+                // the tail of a list held in a global is in a block that no word points into
+                let tail = cons(mem, mem.lookup_sym("b"), mem.nil())?;
+                garbage(mem)?;
+                let list = cons(mem, mem.lookup_sym("a"), tail)?;
+                thread
+                    .globals(mem)
+                    .assoc(mem, mem.lookup_sym("listed"), list)?;
+                garbage(mem)?;
+
+                let tail = tail.get_ptr().heap_address().unwrap();
+                Ok((CellPtr::new_with(thread), tail))
+            }
+        }
+
+        struct Check {}
+        impl Mutator for Check {
+            type Input = (CellPtr<Thread>, usize);
+            type Output = ();
+
+            fn run(
+                &self,
+                mem: &MutatorView,
+                (thread, tail): Self::Input,
+            ) -> Result<Self::Output, RuntimeError> {
+                assert!(mem.is_old(tail));
+
+                let stack = thread.get(mem).stack.get(mem);
+                let check = || -> Result<(), RuntimeError> {
+                    match *IndexedAnyContainer::get(&*stack, mem, 10)? {
+                        Value::Pair(pair) => {
+                            assert!(pair.first.get(mem) == mem.lookup_sym("kept"));
+                            assert!(pair.second.get(mem) == mem.nil());
+                        }
+                        _ => panic!("expected the register to hold a Pair"),
+                    }
+                    Ok(())
+                };
+
+                check()?;
+
+                // new objects go into the freed blocks, not over the survivors
+                garbage(mem)?;
+                check()?;
+                assert!(render(*eval(mem, thread.get(mem), "listed")?) == "(a b)");
+
+                assert!(mem.gc_stats().collections == 1);
+                Ok(())
+            }
+        }
+
+        let mut mem = Memory::new();
+        let setup = mem.mutate(&Setup {}, ()).unwrap();
+        mem.collect_conservative(&setup.0).unwrap();
+        mem.mutate(&Check {}, setup).unwrap();
+    }
+}
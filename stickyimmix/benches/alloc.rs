@@ -0,0 +1,32 @@
+//! Micro-benchmarks for the bump allocator
+//!
+//! Run with `cargo bench`.
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use stickyimmix::BumpBlock;
+
+/// Fill a new, empty block with objects of each size. Allocation runs down the block from its
+/// end, so this measures the bump pointer fast path plus one failed request at the bottom.
+fn fill_block(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BumpBlock::inner_alloc");
+
+    for size in [16, 32, 128].iter() {
+        group.bench_with_input(BenchmarkId::new("fill", size), size, |b, &size| {
+            b.iter_batched(
+                || BumpBlock::new().unwrap(),
+                |mut block| {
+                    while let Some(ptr) = block.inner_alloc(black_box(size)) {
+                        black_box(ptr);
+                    }
+                    block
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fill_block);
+criterion_main!(benches);
@@ -70,13 +70,15 @@ impl SizeClass {
 pub type ArraySize = u32;
 
 /// TODO Object mark bit.
-/// Every object is `Allocated` on creation.
+/// Every object is `Allocated` on creation. An object that has been evacuated is
+/// `Forwarded` and its old storage points at the new copy.
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Mark {
     Allocated,
     Unmarked,
     Marked,
+    Forwarded,
 }
 
 /// A managed-type type-identifier type should implement this!
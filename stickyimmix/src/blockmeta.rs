@@ -4,6 +4,7 @@ use crate::constants;
 // ANCHOR: DefBlockMeta
 pub struct BlockMeta {
     lines: *mut u8,
+    conservative: bool,
 }
 // ANCHOR_END: DefBlockMeta
 
@@ -11,8 +12,18 @@ impl BlockMeta {
     /// Heap allocate a metadata instance so that it doesn't move so we can store pointers
     /// to it.
     pub fn new(block_ptr: *const u8) -> BlockMeta {
+        BlockMeta::with_conservative_marking(block_ptr, true)
+    }
+
+    /// Create a metadata instance, choosing whether the line following a marked line is
+    /// conservatively considered marked too when finding holes. Conservative marking is needed
+    /// when a small object may span a line boundary without the following line being marked.
+    /// If the allocator can guarantee that small objects never span lines, turning it off
+    /// recovers a line per hole.
+    pub fn with_conservative_marking(block_ptr: *const u8, conservative: bool) -> BlockMeta {
         let mut meta = BlockMeta {
             lines: unsafe { block_ptr.add(constants::LINE_MARK_START) as *mut u8 },
+            conservative,
         };
 
         meta.reset();
@@ -49,6 +60,14 @@ impl BlockMeta {
         }
     }
 
+    /// Return the count of unmarked lines. Lines overlapping the mark flags at the end of the
+    /// block are never available for allocation and are not counted.
+    pub fn free_line_count(&self) -> usize {
+        (0..constants::BLOCK_CAPACITY / constants::LINE_SIZE)
+            .filter(|index| unsafe { *self.lines.add(*index) } == 0)
+            .count()
+    }
+
     /// Return an iterator over all the line mark flags
     //pub fn line_iter(&self) -> impl Iterator<Item = &'_ bool> {
     //    self.line_mark.iter()
@@ -64,6 +83,8 @@ impl BlockMeta {
         // The count of consecutive avaliable holes. Must take into account a conservatively marked
         // hole at the beginning of the sequence.
         let mut count = 0;
+        // The number of lines following a marked line that are considered marked
+        let conservative_lines = self.conservative as usize;
         let starting_line = starting_at / constants::LINE_SIZE;
         let lines_required = (alloc_size + constants::LINE_SIZE - 1) / constants::LINE_SIZE;
         // Counting down from the given search start index
@@ -83,10 +104,10 @@ impl BlockMeta {
                 }
             } else {
                 // This block is marked
-                if count > lines_required {
-                    // But at least 2 previous blocks were not marked. Return the hole, considering the
-                    // immediately preceding block as conservatively marked
-                    let limit = (index + 2) * constants::LINE_SIZE;
+                if count >= lines_required + conservative_lines {
+                    // But enough previous blocks were not marked. Return the hole, considering the
+                    // immediately preceding block as conservatively marked if needed
+                    let limit = (index + 1 + conservative_lines) * constants::LINE_SIZE;
                     let cursor = end * constants::LINE_SIZE;
                     return Some((cursor, limit));
                 }
@@ -218,4 +239,95 @@ mod tests {
 
         assert!(got == expect);
     }
+
+    /// Mark the same set of lines in a conservative and a non-conservative BlockMeta
+    fn mark_both(conservative: &mut BlockMeta, precise: &mut BlockMeta, lines: &[usize]) {
+        for line in lines {
+            conservative.mark_line(*line);
+            precise.mark_line(*line);
+        }
+    }
+
+    #[test]
+    fn test_find_next_hole_not_conservative() {
+        // The same mark pattern as test_find_next_hole, but line 5 is available
+        let block = Block::new(constants::BLOCK_SIZE).unwrap();
+        let mut meta = BlockMeta::with_conservative_marking(block.as_ptr(), false);
+
+        for line in &[0, 1, 2, 4, 10] {
+            meta.mark_line(*line);
+        }
+
+        let expect = Some((10 * constants::LINE_SIZE, 5 * constants::LINE_SIZE));
+        let got = meta.find_next_available_hole(10 * constants::LINE_SIZE, constants::LINE_SIZE);
+
+        println!(
+            "test_find_next_hole_not_conservative got {:?} expected {:?}",
+            got, expect
+        );
+
+        assert!(got == expect);
+    }
+
+    #[test]
+    fn test_compare_conservative_marking() {
+        // A single free line between two marked lines is only a hole without conservative
+        // marking
+        let block1 = Block::new(constants::BLOCK_SIZE).unwrap();
+        let block2 = Block::new(constants::BLOCK_SIZE).unwrap();
+        let mut conservative = BlockMeta::new(block1.as_ptr());
+        let mut precise = BlockMeta::with_conservative_marking(block2.as_ptr(), false);
+
+        mark_both(&mut conservative, &mut precise, &[0, 2, 3, 7, 8]);
+
+        let start = 8 * constants::LINE_SIZE;
+
+        // the hole between lines 3 and 7 is one line smaller when conservatively marked
+        let got = conservative.find_next_available_hole(start, constants::LINE_SIZE);
+        assert!(got == Some((7 * constants::LINE_SIZE, 5 * constants::LINE_SIZE)));
+
+        let got = precise.find_next_available_hole(start, constants::LINE_SIZE);
+        assert!(got == Some((7 * constants::LINE_SIZE, 4 * constants::LINE_SIZE)));
+
+        // an object needing all three lines of that hole only fits without conservative marking
+        let got = conservative.find_next_available_hole(start, 3 * constants::LINE_SIZE);
+        assert!(got.is_none());
+
+        let got = precise.find_next_available_hole(start, 3 * constants::LINE_SIZE);
+        assert!(got == Some((7 * constants::LINE_SIZE, 4 * constants::LINE_SIZE)));
+
+        // line 1 is not a usable hole when conservatively marked
+        let start = 2 * constants::LINE_SIZE;
+
+        let got = conservative.find_next_available_hole(start, constants::LINE_SIZE);
+        assert!(got.is_none());
+
+        let got = precise.find_next_available_hole(start, constants::LINE_SIZE);
+        assert!(got == Some((2 * constants::LINE_SIZE, constants::LINE_SIZE)));
+    }
+
+    #[test]
+    fn test_find_hole_every_other_line_not_conservative() {
+        // Every other line is marked, as in test_find_hole_all_conservatively_marked, but each
+        // unmarked line is a usable hole
+        let block = Block::new(constants::BLOCK_SIZE).unwrap();
+        let mut meta = BlockMeta::with_conservative_marking(block.as_ptr(), false);
+
+        for i in 0..constants::LINE_COUNT {
+            if i % 2 == 0 {
+                meta.mark_line(i);
+            }
+        }
+
+        let top = constants::LINE_COUNT - 2;
+        let expect = Some((top * constants::LINE_SIZE, (top - 1) * constants::LINE_SIZE));
+        let got = meta.find_next_available_hole(constants::BLOCK_CAPACITY, constants::LINE_SIZE);
+
+        println!(
+            "test_find_hole_every_other_line_not_conservative got {:?} expected {:?}",
+            got, expect
+        );
+
+        assert!(got == expect);
+    }
 }
@@ -5,6 +5,7 @@ use blockalloc::{Block, BlockError};
 use crate::allocator::AllocError;
 use crate::blockmeta::BlockMeta;
 use crate::constants;
+use crate::ptrcheck;
 
 impl From<BlockError> for AllocError {
     fn from(error: BlockError) -> AllocError {
@@ -36,6 +37,7 @@ impl BumpBlock {
     pub fn new() -> Result<BumpBlock, AllocError> {
         let inner_block = Block::new(constants::BLOCK_SIZE)?;
         let block_ptr = inner_block.as_ptr();
+        ptrcheck::register_block(block_ptr);
 
         let block = BumpBlock {
             cursor: unsafe { block_ptr.add(constants::BLOCK_CAPACITY) },
@@ -65,8 +67,7 @@ impl BumpBlock {
         let next_ptr = ptr.checked_sub(alloc_size)? & constants::ALLOC_ALIGN_MASK;
 
         if next_ptr < limit {
-            let block_relative_limit =
-                unsafe { self.limit.sub(self.block.as_ptr() as usize) } as usize;
+            let block_relative_limit = limit - self.block.as_ptr() as usize;
 
             if block_relative_limit > 0 {
                 if let Some((cursor, limit)) = self
@@ -81,16 +82,69 @@ impl BumpBlock {
 
             None
         } else {
-            self.cursor = next_ptr as *const u8;
+            // derive the new cursor from the block pointer rather than from the integer address
+            // so that it retains the provenance of the block allocation
+            let block_relative_cursor = next_ptr - self.block.as_ptr() as usize;
+            self.cursor = unsafe { self.block.as_ptr().add(block_relative_cursor) };
             Some(self.cursor)
         }
     }
     // ANCHOR_END: DefBumpBlockAlloc
 
+    /// Forget all objects in the block and clear its marks so that the entire block can be
+    /// allocated into again
+    pub fn reset(&mut self) {
+        let block_ptr = self.block.as_ptr();
+        self.cursor = unsafe { block_ptr.add(constants::BLOCK_CAPACITY) };
+        self.limit = block_ptr;
+        self.meta.reset();
+    }
+
     /// Return the size of the hole we're positioned at
     pub fn current_hole_size(&self) -> usize {
         self.cursor as usize - self.limit as usize
     }
+
+    /// Mark the indexed line as containing a live object
+    pub fn mark_line(&mut self, index: usize) {
+        self.meta.mark_line(index);
+    }
+
+    /// Mark the line containing the given address, which must be within this block
+    pub fn mark_address(&mut self, ptr: *const u8) {
+        debug_assert!(self.contains(ptr));
+        let offset = ptr as usize - self.block.as_ptr() as usize;
+        self.meta.mark_line(offset / constants::LINE_SIZE);
+    }
+
+    /// Return true if the address falls within this block
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let start = self.block.as_ptr() as usize;
+        let addr = ptr as usize;
+        addr >= start && addr < start + constants::BLOCK_CAPACITY
+    }
+
+    /// Return true if any line is marked, that is, the block holds at least one live object
+    pub fn has_marked_lines(&self) -> bool {
+        self.meta.free_line_count() < constants::BLOCK_CAPACITY / constants::LINE_SIZE
+    }
+
+    /// Return the number of bytes in unmarked lines
+    pub fn free_bytes(&self) -> usize {
+        self.meta.free_line_count() * constants::LINE_SIZE
+    }
+
+    /// Return true if some, but not all, lines are marked
+    pub fn is_fragmented(&self) -> bool {
+        let free_lines = self.meta.free_line_count();
+        free_lines > 0 && free_lines < constants::BLOCK_CAPACITY / constants::LINE_SIZE
+    }
+}
+
+impl Drop for BumpBlock {
+    fn drop(&mut self) {
+        ptrcheck::unregister_block(self.block.as_ptr());
+    }
 }
 
 #[cfg(test)]
@@ -11,10 +11,11 @@ use crate::bumpblock::BumpBlock;
 use crate::constants;
 use crate::rawptr::RawPtr;
 
-/// A list of blocks as the current block being allocated into and a list
-/// of full blocks
+/// A list of blocks as the current block being allocated into, a list
+/// of full blocks and a list of empty blocks ready for reuse. Blocks
+/// that have been allocated into since the last collection are young;
+/// blocks holding survivors of a collection are promoted to the old list.
 // TODO:
-// free: Vec<BumpBlock>,
 // recycle: Vec<BumpBlock>
 // large: Vec<Thing>
 // ANCHOR: DefBlockList
@@ -22,15 +23,50 @@ struct BlockList {
     head: Option<BumpBlock>,
     overflow: Option<BumpBlock>,
     rest: Vec<BumpBlock>,
+    old: Vec<BumpBlock>,
+    free: Vec<BumpBlock>,
 }
 // ANCHOR_END: DefBlockList
 
+/// Take an empty block from the free list, or allocate a new one if there are none
+fn free_or_new_block(free: &mut Vec<BumpBlock>) -> Result<BumpBlock, AllocError> {
+    match free.pop() {
+        Some(block) => Ok(block),
+        None => BumpBlock::new(),
+    }
+}
+
 impl BlockList {
     fn new() -> BlockList {
         BlockList {
             head: None,
             overflow: None,
             rest: Vec::new(),
+            old: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Take all the young blocks out of the list in the order they were filled
+    fn take_young(&mut self) -> Vec<BumpBlock> {
+        self.rest
+            .drain(..)
+            .chain(self.head.take())
+            .chain(self.overflow.take())
+            .collect()
+    }
+
+    /// Forget all objects in all blocks, moving the emptied blocks to the free list
+    fn reset(&mut self) {
+        // old blocks were filled before any young block
+        let mut blocks: Vec<BumpBlock> = self.old.drain(..).collect();
+        blocks.extend(self.take_young());
+
+        // push in reverse so that blocks are taken off the free list in the order they were
+        // originally filled
+        while let Some(mut block) = blocks.pop() {
+            block.reset();
+            self.free.push(block);
         }
     }
 
@@ -50,7 +86,7 @@ impl BlockList {
 
                     // the block does not have a suitable hole
                     None => {
-                        let previous = replace(overflow, BumpBlock::new()?);
+                        let previous = replace(overflow, free_or_new_block(&mut self.free)?);
 
                         self.rest.push(previous);
 
@@ -61,7 +97,7 @@ impl BlockList {
 
             // We have no blocks to work with yet so make one
             None => {
-                let mut overflow = BumpBlock::new()?;
+                let mut overflow = free_or_new_block(&mut self.free)?;
 
                 // earlier check for object size < block size should
                 // mean we dont fail this expectation
@@ -78,6 +114,100 @@ impl BlockList {
         Ok(space)
     }
     // ANCHOR_END: DefOverflowAlloc
+
+    /// The sweep phase of a minor collection, following marking. Only young blocks are
+    /// visited: those with marked lines hold survivors and are promoted to the old list, the
+    /// others are emptied onto the free list.
+    fn sweep_young(&mut self) -> Sweep {
+        let blocks = self.take_young();
+        let blocks_visited = blocks.len();
+
+        let mut emptied = Vec::new();
+        for mut block in blocks {
+            if block.has_marked_lines() {
+                self.old.push(block);
+            } else {
+                block.reset();
+                emptied.push(block);
+            }
+        }
+
+        let bytes_freed = emptied.len() * constants::BLOCK_SIZE;
+
+        // as for `reset()`, keep the free list in the order the blocks were originally filled
+        while let Some(block) = emptied.pop() {
+            self.free.push(block);
+        }
+
+        Sweep {
+            blocks_visited,
+            bytes_freed,
+        }
+    }
+
+    /// Mark the line the address points into if it falls within a young or old block,
+    /// returning whether it did. Free blocks hold no objects and are not considered.
+    fn mark_conservative(&mut self, ptr: *const u8) -> bool {
+        let block = self
+            .head
+            .iter_mut()
+            .chain(self.overflow.iter_mut())
+            .chain(self.rest.iter_mut())
+            .chain(self.old.iter_mut())
+            .find(|block| block.contains(ptr));
+
+        match block {
+            Some(block) => {
+                block.mark_address(ptr);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return true if the address is within an old block
+    fn is_old(&self, ptr: *const u8) -> bool {
+        self.old.iter().any(|block| block.contains(ptr))
+    }
+
+    /// Return the fraction, from 0.0 to 1.0, of free bytes that are scattered across partially
+    /// occupied blocks rather than in entirely free blocks. This is computed from line marks
+    /// so it is only meaningful after marking.
+    fn fragmentation(&self) -> f64 {
+        let mut free_bytes = 0;
+        let mut fragmented_bytes = 0;
+
+        let blocks = self
+            .head
+            .iter()
+            .chain(self.overflow.iter())
+            .chain(self.rest.iter())
+            .chain(self.old.iter())
+            .chain(self.free.iter());
+        for block in blocks {
+            let block_free_bytes = block.free_bytes();
+
+            free_bytes += block_free_bytes;
+            if block.is_fragmented() {
+                fragmented_bytes += block_free_bytes;
+            }
+        }
+
+        if free_bytes == 0 {
+            0.0
+        } else {
+            fragmented_bytes as f64 / free_bytes as f64
+        }
+    }
+}
+
+/// The outcome of sweeping the young blocks after a minor collection's marking phase
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Sweep {
+    /// The number of young blocks visited
+    pub blocks_visited: usize,
+    /// The size in bytes of the young blocks that held no survivors and were freed
+    pub bytes_freed: usize,
 }
 
 /// A type that implements `AllocRaw` to provide a low-level heap interface.
@@ -98,6 +228,41 @@ impl<H> StickyImmixHeap<H> {
         }
     }
 
+    /// Return the fraction, from 0.0 to 1.0, of free heap bytes that are scattered across partially
+    /// occupied blocks. A high value indicates that evacuating objects out of fragmented blocks
+    /// would free up whole blocks.
+    pub fn fragmentation(&self) -> f64 {
+        let blocks = unsafe { &*self.blocks.get() };
+        blocks.fragmentation()
+    }
+
+    /// Forget every object in the heap. All blocks are kept for reuse by later allocations.
+    /// Any pointer into the heap obtained before the reset must not be used afterwards.
+    pub fn reset(&mut self) {
+        self.blocks.get_mut().reset();
+    }
+
+    /// Sweep only the young blocks after marking, promoting those containing marked lines
+    /// to the old generation and freeing the rest. Old blocks are not visited. Returns the
+    /// number of blocks visited and the bytes freed.
+    pub fn minor_sweep(&mut self) -> Sweep {
+        self.blocks.get_mut().sweep_young()
+    }
+
+    /// Treat the given word as a possible pointer into the heap, as a conservative root scan
+    /// does. If it falls within an allocated block, the line it points into is marked so that
+    /// a following sweep keeps the block. Any other word is ignored. Returns true if a line was
+    /// marked.
+    pub fn mark_conservative(&mut self, word: usize) -> bool {
+        self.blocks.get_mut().mark_conservative(word as *const u8)
+    }
+
+    /// Return true if the object has survived a collection and lives in an old block
+    pub fn is_old<T>(&self, object: RawPtr<T>) -> bool {
+        let blocks = unsafe { &*self.blocks.get() };
+        blocks.is_old(object.as_ptr() as *const u8)
+    }
+
     /// Find a space for a small, medium or large object
     // TODO this just allocates a new block, but should look at
     // recycled blocks first
@@ -129,7 +294,7 @@ impl<H> StickyImmixHeap<H> {
 
                     // the block does not have a suitable hole
                     None => {
-                        let previous = replace(head, BumpBlock::new()?);
+                        let previous = replace(head, free_or_new_block(&mut blocks.free)?);
 
                         blocks.rest.push(previous);
 
@@ -140,7 +305,7 @@ impl<H> StickyImmixHeap<H> {
 
             // We have no blocks to work with yet so make one
             None => {
-                let mut head = BumpBlock::new()?;
+                let mut head = free_or_new_block(&mut blocks.free)?;
 
                 // earlier check for object size < block size should
                 // mean we dont fail this expectation
@@ -432,4 +597,194 @@ mod tests {
             Err(_) => panic!("Allocation failed"),
         }
     }
+
+    #[test]
+    fn test_fragmentation() {
+        let mut blocks = BlockList::new();
+        assert!(blocks.fragmentation() == 0.0);
+
+        // several blocks with every other line marked: all their free space is scattered in
+        // single line holes
+        for _ in 0..4 {
+            let mut block = BumpBlock::new().unwrap();
+            for index in (0..constants::LINE_COUNT).step_by(2) {
+                block.mark_line(index);
+            }
+            blocks.rest.push(block);
+        }
+
+        // a fully marked block has no free space to contribute
+        let mut full = BumpBlock::new().unwrap();
+        for index in 0..constants::LINE_COUNT {
+            full.mark_line(index);
+        }
+        blocks.rest.push(full);
+
+        let fragmentation = blocks.fragmentation();
+        assert!(fragmentation == 1.0);
+
+        // an entirely free block reduces fragmentation, but the free space is still mostly
+        // scattered
+        blocks.head = Some(BumpBlock::new().unwrap());
+
+        let fragmentation = blocks.fragmentation();
+        assert!(fragmentation > 0.6 && fragmentation < 1.0);
+    }
+
+    #[test]
+    fn test_unmarked_heap_fragmentation() {
+        // without any line marking, all free space is in whole blocks
+        let mem = StickyImmixHeap::<TestHeader>::new();
+        assert!(mem.fragmentation() == 0.0);
+
+        for i in 0..1000 {
+            mem.alloc(i as usize).unwrap();
+        }
+        assert!(mem.fragmentation() == 0.0);
+    }
+
+    #[test]
+    fn test_reset_reuses_blocks() {
+        let mut mem = StickyImmixHeap::<TestHeader>::new();
+
+        // fill a few blocks
+        let first = mem.alloc(0usize).unwrap();
+        for i in 1..(constants::BLOCK_SIZE / 4) {
+            mem.alloc(i).unwrap();
+        }
+
+        let block_count = {
+            let blocks = unsafe { &*mem.blocks.get() };
+            blocks.rest.len() + 1
+        };
+        assert!(block_count > 1);
+
+        mem.reset();
+
+        {
+            let blocks = unsafe { &*mem.blocks.get() };
+            assert!(blocks.head.is_none());
+            assert!(blocks.rest.is_empty());
+            assert!(blocks.free.len() == block_count);
+        }
+
+        // allocating the same objects again reuses the same blocks without creating new ones
+        let again = mem.alloc(0usize).unwrap();
+        for i in 1..(constants::BLOCK_SIZE / 4) {
+            mem.alloc(i).unwrap();
+        }
+        assert!(again.as_ptr() == first.as_ptr());
+
+        let blocks = unsafe { &*mem.blocks.get() };
+        assert!(blocks.free.is_empty());
+        assert!(blocks.rest.len() + 1 == block_count);
+    }
+
+    /// Mark the line containing the object, as a marking pass would
+    fn mark_object<T>(mem: &mut StickyImmixHeap<TestHeader>, object: RawPtr<T>) {
+        let ptr = object.as_ptr() as *const u8;
+        let blocks = mem.blocks.get_mut();
+        let block = blocks
+            .head
+            .iter_mut()
+            .chain(blocks.overflow.iter_mut())
+            .chain(blocks.rest.iter_mut())
+            .find(|block| block.contains(ptr))
+            .expect("object not in a young block");
+        block.mark_address(ptr);
+    }
+
+    #[test]
+    fn test_minor_sweep_promotes_survivors() {
+        let mut mem = StickyImmixHeap::<TestHeader>::new();
+
+        // fill a few blocks, keeping one object alive
+        let survivor = mem.alloc(0usize).unwrap();
+        for i in 1..(constants::BLOCK_SIZE / 4) {
+            mem.alloc(i).unwrap();
+        }
+
+        let block_count = {
+            let blocks = unsafe { &*mem.blocks.get() };
+            blocks.rest.len() + 1
+        };
+        assert!(block_count > 1);
+
+        mark_object(&mut mem, survivor);
+        assert!(!mem.is_old(survivor));
+
+        let sweep = mem.minor_sweep();
+        assert!(sweep.blocks_visited == block_count);
+        assert!(sweep.bytes_freed == (block_count - 1) * constants::BLOCK_SIZE);
+
+        // only the block holding the survivor was promoted
+        assert!(mem.is_old(survivor));
+        assert!(unsafe { *survivor.as_ref() } == 0);
+        {
+            let blocks = unsafe { &*mem.blocks.get() };
+            assert!(blocks.head.is_none());
+            assert!(blocks.rest.is_empty());
+            assert!(blocks.old.len() == 1);
+            assert!(blocks.free.len() == block_count - 1);
+        }
+
+        // new objects are young and go into freed blocks
+        let young = mem.alloc(1usize).unwrap();
+        assert!(!mem.is_old(young));
+    }
+
+    #[test]
+    fn test_conservative_marking() {
+        let mut mem = StickyImmixHeap::<TestHeader>::new();
+
+        let survivor = mem.alloc(0usize).unwrap();
+        for i in 1..(constants::BLOCK_SIZE / 4) {
+            mem.alloc(i).unwrap();
+        }
+
+        // words that do not point into a block are ignored
+        let outside = 0usize;
+        assert!(!mem.mark_conservative(&outside as *const usize as usize));
+        assert!(!mem.mark_conservative(0));
+
+        // a word pointing into the middle of an object is enough to keep its block
+        assert!(mem.mark_conservative(survivor.as_word() + 3));
+        mem.minor_sweep();
+        assert!(mem.is_old(survivor));
+        assert!(unsafe { *survivor.as_ref() } == 0);
+
+        // old blocks can be marked conservatively too
+        assert!(mem.mark_conservative(survivor.as_word()));
+    }
+
+    #[test]
+    fn test_minor_sweep_skips_old_blocks() {
+        let mut mem = StickyImmixHeap::<TestHeader>::new();
+
+        let survivor = mem.alloc(0usize).unwrap();
+        mark_object(&mut mem, survivor);
+        let sweep = mem.minor_sweep();
+        assert!(sweep.blocks_visited == 1);
+        assert!(sweep.bytes_freed == 0);
+
+        // nothing young is marked, so only the new young block is visited and it is freed
+        let young = mem.alloc(1usize).unwrap();
+        let sweep = mem.minor_sweep();
+        assert!(sweep.blocks_visited == 1);
+        assert!(sweep.bytes_freed == constants::BLOCK_SIZE);
+        assert!(!mem.is_old(young));
+        assert!(mem.is_old(survivor));
+
+        {
+            let blocks = unsafe { &*mem.blocks.get() };
+            assert!(blocks.old.len() == 1);
+            assert!(blocks.free.len() == 1);
+        }
+
+        // a full reset forgets old blocks too
+        mem.reset();
+        let blocks = unsafe { &*mem.blocks.get() };
+        assert!(blocks.old.is_empty());
+        assert!(blocks.free.len() == 2);
+    }
 }
@@ -5,12 +5,15 @@ mod blockmeta;
 mod bumpblock;
 mod constants;
 mod heap;
+mod ptrcheck;
 mod rawptr;
 
 pub use crate::allocator::{
     AllocError, AllocHeader, AllocObject, AllocRaw, AllocTypeId, ArraySize, Mark, SizeClass,
 };
 
-pub use crate::heap::StickyImmixHeap;
+pub use crate::bumpblock::BumpBlock;
+
+pub use crate::heap::{StickyImmixHeap, Sweep};
 
 pub use crate::rawptr::RawPtr;
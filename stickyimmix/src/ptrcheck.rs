@@ -0,0 +1,125 @@
+/// Debug-build pointer validity checks, enabled by the `ptr-checks` feature.
+///
+/// The base address of every live block is recorded in a global set. Since blocks are
+/// aligned to their size, masking a pointer with `BLOCK_PTR_MASK` yields the base address
+/// of the block it should belong to. A pointer whose block is not in the set either never
+/// pointed into the heap or points into a block that has since been freed.
+///
+/// In release builds, or without the feature, all of these functions are no-ops.
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use crate::constants;
+
+const ENABLED: bool = cfg!(all(feature = "ptr-checks", debug_assertions));
+
+static LIVE_BLOCKS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Record a newly allocated block as live
+pub fn register_block(base: *const u8) {
+    if ENABLED {
+        LIVE_BLOCKS.lock().unwrap().insert(base as usize);
+    }
+}
+
+/// Remove a block from the live set before it is deallocated
+pub fn unregister_block(base: *const u8) {
+    if ENABLED {
+        LIVE_BLOCKS.lock().unwrap().remove(&(base as usize));
+    }
+}
+
+/// Assert that a pointer is non-null
+pub fn check_non_null<T>(ptr: *const T) {
+    if ENABLED {
+        assert!(!ptr.is_null(), "null pointer");
+    }
+}
+
+/// Assert that a pointer about to be dereferenced is non-null and points into a live block
+pub fn check_deref<T>(ptr: *const T) {
+    if ENABLED {
+        check_non_null(ptr);
+
+        let base = ptr as usize & constants::BLOCK_PTR_MASK;
+        assert!(
+            LIVE_BLOCKS.lock().unwrap().contains(&base),
+            "dangling pointer {:p}: not in any live heap block",
+            ptr
+        );
+    }
+}
+
+#[cfg(all(test, feature = "ptr-checks", debug_assertions))]
+mod tests {
+
+    use crate::allocator::{AllocHeader, AllocObject, AllocRaw, AllocTypeId, Mark, SizeClass};
+    use crate::heap::StickyImmixHeap;
+    use crate::rawptr::RawPtr;
+    use std::ptr::null;
+
+    #[derive(PartialEq, Copy, Clone)]
+    struct TestTypeId {}
+    impl AllocTypeId for TestTypeId {}
+
+    struct TestHeader {}
+    impl AllocHeader for TestHeader {
+        type TypeId = TestTypeId;
+
+        fn new<O: AllocObject<TestTypeId>>(_: u32, _: SizeClass, _: Mark) -> TestHeader {
+            TestHeader {}
+        }
+        fn new_array(_: u32, _: SizeClass, _: Mark) -> TestHeader {
+            TestHeader {}
+        }
+        fn mark(&mut self) {}
+        fn is_marked(&self) -> bool {
+            true
+        }
+        fn size_class(&self) -> SizeClass {
+            SizeClass::Small
+        }
+        fn size(&self) -> u32 {
+            8
+        }
+        fn type_id(&self) -> TestTypeId {
+            TestTypeId {}
+        }
+    }
+
+    impl AllocObject<TestTypeId> for usize {
+        const TYPE_ID: TestTypeId = TestTypeId {};
+    }
+
+    #[test]
+    fn live_pointer_passes() {
+        let mem = StickyImmixHeap::<TestHeader>::new();
+        let ptr = mem.alloc(42usize).unwrap();
+        assert!(unsafe { *ptr.as_ref() } == 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "null pointer")]
+    fn null_pointer_panics() {
+        let _ = RawPtr::<usize>::new(null());
+    }
+
+    #[test]
+    #[should_panic(expected = "dangling pointer")]
+    fn dangling_pointer_panics() {
+        let mem = StickyImmixHeap::<TestHeader>::new();
+        let ptr = mem.alloc(42usize).unwrap();
+        drop(mem);
+
+        let _ = unsafe { ptr.as_ref() };
+    }
+
+    #[test]
+    #[should_panic(expected = "dangling pointer")]
+    fn non_heap_pointer_panics() {
+        let value = 42usize;
+        let ptr = RawPtr::new(&value as *const usize);
+
+        let _ = unsafe { ptr.as_ref() };
+    }
+}
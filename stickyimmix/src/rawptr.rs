@@ -1,5 +1,7 @@
 use std::ptr::NonNull;
 
+use crate::ptrcheck;
+
 /// A container for a bare pointer to an object of type `T`.
 /// At this level, compile-time type information is still
 /// part of the type.
@@ -12,6 +14,7 @@ pub struct RawPtr<T: Sized> {
 impl<T: Sized> RawPtr<T> {
     /// Create a new RawPtr from a bare pointer
     pub fn new(ptr: *const T) -> RawPtr<T> {
+        ptrcheck::check_non_null(ptr);
         RawPtr {
             ptr: unsafe { NonNull::new_unchecked(ptr as *mut T) },
         }
@@ -30,9 +33,17 @@ impl<T: Sized> RawPtr<T> {
         self.ptr.cast()
     }
 
+    /// With the `ptr-checks` feature, in debug builds, assert that the pointer is within a live
+    /// heap block. Otherwise a no-op.
+    pub fn check_deref(&self) {
+        ptrcheck::check_deref(self.as_ptr());
+    }
+
     /// Get a `&` reference to the object. Unsafe because there are no guarantees at this level
-    /// about the internal pointer's validity.
+    /// about the internal pointer's validity, though with the `ptr-checks` feature debug builds
+    /// assert that the pointer is within a live heap block.
     pub unsafe fn as_ref(&self) -> &T {
+        self.check_deref();
         self.ptr.as_ref()
     }
 
@@ -41,6 +52,7 @@ impl<T: Sized> RawPtr<T> {
     /// In addition, there can be no compile-time guarantees of mutable aliasing prevention.
     /// Use with caution!
     pub unsafe fn as_mut_ref(&mut self) -> &mut T {
+        self.check_deref();
         self.ptr.as_mut()
     }
 }